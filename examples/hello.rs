@@ -4,7 +4,7 @@ use bevy_lazy_prefabs::*;
 fn main() {
     App::build()
         .add_plugins(DefaultPlugins)
-        .add_plugin(LazyPrefabsPlugin)
+        .add_plugin(LazyPrefabsPlugin::default())
         .add_startup_system(setup.system())
         .add_startup_system_to_stage(StartupStage::PostStartup, check.system())
         .run();
@@ -18,7 +18,7 @@ struct DealsDamage {
 fn main() {
     App::build()
         .add_plugins(DefaultPlugins)
-        .add_plugin(LazyPrefabsPlugin)
+        .add_plugin(LazyPrefabsPlugin::default())
         .add_startup_system(setup.system())
         .add_startup_system_to_stage(StartupStage::PostStartup, read_damage.system())
         .run();
@@ -0,0 +1,196 @@
+use std::ops::Range;
+
+use bevy::{
+    prelude::*,
+    reflect::{DynamicStruct, ReflectRef},
+};
+
+use crate::{
+    build_commands::{get_texture_atlas, BuildPhase, BuildPrefabCommand, PrefabCommandError},
+    dynamic_cast::*,
+};
+
+/// Plays a sequence of frames from the entity's `TextureAtlasSprite`. Requires the `animation`
+/// feature, see [InsertSpriteAnimation].
+#[derive(Debug, Default, Clone, PartialEq, Reflect)]
+#[reflect(Component)]
+pub struct SpriteAnimation {
+    /// The atlas indices to play, in order.
+    pub frames: Vec<u32>,
+    /// Frames per second.
+    pub fps: f32,
+    /// Restart from the first frame after the last one. Otherwise the animation stops on the
+    /// last frame.
+    pub looping: bool,
+    /// The position in `frames` of the current frame.
+    pub current: usize,
+    elapsed: f32,
+}
+
+impl SpriteAnimation {
+    pub fn new(frames: Vec<u32>, fps: f32, looping: bool) -> Self {
+        SpriteAnimation {
+            frames,
+            fps,
+            looping,
+            ..Default::default()
+        }
+    }
+
+    /// The atlas index of the current frame.
+    pub fn frame(&self) -> Option<u32> {
+        self.frames.get(self.current).copied()
+    }
+
+    /// `true` if a non-looping animation has reached its last frame.
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.current + 1 >= self.frames.len()
+    }
+
+    /// Advance the animation by `delta` seconds. Returns the new atlas index if the frame changed.
+    pub fn tick(&mut self, delta: f32) -> Option<u32> {
+        if self.fps <= 0.0 || self.frames.is_empty() || self.is_finished() {
+            return None;
+        }
+        self.elapsed += delta;
+        let frame_time = 1.0 / self.fps;
+        let previous = self.current;
+        while self.elapsed >= frame_time && !self.is_finished() {
+            self.elapsed -= frame_time;
+            self.current = (self.current + 1) % self.frames.len();
+        }
+        match self.current != previous {
+            true => self.frame(),
+            false => None,
+        }
+    }
+}
+
+/// Inserts a [SpriteAnimation], and a `SpriteSheetBundle` if an atlas is given. Requires the
+/// `animation` feature.
+///
+/// ### Properties:
+///
+/// - `frames` - The atlas indices to play, as a list of integers or a range, ie: `[0, 1, 2]` or `(0..4)`.
+/// - `fps` - Optional frames per second. Defaults to 10.
+/// - `looping` - Optional, defaults to `true`.
+/// - `atlas` - Optional. Builds a grid `TextureAtlas` from `texture_path`, `tile_size`, `columns`, `rows` and
+///   `padding`, as with [InsertTextureAtlas](crate::build_commands::InsertTextureAtlas). Otherwise the entity
+///   should already have a `SpriteSheetBundle`.
+///
+/// ### Example
+///
+/// ```ignore
+/// {
+///     InsertSpriteAnimation!(
+///         frames: (0..4),
+///         fps: 8.0,
+///         atlas: { texture_path: "run.png", tile_size: Vec2 { x: 16.0, y: 16.0 }, columns: 4, rows: 1 },
+///     ),
+/// }
+/// ```
+#[derive(Default)]
+pub struct InsertSpriteAnimation;
+impl BuildPrefabCommand for InsertSpriteAnimation {
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
+        let properties =
+            properties.ok_or_else(|| PrefabCommandError::MissingProperty("frames".to_string()))?;
+
+        let frames = get_frames(properties);
+        let fps = properties.try_get_f32("fps").unwrap_or(10.0);
+        let looping = properties.get_or("looping", true);
+        let animation = SpriteAnimation::new(frames, fps, looping);
+
+        if let Ok(atlas) = properties.try_get_path::<DynamicStruct>("atlas") {
+            let atlas = get_texture_atlas(world, Some(atlas))?;
+            world.entity_mut(entity).insert_bundle(SpriteSheetBundle {
+                texture_atlas: atlas,
+                sprite: TextureAtlasSprite::new(animation.frame().unwrap_or_default()),
+                ..Default::default()
+            });
+        }
+
+        world.entity_mut(entity).insert(animation);
+        Ok(())
+    }
+
+    fn key(&self) -> &str {
+        "InsertSpriteAnimation"
+    }
+
+    fn phase(&self) -> BuildPhase {
+        BuildPhase::Bundle
+    }
+}
+
+fn get_frames(properties: &DynamicStruct) -> Vec<u32> {
+    if let Ok(range) = properties.try_get::<Range<i32>>("frames") {
+        return range.clone().map(|i| i.max(0) as u32).collect();
+    }
+    match properties.field("frames").map(|f| f.reflect_ref()) {
+        Some(ReflectRef::List(list)) => list
+            .iter()
+            .filter_map(|v| v.downcast_ref::<i32>())
+            .map(|i| (*i).max(0) as u32)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Advances every [SpriteAnimation] and updates its `TextureAtlasSprite`.
+pub(crate) fn animate_sprites(
+    time: Res<Time>,
+    mut q: Query<(&mut SpriteAnimation, &mut TextureAtlasSprite)>,
+) {
+    for (mut animation, mut sprite) in q.iter_mut() {
+        if let Some(frame) = animation.tick(time.delta_seconds()) {
+            sprite.index = frame;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::PrefabRegistry;
+
+    #[test]
+    fn sprite_animation() {
+        use crate::build_commands::InsertSpriteAnimation;
+        use crate::test_utils::*;
+        use crate::SpriteAnimation;
+
+        let mut world = prefab_world();
+        world
+            .get_resource_mut::<PrefabRegistry>()
+            .unwrap()
+            .register_build_command::<InsertSpriteAnimation>();
+
+        let entity = spawn_prefab_str(
+            &mut world,
+            "{ InsertSpriteAnimation!(frames: [2, 5, 7], fps: 10.0) }",
+        )
+        .unwrap();
+        let mut anim = world.get::<SpriteAnimation>(entity).unwrap().clone();
+        assert_eq!(anim, SpriteAnimation::new(vec![2, 5, 7], 10.0, true));
+        assert_eq!(anim.frame(), Some(2));
+
+        assert_eq!(anim.tick(0.05), None);
+        assert_eq!(anim.tick(0.05), Some(5));
+        assert_eq!(anim.tick(0.25), Some(2));
+
+        let entity =
+            spawn_prefab_str(&mut world, "{ InsertSpriteAnimation!(frames: (0..3)) }").unwrap();
+        let anim = world.get::<SpriteAnimation>(entity).unwrap();
+        assert_eq!(anim.frames, [0, 1, 2]);
+
+        let mut once = SpriteAnimation::new(vec![0, 1, 2], 10.0, false);
+        assert_eq!(once.tick(1.0), Some(2));
+        assert!(once.is_finished());
+        assert_eq!(once.tick(1.0), None);
+    }
+}
@@ -0,0 +1,59 @@
+use std::marker::PhantomData;
+
+use bevy::{asset::Asset, prelude::*};
+
+/// An asset path read from a *.prefab* file, waiting to be loaded as a [Handle].
+///
+/// Inserted by the build commands registered with
+/// [crate::PrefabAppBuilderExt::register_prefab_asset]. On the next update the asset is
+/// loaded from the path and this component is replaced with a `Handle<T>`.
+pub struct PrefabAssetPath<T: Asset> {
+    pub path: String,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Asset> PrefabAssetPath<T> {
+    pub fn new(path: &str) -> Self {
+        PrefabAssetPath {
+            path: path.to_string(),
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Replaces each [PrefabAssetPath] with a handle to the loaded asset.
+pub(crate) fn resolve_asset_paths<T: Asset>(
+    mut commands: Commands,
+    server: Res<AssetServer>,
+    query: Query<(Entity, &PrefabAssetPath<T>)>,
+) {
+    for (entity, asset_path) in query.iter() {
+        let handle: Handle<T> = server.load(asset_path.path.as_str());
+        commands
+            .entity(entity)
+            .insert(handle)
+            .remove::<PrefabAssetPath<T>>();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::*;
+
+    use crate::PrefabRegistry;
+
+    #[test]
+    fn asset_path_command() {
+        use crate::{test_utils::*, PrefabAppBuilderExt, PrefabAssetPath};
+
+        let mut app = App::build();
+        app.init_resource::<PrefabRegistry>()
+            .register_prefab_asset::<Mesh>("LoadMesh");
+
+        let world = app.world_mut();
+        let entity = spawn_prefab_str(world, "{ LoadMesh!(path: \"ship.gltf#Mesh0\") }").unwrap();
+
+        let asset_path = world.get::<PrefabAssetPath<Mesh>>(entity).unwrap();
+        assert_eq!(asset_path.path, "ship.gltf#Mesh0");
+    }
+}
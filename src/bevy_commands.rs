@@ -6,8 +6,23 @@ use bevy::{
 };
 
 use crate::{
-    prefab::{Prefab, PrefabCommandData, PrefabComponent},
-    PrefabRegistry,
+    build_commands::{
+        apply_component, apply_prefab, command_failed, reset_prefab_components, run_command,
+        spawn_children, update_prefab_components, PrefabCommandError,
+    },
+    deferred::{Anchors, PrefabRng},
+    instance::{send_instance_spawned, PrefabInstanceHandle},
+    level::{despawn_all, DespawnLevelCommand, SpawnLevelCommand},
+    pool::{
+        PooledPrefab, PrefabPool, ReusePooledCommand, SpawnFromPoolCommand, SpawnPooledCommand,
+    },
+    prefab::{
+        AppliedPrefab, Prefab, PrefabBuildStep, PrefabCommandData, PrefabComponent, SpawnedFrom,
+    },
+    preload::{PendingPrefabHandle, PrefabText},
+    stream::StartPrefabStreamCommand,
+    table::SpawnFromTableCommand,
+    PrefabRegistry, SpawnBudget,
 };
 
 pub trait SpawnPrefabCommands {
@@ -15,66 +30,606 @@ pub trait SpawnPrefabCommands {
     ///
     /// Prefabs can be loaded from the [PrefabRegistry].
     fn insert_prefab(&mut self, prefab: &Prefab) -> &mut Self;
+
+    /// Apply several [Prefab]s to an entity, one after another.
+    ///
+    /// Each prefab is applied in full before the next, so components and commands
+    /// from later prefabs will override those from earlier ones.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn setup(mut commands: Commands, mut registry: ResMut<PrefabRegistry>) {
+    ///     let enemy = registry.load("base_enemy.prefab").unwrap().clone();
+    ///     let flying = registry.load("flying.prefab").unwrap().clone();
+    ///     commands.spawn().insert_prefabs(&[&enemy, &flying]);
+    /// }
+    /// ```
+    fn insert_prefabs(&mut self, prefabs: &[&Prefab]) -> &mut Self;
+
+    /// Reset the entity's components to the values from the prefab it was spawned from,
+    /// clearing any changes made at runtime.
+    ///
+    /// Only components declared in the prefab are reset. Fields which aren't set in the
+    /// prefab are reset to their default values. The entity must have been spawned from a
+    /// named prefab with [PrefabRegistry::set_tag_spawned] enabled, or from a [PrefabPool].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn restart(mut commands: Commands, players: Query<Entity, With<SpawnedFrom>>) {
+    ///     for player in players.iter() {
+    ///         commands.entity(player).reset_to_prefab();
+    ///     }
+    /// }
+    /// ```
+    fn reset_to_prefab(&mut self) -> &mut Self;
 }
 
 impl SpawnPrefabCommands for EntityCommands<'_, '_> {
     fn insert_prefab(&mut self, prefab: &Prefab) -> &mut Self {
         let id = self.id();
-        for step in prefab.steps.iter() {
-            match step {
-                crate::prefab::PrefabBuildStep::AddComponent(comp) => {
-                    self.commands().add(AddComponentCommand {
-                        entity: id,
-                        component: comp.clone(),
-                    });
-                }
-                crate::prefab::PrefabBuildStep::RunCommand(command) => {
-                    self.commands().add(PrefabProcessCommand {
-                        entity: id,
-                        data: command.clone(),
-                    });
-                }
-            }
+        let commands = self.commands();
+
+        let mut children = Vec::new();
+        let mut anchors = Anchors::default();
+        spawn_children(prefab, id, &mut children, &mut anchors, &mut || {
+            commands.spawn().id()
+        });
+        let anchors = Arc::new(anchors);
+        queue_steps(commands, prefab, id, &anchors, &mut children.into_iter());
+
+        self
+    }
+
+    fn insert_prefabs(&mut self, prefabs: &[&Prefab]) -> &mut Self {
+        for prefab in prefabs {
+            self.insert_prefab(prefab);
         }
+        self
+    }
 
+    fn reset_to_prefab(&mut self) -> &mut Self {
+        let entity = self.id();
+        self.commands().add(ResetToPrefabCommand { entity });
         self
     }
 }
 
-struct AddComponentCommand {
+fn queue_steps(
+    commands: &mut Commands,
+    prefab: &Prefab,
+    id: Entity,
+    anchors: &Arc<Anchors>,
+    children: &mut impl Iterator<Item = Entity>,
+) {
+    if let Some(source) = &prefab.source {
+        commands.add(TagSpawnedCommand {
+            entity: id,
+            source: source.clone(),
+        });
+    }
+    for step in prefab.steps.iter() {
+        match step {
+            PrefabBuildStep::AddComponent(comp) => {
+                commands.add(AddComponentCommand {
+                    entity: id,
+                    component: comp.clone(),
+                    anchors: anchors.clone(),
+                });
+            }
+            PrefabBuildStep::RunCommand(command) => {
+                commands.add(PrefabProcessCommand {
+                    entity: id,
+                    data: command.clone(),
+                    anchors: anchors.clone(),
+                });
+            }
+            PrefabBuildStep::AddChild(child) => {
+                let child_id = children.next().unwrap();
+                queue_steps(commands, child, child_id, anchors, children);
+                commands.entity(id).push_children(&[child_id]);
+            }
+        }
+    }
+    commands.add(InsertCompanionsCommand { entity: id });
+}
+
+pub trait SpawnNamedPrefabCommands<'a> {
+    /// Spawn a prefab by name and place it at `translation`.
+    ///
+    /// The prefab is loaded from the [PrefabRegistry] when the command is applied.
+    /// The translation is set after all of the prefab's build steps have run, so it
+    /// always overrides the prefab's own `Transform`. A `Transform` is inserted if the
+    /// prefab doesn't have one.
+    ///
+    /// If the prefab can't be loaded the fallback prefab is spawned instead, see
+    /// [PrefabRegistry::set_fallback_prefab].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn spawn_enemies(mut commands: Commands) {
+    ///     for i in 0..5 {
+    ///         commands.spawn_prefab_at("enemy.prefab", Vec3::new(i as f32 * 2.0, 0.0, 0.0));
+    ///     }
+    /// }
+    /// ```
+    fn spawn_prefab_at(&mut self, name: &str, translation: Vec3) -> EntityCommands<'a, '_>;
+
+    /// Spawn a prefab by name, reusing an inactive instance from the [PrefabPool] if
+    /// there is one.
+    ///
+    /// A reused instance has its components reset to the values from the prefab. Build
+    /// commands are only run the first time an instance is built, which avoids
+    /// recreating bundles and assets for prefabs that are spawned very frequently.
+    /// Return instances to the pool with [crate::PooledPrefabCommands::despawn_pooled].
+    ///
+    /// The instance is chosen when the command is applied, see
+    /// [SpawnNamedPrefabCommands::spawn_prefab_pooled_in] to get the entity straight away.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn fire(mut commands: Commands) {
+    ///     commands.spawn_prefab_pooled("bullet.prefab");
+    /// }
+    ///
+    /// fn expire(mut commands: Commands, bullets: Query<Entity, Without<PoolInactive>>) {
+    ///     for bullet in bullets.iter() {
+    ///         commands.entity(bullet).despawn_pooled();
+    ///     }
+    /// }
+    /// ```
+    fn spawn_prefab_pooled(&mut self, name: &str);
+
+    /// Spawn a prefab by name like [SpawnNamedPrefabCommands::spawn_prefab_pooled], taking
+    /// the instance from `pool` immediately so the entity can be returned.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// struct Bullet;
+    ///
+    /// fn fire(mut commands: Commands, mut pool: ResMut<PrefabPool>) {
+    ///     commands
+    ///         .spawn_prefab_pooled_in(&mut pool, "bullet.prefab")
+    ///         .insert(Bullet);
+    /// }
+    /// ```
+    fn spawn_prefab_pooled_in(
+        &mut self,
+        pool: &mut PrefabPool,
+        name: &str,
+    ) -> EntityCommands<'a, '_>;
+
+    /// Spawn every prefab instance in a *.level* file.
+    ///
+    /// The level is loaded from the [PrefabRegistry] when the command is applied, see
+    /// [PrefabRegistry::load_level]. Each instance's overrides are applied after its prefab,
+    /// and named instances are given a `Name` component.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn setup(mut commands: Commands) {
+    ///     commands.spawn_level("level1.level");
+    /// }
+    /// ```
+    fn spawn_level(&mut self, name: &str);
+
+    /// Despawn every entity spawned from the named prefab, along with their children.
+    ///
+    /// Entities are found by their [SpawnedFrom] component, so [PrefabRegistry::set_tag_spawned]
+    /// must be enabled when they're spawned.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn clear_enemies(mut commands: Commands) {
+    ///     commands.despawn_prefab_instances("enemy_grunt.prefab");
+    /// }
+    /// ```
+    fn despawn_prefab_instances(&mut self, name: &str);
+
+    /// Despawn every prefab instance spawned by [SpawnNamedPrefabCommands::spawn_level] for
+    /// the given level, along with their children.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn next_level(mut commands: Commands) {
+    ///     commands.despawn_level("level1.level");
+    ///     commands.spawn_level("level2.level");
+    /// }
+    /// ```
+    fn despawn_level(&mut self, name: &str);
+
+    /// Update the tagged entities spawned from a prefab to match the cached version, see
+    /// [PrefabRegistry::set_tag_spawned]. Sent automatically when a prefab changes.
+    fn update_prefab_instances(&mut self, name: &str);
+
+    /// Spawn a prefab by name, applying its build steps over several frames within `budget`.
+    ///
+    /// Useful for very large prefabs, such as an imported level, which would cause a hitch if
+    /// they were applied all at once. Child entities are spawned immediately, but are only
+    /// added to the hierarchy once their own steps have been applied. The entity's
+    /// [crate::PrefabStreamProgress] tracks how much of the prefab has been applied.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn load_level(mut commands: Commands) {
+    ///     commands.spawn_prefab_streamed("castle.prefab", SpawnBudget::Count(200));
+    /// }
+    ///
+    /// fn loading_bar(q: Query<&PrefabStreamProgress>) {
+    ///     for progress in q.iter() {
+    ///         info!("Loading {:.0}%", progress.fraction() * 100.0);
+    ///     }
+    /// }
+    /// ```
+    fn spawn_prefab_streamed(&mut self, name: &str, budget: SpawnBudget) -> EntityCommands<'a, '_>;
+
+    /// Pick a prefab from a *.table* file by weight and spawn it, see [PrefabRegistry::load_table].
+    ///
+    /// The roll is taken from `rng` immediately, so a seeded [PrefabRng] picks the same prefabs
+    /// each time. If the table can't be loaded or has no weight the entity is left empty.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn drop_loot(mut commands: Commands, mut rng: ResMut<PrefabRng>) {
+    ///     commands.spawn_from_table("loot.table", &mut rng);
+    /// }
+    /// ```
+    fn spawn_from_table(&mut self, table: &str, rng: &mut PrefabRng) -> EntityCommands<'a, '_>;
+
+    /// Spawn a prefab from a [PrefabText] asset handle, such as one loaded by another asset.
+    ///
+    /// The entity is spawned immediately but the prefab is applied once the asset has loaded,
+    /// so the registry doesn't need to read the file. It's cached under its path relative
+    /// to the prefab directory. If the asset fails to load a [crate::PrefabLoadFailed] event
+    /// is sent and the entity is left empty.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn spawn_boss(mut commands: Commands, server: Res<AssetServer>) {
+    ///     let boss: Handle<PrefabText> = server.load("prefabs/boss.prefab");
+    ///     commands.spawn_prefab_handle(boss);
+    /// }
+    /// ```
+    fn spawn_prefab_handle(&mut self, handle: Handle<PrefabText>) -> EntityCommands<'a, '_>;
+}
+
+impl<'a> SpawnNamedPrefabCommands<'a> for Commands<'a> {
+    fn spawn_prefab_at(&mut self, name: &str, translation: Vec3) -> EntityCommands<'a, '_> {
+        let entity = self.spawn().id();
+        self.add(SpawnPrefabAtCommand {
+            entity,
+            name: name.to_string(),
+            translation,
+        });
+        self.entity(entity)
+    }
+
+    fn spawn_prefab_pooled(&mut self, name: &str) {
+        self.add(SpawnFromPoolCommand {
+            name: name.to_string(),
+        });
+    }
+
+    fn spawn_prefab_pooled_in(
+        &mut self,
+        pool: &mut PrefabPool,
+        name: &str,
+    ) -> EntityCommands<'a, '_> {
+        let name = name.to_string();
+        match pool.take(&name) {
+            Some(entity) => {
+                self.add(ReusePooledCommand { entity, name });
+                self.entity(entity)
+            }
+            None => {
+                let entity = self.spawn().id();
+                self.add(SpawnPooledCommand { entity, name });
+                self.entity(entity)
+            }
+        }
+    }
+
+    fn spawn_level(&mut self, name: &str) {
+        self.add(SpawnLevelCommand {
+            name: name.to_string(),
+        });
+    }
+
+    fn despawn_prefab_instances(&mut self, name: &str) {
+        self.add(DespawnPrefabInstancesCommand {
+            name: name.to_string(),
+        });
+    }
+
+    fn despawn_level(&mut self, name: &str) {
+        self.add(DespawnLevelCommand {
+            name: name.to_string(),
+        });
+    }
+
+    fn update_prefab_instances(&mut self, name: &str) {
+        self.add(UpdatePrefabInstancesCommand {
+            name: name.to_string(),
+        });
+    }
+
+    fn spawn_prefab_streamed(&mut self, name: &str, budget: SpawnBudget) -> EntityCommands<'a, '_> {
+        let entity = self.spawn().id();
+        self.add(StartPrefabStreamCommand {
+            entity,
+            name: name.to_string(),
+            budget,
+        });
+        self.entity(entity)
+    }
+
+    fn spawn_from_table(&mut self, table: &str, rng: &mut PrefabRng) -> EntityCommands<'a, '_> {
+        let entity = self.spawn().id();
+        self.add(SpawnFromTableCommand {
+            entity,
+            table: table.to_string(),
+            roll: rng.roll(),
+        });
+        self.entity(entity)
+    }
+
+    fn spawn_prefab_handle(&mut self, handle: Handle<PrefabText>) -> EntityCommands<'a, '_> {
+        let entity = self.spawn().insert(PendingPrefabHandle(handle)).id();
+        self.entity(entity)
+    }
+}
+
+struct DespawnPrefabInstancesCommand {
+    name: String,
+}
+
+impl Command for DespawnPrefabInstancesCommand {
+    fn write(self: Box<Self>, world: &mut World) {
+        despawn_instances(world, &self.name);
+    }
+}
+
+/// Despawn every entity which was spawned from the named prefab, along with its children.
+pub(crate) fn despawn_instances(world: &mut World, name: &str) {
+    let entities: Vec<_> = world
+        .query::<(Entity, &SpawnedFrom)>()
+        .iter(world)
+        .filter(|(_, source)| source.0 == name)
+        .map(|(entity, _)| entity)
+        .collect();
+    if let Some(mut pool) = world.get_resource_mut::<PrefabPool>() {
+        pool.forget(&entities);
+    }
+    despawn_all(world, entities);
+}
+
+struct SpawnPrefabAtCommand {
     entity: Entity,
-    component: Arc<PrefabComponent>,
+    name: String,
+    translation: Vec3,
 }
 
-impl Command for AddComponentCommand {
+impl Command for SpawnPrefabAtCommand {
+    fn write(self: Box<Self>, world: &mut World) {
+        spawn_named(world, self.entity, &self.name, Some(self.translation));
+    }
+}
+
+/// Load a prefab by name and apply it to the entity, then move it to `translation` if given.
+pub(crate) fn spawn_named(
+    world: &mut World,
+    entity: Entity,
+    name: &str,
+    translation: Option<Vec3>,
+) {
+    let anchors = world.resource_scope(|world, mut registry: Mut<PrefabRegistry>| {
+        let prefab = registry.load_or_fallback(name)?;
+        registry.record_spawn(name);
+        tag_spawned(&registry, world, entity, name, &prefab);
+        Some(apply_prefab(&registry, &prefab, world, entity))
+    });
+    if let Some(anchors) = anchors {
+        send_instance_spawned(world, name, PrefabInstanceHandle::new(entity, anchors));
+    }
+
+    if let Some(translation) = translation {
+        move_to(world, entity, translation);
+    }
+}
+
+/// Set the entity's translation, inserting a [Transform] if it doesn't have one.
+pub(crate) fn move_to(world: &mut World, entity: Entity, translation: Vec3) {
+    match world.get_mut::<Transform>(entity) {
+        Some(mut transform) => transform.translation = translation,
+        None => {
+            world
+                .entity_mut(entity)
+                .insert(Transform::from_translation(translation));
+        }
+    }
+}
+
+struct ResetToPrefabCommand {
+    entity: Entity,
+}
+
+impl Command for ResetToPrefabCommand {
     fn write(self: Box<Self>, world: &mut World) {
         let entity = self.entity;
-        let component = self.component;
+        let name = match (
+            world.get::<SpawnedFrom>(entity),
+            world.get::<PooledPrefab>(entity),
+        ) {
+            (Some(SpawnedFrom(name)), _) | (None, Some(PooledPrefab(name))) => name.clone(),
+            (None, None) => {
+                warn!(
+                    "Unable to reset {:?} to its prefab, the entity has no SpawnedFrom component",
+                    entity
+                );
+                return;
+            }
+        };
 
-        let registry = world.get_resource::<PrefabRegistry>().unwrap();
+        world.resource_scope(|world, mut registry: Mut<PrefabRegistry>| {
+            match registry.load(&name) {
+                Ok(prefab) => {
+                    let prefab = prefab.clone();
+                    reset_prefab_components(&registry, &prefab, world, entity);
+                }
+                Err(e) => warn!("Unable to reset {:?} to prefab {}: {}", entity, name, e),
+            }
+        });
+    }
+}
+
+struct InsertCompanionsCommand {
+    entity: Entity,
+}
+
+impl Command for InsertCompanionsCommand {
+    fn write(self: Box<Self>, world: &mut World) {
+        world.resource_scope(|world, registry: Mut<PrefabRegistry>| {
+            registry.insert_companions(world, self.entity);
+        });
+    }
+}
+
+struct TagSpawnedCommand {
+    entity: Entity,
+    source: String,
+}
+
+impl Command for TagSpawnedCommand {
+    fn write(self: Box<Self>, world: &mut World) {
+        world.resource_scope(|world, registry: Mut<PrefabRegistry>| {
+            match registry.get_cached(&self.source) {
+                Some(prefab) => tag_spawned(&registry, world, self.entity, &self.source, prefab),
+                None if registry.tag_spawned() => {
+                    world
+                        .entity_mut(self.entity)
+                        .insert(SpawnedFrom(self.source));
+                }
+                None => {}
+            }
+        });
+    }
+}
 
-        let reg = &registry
-            .get_type_data(component.type_name.as_str())
-            .unwrap()
-            .registration;
-        let type_id = reg.type_id();
+/// Tag an entity with the prefab it was spawned from, if enabled with
+/// [PrefabRegistry::set_tag_spawned].
+pub(crate) fn tag_spawned(
+    registry: &PrefabRegistry,
+    world: &mut World,
+    entity: Entity,
+    name: &str,
+    prefab: &Arc<Prefab>,
+) {
+    if registry.tag_spawned() {
+        world
+            .entity_mut(entity)
+            .insert(SpawnedFrom(name.to_string()))
+            .insert(AppliedPrefab(prefab.clone()));
+    }
+}
 
-        let reflect = match reg.data::<ReflectComponent>() {
-            Some(reflect) => reflect,
-            None => panic!("Error reading reflect data. Does the type {} have the '#[reflect(Component)]' attribute?", reg.short_name()),
-        }.clone();
+pub(crate) struct UpdatePrefabInstancesCommand {
+    pub name: String,
+}
 
-        if world.entity(entity).contains_type_id(type_id) {
-            reflect.apply_component(world, entity, &*component.reflect);
-        } else {
-            reflect.add_component(world, entity, &*component.reflect);
+impl Command for UpdatePrefabInstancesCommand {
+    fn write(self: Box<Self>, world: &mut World) {
+        let instances: Vec<_> = world
+            .query::<(Entity, &SpawnedFrom, &AppliedPrefab)>()
+            .iter(world)
+            .filter(|(_, source, _)| source.0 == self.name)
+            .map(|(entity, _, applied)| (entity, applied.0.clone()))
+            .collect();
+        if instances.is_empty() {
+            return;
         }
+
+        world.resource_scope(|world, registry: Mut<PrefabRegistry>| {
+            let prefab = match registry.get_cached(&self.name) {
+                Some(prefab) => prefab.clone(),
+                None => return,
+            };
+            for (entity, old) in instances {
+                update_prefab_components(&registry, &old, &prefab, world, entity);
+                world
+                    .entity_mut(entity)
+                    .insert(AppliedPrefab(prefab.clone()));
+            }
+        });
+    }
+}
+
+struct AddComponentCommand {
+    entity: Entity,
+    component: Arc<PrefabComponent>,
+    anchors: Arc<Anchors>,
+}
+
+impl Command for AddComponentCommand {
+    fn write(self: Box<Self>, world: &mut World) {
+        world.resource_scope(|world, registry: Mut<PrefabRegistry>| {
+            apply_component(
+                &registry,
+                &self.component,
+                world,
+                self.entity,
+                &self.anchors,
+            );
+        });
     }
 }
 
 pub struct PrefabProcessCommand {
     entity: Entity,
     data: Arc<PrefabCommandData>,
+    anchors: Arc<Anchors>,
 }
 
 impl Command for PrefabProcessCommand {
@@ -83,15 +638,207 @@ impl Command for PrefabProcessCommand {
         let data = self.data;
         let command_name = data.name.as_str();
 
-        let reg = world.get_resource::<PrefabRegistry>().unwrap();
-        let command = reg.get_build_command(command_name).unwrap_or_else(|| {
-            panic!(
-                "Error performing prefab command {}. Was it registered in the PrefabRegistry?",
-                command_name
-            )
+        let command = match world.get_resource::<PrefabRegistry>() {
+            Some(reg) => reg
+                .get_build_command(command_name)
+                .cloned()
+                .ok_or_else(|| PrefabCommandError::UnregisteredCommand(command_name.to_string())),
+            None => Err(PrefabCommandError::missing_resource::<PrefabRegistry>()),
+        };
+        match command {
+            Ok(command) => run_command(None, &*command, &data, world, entity, &self.anchors),
+            Err(e) => command_failed(world, command_name, entity, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::*;
+
+    use crate::PrefabRegistry;
+
+    #[test]
+    fn spawn_prefab_at() {
+        use crate::{test_utils::*, SpawnNamedPrefabCommands};
+        use bevy::ecs::system::CommandQueue;
+
+        let mut world = prefab_world();
+        {
+            let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.register_type::<Transform>();
+            reg.register_type::<Visible>();
+            reg.replace_prefab("moved", "{ Transform { translation: Vec3 { x: 1.0 }, scale: Vec3 { x: 2.0, y: 2.0, z: 2.0 } } }")
+                .unwrap();
+            reg.replace_prefab("unplaced", "{ Visible }").unwrap();
+        }
+
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        let moved = commands.spawn_prefab_at("moved", Vec3::Y).id();
+        let unplaced = commands.spawn_prefab_at("unplaced", Vec3::Z).id();
+        queue.apply(&mut world);
+
+        let transform = world.get::<Transform>(moved).unwrap();
+        assert_eq!(transform.translation, Vec3::Y);
+        assert_eq!(transform.scale, Vec3::splat(2.0));
+        assert_component(&world, unplaced, &Transform::from_translation(Vec3::Z));
+    }
+
+    #[test]
+    fn insert_prefabs() {
+        use crate::{parse::parse_prefab_str, test_utils::*, SpawnPrefabCommands};
+        use bevy::ecs::system::CommandQueue;
+
+        let mut world = prefab_world();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_type::<Stats>();
+            reg.register_type::<Visible>();
         });
+        let reg = world.get_resource::<PrefabRegistry>().unwrap();
+        let input = "{ Stats { health: 10, title: \"base\" }, Visible { is_visible: false } }";
+        let base = parse_prefab_str(input, reg).unwrap();
+        let upgrade = parse_prefab_str("{ Stats { health: 20, speed: 2.0 } }", reg).unwrap();
+
+        let mut queue = CommandQueue::default();
+        let entity = Commands::new(&mut queue, &world)
+            .spawn()
+            .insert_prefabs(&[&base, &upgrade])
+            .id();
+        queue.apply(&mut world);
+
+        let stats = Stats {
+            health: 20,
+            speed: 2.0,
+            title: "base".to_string(),
+        };
+        assert_component(&world, entity, &stats);
+        assert!(!world.get::<Visible>(entity).unwrap().is_visible);
+    }
+
+    #[test]
+    fn spawn_prefab_at_with_nested_load() {
+        use crate::{build_commands::LoadPrefab, test_utils::*, SpawnNamedPrefabCommands};
+        use bevy::ecs::system::CommandQueue;
+
+        let tmp = TempDir::new("nested_load");
+        let dir = tmp.path();
+        std::fs::write(
+            dir.join("inner.prefab"),
+            "{ Visible { is_visible: false } }",
+        )
+        .unwrap();
+
+        let mut world = prefab_world();
+        {
+            let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.set_prefab_dir(&dir);
+            reg.register_type::<Transform>();
+            reg.register_type::<Visible>();
+            reg.register_build_command::<LoadPrefab>();
+            reg.replace_prefab("outer", r#"{ LoadPrefab!(name: "inner.prefab") }"#)
+                .unwrap();
+        }
+
+        let mut queue = CommandQueue::default();
+        let outer = Commands::new(&mut queue, &world)
+            .spawn_prefab_at("outer", Vec3::X)
+            .id();
+        queue.apply(&mut world);
+
+        assert!(!world.get::<Visible>(outer).unwrap().is_visible);
+        assert_component(&world, outer, &Transform::from_translation(Vec3::X));
+    }
+
+    #[test]
+    fn reset_to_prefab() {
+        use crate::{test_utils::*, SpawnPrefabCommands};
+        use bevy::ecs::system::CommandQueue;
+
+        let mut world = prefab_world();
+        let prefab = {
+            let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.register_type::<Transform>();
+            reg.set_tag_spawned(true);
+            reg.replace_prefab("player", "{ Transform { scale: Vec3 { x: 2.0 } } }")
+                .unwrap()
+                .clone()
+        };
+
+        let mut queue = CommandQueue::default();
+        let player = Commands::new(&mut queue, &world)
+            .spawn()
+            .insert_prefab(&prefab)
+            .id();
+        queue.apply(&mut world);
+
+        let mut transform = world.get_mut::<Transform>(player).unwrap();
+        transform.translation = Vec3::ONE;
+        transform.scale.x = 5.0;
+
+        Commands::new(&mut queue, &world)
+            .entity(player)
+            .reset_to_prefab();
+        queue.apply(&mut world);
+
+        let expected = Transform {
+            scale: Vec3::new(2.0, 0.0, 0.0),
+            ..Default::default()
+        };
+        assert_component(&world, player, &expected);
+    }
+
+    #[test]
+    fn fallback_prefab() {
+        use crate::{test_utils::*, SpawnNamedPrefabCommands};
+        use bevy::ecs::system::CommandQueue;
+
+        let mut world = prefab_world();
+        {
+            let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.register_type::<Visible>();
+            reg.replace_prefab("error", "{ Visible }").unwrap();
+            reg.set_fallback_prefab(Some("error"));
+        }
+
+        let mut queue = CommandQueue::default();
+        let entity = Commands::new(&mut queue, &world)
+            .spawn_prefab_at("missing.prefab", Vec3::X)
+            .id();
+        queue.apply(&mut world);
+
+        assert!(world.get::<Visible>(entity).is_some());
+        let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+        let failed = reg.take_failed();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].name, "missing.prefab");
+    }
+
+    #[test]
+    fn despawn_prefab_instances() {
+        use crate::{test_utils::*, SpawnNamedPrefabCommands};
+        use bevy::ecs::system::CommandQueue;
+
+        let mut world = prefab_world();
+        {
+            let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.register_type::<Stats>();
+            reg.set_tag_spawned(true);
+            reg.replace_prefab("grunt", "{ Stats, children [ { Stats } ] }")
+                .unwrap();
+            reg.replace_prefab("tree", "{ Stats }").unwrap();
+        }
+
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        commands.spawn_prefab_at("grunt", Vec3::ZERO);
+        commands.spawn_prefab_at("grunt", Vec3::ZERO);
+        commands.spawn_prefab_at("tree", Vec3::ZERO);
+        queue.apply(&mut world);
+        assert_eq!(world.query::<&Stats>().iter(&world).count(), 5);
 
-        let command = command.clone();
-        command.run(data.properties.as_ref(), world, entity);
+        Commands::new(&mut queue, &world).despawn_prefab_instances("grunt");
+        queue.apply(&mut world);
+        assert_eq!(world.query::<&Stats>().iter(&world).count(), 1);
     }
 }
@@ -1,8 +1,46 @@
 //! Commands used for handling more complex prefab entity initialization, such as bundles, materials, and meshes.
 
-use bevy::{prelude::*, reflect::DynamicStruct};
+use std::marker::PhantomData;
 
-use crate::{dynamic_cast::*, PrefabRegistry};
+#[cfg(feature = "3d")]
+use bevy::render::{
+    pipeline::{PipelineDescriptor, RenderPipeline},
+    shader::ShaderStages,
+};
+#[cfg(any(feature = "2d", feature = "3d"))]
+use bevy::utils::HashMap;
+use bevy::{
+    app::Events,
+    prelude::*,
+    reflect::{
+        DynamicMap, DynamicStruct, GetTypeRegistration, Map, ReflectDeserialize, ReflectMut,
+        ReflectRef, TypeRegistration,
+    },
+};
+#[cfg(feature = "2d")]
+use bevy::{
+    asset::LoadState,
+    render::texture::{AddressMode, FilterMode},
+};
+
+#[cfg(feature = "animation")]
+pub use crate::animation::InsertSpriteAnimation;
+pub use crate::lifetime::DespawnAfter;
+#[cfg(feature = "rapier")]
+pub use crate::physics::{InsertCollider, InsertRigidBody};
+#[cfg(feature = "rhai")]
+pub use crate::script::RunScript;
+#[cfg(feature = "tilemap")]
+pub use crate::tilemap::InsertTilemapLayer;
+use crate::{
+    deferred::{resolve_deferred, resolve_properties, Anchors, PrefabFlags},
+    dynamic_cast::*,
+    prefab::{Prefab, PrefabBuildStep, PrefabCommandData, PrefabComponent},
+    registry::{ReflectType, TypeInfo},
+    writer::{explain_step, explain_value, write_value},
+    PrefabRegistry,
+};
+use thiserror::Error;
 
 /// A build command for handling more complex prefab entity initialization.
 ///
@@ -10,36 +48,215 @@ use crate::{dynamic_cast::*, PrefabRegistry};
 /// reasonably be handled from a text file. This includes things like inserting bundles,
 /// loading handles for meshes and materials, and initializing any other kind of asset or
 /// property that requires external data.
+///
+/// This is the only trait for extending prefabs with new commands. Implement it and
+/// register the command with [PrefabRegistry::register_build_command], or register a
+/// closure with [PrefabRegistry::register_command_fn] for simple commands.
+///
+/// A command that can't be applied, such as from a missing property or resource, returns a
+/// [PrefabCommandError]. The error is logged and sent as a [PrefabCommandFailed] event, and
+/// the rest of the prefab is still applied.
+///
+/// ## Example
+///
+/// ```
+/// use bevy::{prelude::*, reflect::DynamicStruct};
+/// use bevy_lazy_prefabs::{build_commands::*, dynamic_cast::*};
+///
+/// struct Label(String);
+///
+/// // Used as `InsertLabel!(text: "Hello")` in a prefab file.
+/// #[derive(Default)]
+/// struct InsertLabel;
+/// impl BuildPrefabCommand for InsertLabel {
+///     fn run(
+///         &self,
+///         properties: Option<&DynamicStruct>,
+///         world: &mut World,
+///         entity: Entity,
+///     ) -> Result<(), PrefabCommandError> {
+///         let text = required::<String>(properties, "text")?;
+///         world.entity_mut(entity).insert(Label(text.clone()));
+///         Ok(())
+///     }
+///
+///     fn key(&self) -> &str {
+///         "InsertLabel"
+///     }
+/// }
+/// ```
 pub trait BuildPrefabCommand {
     /// Process and modify the prefab entity as needed.
     ///
     /// ### Arguments
     ///
     ///  - `properties` - An optional  [DynamicStruct] containing any properties read
-    /// from the *.prefab* file. [None] if no properties were receieved.
+    ///    from the *.prefab* file. [None] if no properties were receieved.
     ///  - `entity` - The prefab entity, to be modified as needed.
-    ///
-    /// ### Example
-    ///
-    /// ```ignore
-    /// struct Name {
-    ///     value: String,
-    /// }
-    ///
-    /// // Insert a name component whose value is receieved from a *.prefab* file.
-    /// pub fn process_prefab(&self, properties: Option<&DynamicStruct>, world: &mut World, entity: Entity) {
-    ///     if let Some(props) = properties {
-    ///         if let Ok(name) = props.try_get::<String>("name") {
-    ///             world.entity_mut(entity).insert(Name { value: name.clone() });
-    ///         }
-    ///     }
-    /// }
-    /// ```
-    fn run(&self, properties: Option<&DynamicStruct>, world: &mut World, entity: Entity);
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError>;
+
+    /// Run the command while the prefab is applied with the [PrefabRegistry] taken out of the
+    /// world, which is how prefabs are usually spawned. Commands which need to read the registry
+    /// should override this, the default calls [BuildPrefabCommand::run].
+    fn run_with_registry(
+        &self,
+        _registry: &PrefabRegistry,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
+        self.run(properties, world, entity)
+    }
 
     /// The key for this command. This is the name you refer to the command by
     /// from your *.prefab* file.
     fn key(&self) -> &str;
+
+    /// The [BuildPhase] this command runs in. Defaults to [BuildPhase::Default].
+    fn phase(&self) -> BuildPhase {
+        BuildPhase::Default
+    }
+
+    /// The properties which hold the names of other prefabs. Names written in these properties
+    /// are resolved relative to the file they're written in when the prefab is loaded, see
+    /// [PrefabRegistry::set_resolver].
+    ///
+    /// Properties inside groups are separated by dots, ie: `legend.prefab`. Lists are searched
+    /// element by element. Defaults to none.
+    fn prefab_properties(&self) -> &[&'static str] {
+        &[]
+    }
+}
+
+/// Errors returned from a [BuildPrefabCommand].
+#[derive(Error, Debug)]
+pub enum PrefabCommandError {
+    #[error("Missing required property '{0}'.")]
+    MissingProperty(String),
+    #[error("Invalid property '{0}' - {1}.")]
+    InvalidProperty(String, String),
+    #[error("Missing resource {0}.")]
+    MissingResource(String),
+    #[error("The asset {0} isn't loaded.")]
+    MissingAsset(String),
+    #[error("Failed to load prefab '{0}'.")]
+    PrefabLoadFailed(String),
+    #[error("The build command {0} isn't registered in the PrefabRegistry.")]
+    UnregisteredCommand(String),
+    #[error("{0}")]
+    Other(String),
+}
+
+impl PrefabCommandError {
+    /// A [PrefabCommandError::MissingResource] error for the resource type `T`.
+    pub fn missing_resource<T>() -> Self {
+        PrefabCommandError::MissingResource(std::any::type_name::<T>().to_string())
+    }
+}
+
+/// Get a property which the command can't run without.
+///
+/// Returns [PrefabCommandError::MissingProperty] if the property isn't set, or
+/// [PrefabCommandError::InvalidProperty] if it has the wrong type.
+pub fn required<'a, T: Reflect + GetTypeRegistration>(
+    properties: Option<&'a DynamicStruct>,
+    name: &str,
+) -> Result<&'a T, PrefabCommandError> {
+    let props = properties.ok_or_else(|| PrefabCommandError::MissingProperty(name.to_string()))?;
+    if props.field(name).is_none() {
+        return Err(PrefabCommandError::MissingProperty(name.to_string()));
+    }
+    props
+        .try_get::<T>(name)
+        .map_err(|e| PrefabCommandError::InvalidProperty(name.to_string(), e.to_string()))
+}
+
+/// Event sent when a build command fails while a prefab is being applied.
+#[derive(Debug, Clone)]
+pub struct PrefabCommandFailed {
+    /// The key of the command that failed.
+    pub command: String,
+    /// The entity the command was run on.
+    pub entity: Entity,
+    /// A description of the error.
+    pub error: String,
+}
+
+/// Determines when a prefab build step runs relative to other steps.
+///
+/// Build steps are applied one phase at a time. Inside each phase steps are applied
+/// in the order they are written in the *.prefab* file. Components and child entities
+/// are always applied in the [BuildPhase::Default] phase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BuildPhase {
+    /// For commands that insert bundles. These run first so components and other commands
+    /// can override the bundle's values.
+    Bundle,
+    /// Components, child entities and most commands.
+    Default,
+    /// For commands that modify existing components, such as [SetColorMaterial].
+    Post,
+}
+
+/// A [BuildPrefabCommand] which runs a closure, see [PrefabRegistry::register_command_fn].
+pub(crate) struct FnCommand<F> {
+    pub key: String,
+    pub func: F,
+}
+
+impl<F> BuildPrefabCommand for FnCommand<F>
+where
+    F: Fn(Option<&DynamicStruct>, &mut World, Entity),
+{
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
+        (self.func)(properties, world, entity);
+        Ok(())
+    }
+
+    fn key(&self) -> &str {
+        &self.key
+    }
+}
+
+/// Inserts a default `B` bundle, see [PrefabRegistry::register_bundle].
+pub(crate) struct InsertBundle<B> {
+    pub key: String,
+    pub bundle: PhantomData<fn() -> B>,
+}
+
+impl<B: Bundle + Default> BuildPrefabCommand for InsertBundle<B> {
+    fn run(
+        &self,
+        _properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
+        world.entity_mut(entity).insert_bundle(B::default());
+        Ok(())
+    }
+
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn phase(&self) -> BuildPhase {
+        BuildPhase::Bundle
+    }
+}
+
+/// The key of the command registered for a bundle written as `bundle Name { .. }`.
+pub(crate) fn bundle_command_key(name: &str) -> String {
+    format!("bundle {}", name)
 }
 
 /// Sets [ColorMaterial] values on the entity.
@@ -48,34 +265,64 @@ pub trait BuildPrefabCommand {
 ///
 /// - `color` - The color for the material.
 /// - `texture_path` - The path to the texture for the material.
+/// - `filter` - The texture's sampler filter, `"nearest"` or `"linear"`.
+/// - `address_mode` - The texture's sampler address mode, `"clamp"`, `"repeat"` or `"mirror"`.
+///
+/// If the entity's material hasn't been added to `Assets<ColorMaterial>` yet, such as while
+/// it's still loading, the values are applied once it's available.
+#[cfg(feature = "2d")]
 #[derive(Default)]
 pub struct SetColorMaterial;
+#[cfg(feature = "2d")]
 impl BuildPrefabCommand for SetColorMaterial {
-    fn run(&self, properties: Option<&DynamicStruct>, world: &mut World, entity: Entity) {
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
         let (color, path) = get_material_props(properties);
+        let sampler = get_sampler_props(properties);
 
-        if let Some(existing_mat) = world.get_mut::<Handle<ColorMaterial>>(entity) {
-            let existing_mat = existing_mat.clone_weak();
-            world.resource_scope(|world, mut materials: Mut<Assets<ColorMaterial>>| {
-                let mat = materials.get_mut(existing_mat).unwrap();
-
-                if let Some(col) = color {
-                    mat.color = *col;
-                }
-                if let Some(path) = path {
-                    let server = world.get_resource::<AssetServer>().unwrap();
-                    let tex: Handle<Texture> = server.load(path.as_str());
-                    mat.texture = Some(tex);
+        let existing_mat = match world.get::<Handle<ColorMaterial>>(entity) {
+            Some(handle) => handle.clone_weak(),
+            None => return Ok(()),
+        };
+        let tex: Option<Handle<Texture>> = match path {
+            Some(path) => Some(load_asset(world, path)?),
+            None => None,
+        };
+        let pending = PendingColorMaterial {
+            color: color.copied(),
+            texture: tex,
+            sampler,
+        };
+        let mut materials = world
+            .get_resource_mut::<Assets<ColorMaterial>>()
+            .ok_or_else(PrefabCommandError::missing_resource::<Assets<ColorMaterial>>)?;
+        match materials.get_mut(existing_mat) {
+            Some(mat) => {
+                if let Some((tex, sampler)) = pending.apply(mat) {
+                    queue_texture_sampler(world, tex, sampler);
                 }
-            });
+            }
+            None => {
+                world.entity_mut(entity).insert(pending);
+            }
         }
+        Ok(())
     }
 
     fn key(&self) -> &str {
         "SetColorMaterial"
     }
+
+    fn phase(&self) -> BuildPhase {
+        BuildPhase::Post
+    }
 }
 
+#[cfg(feature = "2d")]
 fn get_material_props(properties: Option<&DynamicStruct>) -> (Option<&Color>, Option<&String>) {
     if let Some(properties) = properties {
         let color = properties.try_get::<Color>("color").ok();
@@ -86,106 +333,886 @@ fn get_material_props(properties: Option<&DynamicStruct>) -> (Option<&Color>, Op
     (None, None)
 }
 
+#[cfg(feature = "2d")]
 fn get_color_material(
     world: &mut World,
     material_props: (Option<&Color>, Option<&String>),
-) -> Option<Handle<ColorMaterial>> {
+    sampler: Option<TextureSampler>,
+) -> Result<Option<Handle<ColorMaterial>>, PrefabCommandError> {
     let (col, path) = material_props;
 
     let tex: Option<Handle<Texture>> = match path {
-        Some(path) => {
-            let server = world.get_resource::<AssetServer>().unwrap();
-            Some(server.load(path.as_str()))
-        }
+        Some(path) => Some(load_asset(world, path)?),
         None => None,
     };
 
+    if let (Some(sampler), Some(tex)) = (sampler, &tex) {
+        queue_texture_sampler(world, tex.clone(), sampler);
+    }
+
     if col.is_none() && tex.is_none() {
-        return None;
+        return Ok(None);
     }
 
-    let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+    let mut materials = world
+        .get_resource_mut::<Assets<ColorMaterial>>()
+        .ok_or_else(PrefabCommandError::missing_resource::<Assets<ColorMaterial>>)?;
     let mat = ColorMaterial {
         texture: tex,
         color: col.cloned().unwrap_or_default(),
     };
-    Some(materials.add(mat))
+    Ok(Some(materials.add(mat)))
+}
+
+/// Start loading an asset with the [AssetServer].
+#[cfg(any(feature = "2d", feature = "ui"))]
+fn load_asset<T: bevy::asset::Asset>(
+    world: &World,
+    path: &str,
+) -> Result<Handle<T>, PrefabCommandError> {
+    let server = world
+        .get_resource::<AssetServer>()
+        .ok_or_else(PrefabCommandError::missing_resource::<AssetServer>)?;
+    Ok(server.load(path))
+}
+
+/// [SetColorMaterial] values waiting for the entity's material to be added to
+/// `Assets<ColorMaterial>`.
+#[cfg(feature = "2d")]
+pub(crate) struct PendingColorMaterial {
+    color: Option<Color>,
+    texture: Option<Handle<Texture>>,
+    sampler: Option<TextureSampler>,
+}
+
+#[cfg(feature = "2d")]
+impl PendingColorMaterial {
+    /// Set the values on the material, returning the texture if its sampler still needs
+    /// to be configured.
+    fn apply(&self, mat: &mut ColorMaterial) -> Option<(Handle<Texture>, TextureSampler)> {
+        if let Some(col) = self.color {
+            mat.color = col;
+        }
+        if self.texture.is_some() {
+            mat.texture = self.texture.clone();
+        }
+        Some((mat.texture.clone()?, self.sampler?))
+    }
+}
+
+/// Applies [SetColorMaterial] values to materials which weren't loaded when the prefab was
+/// spawned. Waiting stops if the material fails to load.
+#[cfg(feature = "2d")]
+pub(crate) fn apply_pending_color_materials(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut samplers: ResMut<PendingTextureSamplers>,
+    server: Res<AssetServer>,
+    query: Query<(Entity, &Handle<ColorMaterial>, &PendingColorMaterial)>,
+) {
+    for (entity, handle, pending) in query.iter() {
+        match materials.get_mut(handle) {
+            Some(mat) => {
+                if let Some(texture_sampler) = pending.apply(mat) {
+                    samplers.0.push(texture_sampler);
+                }
+            }
+            None if server.get_load_state(handle) == LoadState::Failed => {
+                warn!("Material for SetColorMaterial failed to load");
+            }
+            None => continue,
+        }
+        commands.entity(entity).remove::<PendingColorMaterial>();
+    }
+}
+
+/// Sampler settings read from the `filter` and `address_mode` command properties.
+#[cfg(feature = "2d")]
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct TextureSampler {
+    filter: Option<FilterMode>,
+    address_mode: Option<AddressMode>,
+}
+
+#[cfg(feature = "2d")]
+fn get_sampler_props(properties: Option<&DynamicStruct>) -> Option<TextureSampler> {
+    let properties = properties?;
+    let filter = match properties.try_get::<String>("filter").map(String::as_str) {
+        Ok("nearest") => Some(FilterMode::Nearest),
+        Ok("linear") => Some(FilterMode::Linear),
+        Ok(other) => {
+            warn!("Unknown texture filter '{}'", other);
+            None
+        }
+        Err(_) => None,
+    };
+    let address_mode = match properties
+        .try_get::<String>("address_mode")
+        .map(String::as_str)
+    {
+        Ok("clamp") => Some(AddressMode::ClampToEdge),
+        Ok("repeat") => Some(AddressMode::Repeat),
+        Ok("mirror") => Some(AddressMode::MirrorRepeat),
+        Ok(other) => {
+            warn!("Unknown texture address mode '{}'", other);
+            None
+        }
+        Err(_) => None,
+    };
+
+    if filter.is_none() && address_mode.is_none() {
+        return None;
+    }
+    Some(TextureSampler {
+        filter,
+        address_mode,
+    })
+}
+
+/// Textures waiting to finish loading before their sampler can be configured.
+#[cfg(feature = "2d")]
+#[derive(Default)]
+pub(crate) struct PendingTextureSamplers(Vec<(Handle<Texture>, TextureSampler)>);
+
+#[cfg(feature = "2d")]
+fn queue_texture_sampler(world: &mut World, texture: Handle<Texture>, sampler: TextureSampler) {
+    world
+        .get_resource_or_insert_with(PendingTextureSamplers::default)
+        .0
+        .push((texture, sampler));
+}
+
+/// Applies queued sampler settings to textures once they've been loaded.
+#[cfg(feature = "2d")]
+pub(crate) fn apply_texture_samplers(
+    mut pending: ResMut<PendingTextureSamplers>,
+    mut textures: ResMut<Assets<Texture>>,
+    server: Res<AssetServer>,
+) {
+    if pending.0.is_empty() {
+        return;
+    }
+    pending.0.retain(|(handle, sampler)| {
+        let texture = match textures.get_mut(handle) {
+            Some(texture) => texture,
+            None => return server.get_load_state(handle) != LoadState::Failed,
+        };
+        if let Some(filter) = sampler.filter {
+            texture.sampler.mag_filter = filter;
+            texture.sampler.min_filter = filter;
+            texture.sampler.mipmap_filter = filter;
+        }
+        if let Some(mode) = sampler.address_mode {
+            texture.sampler.address_mode_u = mode;
+            texture.sampler.address_mode_v = mode;
+            texture.sampler.address_mode_w = mode;
+        }
+        false
+    });
 }
 
 /// Loads a prefab and performs it's build steps on the entity.
 ///
 /// ### Required Property:
 ///
-/// - `name` - The name of the prefab, including the extension.
+/// - `name` - The name of the prefab, including the extension. Relative to the prefab file the
+///   command is written in, see [PrefabRegistry::set_resolver].
 #[derive(Default)]
 pub struct LoadPrefab;
 impl BuildPrefabCommand for LoadPrefab {
-    fn run(&self, properties: Option<&DynamicStruct>, world: &mut World, entity: Entity) {
-        if let Some(props) = properties {
-            if let Ok(name) = props.try_get::<String>("name") {
-                world.resource_scope(|world, mut reg: Mut<PrefabRegistry>| {
-
-                    let prefab = reg.load(name.as_str()).unwrap().clone();
-
-                    for step in prefab.steps.iter() {
-                        match step {
-                            crate::prefab::PrefabBuildStep::AddComponent(comp) => {
-                                let reg = &reg
-                                    .get_type_data(comp.type_name.as_str())
-                                    .unwrap()
-                                    .registration;
-                                let type_id = reg.type_id();
-                                let reflect = match reg.data::<ReflectComponent>() {
-                                    Some(reflect) => reflect,
-                                    None => panic!("Error reading reflect data. 
-                                        Does the type {} have the '#[reflect(Component)]' attribute?", reg.short_name()),
-                                }.clone();
-                                if world.entity(entity).contains_type_id(type_id) {
-                                    reflect.apply_component(world, entity, &*comp.reflect);
-                                } else {
-                                    reflect.add_component(world, entity, &*comp.reflect);
-                                }
-                            },
-                            crate::prefab::PrefabBuildStep::RunCommand(data) => {
-                                let cmd = reg.get_build_command(data.name.as_str()).unwrap();
-
-                                cmd.run(data.properties.as_ref(), world, entity);
-                            },
-                        }
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
+        if !world.contains_resource::<PrefabRegistry>() {
+            return Err(PrefabCommandError::missing_resource::<PrefabRegistry>());
+        }
+        world.resource_scope(|world, registry: Mut<PrefabRegistry>| {
+            self.run_with_registry(&registry, properties, world, entity)
+        })
+    }
+
+    fn run_with_registry(
+        &self,
+        registry: &PrefabRegistry,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
+        let name = required::<String>(properties, "name")?;
+        let prefab = registry
+            .load_shared_or_fallback(name.as_str())
+            .ok_or_else(|| PrefabCommandError::PrefabLoadFailed(name.clone()))?;
+        apply_prefab(registry, &prefab, world, entity);
+        Ok(())
+    }
+
+    fn key(&self) -> &str {
+        "LoadPrefab"
+    }
+
+    fn prefab_properties(&self) -> &[&'static str] {
+        &["name"]
+    }
+}
+
+/// Inserts a [Timer] component.
+///
+/// ### Properties:
+///
+/// - `seconds` - The duration of the timer, in seconds or as a duration value, ie: `2.5s`
+///   or `300ms`.
+/// - `repeating` - Optional, defaults to `false`.
+///
+/// ### Example
+///
+/// ```ignore
+/// Spawner {
+///     InsertTimer!(seconds: 500ms, repeating: true),
+/// }
+/// ```
+#[derive(Default)]
+pub struct InsertTimer;
+impl BuildPrefabCommand for InsertTimer {
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
+        let props = properties
+            .filter(|props| props.field("seconds").is_some())
+            .ok_or_else(|| PrefabCommandError::MissingProperty("seconds".to_string()))?;
+        let duration = props.try_get_duration("seconds").map_err(|e| {
+            PrefabCommandError::InvalidProperty("seconds".to_string(), e.to_string())
+        })?;
+        let repeating = props.get_or("repeating", false);
+        world
+            .entity_mut(entity)
+            .insert(Timer::new(duration, repeating));
+        Ok(())
+    }
+
+    fn key(&self) -> &str {
+        "InsertTimer"
+    }
+}
+
+/// Spawns a grid of prefabs as children of the entity, from rows of characters and a legend
+/// mapping each character to a prefab.
+///
+/// Tiles are laid out from the top left, with each row below the last. Characters missing
+/// from the legend are left empty.
+///
+/// ### Properties:
+///
+/// - `map` - A list of strings, one per row.
+/// - `legend` - A list of `{ char: '#', prefab: "wall.prefab" }` entries.
+/// - `tile_size` - Optional `Vec2` distance between tiles. Defaults to 1.
+///
+/// ### Example
+///
+/// ```ignore
+/// Room {
+///     Transform,
+///     SpawnTileMap!(
+///         tile_size: Vec2 { x: 16.0, y: 16.0 },
+///         map: [
+///             "#####",
+///             "#..@#",
+///             "#####",
+///         ],
+///         legend: [
+///             { char: '#', prefab: "wall.prefab" },
+///             { char: '.', prefab: "floor.prefab" },
+///             { char: '@', prefab: "player.prefab" },
+///         ],
+///     ),
+/// }
+/// ```
+#[derive(Default)]
+pub struct SpawnTileMap;
+impl BuildPrefabCommand for SpawnTileMap {
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
+        if !world.contains_resource::<PrefabRegistry>() {
+            return Err(PrefabCommandError::missing_resource::<PrefabRegistry>());
+        }
+        world.resource_scope(|world, registry: Mut<PrefabRegistry>| {
+            self.run_with_registry(&registry, properties, world, entity)
+        })
+    }
+
+    fn run_with_registry(
+        &self,
+        registry: &PrefabRegistry,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
+        let props =
+            properties.ok_or_else(|| PrefabCommandError::MissingProperty("map".to_string()))?;
+        let tile_size = props.get_or("tile_size", Vec2::ONE);
+        let rows = get_list::<String>(props, "map");
+        let legend: Vec<(u8, String)> = match props.field("legend").map(|f| f.reflect_ref()) {
+            Some(ReflectRef::List(list)) => list
+                .iter()
+                .filter_map(|entry| entry.downcast_ref::<DynamicStruct>())
+                .filter_map(|entry| {
+                    let ch = entry.try_get::<u8>("char").ok()?;
+                    let prefab = entry.try_get::<String>("prefab").ok()?;
+                    Some((*ch, prefab.clone()))
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        for (y, row) in rows.iter().enumerate() {
+            for (x, ch) in row.bytes().enumerate() {
+                let name = match legend.iter().find(|(c, _)| *c == ch) {
+                    Some((_, name)) => name,
+                    None => continue,
+                };
+                let prefab = match registry.load_shared_or_fallback(name) {
+                    Some(prefab) => prefab,
+                    None => continue,
+                };
+                let offset = Vec3::new(x as f32 * tile_size.x, -(y as f32) * tile_size.y, 0.0);
+                let tile = world.spawn().id();
+                apply_prefab(registry, &prefab, world, tile);
+                let mut tile_mut = world.entity_mut(tile);
+                match tile_mut.get_mut::<Transform>() {
+                    Some(mut transform) => transform.translation += offset,
+                    None => {
+                        tile_mut.insert_bundle((
+                            Transform::from_translation(offset),
+                            GlobalTransform::default(),
+                        ));
                     }
-                });
+                }
+                world.entity_mut(entity).push_children(&[tile]);
             }
         }
+        Ok(())
     }
 
     fn key(&self) -> &str {
-        "LoadPrefab"
+        "SpawnTileMap"
+    }
+
+    fn prefab_properties(&self) -> &[&'static str] {
+        &["legend.prefab"]
+    }
+}
+
+/// Collect the values of a list property which have the type `T`.
+fn get_list<T: Reflect + Clone>(props: &DynamicStruct, field: &str) -> Vec<T> {
+    match props.field(field).map(|f| f.reflect_ref()) {
+        Some(ReflectRef::List(list)) => list
+            .iter()
+            .filter_map(|v| v.downcast_ref::<T>())
+            .cloned()
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Apply the prefab to the entity, returning the entities of any anchored prefabs in its
+/// hierarchy.
+pub(crate) fn apply_prefab(
+    reg: &PrefabRegistry,
+    prefab: &Prefab,
+    world: &mut World,
+    entity: Entity,
+) -> Anchors {
+    // Child entities are spawned up front so every entity in the hierarchy can be
+    // referred to by its anchor.
+    let mut children = Vec::new();
+    let mut anchors = Anchors::default();
+    spawn_children(prefab, entity, &mut children, &mut anchors, &mut || {
+        world.spawn().id()
+    });
+    apply_steps(
+        reg,
+        prefab,
+        world,
+        entity,
+        &anchors,
+        &mut children.into_iter(),
+    );
+    anchors
+}
+
+/// Create an entity for every child in the prefab hierarchy, in the order they're
+/// applied, and record the entities of any anchored prefabs.
+pub(crate) fn spawn_children(
+    prefab: &Prefab,
+    entity: Entity,
+    children: &mut Vec<Entity>,
+    anchors: &mut Anchors,
+    spawn: &mut impl FnMut() -> Entity,
+) {
+    if let Some(anchor) = &prefab.anchor {
+        anchors.insert(anchor.clone(), entity);
+    }
+    for step in prefab.steps.iter() {
+        if let PrefabBuildStep::AddChild(child) = step {
+            let child_entity = spawn();
+            children.push(child_entity);
+            spawn_children(child, child_entity, children, anchors, spawn);
+        }
+    }
+}
+
+fn apply_steps(
+    reg: &PrefabRegistry,
+    prefab: &Prefab,
+    world: &mut World,
+    entity: Entity,
+    anchors: &Anchors,
+    children: &mut impl Iterator<Item = Entity>,
+) {
+    for step in prefab.steps.iter() {
+        if reg.explain_spawns() {
+            info!("{:?}: {}", entity, explain_step(reg, step));
+        }
+        match step {
+            PrefabBuildStep::AddComponent(comp) => {
+                apply_component(reg, comp, world, entity, anchors);
+            }
+            PrefabBuildStep::RunCommand(data) => {
+                if reg.explain_spawns() {
+                    explain_skipped_command(data, world, entity);
+                }
+                match reg.get_build_command(data.name.as_str()) {
+                    Some(cmd) => run_command(Some(reg), &**cmd, data, world, entity, anchors),
+                    None => command_failed(
+                        world,
+                        &data.name,
+                        entity,
+                        PrefabCommandError::UnregisteredCommand(data.name.clone()),
+                    ),
+                }
+            }
+            PrefabBuildStep::AddChild(child) => {
+                let child_entity = children.next().unwrap();
+                apply_steps(reg, child, world, child_entity, anchors, children);
+                world.entity_mut(entity).push_children(&[child_entity]);
+            }
+        }
+    }
+    let companions = reg.insert_companions(world, entity);
+    if reg.explain_spawns() && !companions.is_empty() {
+        info!(
+            "{:?}: inserted companions {}",
+            entity,
+            companions.join(", ")
+        );
+    }
+}
+
+/// Log a command which won't run because its flag isn't set, for
+/// [PrefabRegistry::set_explain_spawns].
+pub(crate) fn explain_skipped_command(data: &PrefabCommandData, world: &World, entity: Entity) {
+    if let Some(flag) = data.flag.as_deref() {
+        if !PrefabFlags::is_set_in(world, flag) {
+            info!(
+                "{:?}: skipped {}, flag \"{}\" isn't set",
+                entity, data.name, flag
+            );
+        }
+    }
+}
+
+/// Run a build command, evaluating any deferred values in its properties first. `reg` is the
+/// registry if it's been taken out of the world to apply the prefab.
+pub(crate) fn run_command(
+    reg: Option<&PrefabRegistry>,
+    cmd: &dyn BuildPrefabCommand,
+    data: &PrefabCommandData,
+    world: &mut World,
+    entity: Entity,
+    anchors: &Anchors,
+) {
+    if let Some(flag) = &data.flag {
+        if !PrefabFlags::is_set_in(world, flag) {
+            return;
+        }
+    }
+    let resolved = match (&data.properties, data.deferred) {
+        (Some(props), true) => Some(resolve_properties(props, world, anchors)),
+        _ => None,
+    };
+    let props = resolved.as_ref().or(data.properties.as_ref());
+    let result = match reg {
+        Some(reg) => cmd.run_with_registry(reg, props, world, entity),
+        None => cmd.run(props, world, entity),
+    };
+    if let Err(e) = result {
+        command_failed(world, cmd.key(), entity, e);
+    }
+}
+
+/// Log a build command error and send a [PrefabCommandFailed] event for it.
+pub(crate) fn command_failed(
+    world: &mut World,
+    command: &str,
+    entity: Entity,
+    e: PrefabCommandError,
+) {
+    error!("Error running build command {}: {}", command, e);
+    if let Some(mut events) = world.get_resource_mut::<Events<PrefabCommandFailed>>() {
+        events.send(PrefabCommandFailed {
+            command: command.to_string(),
+            entity,
+            error: e.to_string(),
+        });
+    }
+}
+
+/// Reset the components declared in the prefab to their prefab values.
+///
+/// Build commands and child entities are left untouched.
+pub(crate) fn reset_prefab_components(
+    reg: &PrefabRegistry,
+    prefab: &Prefab,
+    world: &mut World,
+    entity: Entity,
+) {
+    for step in prefab.steps.iter() {
+        if let PrefabBuildStep::AddComponent(comp) = step {
+            reset_component(reg, comp, world, entity);
+        }
+    }
+}
+
+/// Update an entity which had `old` applied to match `new`. Components which are no longer
+/// declared in the prefab are removed. Only fields whose value in `new` differs from `old`
+/// are applied, so fields changed at runtime keep their values unless they were also changed
+/// in the prefab. Build commands and children are not run again.
+pub(crate) fn update_prefab_components(
+    reg: &PrefabRegistry,
+    old: &Prefab,
+    new: &Prefab,
+    world: &mut World,
+    entity: Entity,
+) {
+    for comp in old.components() {
+        if new.components().any(|c| c.type_name == comp.type_name) {
+            continue;
+        }
+        match reg.get_type_data(&comp.type_name).and_then(|info| info.remove) {
+            Some(remove) => remove(world, entity),
+            None => warn!(
+                "Unable to remove {} from {:?}. Components must be registered with PrefabRegistry::register_component to be removed when their prefab changes",
+                comp.type_name, entity
+            ),
+        }
+    }
+    let anchors = Anchors::default();
+    for comp in new.components() {
+        let previous = old.components().find(|c| c.type_name == comp.type_name);
+        let changed = match previous {
+            Some(previous) => match changed_fields(&*previous.reflect, &*comp.reflect) {
+                Some(changed) => changed,
+                None => continue,
+            },
+            None => comp.reflect.clone_value(),
+        };
+        let changed = PrefabComponent {
+            type_name: comp.type_name.clone(),
+            reflect: changed,
+            deferred: comp.deferred,
+        };
+        apply_component(reg, &changed, world, entity, &anchors);
+    }
+}
+
+/// The parts of `new` which differ from `old`, or [None] if they're equal. Struct fields are
+/// compared individually.
+fn changed_fields(old: &dyn Reflect, new: &dyn Reflect) -> Option<Box<dyn Reflect>> {
+    match (old.reflect_ref(), new.reflect_ref()) {
+        (ReflectRef::Struct(old), ReflectRef::Struct(new)) => {
+            let mut changed = DynamicStruct::default();
+            changed.set_name(new.type_name().to_string());
+            for (i, value) in new.iter_fields().enumerate() {
+                let name = new.name_at(i).unwrap();
+                let value = match old.field(name) {
+                    Some(previous) => changed_fields(previous, value),
+                    None => Some(value.clone_value()),
+                };
+                if let Some(value) = value {
+                    changed.insert_boxed(name, value);
+                }
+            }
+            match changed.field_len() {
+                0 => None,
+                _ => Some(Box::new(changed)),
+            }
+        }
+        _ if new.reflect_partial_eq(old) == Some(true) => None,
+        _ => Some(new.clone_value()),
+    }
+}
+
+/// Add a prefab component to the entity, or overwrite its values if the entity
+/// already has the component.
+pub(crate) fn apply_component(
+    reg: &PrefabRegistry,
+    comp: &PrefabComponent,
+    world: &mut World,
+    entity: Entity,
+    anchors: &Anchors,
+) {
+    let info = reg.get_type_data(&comp.type_name).unwrap();
+    if comp.deferred {
+        match resolve_deferred(&*comp.reflect, world, anchors) {
+            Some(value) => {
+                if reg.explain_spawns() {
+                    info!("{:?}: resolved to {}", entity, explain_value(&*value));
+                }
+                apply_reflect(info, &*value, world, entity);
+            }
+            None if reg.explain_spawns() => {
+                info!(
+                    "{:?}: skipped {}, its deferred values couldn't be evaluated",
+                    entity, comp.type_name
+                );
+            }
+            None => {}
+        }
+    } else {
+        apply_reflect(info, &*comp.reflect, world, entity);
+    }
+}
+
+/// Like [apply_component], but any fields not set by the prefab are reset to their
+/// default values rather than left unchanged. Entity references are reset to default.
+fn reset_component(
+    reg: &PrefabRegistry,
+    comp: &PrefabComponent,
+    world: &mut World,
+    entity: Entity,
+) {
+    let info = reg.get_type_data(&comp.type_name).unwrap();
+    let mut value = info.construct(world);
+    if comp.deferred {
+        if let Some(resolved) = resolve_deferred(&*comp.reflect, world, &Anchors::default()) {
+            apply_value(&info.registration, &mut *value, &*resolved);
+        }
+    } else {
+        apply_value(&info.registration, &mut *value, &*comp.reflect);
+    }
+    apply_reflect(info, &*value, world, entity);
+}
+
+fn apply_reflect(info: &TypeInfo, value: &dyn Reflect, world: &mut World, entity: Entity) {
+    if let Some(apply) = &info.apply {
+        apply(world, entity, value);
+        return;
+    }
+    let registration = &info.registration;
+    let reflect = match registration.data::<ReflectComponent>() {
+        Some(reflect) => reflect,
+        None => panic!(
+            "Error reading reflect data. 
+            Does the type {} have the '#[reflect(Component)]' attribute?",
+            registration.short_name()
+        ),
+    }
+    .clone();
+    if !world
+        .entity(entity)
+        .contains_type_id(registration.type_id())
+    {
+        reflect.add_component(world, entity, value);
+        if info.reflect_type != ReflectType::Map {
+            return;
+        }
+    }
+    if let Some(mut component) = reflect.reflect_component_mut(world, entity) {
+        apply_value(registration, &mut *component, value);
     }
 }
 
+/// Apply a prefab's value to a component. Maps are replaced with the component's entries
+/// merged with the value's, since applying a map only changes keys it already has.
+pub(crate) fn apply_value(
+    registration: &TypeRegistration,
+    target: &mut dyn Reflect,
+    value: &dyn Reflect,
+) {
+    let (existing, new) = match (target.reflect_mut(), value.reflect_ref()) {
+        (ReflectMut::Map(existing), ReflectRef::Map(new)) => (existing, new),
+        _ => {
+            target.apply(value);
+            return;
+        }
+    };
+    let mut merged = DynamicMap::default();
+    for (key, value) in existing.iter().chain(new.iter()) {
+        merged.insert_boxed(key.clone_value(), value.clone_value());
+    }
+    let map = deserialize_map(registration, &merged);
+    if !map.map_or(false, |map| existing.set(map).is_ok()) {
+        warn!(
+            "Unable to apply {}, its keys and values must be strings, numbers or booleans.",
+            registration.short_name()
+        );
+    }
+}
+
+/// Convert a map to the concrete map type of `registration`, by writing it as RON and
+/// reading it back with the type's [ReflectDeserialize] data.
+fn deserialize_map(registration: &TypeRegistration, map: &dyn Map) -> Option<Box<dyn Reflect>> {
+    let entries: Option<Vec<_>> = map
+        .iter()
+        .map(|(key, value)| Some(format!("{}: {}", write_value(key)?, write_value(value)?)))
+        .collect();
+    let text = format!("{{{}}}", entries?.join(", "));
+    let mut deserializer = ron::de::Deserializer::from_str(&text).ok()?;
+    registration
+        .data::<ReflectDeserialize>()?
+        .deserialize(&mut deserializer)
+        .ok()
+}
+
 /// Inserts a [SpriteBundle].
 ///
 /// ### Optional Properties:
 ///
 /// - `color` - The color for the material.
 /// - `texture_path` - The path to the texture for the material.
+/// - `filter` - The texture's sampler filter, `"nearest"` or `"linear"`.
+/// - `address_mode` - The texture's sampler address mode, `"clamp"`, `"repeat"` or `"mirror"`.
+#[cfg(feature = "2d")]
 #[derive(Default)]
 pub struct InsertSpriteBundle;
+#[cfg(feature = "2d")]
 impl BuildPrefabCommand for InsertSpriteBundle {
-    fn run(&self, properties: Option<&DynamicStruct>, world: &mut World, entity: Entity) {
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
         let (color, path) = get_material_props(properties);
-        let mat = get_color_material(world, (color, path));
+        let sampler = get_sampler_props(properties);
+        let mat = get_color_material(world, (color, path), sampler)?;
 
         let mut entity = world.entity_mut(entity);
         entity.insert_bundle(SpriteBundle {
             material: mat.unwrap_or_default(),
             ..Default::default()
         });
+        Ok(())
     }
 
     fn key(&self) -> &str {
         "InsertSpriteBundle"
     }
+
+    fn phase(&self) -> BuildPhase {
+        BuildPhase::Bundle
+    }
+}
+
+/// Inserts a `Handle<TextureAtlas>` for an atlas built from a grid of equally sized tiles.
+///
+/// Atlases with the same properties are only created once and shared by every entity using them.
+///
+/// ### Properties:
+///
+/// - `texture_path` - The path to the atlas texture.
+/// - `tile_size` - The size of each tile, as a `Vec2`.
+/// - `columns` - Optional number of columns in the grid. Defaults to 1.
+/// - `rows` - Optional number of rows in the grid. Defaults to 1.
+/// - `padding` - Optional `Vec2` space between tiles. Defaults to 0.
+///
+/// ### Example
+///
+/// ```ignore
+/// {
+///     InsertTextureAtlas!(
+///         texture_path: "tiles.png",
+///         tile_size: Vec2 { x: 16.0, y: 16.0 },
+///         columns: 8,
+///         rows: 4,
+///     ),
+/// }
+/// ```
+#[cfg(feature = "2d")]
+#[derive(Default)]
+pub struct InsertTextureAtlas;
+#[cfg(feature = "2d")]
+impl BuildPrefabCommand for InsertTextureAtlas {
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
+        let atlas = get_texture_atlas(world, properties)?;
+        world.entity_mut(entity).insert(atlas);
+        Ok(())
+    }
+
+    fn key(&self) -> &str {
+        "InsertTextureAtlas"
+    }
+}
+
+/// The texture path, tile size, columns, rows and padding of a grid atlas.
+#[cfg(feature = "2d")]
+type AtlasKey = (String, [u32; 2], usize, usize, [u32; 2]);
+
+/// Atlases created by [InsertTextureAtlas], by [AtlasKey].
+#[cfg(feature = "2d")]
+#[derive(Default)]
+struct TextureAtlases(HashMap<AtlasKey, Handle<TextureAtlas>>);
+
+/// Get the grid [TextureAtlas] described by the properties, creating it if it hasn't been used yet.
+#[cfg(feature = "2d")]
+pub(crate) fn get_texture_atlas(
+    world: &mut World,
+    properties: Option<&DynamicStruct>,
+) -> Result<Handle<TextureAtlas>, PrefabCommandError> {
+    let path = required::<String>(properties, "texture_path")?.clone();
+    let tile_size = *required::<Vec2>(properties, "tile_size")?;
+    let props = properties.unwrap();
+    let columns = props.try_get_u32("columns").unwrap_or(1) as usize;
+    let rows = props.try_get_u32("rows").unwrap_or(1) as usize;
+    let padding = props.get_or("padding", Vec2::ZERO);
+
+    let bits = |v: Vec2| [v.x.to_bits(), v.y.to_bits()];
+    let key = (path, bits(tile_size), columns, rows, bits(padding));
+    let cached = world
+        .get_resource_or_insert_with(TextureAtlases::default)
+        .0
+        .get(&key)
+        .cloned();
+    if let Some(cached) = cached {
+        return Ok(cached);
+    }
+
+    let texture = load_asset(world, &key.0)?;
+    let atlas = TextureAtlas::from_grid_with_padding(texture, tile_size, columns, rows, padding);
+    let atlas = world
+        .get_resource_mut::<Assets<TextureAtlas>>()
+        .ok_or_else(PrefabCommandError::missing_resource::<Assets<TextureAtlas>>)?
+        .add(atlas);
+    world
+        .get_resource_mut::<TextureAtlases>()
+        .unwrap()
+        .0
+        .insert(key, atlas.clone());
+    Ok(atlas)
 }
 
 /// Inserts a [PbrBundle].
@@ -195,50 +1222,63 @@ impl BuildPrefabCommand for InsertSpriteBundle {
 /// - `shape` - The shape to use for the mesh. Accepts `shape::Cube`, `shape::Plane` or `shape::Quad`.
 /// - `size` - For a Cube or Plane the size is a single `f32`. For a Quad the size is a `Vec2`.
 /// - `flip` - A `bool` that determines the texture coordinates on a [shape::Quad].
+#[cfg(feature = "3d")]
 #[derive(Default)]
 pub struct InsertPbrBundle;
+#[cfg(feature = "3d")]
 impl BuildPrefabCommand for InsertPbrBundle {
-    fn run(&self, properties: Option<&DynamicStruct>, world: &mut World, entity: Entity) {
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
         let mut bundle = PbrBundle::default();
 
         if let Some(properties) = properties {
             if let Some(mesh) = get_mesh(properties) {
-                world.resource_scope(|_, mut meshes: Mut<Assets<Mesh>>| {
-                    let handle = meshes.add(mesh);
-                    bundle.mesh = handle;
-                });
+                bundle.mesh = world
+                    .get_resource_mut::<Assets<Mesh>>()
+                    .ok_or_else(PrefabCommandError::missing_resource::<Assets<Mesh>>)?
+                    .add(mesh);
             }
 
             if let Ok(color) = properties.try_get::<Color>("color") {
-                world.resource_scope(|_, mut materials: Mut<Assets<StandardMaterial>>| {
-                    let mat = materials.add(StandardMaterial::from(*color));
-                    bundle.material = mat;
-                });
+                bundle.material = world
+                    .get_resource_mut::<Assets<StandardMaterial>>()
+                    .ok_or_else(PrefabCommandError::missing_resource::<Assets<StandardMaterial>>)?
+                    .add(StandardMaterial::from(*color));
             }
         }
 
         world.entity_mut(entity).insert_bundle(bundle);
+        Ok(())
     }
 
     fn key(&self) -> &str {
         "InsertPbrBundle"
     }
+
+    fn phase(&self) -> BuildPhase {
+        BuildPhase::Bundle
+    }
 }
 
+#[cfg(feature = "3d")]
 fn get_mesh(props: &DynamicStruct) -> Option<Mesh> {
     if let Ok(shape) = props.try_get::<String>("shape") {
         return match shape.as_str() {
             "Plane" => {
-                let size = *props.try_get::<f32>("size").unwrap_or(&1.0);
+                let size = props.try_get_f32("size").unwrap_or(1.0);
                 Some(Mesh::from(shape::Plane { size }))
             }
             "Cube" => {
-                let size = *props.try_get::<f32>("size").unwrap_or(&1.0);
+                let size = props.try_get_f32("size").unwrap_or(1.0);
                 Some(Mesh::from(shape::Cube { size }))
             }
             "Quad" => {
-                let size = *props.try_get::<Vec2>("size").unwrap_or(&Vec2::ONE);
-                let flip = *props.try_get::<bool>("flip").unwrap_or(&false);
+                let size = props.get_or("size", Vec2::ONE);
+                let flip = props.get_or("flip", false);
                 Some(Mesh::from(shape::Quad { size, flip }))
             }
             _ => None,
@@ -247,29 +1287,146 @@ fn get_mesh(props: &DynamicStruct) -> Option<Mesh> {
     None
 }
 
+/// Inserts a [MeshBundle] rendered with a custom shader pipeline.
+///
+/// Pipelines are shared by every entity using the same shaders.
+///
+/// ### Properties:
+///
+/// - `vertex` - The path to the vertex shader, relative to the *assets* directory.
+/// - `fragment` - Optional path to the fragment shader.
+/// - `shape` / `size` - Optional mesh, as with [InsertPbrBundle]. Defaults to a unit `shape::Quad`.
+///
+/// ### Example
+///
+/// ```ignore
+/// {
+///     InsertShaderPipeline!(
+///         vertex: "shaders/wave.vert",
+///         fragment: "shaders/wave.frag",
+///         shape: shape::Quad,
+///         size: Vec2 { x: 2.0, y: 2.0 },
+///     ),
+/// }
+/// ```
+#[cfg(feature = "3d")]
+#[derive(Default)]
+pub struct InsertShaderPipeline;
+#[cfg(feature = "3d")]
+impl BuildPrefabCommand for InsertShaderPipeline {
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
+        let vertex = required::<String>(properties, "vertex")?.clone();
+        let fragment = properties.and_then(|p| p.try_get::<String>("fragment").ok().cloned());
+
+        let pipeline = get_shader_pipeline(world, vertex, fragment)?;
+        let mesh = properties
+            .and_then(get_mesh)
+            .unwrap_or_else(|| Mesh::from(shape::Quad::new(Vec2::ONE)));
+        let mesh = world
+            .get_resource_mut::<Assets<Mesh>>()
+            .ok_or_else(PrefabCommandError::missing_resource::<Assets<Mesh>>)?
+            .add(mesh);
+
+        world.entity_mut(entity).insert_bundle(MeshBundle {
+            mesh,
+            render_pipelines: RenderPipelines::from_pipelines(vec![RenderPipeline::new(pipeline)]),
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    fn key(&self) -> &str {
+        "InsertShaderPipeline"
+    }
+
+    fn phase(&self) -> BuildPhase {
+        BuildPhase::Bundle
+    }
+}
+
+/// Pipelines created by [InsertShaderPipeline], by vertex and fragment shader path.
+#[cfg(feature = "3d")]
+#[derive(Default)]
+struct ShaderPipelines(HashMap<(String, Option<String>), Handle<PipelineDescriptor>>);
+
+#[cfg(feature = "3d")]
+fn get_shader_pipeline(
+    world: &mut World,
+    vertex: String,
+    fragment: Option<String>,
+) -> Result<Handle<PipelineDescriptor>, PrefabCommandError> {
+    let key = (vertex, fragment);
+    let cached = world
+        .get_resource_or_insert_with(ShaderPipelines::default)
+        .0
+        .get(&key)
+        .cloned();
+    if let Some(cached) = cached {
+        return Ok(cached);
+    }
+
+    let server = world
+        .get_resource::<AssetServer>()
+        .ok_or_else(PrefabCommandError::missing_resource::<AssetServer>)?;
+    let stages = ShaderStages {
+        vertex: server.load::<Shader, _>(key.0.as_str()),
+        fragment: key
+            .1
+            .as_ref()
+            .map(|path| server.load::<Shader, _>(path.as_str())),
+    };
+    let pipeline = world
+        .get_resource_mut::<Assets<PipelineDescriptor>>()
+        .ok_or_else(PrefabCommandError::missing_resource::<Assets<PipelineDescriptor>>)?
+        .add(PipelineDescriptor::default_config(stages));
+    world
+        .get_resource_mut::<ShaderPipelines>()
+        .unwrap()
+        .0
+        .insert(key, pipeline.clone());
+    Ok(pipeline)
+}
+
 /// Inserts an [OrthographicCameraBundle].
 ///
 /// # Optional Property
 ///
 /// - `scale` - Determines the scale of the orthographic projection.
+#[cfg(feature = "2d")]
 #[derive(Default)]
 pub struct InsertOrthographicCameraBundle;
+#[cfg(feature = "2d")]
 impl BuildPrefabCommand for InsertOrthographicCameraBundle {
-    fn run(&self, properties: Option<&DynamicStruct>, world: &mut World, entity: Entity) {
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
         let mut bundle = OrthographicCameraBundle::new_2d();
 
         if let Some(props) = properties {
-            if let Ok(scale) = props.try_get::<f32>("scale") {
-                bundle.orthographic_projection.scale = *scale;
+            if let Ok(scale) = props.try_get_f32("scale") {
+                bundle.orthographic_projection.scale = scale;
             }
         }
 
         world.entity_mut(entity).insert_bundle(bundle);
+        Ok(())
     }
 
     fn key(&self) -> &str {
         "InsertOrthographicCameraBundle"
     }
+
+    fn phase(&self) -> BuildPhase {
+        BuildPhase::Bundle
+    }
 }
 
 /// Inserts a [PerspectiveCameraBundle].
@@ -278,10 +1435,17 @@ impl BuildPrefabCommand for InsertOrthographicCameraBundle {
 ///
 /// - `position` - A `Vec3` that sets the intial position of the camera.
 /// - `looking_at` - A `Vec3` that determins where the camera is initially looking.
+#[cfg(feature = "3d")]
 #[derive(Default)]
 pub struct InsertPerspectiveCameraBundle;
+#[cfg(feature = "3d")]
 impl BuildPrefabCommand for InsertPerspectiveCameraBundle {
-    fn run(&self, properties: Option<&DynamicStruct>, world: &mut World, entity: Entity) {
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
         let mut bundle = PerspectiveCameraBundle::new_3d();
 
         if let Some(props) = properties {
@@ -295,9 +1459,602 @@ impl BuildPrefabCommand for InsertPerspectiveCameraBundle {
         }
 
         world.entity_mut(entity).insert_bundle(bundle);
+        Ok(())
     }
 
     fn key(&self) -> &str {
         "InsertPerspectiveCameraBundle"
     }
+
+    fn phase(&self) -> BuildPhase {
+        BuildPhase::Bundle
+    }
+}
+
+/// Inserts a [Text2dBundle].
+///
+/// ### Optional Properties:
+///
+/// - `text` - The text to display.
+/// - `style` - A property group for the text style:
+///   - `font` - The path to the font.
+///   - `size` - The font size.
+///   - `color` - The text color.
+#[cfg(feature = "2d")]
+#[derive(Default)]
+pub struct InsertText2dBundle;
+#[cfg(feature = "2d")]
+impl BuildPrefabCommand for InsertText2dBundle {
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
+        let text = get_text(properties, world)?;
+        world.entity_mut(entity).insert_bundle(Text2dBundle {
+            text,
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    fn key(&self) -> &str {
+        "InsertText2dBundle"
+    }
+
+    fn phase(&self) -> BuildPhase {
+        BuildPhase::Bundle
+    }
+}
+
+/// Inserts a UI [TextBundle].
+///
+/// ### Optional Properties:
+///
+/// - `text` - The text to display.
+/// - `style` - A property group for the text style:
+///   - `font` - The path to the font.
+///   - `size` - The font size.
+///   - `color` - The text color.
+#[cfg(feature = "ui")]
+#[derive(Default)]
+pub struct InsertTextBundle;
+#[cfg(feature = "ui")]
+impl BuildPrefabCommand for InsertTextBundle {
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
+        let text = get_text(properties, world)?;
+        world.entity_mut(entity).insert_bundle(TextBundle {
+            text,
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    fn key(&self) -> &str {
+        "InsertTextBundle"
+    }
+
+    fn phase(&self) -> BuildPhase {
+        BuildPhase::Bundle
+    }
+}
+
+#[cfg(any(feature = "2d", feature = "ui"))]
+fn get_text(
+    properties: Option<&DynamicStruct>,
+    world: &mut World,
+) -> Result<Text, PrefabCommandError> {
+    let mut value = String::new();
+    let mut style = TextStyle::default();
+    if let Some(props) = properties {
+        if let Ok(text) = props.try_get::<String>("text") {
+            value = text.clone();
+        }
+        if let Ok(group) = props.try_get_path::<DynamicStruct>("style") {
+            if let Ok(path) = group.try_get::<String>("font") {
+                style.font = load_asset(world, path)?;
+            }
+            if let Ok(size) = group.try_get_f32("size") {
+                style.font_size = size;
+            }
+            if let Ok(color) = group.try_get::<Color>("color") {
+                style.color = *color;
+            }
+        }
+    }
+    Ok(Text::with_section(value, style, TextAlignment::default()))
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::*;
+
+    use crate::{
+        parse::parse_prefab_str, test_utils::Stats, LoadPrefabError, PrefabBuildStep,
+        PrefabRegistry,
+    };
+
+    #[test]
+    fn tile_map() {
+        use crate::build_commands::SpawnTileMap;
+        use crate::test_utils::*;
+        use crate::SpawnPrefabCommands;
+        use bevy::ecs::system::CommandQueue;
+
+        let mut world = prefab_world();
+        let room = {
+            let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.register_type::<Stats>();
+            reg.register_type::<Transform>();
+            reg.register_build_command::<SpawnTileMap>();
+            reg.replace_prefab("wall", "{ Stats { health: 10 } }")
+                .unwrap();
+            reg.replace_prefab("player", "{ Transform { translation: Vec3 { z: 1.0 } } }")
+                .unwrap();
+            reg.replace_prefab(
+                "room",
+                r###"{ SpawnTileMap!(
+                    tile_size: Vec2 { x: 2.0, y: 3.0 },
+                    map: ["##", ".@"],
+                    legend: [{ char: '#', prefab: "wall" }, { char: '@', prefab: "player" }],
+                ) }"###,
+            )
+            .unwrap();
+            reg.load("room").unwrap().clone()
+        };
+
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        let entity = commands.spawn().insert_prefab(&room).id();
+        queue.apply(&mut world);
+
+        let children = world.get::<Children>(entity).unwrap();
+        assert_eq!(children.len(), 3);
+        let translations: Vec<_> = children
+            .iter()
+            .map(|c| world.get::<Transform>(*c).unwrap().translation)
+            .collect();
+        assert_eq!(
+            translations,
+            [
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(2.0, -3.0, 1.0)
+            ]
+        );
+        assert_eq!(world.get::<Stats>(children[1]).unwrap().health, 10);
+    }
+
+    #[test]
+    fn pending_color_material() {
+        use crate::build_commands::{
+            apply_pending_color_materials, PendingColorMaterial, PendingTextureSamplers,
+            SetColorMaterial,
+        };
+        use crate::SpawnPrefabWorldExt;
+        use bevy::{asset::HandleId, ecs::system::System};
+
+        let mut app = App::build();
+        app.add_plugin(bevy::core::CorePlugin)
+            .add_plugin(bevy::asset::AssetPlugin)
+            .add_asset::<ColorMaterial>()
+            .add_asset::<Texture>()
+            .init_resource::<PendingTextureSamplers>()
+            .init_resource::<PrefabRegistry>();
+        let world = app.world_mut();
+
+        let prefab = {
+            let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.register_build_command::<SetColorMaterial>();
+            parse_prefab_str("{ SetColorMaterial!(color: Color::RED) }", &reg).unwrap()
+        };
+        let id = HandleId::random::<ColorMaterial>();
+        let entity = world.spawn().insert(Handle::<ColorMaterial>::weak(id)).id();
+        world.insert_prefab(entity, &prefab);
+        assert!(world.get::<PendingColorMaterial>(entity).is_some());
+
+        let mut system = apply_pending_color_materials.system();
+        system.initialize(world);
+        system.run((), world);
+        system.apply_buffers(world);
+        assert!(world.get::<PendingColorMaterial>(entity).is_some());
+
+        world
+            .get_resource_mut::<Assets<ColorMaterial>>()
+            .unwrap()
+            .set_untracked(id, ColorMaterial::default());
+        system.run((), world);
+        system.apply_buffers(world);
+        assert!(world.get::<PendingColorMaterial>(entity).is_none());
+        let materials = world.get_resource::<Assets<ColorMaterial>>().unwrap();
+        assert_eq!(materials.get(id).unwrap().color, Color::RED);
+    }
+
+    #[test]
+    fn texture_atlas() {
+        use crate::{build_commands::InsertTextureAtlas, test_utils::*};
+
+        let mut app = App::build();
+        app.add_plugin(bevy::core::CorePlugin)
+            .add_plugin(bevy::asset::AssetPlugin)
+            .add_asset::<TextureAtlas>()
+            .add_asset::<Texture>()
+            .init_resource::<PrefabRegistry>();
+        let world = app.world_mut();
+        world
+            .get_resource_mut::<PrefabRegistry>()
+            .unwrap()
+            .register_build_command::<InsertTextureAtlas>();
+
+        let input = r#"{ InsertTextureAtlas!(
+            texture_path: "tiles.png",
+            tile_size: Vec2 { x: 16.0, y: 8.0 },
+            columns: 4,
+            rows: 2,
+        ) }"#;
+        let a = spawn_prefab_str(world, input).unwrap();
+        let b = spawn_prefab_str(world, input).unwrap();
+        let c = spawn_prefab_str(
+            world,
+            r#"{ InsertTextureAtlas!(texture_path: "tiles.png", tile_size: Vec2 { x: 16.0, y: 8.0 }) }"#,
+        )
+        .unwrap();
+
+        let handle = world.get::<Handle<TextureAtlas>>(a).unwrap().clone();
+        assert_eq!(world.get::<Handle<TextureAtlas>>(b), Some(&handle));
+        assert_ne!(world.get::<Handle<TextureAtlas>>(c), Some(&handle));
+
+        let atlases = world.get_resource::<Assets<TextureAtlas>>().unwrap();
+        let atlas = atlases.get(&handle).unwrap();
+        assert_eq!(atlas.len(), 8);
+        assert_eq!(atlas.textures[5].min, Vec2::new(16.0, 8.0));
+        assert_eq!(atlases.iter().count(), 2);
+    }
+
+    #[test]
+    fn phase_order() {
+        let mut reg = PrefabRegistry::default();
+        reg.register_type::<Visible>();
+        reg.register_build_command::<crate::build_commands::SetColorMaterial>();
+        reg.register_build_command::<crate::build_commands::InsertSpriteBundle>();
+
+        let prefab = parse_prefab_str(
+            "{ SetColorMaterial!(), Visible, Unknown!(), InsertSpriteBundle!() }",
+            &mut reg,
+        )
+        .unwrap();
+
+        let names: Vec<_> = prefab
+            .steps
+            .iter()
+            .map(|step| match step {
+                PrefabBuildStep::RunCommand(command) => command.name.as_str(),
+                PrefabBuildStep::AddComponent(comp) => &*comp.type_name,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "InsertSpriteBundle",
+                "Visible",
+                "Unknown",
+                "SetColorMaterial"
+            ]
+        );
+    }
+
+    #[derive(Default)]
+    struct Trace(Vec<String>);
+
+    #[test]
+    fn apply_order() {
+        use crate::stream::apply_prefab_streams;
+        use crate::test_utils::*;
+        use crate::{SpawnBudget, SpawnNamedPrefabCommands};
+        use bevy::ecs::system::CommandQueue;
+
+        let mut world = prefab_world();
+        {
+            let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.register_type::<Stats>();
+            for key in ["A", "B", "C"] {
+                reg.register_command_fn(key, move |_, world, entity| {
+                    let health = world.get::<Stats>(entity).map_or(0, |s| s.health);
+                    let mut entity = world.entity_mut(entity);
+                    if entity.get::<Trace>().is_none() {
+                        entity.insert(Trace::default());
+                    }
+                    let mut trace = entity.get_mut::<Trace>().unwrap();
+                    trace.0.push(format!("{}{}", key, health));
+                });
+            }
+        }
+        let trace = |world: &World, entity| world.get::<Trace>(entity).unwrap().0.join(" ");
+
+        // Components and commands are applied in file order, and later fields win.
+        let input = "{ A!(), Stats { health: 1, health: 2 }, B!(), Stats { health: 3 }, C!() }";
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        assert_eq!(trace(&world, entity), "A0 B2 C3");
+
+        let input = "{ A!(after: \"C\"), Stats { health: 1 }, B!(), C!(before: \"B\") }";
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        assert_eq!(trace(&world, entity), "C1 B1 A1");
+
+        // Streamed prefabs are applied in the same order.
+        world
+            .get_resource_mut::<PrefabRegistry>()
+            .unwrap()
+            .replace_prefab("ordered", input)
+            .unwrap();
+        let mut queue = CommandQueue::default();
+        let entity = Commands::new(&mut queue, &world)
+            .spawn_prefab_streamed("ordered", SpawnBudget::Count(1))
+            .id();
+        queue.apply(&mut world);
+        for _ in 0..5 {
+            apply_prefab_streams(&mut world);
+        }
+        assert_eq!(trace(&world, entity), "C1 B1 A1");
+
+        let reg = world.get_resource::<PrefabRegistry>().unwrap();
+        let err = parse_prefab_str("{ A!(after: \"D\") }", reg).unwrap_err();
+        assert!(matches!(err, LoadPrefabError::MissingOrderTarget(..)));
+
+        // The hints aren't passed on to the commands.
+        let prefab = parse_prefab_str(
+            "{ A!(after: \"B\", size: 2), B!(), C!(before: \"B\") }",
+            reg,
+        );
+        let steps = prefab.unwrap().steps;
+        let properties: Vec<_> = steps
+            .iter()
+            .map(|step| match step {
+                PrefabBuildStep::RunCommand(c) => c.properties.as_ref().map(|p| p.field_len()),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(properties, [None, None, Some(1)]);
+        match &steps[2] {
+            PrefabBuildStep::RunCommand(a) => {
+                assert_eq!(a.name, "A");
+                assert!(a.properties.as_ref().unwrap().field("size").is_some());
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn companion_components() {
+        use crate::{test_utils::*, SpawnPrefabCommands};
+        use bevy::ecs::system::CommandQueue;
+
+        let mut world = prefab_world();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_type::<Transform>();
+            // Registered out of order to check that rules chain.
+            reg.add_companion::<GlobalTransform, Visible>();
+            reg.add_companion::<Transform, GlobalTransform>();
+        });
+
+        let entity = spawn_prefab_str(&mut world, "{ children [ { Transform } ] }").unwrap();
+        let child = world.get::<Children>(entity).unwrap()[0];
+        assert!(world.get::<GlobalTransform>(entity).is_none());
+        assert!(world.get::<GlobalTransform>(child).is_some());
+        assert!(world.get::<Visible>(child).is_some());
+
+        let prefab = parse_prefab_str(
+            "{ Transform }",
+            world.get_resource::<PrefabRegistry>().unwrap(),
+        )
+        .unwrap();
+        let mut queue = CommandQueue::default();
+        let entity = Commands::new(&mut queue, &world)
+            .spawn()
+            .insert_prefab(&prefab)
+            .id();
+        queue.apply(&mut world);
+        assert!(world.get::<Visible>(entity).is_some());
+    }
+
+    #[test]
+    fn flagged_commands() {
+        use crate::build_commands::InsertTimer;
+        use crate::{test_utils::*, PrefabFlags};
+
+        let mut world = prefab_world();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_type::<Transform>();
+            reg.register_build_command::<InsertTimer>();
+        });
+
+        let input = r#"{
+            Transform,
+            if "high_quality" { InsertTimer!(seconds: 2.0), }
+        }"#;
+        let prefab = parse_prefab_str(input, world.get_resource::<PrefabRegistry>().unwrap());
+        let prefab = prefab.unwrap();
+        let command = prefab.commands().next().unwrap();
+        assert_eq!(command.flag(), Some("high_quality"));
+
+        let reparsed = parse_prefab_str(
+            &prefab.to_string(),
+            world.get_resource::<PrefabRegistry>().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            reparsed.commands().next().unwrap().flag(),
+            Some("high_quality")
+        );
+
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        assert!(world.get::<Transform>(entity).is_some());
+        assert!(world.get::<Timer>(entity).is_none());
+
+        let mut flags = PrefabFlags::default();
+        flags.set("high_quality", true);
+        world.insert_resource(flags);
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        assert!(world.get::<Timer>(entity).is_some());
+
+        world
+            .get_resource_mut::<PrefabFlags>()
+            .unwrap()
+            .set("high_quality", false);
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        assert!(world.get::<Timer>(entity).is_none());
+    }
+
+    #[derive(Bundle, Default)]
+    struct UnitBundle {
+        stats: Stats,
+        transform: Transform,
+        global_transform: GlobalTransform,
+    }
+
+    #[test]
+    fn bundles() {
+        use crate::test_utils::*;
+
+        let mut world = prefab_world();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_type::<Vec3>();
+            reg.register_type::<Transform>();
+            reg.register_type::<Stats>();
+            reg.register_bundle::<UnitBundle>();
+        });
+
+        let input = "{ Stats { health: 4 }, bundle UnitBundle { Transform { translation: Vec3 { x: 2.0 } } } }";
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        assert_eq!(world.get::<Stats>(entity).unwrap().health, 4);
+        assert_eq!(world.get::<Transform>(entity).unwrap().translation.x, 2.0);
+        assert!(world.get::<GlobalTransform>(entity).is_some());
+
+        let reg = world.get_resource::<PrefabRegistry>().unwrap();
+        let prefab = parse_prefab_str(input, reg).unwrap();
+        let reparsed = parse_prefab_str(&prefab.to_string(), reg).unwrap();
+        assert_eq!(reparsed.steps().len(), prefab.steps().len());
+
+        let err = parse_prefab_str("{ bundle MissingBundle {} }", reg).unwrap_err();
+        assert!(
+            matches!(err, LoadPrefabError::UnregisteredBundle(name) if name == "MissingBundle")
+        );
+    }
+
+    #[test]
+    fn command_errors() {
+        use crate::build_commands::{InsertTimer, LoadPrefab};
+        use crate::{test_utils::*, PrefabCommandFailed, SpawnPrefabCommands};
+        use bevy::{app::Events, ecs::system::CommandQueue};
+
+        let mut world = prefab_world();
+        world.insert_resource(Events::<PrefabCommandFailed>::default());
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_type::<Stats>();
+            reg.register_build_command::<InsertTimer>();
+            reg.register_build_command::<LoadPrefab>();
+        });
+
+        let input = r#"{
+            InsertTimer!(repeating: true),
+            InsertTimer!(seconds: "soon"),
+            LoadPrefab!(name: "base.prefab"),
+            Unknown!(),
+            Stats { health: 2 },
+        }"#;
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        assert_eq!(world.get::<Stats>(entity).unwrap().health, 2);
+        assert!(world.get::<Timer>(entity).is_none());
+
+        // Commands which aren't registered fail the same way when applied through Commands.
+        let prefab = parse_prefab_str("{ Unknown!() }", world.get_resource().unwrap()).unwrap();
+        let mut queue = CommandQueue::default();
+        let other = Commands::new(&mut queue, &world)
+            .spawn()
+            .insert_prefab(&prefab)
+            .id();
+        queue.apply(&mut world);
+
+        let events = world.get_resource::<Events<PrefabCommandFailed>>().unwrap();
+        let failed: Vec<_> = events.get_reader().iter(events).cloned().collect();
+        assert_eq!(failed.len(), 5);
+        assert!(failed[..4].iter().all(|f| f.entity == entity));
+        assert_eq!(failed[0].command, "InsertTimer");
+        assert_eq!(failed[0].error, "Missing required property 'seconds'.");
+        assert!(failed[1].error.starts_with("Invalid property 'seconds'"));
+        assert_eq!(failed[2].command, "LoadPrefab");
+        assert_eq!(failed[2].error, "Failed to load prefab 'base.prefab'.");
+        let unregistered = "The build command Unknown isn't registered in the PrefabRegistry.";
+        assert_eq!(failed[3].command, "Unknown");
+        assert_eq!(failed[3].error, unregistered);
+        assert_eq!(failed[4].entity, other);
+        assert_eq!(failed[4].error, unregistered);
+    }
+
+    #[test]
+    fn text_style_group() {
+        use crate::{build_commands::InsertText2dBundle, test_utils::*};
+
+        let mut world = prefab_world();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_build_command::<InsertText2dBundle>();
+        });
+
+        let input =
+            "{ InsertText2dBundle!(text: \"Score\", style { size: 24.0, color: #ff0000 }) }";
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        let text = world.get::<Text>(entity).unwrap();
+        assert_eq!(text.sections[0].value, "Score");
+        assert_eq!(text.sections[0].style.font_size, 24.0);
+        assert_eq!(text.sections[0].style.color, Color::RED);
+
+        let reg = world.get_resource::<PrefabRegistry>().unwrap();
+        let prefab = parse_prefab_str(input, reg).unwrap();
+        assert!(prefab.to_string().contains("style { size: 24.0, color: "));
+        assert!(parse_prefab_str(&prefab.to_string(), reg).is_ok());
+    }
+
+    #[test]
+    fn collection_components() {
+        use crate::{build_commands::apply_prefab, test_utils::*, writer::write_component};
+        use bevy::utils::HashMap;
+
+        let mut world = prefab_world();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_type_with(HashMap::<String, f32>::default);
+            reg.register_type_with(Vec::<u8>::default);
+            let map_name = reg.get_type_data(std::any::type_name::<HashMap<String, f32>>());
+            let map_name = map_name.unwrap().type_name.clone();
+            reg.register_alias("Speeds", &map_name);
+            let list_name = reg.get_type_data(std::any::type_name::<Vec<u8>>());
+            let list_name = list_name.unwrap().type_name.clone();
+            reg.register_alias("Bytes", &list_name);
+        });
+
+        let input = "{ Speeds { walk: 2.0, run: 5.5 } Bytes [1, 2] }";
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        world.resource_scope(|world, reg: Mut<PrefabRegistry>| {
+            let prefab = parse_prefab_str("{ Speeds { run: 6.0 } }", &reg).unwrap();
+            apply_prefab(&reg, &prefab, world, entity);
+        });
+
+        let speeds = world.get::<HashMap<String, f32>>(entity).unwrap();
+        assert_eq!(speeds.get("walk"), Some(&2.0));
+        assert_eq!(speeds.get("run"), Some(&6.0));
+        assert_eq!(world.get::<Vec<u8>>(entity), Some(&vec![1, 2]));
+
+        let mut out = String::new();
+        write_component(&mut out, "Speeds", speeds, 0);
+        write_component(&mut out, "Bytes", world.get::<Vec<u8>>(entity).unwrap(), 0);
+        assert_eq!(
+            out,
+            "Speeds {\n    run: 6.0,\n    walk: 2.0,\n},\nBytes [1, 2],\n"
+        );
+    }
 }
@@ -0,0 +1,772 @@
+use std::ops::Range;
+
+use bevy::{
+    asset::HandleId,
+    prelude::*,
+    reflect::{
+        DynamicList, DynamicMap, DynamicStruct, DynamicTuple, DynamicTupleStruct, ReflectRef,
+    },
+    utils::{HashMap, HashSet},
+};
+use serde::{Deserialize, Serialize};
+
+/// The random number generator used for `rand` and `rand_int` values in *.prefab* files.
+///
+/// Seeded randomly by default. Insert a seeded generator to make spawned prefabs
+/// deterministic.
+///
+/// ## Example
+///
+/// ```
+/// use bevy::prelude::*;
+/// use bevy_lazy_prefabs::*;
+///
+/// fn setup(mut commands: Commands) {
+///     commands.insert_resource(PrefabRng::with_seed(42));
+/// }
+/// ```
+pub struct PrefabRng(u64);
+
+impl PrefabRng {
+    /// Create a generator which always produces the same sequence of values for a given seed.
+    pub fn with_seed(seed: u64) -> Self {
+        PrefabRng(seed)
+    }
+
+    fn next(&mut self) -> fastrand::Rng {
+        let rng = fastrand::Rng::with_seed(self.0);
+        self.0 = rng.u64(..);
+        rng
+    }
+
+    /// A random number between 0 and 1.
+    pub(crate) fn roll(&mut self) -> f32 {
+        self.next().f32()
+    }
+}
+
+impl Default for PrefabRng {
+    fn default() -> Self {
+        PrefabRng(fastrand::u64(..))
+    }
+}
+
+/// The entities spawned for each anchored prefab (`Turret @turret { .. }`) in a prefab
+/// hierarchy, used to resolve `@name` values.
+pub(crate) type Anchors = HashMap<String, Entity>;
+
+/// Values which can be referred to from a *.prefab* file as `${name}`.
+///
+/// Variables are read each time a prefab is spawned, so changing a variable affects
+/// every prefab spawned afterwards. Fields referring to a variable which isn't set
+/// are left unchanged. Variables can also be used inside strings, ie:
+/// `"level_${level}.png"`.
+///
+/// ## Example
+///
+/// ```
+/// use bevy::prelude::*;
+/// use bevy_lazy_prefabs::*;
+///
+/// // Used as `Health { max: ${enemy_health} }` in a prefab file.
+/// fn set_difficulty(mut vars: ResMut<PrefabVars>) {
+///     vars.set("enemy_health", 150);
+/// }
+/// ```
+#[derive(Default)]
+pub struct PrefabVars {
+    values: HashMap<String, Box<dyn Reflect>>,
+}
+
+impl PrefabVars {
+    /// Set the value of a variable, replacing any previous value.
+    pub fn set(&mut self, name: &str, value: impl Reflect) {
+        self.values.insert(name.to_string(), Box::new(value));
+    }
+
+    /// Get the value of a variable.
+    pub fn get(&self, name: &str) -> Option<&dyn Reflect> {
+        self.values.get(name).map(|value| &**value)
+    }
+
+    /// Remove a variable, returning its value.
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn Reflect>> {
+        self.values.remove(name)
+    }
+}
+
+/// Runtime flags which gate the build commands inside `if "flag" { .. }` blocks in *.prefab*
+/// files, such as quality settings chosen from a settings menu.
+///
+/// Flags are read each time a command is run, so changing a flag affects every prefab
+/// spawned afterwards. Commands gated on a flag which isn't set are skipped. Without the
+/// resource, every gated command is skipped.
+///
+/// ## Example
+///
+/// ```
+/// use bevy::prelude::*;
+/// use bevy_lazy_prefabs::*;
+///
+/// // Enables `if "high_quality" { .. }` blocks in prefab files.
+/// fn apply_settings(mut flags: ResMut<PrefabFlags>) {
+///     flags.set("high_quality", true);
+/// }
+/// ```
+#[derive(Default)]
+pub struct PrefabFlags {
+    flags: HashSet<String>,
+}
+
+impl PrefabFlags {
+    /// Set or clear a flag.
+    pub fn set(&mut self, flag: &str, enabled: bool) {
+        match enabled {
+            true => self.flags.insert(flag.to_string()),
+            false => self.flags.remove(flag),
+        };
+    }
+
+    /// Returns true if the flag is set.
+    pub fn is_set(&self, flag: &str) -> bool {
+        self.flags.contains(flag)
+    }
+
+    /// Returns true if the world has a [PrefabFlags] resource with the flag set.
+    pub(crate) fn is_set_in(world: &World, flag: &str) -> bool {
+        world
+            .get_resource::<PrefabFlags>()
+            .map_or(false, |flags| flags.is_set(flag))
+    }
+}
+
+/// Looks up localized text for `tr("key")` values, see [PrefabLocalization].
+pub trait Localize: Send + Sync + 'static {
+    /// Returns the text for the key in the current language, or [None] if there isn't any.
+    fn localize(&self, key: &str) -> Option<String>;
+}
+
+impl Localize for HashMap<String, String> {
+    fn localize(&self, key: &str) -> Option<String> {
+        self.get(key).cloned()
+    }
+}
+
+/// The localization used for `tr("key")` string values in *.prefab* files.
+///
+/// Keys are looked up each time a prefab is spawned, so switching languages affects
+/// every prefab spawned afterwards. Keys with no localized text are used as-is.
+///
+/// ## Example
+///
+/// ```
+/// use bevy::{prelude::*, utils::HashMap};
+/// use bevy_lazy_prefabs::*;
+///
+/// // Used as `Item { name: tr("item.sword.name") }` in a prefab file.
+/// fn set_language(mut localization: ResMut<PrefabLocalization>) {
+///     let mut french = HashMap::default();
+///     french.insert("item.sword.name".to_string(), "Épée".to_string());
+///     localization.set(french);
+/// }
+/// ```
+#[derive(Default)]
+pub struct PrefabLocalization {
+    localize: Option<Box<dyn Localize>>,
+}
+
+impl PrefabLocalization {
+    pub fn new(localize: impl Localize) -> Self {
+        PrefabLocalization {
+            localize: Some(Box::new(localize)),
+        }
+    }
+
+    /// Replace the current localization.
+    pub fn set(&mut self, localize: impl Localize) {
+        self.localize = Some(Box::new(localize));
+    }
+
+    /// Look up the text for a key, falling back to the key itself.
+    pub fn get(&self, key: &str) -> String {
+        match self.localize.as_ref().and_then(|l| l.localize(key)) {
+            Some(text) => text,
+            None => {
+                warn!("No localized text for the key '{}'", key);
+                key.to_string()
+            }
+        }
+    }
+}
+
+/// A value in a *.prefab* file which is evaluated each time the prefab is applied,
+/// rather than once when it's parsed.
+#[derive(Debug, Clone, PartialEq, Reflect, Serialize, Deserialize)]
+#[reflect_value(PartialEq, Serialize, Deserialize)]
+pub(crate) enum DeferredValue {
+    Const(f32),
+    /// `rand(min..max)`
+    RandomFloat(Range<f32>),
+    /// `rand_int(min..max)`
+    RandomInt(Range<i32>),
+    /// A `Vec2` or `Vec3` with at least one deferred field.
+    Vector(Vec<DeferredValue>),
+    /// `${name}`, a value from [PrefabVars].
+    Var(String),
+    /// A string containing `${name}` variables.
+    Template(String),
+    /// `@name`, the entity spawned for the prefab with the anchor `@name`.
+    EntityRef(String),
+    /// `tr("key")`, localized text from [PrefabLocalization].
+    Localized(String),
+    /// `asset("path")`, an asset loaded through the `AssetServer`.
+    Asset(String),
+    /// A number converted to the type of the field it's written to, ie: `rand_int(0..4)` for a
+    /// `u32` field. Holds the full name of the field's type.
+    Number(Box<DeferredValue>, String),
+}
+
+impl DeferredValue {
+    /// Wrap a number in [DeferredValue::Number] if it needs converting to be written to
+    /// `target`, or [None] if it doesn't.
+    pub(crate) fn fit_number(&self, target: &dyn Reflect) -> Option<DeferredValue> {
+        let natural = match self {
+            DeferredValue::RandomInt(_) => target.is::<i32>(),
+            DeferredValue::Const(_) | DeferredValue::RandomFloat(_) => target.is::<f32>(),
+            DeferredValue::Var(_) => false,
+            _ => return None,
+        };
+        if natural || (as_integer(target).is_none() && as_float(target).is_none()) {
+            return None;
+        }
+        Some(DeferredValue::Number(
+            Box::new(self.clone()),
+            target.type_name().to_string(),
+        ))
+    }
+
+    /// Evaluate the value, or [None] if it refers to a variable that isn't set.
+    fn evaluate(&self, world: &mut World, anchors: &Anchors) -> Option<Box<dyn Reflect>> {
+        match self {
+            DeferredValue::RandomInt(range) => Some(Box::new(random_int(range, world))),
+            DeferredValue::Vector(values) => {
+                let values: Vec<_> = values.iter().map(|v| v.evaluate_f32(world)).collect();
+                match values[..] {
+                    [x, y] => Some(Box::new(Vec2::new(x, y))),
+                    [x, y, z] => Some(Box::new(Vec3::new(x, y, z))),
+                    _ => unreachable!(),
+                }
+            }
+            DeferredValue::Var(name) => get_var(name, world).map(|value| value.clone_value()),
+            DeferredValue::Template(text) => Some(Box::new(fill_template(text, world))),
+            DeferredValue::Localized(key) => {
+                let text = world
+                    .get_resource_or_insert_with(PrefabLocalization::default)
+                    .get(key);
+                Some(Box::new(text))
+            }
+            DeferredValue::Asset(path) => load_asset(path, world),
+            DeferredValue::EntityRef(name) => match anchors.get(name) {
+                Some(entity) => Some(Box::new(*entity)),
+                None => {
+                    warn!("No entity in the prefab has the anchor @{}", name);
+                    None
+                }
+            },
+            DeferredValue::Number(value, type_name) => {
+                let value = value.evaluate(world, anchors)?;
+                let number = convert_number(&*value, type_name);
+                if number.is_none() {
+                    warn!(
+                        "Can't convert the value {:?} to {}, the field is skipped",
+                        value, type_name
+                    );
+                }
+                number
+            }
+            _ => Some(Box::new(self.evaluate_f32(world))),
+        }
+    }
+
+    fn evaluate_f32(&self, world: &mut World) -> f32 {
+        match self {
+            DeferredValue::Const(f) => *f,
+            DeferredValue::RandomFloat(range) => {
+                let mut rng = world.get_resource_or_insert_with(PrefabRng::default);
+                range.start + rng.next().f32() * (range.end - range.start)
+            }
+            DeferredValue::RandomInt(range) => random_int(range, world) as f32,
+            DeferredValue::Var(name) => match get_var(name, world) {
+                Some(value) => value
+                    .downcast_ref::<f32>()
+                    .copied()
+                    .or_else(|| value.downcast_ref::<i32>().map(|i| *i as f32))
+                    .unwrap_or_default(),
+                None => 0.0,
+            },
+            DeferredValue::Number(value, _) => value.evaluate_f32(world),
+            DeferredValue::Vector(_)
+            | DeferredValue::Template(_)
+            | DeferredValue::EntityRef(_)
+            | DeferredValue::Localized(_)
+            | DeferredValue::Asset(_) => 0.0,
+        }
+    }
+}
+
+/// Strong handles to the assets loaded by `asset("path")` values in *.prefab* files.
+///
+/// Reflected handles only store the asset's id, so the handles set on prefab components
+/// don't keep their assets loaded. Instead every asset requested by a prefab is kept
+/// loaded by this resource until it's cleared.
+#[derive(Default)]
+pub struct PrefabAssetHandles(HashMap<HandleId, HandleUntyped>);
+
+impl PrefabAssetHandles {
+    /// The number of assets loaded by prefabs.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Release the handles, allowing the assets to be unloaded once nothing else uses them.
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// Start loading an asset, returning a value which can be applied to a `Handle<T>` of any
+/// asset type. Asset ids only depend on the path, so the asset type isn't needed.
+fn load_asset(path: &str, world: &mut World) -> Option<Box<dyn Reflect>> {
+    let handle = match world.get_resource::<AssetServer>() {
+        Some(server) => server.load_untyped(path),
+        None => {
+            warn!("Can't load asset '{}' without an AssetServer", path);
+            return None;
+        }
+    };
+    let id = handle.id;
+    world
+        .get_resource_or_insert_with(PrefabAssetHandles::default)
+        .0
+        .insert(id, handle);
+    let mut value = DynamicStruct::default();
+    value.insert("id", id);
+    Some(Box::new(value))
+}
+
+fn get_var<'a>(name: &str, world: &'a World) -> Option<&'a dyn Reflect> {
+    let value = world
+        .get_resource::<PrefabVars>()
+        .and_then(|vars| vars.get(name));
+    if value.is_none() {
+        warn!("Prefab variable '{}' is not set", name);
+    }
+    value
+}
+
+/// Replace each `${name}` in the text with the value of the variable.
+fn fill_template(text: &str, world: &World) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        out.push_str(&rest[..start]);
+        if let Some(value) = get_var(&rest[start + 2..end], world) {
+            out.push_str(&var_to_string(value));
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn var_to_string(value: &dyn Reflect) -> String {
+    if let Some(s) = value.downcast_ref::<String>() {
+        return s.clone();
+    }
+    if let Some(i) = value.downcast_ref::<i32>() {
+        return i.to_string();
+    }
+    if let Some(f) = value.downcast_ref::<f32>() {
+        return f.to_string();
+    }
+    if let Some(b) = value.downcast_ref::<bool>() {
+        return b.to_string();
+    }
+    format!("{:?}", value)
+}
+
+fn random_int(range: &Range<i32>, world: &mut World) -> i32 {
+    if range.start >= range.end {
+        return range.start;
+    }
+    let mut rng = world.get_resource_or_insert_with(PrefabRng::default);
+    rng.next().i32(range.clone())
+}
+
+/// Read any integer type.
+fn as_integer(value: &dyn Reflect) -> Option<i128> {
+    macro_rules! integers {
+        ($($t:ty),*) => {$(
+            if let Some(n) = value.downcast_ref::<$t>() {
+                return Some(*n as i128);
+            }
+        )*};
+    }
+    integers!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, usize);
+    value
+        .downcast_ref::<u128>()
+        .and_then(|n| i128::try_from(*n).ok())
+}
+
+fn as_float(value: &dyn Reflect) -> Option<f64> {
+    match value.downcast_ref::<f32>() {
+        Some(f) => Some(*f as f64),
+        None => value.downcast_ref::<f64>().copied(),
+    }
+}
+
+/// Convert a number to the number type with the full name `type_name`, or [None] if it's not
+/// a number or doesn't fit in the type. Floats aren't converted to integers.
+fn convert_number(value: &dyn Reflect, type_name: &str) -> Option<Box<dyn Reflect>> {
+    macro_rules! integers {
+        ($($t:ty),*) => {$(
+            if type_name == std::any::type_name::<$t>() {
+                let n = <$t>::try_from(as_integer(value)?).ok()?;
+                return Some(Box::new(n));
+            }
+        )*};
+    }
+    integers!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+    let f = as_float(value).or_else(|| as_integer(value).map(|n| n as f64))?;
+    if type_name == std::any::type_name::<f32>() {
+        return Some(Box::new(f as f32));
+    }
+    if type_name == std::any::type_name::<f64>() {
+        return Some(Box::new(f));
+    }
+    None
+}
+
+/// Returns true if the value contains any [DeferredValue]s.
+pub(crate) fn is_deferred(value: &dyn Reflect) -> bool {
+    if value.is::<DeferredValue>() {
+        return true;
+    }
+    match value.reflect_ref() {
+        ReflectRef::Struct(s) => s.iter_fields().any(is_deferred),
+        ReflectRef::TupleStruct(s) => s.iter_fields().any(is_deferred),
+        ReflectRef::Tuple(t) => t.iter_fields().any(is_deferred),
+        ReflectRef::List(l) => l.iter().any(is_deferred),
+        ReflectRef::Map(m) => m.iter().any(|(_, v)| is_deferred(v)),
+        ReflectRef::Value(_) => false,
+    }
+}
+
+/// Copy a value, replacing any [DeferredValue]s with their evaluated results.
+///
+/// Fields which refer to a variable that isn't set are left out. Returns [None] if
+/// the value itself is such a variable.
+pub(crate) fn resolve_deferred(
+    value: &dyn Reflect,
+    world: &mut World,
+    anchors: &Anchors,
+) -> Option<Box<dyn Reflect>> {
+    if let Some(deferred) = value.downcast_ref::<DeferredValue>() {
+        return deferred.evaluate(world, anchors);
+    }
+    let resolved: Box<dyn Reflect> = match value.reflect_ref() {
+        ReflectRef::Struct(s) => {
+            let mut out = DynamicStruct::default();
+            out.set_name(s.type_name().to_string());
+            for i in 0..s.field_len() {
+                if let Some(field) = resolve_deferred(s.field_at(i).unwrap(), world, anchors) {
+                    out.insert_boxed(s.name_at(i).unwrap(), field);
+                }
+            }
+            Box::new(out)
+        }
+        ReflectRef::TupleStruct(s) => {
+            let mut out = DynamicTupleStruct::default();
+            out.set_name(s.type_name().to_string());
+            for field in s.iter_fields() {
+                out.insert_boxed(resolve_deferred(field, world, anchors)?);
+            }
+            Box::new(out)
+        }
+        ReflectRef::Tuple(t) => {
+            let mut out = DynamicTuple::default();
+            for field in t.iter_fields() {
+                out.insert_boxed(resolve_deferred(field, world, anchors)?);
+            }
+            Box::new(out)
+        }
+        ReflectRef::List(l) => {
+            let mut out = DynamicList::default();
+            for item in l.iter() {
+                out.push_box(resolve_deferred(item, world, anchors)?);
+            }
+            Box::new(out)
+        }
+        ReflectRef::Map(m) => {
+            let mut out = DynamicMap::default();
+            for (key, value) in m.iter() {
+                if let Some(value) = resolve_deferred(value, world, anchors) {
+                    out.insert_boxed(key.clone_value(), value);
+                }
+            }
+            Box::new(out)
+        }
+        ReflectRef::Value(v) => v.clone_value(),
+    };
+    Some(resolved)
+}
+
+/// Resolve the deferred values in a build command's properties.
+pub(crate) fn resolve_properties(
+    properties: &DynamicStruct,
+    world: &mut World,
+    anchors: &Anchors,
+) -> DynamicStruct {
+    let resolved = resolve_deferred(properties, world, anchors).unwrap();
+    *resolved.downcast::<DynamicStruct>().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::*;
+
+    use crate::{dynamic_cast::*, parse::parse_prefab_str, PrefabRegistry};
+
+    #[derive(Default, Reflect)]
+    #[reflect(Component)]
+    struct Skin {
+        texture: Handle<Texture>,
+    }
+
+    #[test]
+    fn asset_values() {
+        use crate::{test_utils::*, PrefabAssetHandles};
+
+        struct Icon(Handle<Texture>);
+
+        let mut app = App::build();
+        app.add_plugin(bevy::core::CorePlugin)
+            .add_plugin(bevy::asset::AssetPlugin)
+            .init_resource::<PrefabRegistry>();
+        let world = app.world_mut();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_type::<Skin>();
+            reg.register_command_fn("InsertIcon", |props, world, entity| {
+                if let Some(Ok(handle)) = props.map(|p| p.try_get_handle::<Texture>("icon")) {
+                    world.entity_mut(entity).insert(Icon(handle));
+                }
+            });
+        });
+
+        let input = r#"{
+            Skin { texture: asset("textures/skin.png") },
+            InsertIcon!(icon: asset("textures/icon.png")),
+        }"#;
+        let entity = spawn_prefab_str(world, input).unwrap();
+
+        let server = world.get_resource::<AssetServer>().unwrap();
+        let skin: Handle<Texture> = server.get_handle("textures/skin.png");
+        let icon: Handle<Texture> = server.get_handle("textures/icon.png");
+        assert_eq!(world.get::<Skin>(entity).unwrap().texture.id, skin.id);
+        assert_eq!(world.get::<Icon>(entity).unwrap().0.id, icon.id);
+        assert_eq!(world.get_resource::<PrefabAssetHandles>().unwrap().len(), 2);
+
+        // Without an AssetServer the field is left unset.
+        let mut world = prefab_world();
+        world
+            .get_resource_mut::<PrefabRegistry>()
+            .unwrap()
+            .register_type::<Skin>();
+        let entity =
+            spawn_prefab_str(&mut world, r#"{ Skin { texture: asset("skin.png") } }"#).unwrap();
+        assert_eq!(
+            world.get::<Skin>(entity).unwrap().texture,
+            Handle::default()
+        );
+    }
+
+    #[test]
+    fn random_values() {
+        use crate::{test_utils::*, PrefabRng};
+
+        let mut world = prefab_world();
+        world.insert_resource(PrefabRng::with_seed(7));
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_type::<Transform>();
+            reg.register_command_fn("Count", |props, world, entity| {
+                let count = *props.unwrap().get::<i32>("count");
+                world.entity_mut(entity).insert(count);
+            });
+        });
+
+        let input = "{
+            Transform { translation: Vec3 { x: rand(-5.0..5.0), y: 1.0 } },
+            Count!(count: rand_int(0..4)),
+        }";
+        let spawn = |world: &mut World| {
+            let entity = spawn_prefab_str(world, input).unwrap();
+            let translation = world.get::<Transform>(entity).unwrap().translation;
+            (translation, *world.get::<i32>(entity).unwrap())
+        };
+
+        let values: Vec<_> = (0..20).map(|_| spawn(&mut world)).collect();
+        for (translation, count) in values.iter() {
+            assert!((-5.0..5.0).contains(&translation.x));
+            assert_eq!(translation.y, 1.0);
+            assert!((0..4).contains(count));
+        }
+        assert!(values.iter().any(|(t, _)| t.x != values[0].0.x));
+
+        world.insert_resource(PrefabRng::with_seed(7));
+        assert_eq!(spawn(&mut world), values[0]);
+
+        let reg = world.get_resource::<PrefabRegistry>().unwrap();
+        let prefab = parse_prefab_str(input, &reg).unwrap();
+        assert!(prefab
+            .to_string()
+            .contains("translation: Vec3 { x: rand(-5.0..5.0), y: 1.0, z: 0.0 }"));
+        assert!(prefab.to_string().contains("count: rand_int(0..4)"));
+    }
+
+    #[test]
+    fn prefab_vars() {
+        use crate::{test_utils::*, PrefabVars};
+
+        let mut world = prefab_world();
+        world.insert_resource(PrefabVars::default());
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_type::<Stats>();
+            reg.register_type::<Transform>();
+        });
+
+        let input = "{
+            Stats { health: ${difficulty.health}, speed: 2.0, title: \"Level-${level}\" },
+            Transform { translation: Vec3 { x: ${spawn_x} } },
+        }";
+        {
+            let mut vars = world.get_resource_mut::<PrefabVars>().unwrap();
+            vars.set("difficulty.health", 150);
+            vars.set("level", 3);
+            vars.set("spawn_x", 4.5_f32);
+        }
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        let expected = Stats {
+            health: 150,
+            speed: 2.0,
+            title: "Level-3".to_string(),
+        };
+        assert_component(&world, entity, &expected);
+        assert_eq!(world.get::<Transform>(entity).unwrap().translation.x, 4.5);
+
+        // Unset variables leave the field unchanged.
+        world
+            .get_resource_mut::<PrefabVars>()
+            .unwrap()
+            .remove("difficulty.health");
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        assert_eq!(world.get::<Stats>(entity).unwrap().health, 0);
+
+        let reg = world.get_resource::<PrefabRegistry>().unwrap();
+        let prefab = parse_prefab_str(input, &reg).unwrap();
+        assert!(prefab.to_string().contains("health: ${difficulty.health},"));
+        assert!(prefab.to_string().contains("title: \"Level-${level}\","));
+    }
+
+    #[derive(Reflect)]
+    #[reflect(Component)]
+    struct Aim {
+        target: Entity,
+    }
+
+    impl Default for Aim {
+        fn default() -> Self {
+            Aim {
+                target: Entity::new(u32::MAX),
+            }
+        }
+    }
+
+    #[test]
+    fn localized_strings() {
+        use crate::{test_utils::*, PrefabLocalization};
+        use bevy::utils::HashMap;
+
+        let mut world = prefab_world();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_type::<Stats>();
+        });
+
+        let input = "{ Stats { title: tr(\"item.sword.name\") } }";
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        assert_eq!(world.get::<Stats>(entity).unwrap().title, "item.sword.name");
+
+        let mut english = HashMap::default();
+        english.insert("item.sword.name".to_string(), "Sword".to_string());
+        world.insert_resource(PrefabLocalization::new(english));
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        assert_eq!(world.get::<Stats>(entity).unwrap().title, "Sword");
+
+        let reg = world.get_resource::<PrefabRegistry>().unwrap();
+        let prefab = parse_prefab_str(input, reg).unwrap();
+        assert!(prefab
+            .to_string()
+            .contains("title: tr(\"item.sword.name\")"));
+    }
+
+    #[test]
+    fn entity_refs() {
+        use crate::{test_utils::*, SpawnPrefabCommands};
+        use bevy::ecs::system::CommandQueue;
+
+        let mut world = prefab_world();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_type::<Aim>();
+            reg.register_command_fn("Link", |props, world, entity| {
+                let to = *props.unwrap().get::<Entity>("to");
+                world.entity_mut(entity).insert(to);
+            });
+        });
+
+        let input = "Tank @tank {
+            children [
+                Gun { Aim { target: @turret }, Link!(to: @tank) },
+                Turret @turret {},
+            ],
+        }";
+        let check = |world: &World, tank: Entity| {
+            let children = world.get::<Children>(tank).unwrap();
+            let (gun, turret) = (children[0], children[1]);
+            assert_eq!(world.get::<Aim>(gun).unwrap().target, turret);
+            assert_eq!(*world.get::<Entity>(gun).unwrap(), tank);
+        };
+
+        let tank = spawn_prefab_str(&mut world, input).unwrap();
+        check(&world, tank);
+
+        let prefab =
+            parse_prefab_str(input, world.get_resource::<PrefabRegistry>().unwrap()).unwrap();
+        let mut queue = CommandQueue::default();
+        let tank = Commands::new(&mut queue, &world)
+            .spawn()
+            .insert_prefab(&prefab)
+            .id();
+        queue.apply(&mut world);
+        check(&world, tank);
+
+        assert!(prefab.to_string().starts_with("Tank @tank {"));
+        assert!(prefab.to_string().contains("target: @turret"));
+    }
+}
@@ -1,6 +1,10 @@
 //! Utility traits for easily retrieving values from [Reflect] components.
 
-use bevy::reflect::{DynamicStruct, GetTypeRegistration, Reflect, Struct};
+use bevy::{
+    asset::{Asset, Handle, HandleId},
+    reflect::{DynamicStruct, GetTypeRegistration, Reflect, Struct},
+    utils::Duration,
+};
 use thiserror::Error;
 
 /// A utility trait for easily casting [Reflect] components to an underlying type.
@@ -11,6 +15,10 @@ pub trait DynamicCast: Reflect {
     /// Downcast to `&mut T` and unwrap immediately. Will panic if given
     /// the wrong type.
     fn cast_mut<T: Reflect>(&mut self) -> &mut T;
+    /// Tries to downcast to `&T`.
+    fn try_cast_ref<T: Reflect>(&self) -> Result<&T, DynamicCastError>;
+    /// Tries to downcast to `&mut T`.
+    fn try_cast_mut<T: Reflect>(&mut self) -> Result<&mut T, DynamicCastError>;
 }
 
 impl DynamicCast for dyn Reflect {
@@ -21,6 +29,27 @@ impl DynamicCast for dyn Reflect {
     fn cast_mut<T: Reflect>(&mut self) -> &mut T {
         self.downcast_mut::<T>().unwrap()
     }
+
+    fn try_cast_ref<T: Reflect>(&self) -> Result<&T, DynamicCastError> {
+        let type_name = self.type_name().to_string();
+        self.downcast_ref::<T>().ok_or_else(|| {
+            DynamicCastError::WrongType(type_name, std::any::type_name::<T>().to_string())
+        })
+    }
+
+    fn try_cast_mut<T: Reflect>(&mut self) -> Result<&mut T, DynamicCastError> {
+        let type_name = self.type_name().to_string();
+        self.downcast_mut::<T>().ok_or_else(|| {
+            DynamicCastError::WrongType(type_name, std::any::type_name::<T>().to_string())
+        })
+    }
+}
+
+/// Errors returned from the [DynamicCast] trait.
+#[derive(Error, Debug)]
+pub enum DynamicCastError {
+    #[error("The type {0} failed to downcast into the type {1}")]
+    WrongType(String, String),
 }
 
 /// Errors returned from the [GetValue] trait.
@@ -30,6 +59,8 @@ pub enum GetValueError {
     FieldDoesntExist(String, String),
     #[error("The type {0} failed to downcast into the type {1}")]
     FailedCast(String, String),
+    #[error("Invalid field path {0}: {1}")]
+    InvalidPath(String, String),
 }
 
 /// A utility trait for easily retrieving the value of a field from a [DynamicStruct].
@@ -53,9 +84,108 @@ pub trait GetValue {
         &mut self,
         field_name: &str,
     ) -> Result<&mut T, GetValueError>;
+
+    /// Retrieves a reference to a nested value from a path such as `"style.size"` or
+    /// `"layers[0].tile"` and unwraps immediately. Will panic if given the wrong type or
+    /// the path is invalid.
+    fn get_path<T: Reflect>(&self, path: &str) -> &T;
+
+    /// Tries to retrieve a reference to a nested value from a path, see [GetValue::get_path].
+    fn try_get_path<T: Reflect>(&self, path: &str) -> Result<&T, GetValueError>;
+
+    /// Retrieves a mutable reference to a nested value from a path and unwraps immediately.
+    /// Will panic if given the wrong type or the path is invalid.
+    fn get_path_mut<T: Reflect>(&mut self, path: &str) -> &mut T;
+
+    /// Tries to retrieve a mutable reference to a nested value from a path.
+    fn try_get_path_mut<T: Reflect>(&mut self, path: &str) -> Result<&mut T, GetValueError>;
+
+    /// Retrieves a copy of the field value, or `default` if the field doesn't exist or
+    /// has the wrong type.
+    fn get_or<T: Reflect + GetTypeRegistration + Clone>(&self, field_name: &str, default: T) -> T {
+        match self.try_get::<T>(field_name) {
+            Ok(value) => value.clone(),
+            Err(_) => default,
+        }
+    }
+
+    /// Tries to retrieve an `f32` field. An `i32` value is converted, so `10` can be
+    /// written in place of `10.0`.
+    fn try_get_f32(&self, field_name: &str) -> Result<f32, GetValueError> {
+        match self.try_get::<f32>(field_name) {
+            Ok(f) => Ok(*f),
+            Err(e) => self
+                .try_get::<i32>(field_name)
+                .map(|i| *i as f32)
+                .map_err(|_| e),
+        }
+    }
+
+    /// Tries to retrieve a [Duration] field. A non-negative number is converted from seconds,
+    /// so `5.0` can be written in place of `5s`.
+    fn try_get_duration(&self, field_name: &str) -> Result<Duration, GetValueError> {
+        match self.try_get::<Duration>(field_name) {
+            Ok(d) => Ok(*d),
+            Err(e) => match self.try_get_f32(field_name) {
+                Ok(f) if f >= 0.0 => Ok(Duration::from_secs_f32(f)),
+                Ok(_) => Err(GetValueError::FailedCast(
+                    "negative f32".to_string(),
+                    "Duration".to_string(),
+                )),
+                Err(_) => Err(e),
+            },
+        }
+    }
+
+    /// Tries to retrieve an asset handle written as `asset("path")`. The handle is weak, the
+    /// asset is kept loaded by [crate::PrefabAssetHandles].
+    fn try_get_handle<T: Asset>(&self, field_name: &str) -> Result<Handle<T>, GetValueError> {
+        let value = self.try_get_path::<DynamicStruct>(field_name)?;
+        let id = value.try_get::<HandleId>("id")?;
+        Ok(Handle::weak(*id))
+    }
+
+    /// Tries to retrieve a `u32` field. A non-negative `i32` value is converted.
+    fn try_get_u32(&self, field_name: &str) -> Result<u32, GetValueError> {
+        match self.try_get::<u32>(field_name) {
+            Ok(u) => Ok(*u),
+            Err(e) => match self.try_get::<i32>(field_name) {
+                Ok(i) if *i >= 0 => Ok(*i as u32),
+                _ => Err(e),
+            },
+        }
+    }
 }
 
 impl GetValue for DynamicStruct {
+    fn get_path<T: Reflect>(&self, path: &str) -> &T {
+        self.try_get_path(path).unwrap()
+    }
+
+    fn try_get_path<T: Reflect>(&self, path: &str) -> Result<&T, GetValueError> {
+        let value = bevy::reflect::GetPath::path(self, path)
+            .map_err(|e| GetValueError::InvalidPath(path.to_string(), e.to_string()))?;
+        value.downcast_ref::<T>().ok_or_else(|| {
+            GetValueError::FailedCast(
+                value.type_name().to_string(),
+                std::any::type_name::<T>().to_string(),
+            )
+        })
+    }
+
+    fn get_path_mut<T: Reflect>(&mut self, path: &str) -> &mut T {
+        self.try_get_path_mut(path).unwrap()
+    }
+
+    fn try_get_path_mut<T: Reflect>(&mut self, path: &str) -> Result<&mut T, GetValueError> {
+        let value = bevy::reflect::GetPath::path_mut(self, path)
+            .map_err(|e| GetValueError::InvalidPath(path.to_string(), e.to_string()))?;
+        let type_name = value.type_name().to_string();
+        value.downcast_mut::<T>().ok_or_else(|| {
+            GetValueError::FailedCast(type_name, std::any::type_name::<T>().to_string())
+        })
+    }
+
     fn get<T: Reflect>(&self, field_name: &str) -> &T {
         self.field(field_name).unwrap().downcast_ref::<T>().unwrap()
     }
@@ -106,6 +236,86 @@ impl GetValue for DynamicStruct {
     }
 }
 
+/// Build a [DynamicStruct] from `name: value` pairs, ie: for passing properties to a
+/// [crate::build_commands::BuildPrefabCommand] from code.
+///
+/// Values can be any [Reflect] type. As in a *.prefab* file, string literals are converted
+/// to [String]s and float literals to `f32`s.
+///
+/// ## Example
+///
+/// ```
+/// use bevy::prelude::*;
+/// use bevy_lazy_prefabs::{dynamic_cast::GetValue, dynamic_struct};
+///
+/// let props = dynamic_struct! {
+///     color: Color::RED,
+///     texture_path: "alien.png",
+///     style: dynamic_struct! { size: 24.0 },
+/// };
+/// assert_eq!(props.get::<String>("texture_path"), "alien.png");
+/// assert_eq!(*props.get_path::<f32>("style.size"), 24.0);
+/// ```
+#[macro_export]
+macro_rules! dynamic_struct {
+    ($($name:ident : $value:expr),* $(,)?) => {{
+        #[allow(unused_imports)]
+        use $crate::dynamic_cast::macro_support::{TakePropertyLiteral, TakePropertyReflect};
+        #[allow(unused_mut)]
+        let mut props = $crate::dynamic_cast::macro_support::DynamicStruct::default();
+        $(
+            props.insert_boxed(
+                stringify!($name),
+                (&$crate::dynamic_cast::macro_support::Property::new($value)).take_property(),
+            );
+        )*
+        props
+    }};
+}
+
+/// Used by [dynamic_struct] to convert string and float literals.
+#[doc(hidden)]
+pub mod macro_support {
+    use std::cell::Cell;
+
+    pub use bevy::reflect::DynamicStruct;
+    use bevy::reflect::Reflect;
+
+    pub struct Property<T>(Cell<Option<T>>);
+
+    impl<T> Property<T> {
+        pub fn new(value: T) -> Self {
+            Property(Cell::new(Some(value)))
+        }
+    }
+
+    pub trait TakePropertyLiteral {
+        fn take_property(&self) -> Box<dyn Reflect>;
+    }
+
+    impl TakePropertyLiteral for Property<&str> {
+        fn take_property(&self) -> Box<dyn Reflect> {
+            Box::new(self.0.take().unwrap().to_string())
+        }
+    }
+
+    impl TakePropertyLiteral for Property<f64> {
+        fn take_property(&self) -> Box<dyn Reflect> {
+            Box::new(self.0.take().unwrap() as f32)
+        }
+    }
+
+    pub trait TakePropertyReflect {
+        fn take_property(&self) -> Box<dyn Reflect>;
+    }
+
+    impl<T: Reflect> TakePropertyReflect for &Property<T> {
+        fn take_property(&self) -> Box<dyn Reflect> {
+            Box::new(self.0.take().unwrap())
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -133,6 +343,19 @@ mod test {
         assert_eq!(a.q, 10);
     }
 
+    #[test]
+    fn try_cast() {
+        let mut a: Box<dyn Reflect> = Box::new(Test { i: 5, q: 10 });
+
+        assert_eq!(a.try_cast_ref::<Test>().unwrap().i, 5);
+        a.try_cast_mut::<Test>().unwrap().q = 20;
+        assert_eq!(a.cast_ref::<Test>().q, 20);
+
+        let err = a.try_cast_ref::<f32>().unwrap_err();
+        assert!(matches!(err, DynamicCastError::WrongType(_, to) if to == "f32"));
+        assert!(a.try_cast_mut::<i32>().is_err());
+    }
+
     #[test]
     fn mut_test() {
         let a = Test { i: 0, q: 0 };
@@ -146,6 +369,73 @@ mod test {
         assert_eq!(*a.get::<i32>("i"), 10i32);
     }
 
+    #[test]
+    fn coercion() {
+        let mut props = DynamicStruct::default();
+        props.insert("size", 10);
+        props.insert("scale", 0.5f32);
+        props.insert("count", -1);
+
+        assert_eq!(props.try_get_f32("size").unwrap(), 10.0);
+        assert_eq!(props.try_get_f32("scale").unwrap(), 0.5);
+        assert_eq!(props.try_get_u32("size").unwrap(), 10);
+        assert!(props.try_get_u32("count").is_err());
+        assert!(props.try_get_f32("missing").is_err());
+
+        assert_eq!(props.get_or("scale", 1.0f32), 0.5);
+        assert_eq!(props.get_or("missing", 1.0f32), 1.0);
+        assert_eq!(props.get_or("size", 1.0f32), 1.0);
+    }
+
+    #[test]
+    fn paths() {
+        use bevy::math::Vec2;
+        use bevy::reflect::DynamicList;
+
+        let mut layer = DynamicStruct::default();
+        layer.insert("offset", Vec2::new(1.0, 2.0));
+        let mut layers = DynamicList::default();
+        layers.push(layer);
+        let mut props = DynamicStruct::default();
+        props.insert("layers", layers);
+        props.insert("test", Test { i: 3, q: 4 });
+
+        assert_eq!(
+            *props.get_path::<Vec2>("layers[0].offset"),
+            Vec2::new(1.0, 2.0)
+        );
+        assert_eq!(*props.get_path::<i32>("test.q"), 4);
+
+        *props.get_path_mut::<i32>("test.i") = 7;
+        assert_eq!(props.get::<Test>("test").i, 7);
+
+        assert!(matches!(
+            props.try_get_path::<i32>("test.missing"),
+            Err(GetValueError::InvalidPath(..))
+        ));
+        assert!(matches!(
+            props.try_get_path_mut::<f32>("test.i"),
+            Err(GetValueError::FailedCast(..))
+        ));
+    }
+
+    #[test]
+    fn dynamic_struct_macro() {
+        let name = String::from("sword");
+        let props = crate::dynamic_struct! {
+            name: name,
+            count: 3,
+            scale: 0.5,
+            test: Test { i: 1, q: 2 },
+        };
+
+        assert_eq!(props.get::<String>("name"), "sword");
+        assert_eq!(*props.get::<i32>("count"), 3);
+        assert_eq!(*props.get::<f32>("scale"), 0.5);
+        assert_eq!(props.get::<Test>("test").q, 2);
+        assert_eq!(crate::dynamic_struct! {}.field_len(), 0);
+    }
+
     #[test]
     fn auto_cast() {
         let a = Test { i: 15, q: 25 };
@@ -0,0 +1,102 @@
+//! An egui window for browsing and spawning prefabs. Requires the `editor` feature.
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::{egui, EguiContext};
+
+use crate::{PrefabLoadFailed, PrefabRegistry, SpawnPrefabCommands, SpawnedFrom};
+
+/// Shows a window listing every prefab in the prefab directories, with a button to
+/// spawn each one, how many entities are currently spawned from it and any load errors.
+///
+/// Requires `bevy_egui::EguiPlugin`. Enables [PrefabRegistry::set_tag_spawned] so spawned
+/// entities can be counted.
+///
+/// ## Example
+///
+/// ```ignore
+/// App::build()
+///     .add_plugins(DefaultPlugins)
+///     .add_plugin(EguiPlugin)
+///     .add_plugin(LazyPrefabsPlugin::default())
+///     .add_plugin(PrefabEditorPlugin)
+///     .run();
+/// ```
+pub struct PrefabEditorPlugin;
+impl Plugin for PrefabEditorPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<PrefabEditorState>()
+            .add_startup_system(enable_tag_spawned.system())
+            .add_system(record_load_failures.system())
+            .add_system(prefab_editor_window.system());
+    }
+}
+
+#[derive(Default)]
+struct PrefabEditorState {
+    names: Option<Vec<String>>,
+    errors: Vec<PrefabLoadFailed>,
+}
+
+fn enable_tag_spawned(mut registry: ResMut<PrefabRegistry>) {
+    registry.set_tag_spawned(true);
+}
+
+fn record_load_failures(
+    mut state: ResMut<PrefabEditorState>,
+    mut events: EventReader<PrefabLoadFailed>,
+) {
+    state.errors.extend(events.iter().cloned());
+}
+
+fn prefab_editor_window(
+    egui_context: ResMut<EguiContext>,
+    mut state: ResMut<PrefabEditorState>,
+    mut registry: ResMut<PrefabRegistry>,
+    mut commands: Commands,
+    spawned: Query<&SpawnedFrom>,
+) {
+    let state = &mut *state;
+    let names = state.names.get_or_insert_with(|| registry.prefab_names());
+
+    let mut counts: HashMap<&str, usize> = HashMap::default();
+    for from in spawned.iter() {
+        *counts.entry(from.0.as_str()).or_default() += 1;
+    }
+
+    let mut spawn = None;
+    let mut refresh = false;
+    egui::Window::new("Prefabs").show(egui_context.ctx(), |ui| {
+        if ui.button("Refresh").clicked() {
+            refresh = true;
+        }
+        ui.separator();
+        for name in names.iter() {
+            ui.horizontal(|ui| {
+                if ui.button("Spawn").clicked() {
+                    spawn = Some(name.clone());
+                }
+                ui.label(name.as_str());
+                ui.label(format!("({})", counts.get(name.as_str()).unwrap_or(&0)));
+            });
+        }
+        if !state.errors.is_empty() {
+            ui.separator();
+            ui.heading("Errors");
+            for failed in state.errors.iter() {
+                let text = format!("{}: {}", failed.name, failed.error);
+                ui.colored_label(egui::Color32::RED, text);
+            }
+        }
+    });
+
+    if let Some(name) = spawn {
+        // Failures are reported through PrefabLoadFailed events.
+        if let Ok(prefab) = registry.load(&name) {
+            commands.spawn().insert_prefab(prefab);
+        }
+    }
+    if refresh {
+        state.names = None;
+        state.errors.clear();
+    }
+}
@@ -0,0 +1,234 @@
+use bevy::{app::Events, prelude::*};
+
+use crate::{
+    bevy_commands::{despawn_instances, move_to, tag_spawned},
+    build_commands::apply_prefab,
+    deferred::Anchors,
+    prefab::Prefab,
+    PrefabRegistry,
+};
+
+/// The entities spawned for a prefab: the root entity, and any entities in its hierarchy
+/// which were given an anchor, ie: `Turret @turret { .. }`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefabInstanceHandle {
+    entity: Entity,
+    anchors: Anchors,
+}
+
+impl PrefabInstanceHandle {
+    pub(crate) fn new(entity: Entity, anchors: Anchors) -> Self {
+        PrefabInstanceHandle { entity, anchors }
+    }
+
+    /// The root entity of the prefab.
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+
+    /// The entity spawned for the prefab with the given anchor, without the `@`.
+    pub fn anchor(&self, name: &str) -> Option<Entity> {
+        self.anchors.get(name).copied()
+    }
+
+    /// Every anchored entity in the prefab hierarchy, by anchor name.
+    pub fn anchors(&self) -> impl Iterator<Item = (&str, Entity)> {
+        self.anchors.iter().map(|(name, e)| (name.as_str(), *e))
+    }
+}
+
+/// An event sent when a prefab is spawned by name with `Commands`, such as with
+/// [crate::SpawnNamedPrefabCommands::spawn_prefab_at]. Use it to find the prefab's anchored
+/// child entities once the spawn has been applied.
+#[derive(Debug, Clone)]
+pub struct PrefabInstanceSpawned {
+    /// The name the prefab was spawned with.
+    pub name: String,
+    pub instance: PrefabInstanceHandle,
+}
+
+/// Send a [PrefabInstanceSpawned] event, if the app has registered the event.
+pub(crate) fn send_instance_spawned(world: &mut World, name: &str, instance: PrefabInstanceHandle) {
+    if let Some(mut events) = world.get_resource_mut::<Events<PrefabInstanceSpawned>>() {
+        events.send(PrefabInstanceSpawned {
+            name: name.to_string(),
+            instance,
+        });
+    }
+}
+
+/// Spawn prefabs directly into a [World], returning the spawned entities immediately.
+///
+/// Nothing is queued, so these work in any world regardless of whether it's part of an
+/// app: from exclusive systems, in a custom [Schedule](bevy::ecs::schedule::Schedule) run
+/// by hand, or in a separate world such as a headless server simulation or a test. The
+/// world only needs its own [PrefabRegistry], with the types its prefabs use registered.
+/// Unlike spawning with `Commands` no [PrefabInstanceSpawned] event is sent, since the
+/// handle is returned directly.
+///
+/// ## Example
+///
+/// ```
+/// use bevy::prelude::*;
+/// use bevy_lazy_prefabs::*;
+///
+/// fn spawn_tank(world: &mut World) {
+///     if let Some(tank) = world.spawn_prefab_at("tank.prefab", Vec3::new(5.0, 0.0, 0.0)) {
+///         let turret = tank.anchor("turret");
+///     }
+/// }
+///
+/// // A world which isn't part of the app, stepped with its own schedule.
+/// let mut world = World::new();
+/// let mut registry = PrefabRegistry::default();
+/// registry.replace_prefab("tank.prefab", "{ }").unwrap();
+/// world.insert_resource(registry);
+/// let mut schedule = Schedule::default();
+/// schedule.add_stage("update", SystemStage::single_threaded().with_system(spawn_tank.exclusive_system()));
+/// schedule.run(&mut world);
+/// assert_eq!(world.query::<&Transform>().iter(&world).count(), 1);
+/// ```
+pub trait SpawnPrefabWorldExt {
+    /// Spawn a prefab by name. Returns [None] if the prefab or the fallback prefab couldn't
+    /// be loaded, see [PrefabRegistry::set_fallback_prefab].
+    fn spawn_prefab(&mut self, name: &str) -> Option<PrefabInstanceHandle>;
+
+    /// Spawn a prefab by name and move it to `translation`, inserting a [Transform] if the
+    /// prefab doesn't have one.
+    fn spawn_prefab_at(&mut self, name: &str, translation: Vec3) -> Option<PrefabInstanceHandle>;
+
+    /// Despawn every entity spawned from the named prefab, along with its children.
+    ///
+    /// Entities are found by their [SpawnedFrom](crate::SpawnedFrom) component, so
+    /// [PrefabRegistry::set_tag_spawned] must be enabled when they're spawned.
+    fn despawn_prefab_instances(&mut self, name: &str);
+
+    /// Apply a prefab's build steps to an existing entity.
+    fn insert_prefab(&mut self, entity: Entity, prefab: &Prefab) -> PrefabInstanceHandle;
+}
+
+impl SpawnPrefabWorldExt for World {
+    fn spawn_prefab(&mut self, name: &str) -> Option<PrefabInstanceHandle> {
+        self.resource_scope(|world, mut registry: Mut<PrefabRegistry>| {
+            let prefab = registry.load_or_fallback(name)?;
+            let entity = world.spawn().id();
+            registry.record_spawn(name);
+            tag_spawned(&registry, world, entity, name, &prefab);
+            let anchors = apply_prefab(&registry, &prefab, world, entity);
+            Some(PrefabInstanceHandle::new(entity, anchors))
+        })
+    }
+
+    fn spawn_prefab_at(&mut self, name: &str, translation: Vec3) -> Option<PrefabInstanceHandle> {
+        let instance = self.spawn_prefab(name)?;
+        move_to(self, instance.entity(), translation);
+        Some(instance)
+    }
+
+    fn despawn_prefab_instances(&mut self, name: &str) {
+        despawn_instances(self, name);
+    }
+
+    fn insert_prefab(&mut self, entity: Entity, prefab: &Prefab) -> PrefabInstanceHandle {
+        self.resource_scope(|world, registry: Mut<PrefabRegistry>| {
+            let anchors = apply_prefab(&registry, prefab, world, entity);
+            PrefabInstanceHandle::new(entity, anchors)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::*;
+
+    use crate::PrefabRegistry;
+
+    #[test]
+    fn instance_handle() {
+        use crate::test_utils::*;
+        use crate::{PrefabInstanceSpawned, SpawnNamedPrefabCommands, SpawnPrefabWorldExt};
+        use bevy::{app::Events, ecs::system::CommandQueue};
+
+        let mut world = prefab_world();
+        world.insert_resource(Events::<PrefabInstanceSpawned>::default());
+        {
+            let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.register_type::<Stats>();
+            reg.register_type::<Transform>();
+            reg.replace_prefab(
+                "tank",
+                "{ Stats, children [ Turret @turret { Stats { health: 4 } } ] }",
+            )
+            .unwrap();
+        }
+
+        let tank = world.spawn_prefab("tank").unwrap();
+        let turret = tank.anchor("turret").unwrap();
+        assert_eq!(world.get::<Stats>(turret).unwrap().health, 4);
+        assert_eq!(world.get::<Parent>(turret).unwrap().0, tank.entity());
+        assert!(world.spawn_prefab("missing").is_none());
+
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        let entity = commands.spawn_prefab_at("tank", Vec3::X).id();
+        queue.apply(&mut world);
+
+        let events = world
+            .get_resource::<Events<PrefabInstanceSpawned>>()
+            .unwrap();
+        let spawned: Vec<_> = events.get_reader().iter(events).cloned().collect();
+        assert_eq!(spawned.len(), 1);
+        assert_eq!(spawned[0].name, "tank");
+        assert_eq!(spawned[0].instance.entity(), entity);
+        let turret = spawned[0].instance.anchor("turret").unwrap();
+        assert_eq!(world.get::<Parent>(turret).unwrap().0, entity);
+    }
+
+    #[test]
+    fn spawn_into_world() {
+        use crate::test_utils::*;
+        use crate::SpawnPrefabWorldExt;
+
+        // A server world which isn't part of any app, stepped with its own schedule.
+        let mut server = prefab_world();
+        {
+            let mut reg = server.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.register_type::<Stats>();
+            reg.register_type::<Transform>();
+            reg.replace_prefab("grunt", "{ Stats { health: 3 } }")
+                .unwrap();
+            reg.set_tag_spawned(true);
+        }
+
+        fn spawn_wave(world: &mut World) {
+            for x in 0..3 {
+                world.spawn_prefab_at("grunt", Vec3::X * x as f32).unwrap();
+            }
+        }
+        let mut schedule = Schedule::default();
+        schedule.add_stage(
+            "simulate",
+            SystemStage::single_threaded().with_system(spawn_wave.exclusive_system()),
+        );
+        schedule.run(&mut server);
+
+        let mut xs: Vec<_> = server
+            .query::<(&Stats, &Transform)>()
+            .iter(&server)
+            .map(|(stats, transform)| {
+                assert_eq!(stats.health, 3);
+                transform.translation.x
+            })
+            .collect();
+        xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(xs, vec![0.0, 1.0, 2.0]);
+
+        // A second world is unaffected and needs its own registry.
+        let mut other = prefab_world();
+        assert!(other.spawn_prefab("grunt").is_none());
+        assert_eq!(other.query::<&Stats>().iter(&other).count(), 0);
+
+        server.despawn_prefab_instances("grunt");
+        assert_eq!(server.query::<&Stats>().iter(&server).count(), 0);
+    }
+}
@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use bevy::reflect::{
+    serde::{ReflectDeserializer, ReflectSerializer},
+    DynamicStruct, Reflect, TypeRegistryInternal,
+};
+use serde::{de::DeserializeSeed, Deserialize, Serialize};
+use serde_json::value::{to_raw_value, RawValue};
+
+use crate::{
+    deferred::is_deferred,
+    parse::LoadPrefabError,
+    prefab::{Prefab, PrefabBuildStep, PrefabCommandData, PrefabComponent, PrefabMeta},
+    PrefabRegistry,
+};
+
+#[derive(Serialize, Deserialize)]
+struct JsonPrefab {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    anchor: Option<String>,
+    #[serde(default, skip_serializing_if = "PrefabMeta::is_empty")]
+    meta: PrefabMeta,
+    #[serde(default)]
+    steps: Vec<JsonStep>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum JsonStep {
+    Component {
+        type_name: String,
+        value: Box<RawValue>,
+    },
+    Command {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        properties: Option<Box<RawValue>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        flag: Option<String>,
+    },
+    Child(JsonPrefab),
+}
+
+impl Prefab {
+    /// Write the prefab as JSON. Requires the `json` feature.
+    ///
+    /// Component values and command properties are written with bevy's reflect
+    /// serializer, in the same format as a bevy scene file.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        let registry = TypeRegistryInternal::default();
+        serde_json::to_string_pretty(&to_json_prefab(self, &registry)?)
+    }
+
+    /// Read a prefab written by [Prefab::to_json]. Requires the `json` feature.
+    ///
+    /// Components in the prefab must be registered with the registry.
+    pub fn from_json(json: &str, registry: &PrefabRegistry) -> Result<Prefab, LoadPrefabError> {
+        let prefab: JsonPrefab = serde_json::from_str(json)?;
+        from_json_prefab(prefab, registry, &registry.reflect_type_registry())
+    }
+}
+
+fn to_json_prefab(
+    prefab: &Prefab,
+    registry: &TypeRegistryInternal,
+) -> Result<JsonPrefab, serde_json::Error> {
+    let to_value = |value: &dyn Reflect| to_raw_value(&ReflectSerializer::new(value, registry));
+    let mut steps = Vec::new();
+    for step in prefab.steps.iter() {
+        steps.push(match step {
+            PrefabBuildStep::AddComponent(comp) => JsonStep::Component {
+                type_name: comp.type_name.to_string(),
+                value: to_value(&*comp.reflect)?,
+            },
+            PrefabBuildStep::RunCommand(command) => JsonStep::Command {
+                name: command.name.clone(),
+                properties: match &command.properties {
+                    Some(props) => Some(to_value(props)?),
+                    None => None,
+                },
+                flag: command.flag.clone(),
+            },
+            PrefabBuildStep::AddChild(child) => JsonStep::Child(to_json_prefab(child, registry)?),
+        });
+    }
+    Ok(JsonPrefab {
+        name: prefab.name.clone(),
+        anchor: prefab.anchor.clone(),
+        meta: prefab.meta.clone(),
+        steps,
+    })
+}
+
+fn from_json_prefab(
+    prefab: JsonPrefab,
+    registry: &PrefabRegistry,
+    type_registry: &TypeRegistryInternal,
+) -> Result<Prefab, LoadPrefabError> {
+    // Read through a raw value, as bevy's deserializer expects the fields in the order
+    // they were written.
+    let from_value = |value: Box<RawValue>| {
+        let mut deserializer = serde_json::Deserializer::from_str(value.get());
+        ReflectDeserializer::new(type_registry).deserialize(&mut deserializer)
+    };
+    let mut steps = Vec::new();
+    for step in prefab.steps {
+        steps.push(match step {
+            JsonStep::Component { type_name, value } => {
+                let info = registry
+                    .get_type_data(&type_name)
+                    .ok_or_else(|| registry.unregistered_type_error(&type_name))?;
+                let reflect = from_value(value)?;
+                PrefabBuildStep::AddComponent(Arc::new(PrefabComponent {
+                    type_name: info.type_name.clone(),
+                    deferred: is_deferred(&*reflect),
+                    reflect,
+                }))
+            }
+            JsonStep::Command {
+                name,
+                properties,
+                flag,
+            } => {
+                let properties = match properties {
+                    Some(value) => match from_value(value)?.downcast::<DynamicStruct>() {
+                        Ok(props) => Some(*props),
+                        Err(_) => {
+                            let e = format!("{} properties", name);
+                            return Err(LoadPrefabError::ValueParseError(e, "JSON".to_string()));
+                        }
+                    },
+                    None => None,
+                };
+                let (name, deprecated_key) = registry.resolve_command_key(name);
+                PrefabBuildStep::RunCommand(Arc::new(PrefabCommandData {
+                    deferred: properties.as_ref().map_or(false, |p| is_deferred(p)),
+                    name,
+                    properties,
+                    deprecated_key,
+                    flag,
+                }))
+            }
+            JsonStep::Child(child) => PrefabBuildStep::AddChild(Arc::new(from_json_prefab(
+                child,
+                registry,
+                type_registry,
+            )?)),
+        });
+    }
+    Ok(Prefab {
+        name: prefab.name,
+        source: None,
+        anchor: prefab.anchor,
+        meta: prefab.meta,
+        steps,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::*;
+
+    use crate::{parse::parse_prefab_str, test_utils::Stats, PrefabRegistry};
+
+    #[test]
+    fn json_round_trip() {
+        use super::LoadPrefabError;
+        use crate::Prefab;
+
+        let mut reg = PrefabRegistry::default();
+        reg.register_type::<Stats>();
+        reg.register_type::<Transform>();
+        reg.register_command_fn("Emit", |_, _, _| {});
+
+        let input = "Enemy {
+            meta { tags: [\"enemy\"] },
+            Stats { health: 5, title: \"Orc\" },
+            Transform { translation: Vec3 { x: rand(0.0..1.0) } },
+            Emit!(rate: 2, style { color: Color::RED }),
+            children [ Gun @gun { Stats { speed: 1.5 } } ],
+        }";
+        let prefab = parse_prefab_str(input, &reg).unwrap();
+        let json = prefab.to_json().unwrap();
+        assert!(json.contains("\"type_name\": \"Stats\""));
+
+        let loaded = Prefab::from_json(&json, &reg).unwrap();
+        assert_eq!(loaded.to_string(), prefab.to_string());
+        assert!(loaded.components().nth(1).unwrap().is_deferred());
+
+        let reg = PrefabRegistry::default();
+        assert!(matches!(
+            Prefab::from_json(&json, &reg),
+            Err(LoadPrefabError::UnregisteredPrefabComponent(..))
+        ));
+        assert!(matches!(
+            Prefab::from_json("{ steps: 5 }", &reg),
+            Err(LoadPrefabError::JsonError(_))
+        ));
+    }
+}
@@ -0,0 +1,203 @@
+use std::sync::Arc;
+
+use bevy::{
+    ecs::system::Command, prelude::*, transform::hierarchy::despawn_with_children_recursive,
+};
+
+use crate::{
+    bevy_commands::tag_spawned,
+    build_commands::apply_prefab,
+    instance::{send_instance_spawned, PrefabInstanceHandle},
+    prefab::Prefab,
+    PrefabRegistry,
+};
+
+/// A list of prefab instances loaded from a *.level* file, see [PrefabRegistry::load_level].
+///
+/// ```ignore
+/// // level1.level
+/// Player: "player.prefab" { Transform { translation: Vec3 { x: -5.0 } } },
+/// Grunt1: "enemy_grunt.prefab" { Transform { translation: Vec3 { x: 5.0 } }, Health { max: 3 } },
+/// "tree.prefab",
+/// ```
+#[derive(Debug)]
+pub struct Level {
+    pub(crate) instances: Vec<LevelInstance>,
+}
+
+impl Level {
+    /// The prefab instances in the level, in the order they're spawned.
+    pub fn instances(&self) -> &[LevelInstance] {
+        &self.instances
+    }
+}
+
+/// A single prefab instance in a [Level].
+#[derive(Debug)]
+pub struct LevelInstance {
+    pub(crate) name: Option<String>,
+    pub(crate) prefab: String,
+    pub(crate) overrides: Option<Arc<Prefab>>,
+}
+
+impl LevelInstance {
+    /// The instance's name. Spawned instances are given a `Name` component with this value.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The name of the prefab to spawn.
+    pub fn prefab(&self) -> &str {
+        &self.prefab
+    }
+
+    /// Components and commands applied after the prefab to customize the instance.
+    pub fn overrides(&self) -> Option<&Prefab> {
+        self.overrides.as_deref()
+    }
+}
+
+/// A component identifying the *.level* file an entity was spawned from. Inserted on every
+/// instance spawned by [crate::SpawnNamedPrefabCommands::spawn_level].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Reflect)]
+#[reflect(Component)]
+pub struct SpawnedFromLevel(pub String);
+
+pub(crate) struct SpawnLevelCommand {
+    pub name: String,
+}
+
+impl Command for SpawnLevelCommand {
+    fn write(self: Box<Self>, world: &mut World) {
+        world.resource_scope(|world, mut registry: Mut<PrefabRegistry>| {
+            let level = match registry.load_level(&self.name) {
+                Ok(level) => level.clone(),
+                Err(e) => {
+                    warn!("Unable to spawn level {}: {}", self.name, e);
+                    return;
+                }
+            };
+
+            for instance in level.instances.iter() {
+                let prefab = match registry.load_or_fallback(&instance.prefab) {
+                    Some(prefab) => prefab,
+                    None => continue,
+                };
+                let entity = world
+                    .spawn()
+                    .insert(SpawnedFromLevel(self.name.clone()))
+                    .id();
+                registry.record_spawn(&instance.prefab);
+                tag_spawned(&registry, world, entity, &instance.prefab, &prefab);
+                let mut anchors = apply_prefab(&registry, &prefab, world, entity);
+                if let Some(overrides) = &instance.overrides {
+                    anchors.extend(apply_prefab(&registry, overrides, world, entity));
+                }
+                if let Some(name) = &instance.name {
+                    world.entity_mut(entity).insert(Name::new(name.clone()));
+                }
+                let handle = PrefabInstanceHandle::new(entity, anchors);
+                send_instance_spawned(world, &instance.prefab, handle);
+            }
+        });
+    }
+}
+
+pub(crate) struct DespawnLevelCommand {
+    pub name: String,
+}
+
+impl Command for DespawnLevelCommand {
+    fn write(self: Box<Self>, world: &mut World) {
+        let entities: Vec<_> = world
+            .query::<(Entity, &SpawnedFromLevel)>()
+            .iter(world)
+            .filter(|(_, level)| level.0 == self.name)
+            .map(|(entity, _)| entity)
+            .collect();
+        despawn_all(world, entities);
+    }
+}
+
+/// Despawn the entities and their children, skipping any already despawned as a child of another.
+pub(crate) fn despawn_all(world: &mut World, entities: Vec<Entity>) {
+    for entity in entities {
+        if world.get_entity(entity).is_some() {
+            despawn_with_children_recursive(world, entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::*;
+
+    use crate::PrefabRegistry;
+
+    #[test]
+    fn spawn_level() {
+        use crate::{test_utils::*, SpawnNamedPrefabCommands};
+        use bevy::ecs::system::CommandQueue;
+
+        let tmp = TempDir::new("spawn_level");
+        let dir = tmp.path();
+        std::fs::write(
+            dir.join("grunt.prefab"),
+            "{ Stats { health: 5, speed: 1.0 } }",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("test.level"),
+            "// Instances can be named and customized
+            \"grunt.prefab\"
+            Boss: \"grunt.prefab\" { Stats { health: 50 }, Transform }",
+        )
+        .unwrap();
+
+        let mut world = prefab_world();
+        {
+            let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.set_prefab_dir(&dir);
+            reg.register_type::<Stats>();
+            reg.register_type::<Transform>();
+            let level = reg.load_level("test.level").unwrap();
+            assert_eq!(level.instances().len(), 2);
+            assert_eq!(level.instances()[0].name(), None);
+            assert_eq!(level.instances()[1].name(), Some("Boss"));
+            assert_eq!(level.instances()[1].prefab(), "grunt.prefab");
+            assert!(reg.load_level("missing.level").is_err());
+        }
+
+        let mut queue = CommandQueue::default();
+        Commands::new(&mut queue, &world).spawn_level("test.level");
+        queue.apply(&mut world);
+
+        let mut spawned: Vec<_> = world
+            .query::<(&Stats, Option<&Name>, Option<&Transform>)>()
+            .iter(&world)
+            .map(|(stats, name, transform)| {
+                (
+                    stats.health,
+                    stats.speed,
+                    name.map(|n| n.as_str().to_string()),
+                    transform.is_some(),
+                )
+            })
+            .collect();
+        spawned.sort_by_key(|(health, ..)| *health);
+        assert_eq!(
+            spawned,
+            [
+                (5, 1.0, None, false),
+                (50, 1.0, Some("Boss".to_string()), true)
+            ]
+        );
+
+        Commands::new(&mut queue, &world).despawn_level("other.level");
+        queue.apply(&mut world);
+        assert_eq!(world.query::<&Stats>().iter(&world).count(), 2);
+        Commands::new(&mut queue, &world).despawn_level("test.level");
+        queue.apply(&mut world);
+        assert_eq!(world.query::<&Stats>().iter(&world).count(), 0);
+    }
+}
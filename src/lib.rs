@@ -1,7 +1,7 @@
 //! [![License: MIT](https://img.shields.io/badge/License-MIT-yellow.svg)](https://opensource.org/licenses/MIT)
 //! [![Crates.io](https://img.shields.io/crates/v/bevy_lazy_prefabs)](https://crates.io/crates/bevy_lazy_prefabs)
 //! [![docs](https://docs.rs/bevy_lazy_prefabs/badge.svg)](https://docs.rs/bevy_lazy_prefabs/)
-//! 
+//!
 //!  A crate for simple human readable/writable prefab text files in bevy.
 //!
 //! Note: This is not intended to be a flexible and long-term prefab solution, but should serve well for
@@ -30,11 +30,82 @@
 //!
 //! Custom components will only work in prefabs if they derive `Reflect` and `Default`, and if they have the
 //! `#[reflect(Component)]` attribute. Most built in bevy types already meet this constraint. They must also be
-//! registered with the [PrefabRegistry] during setup.
+//! registered with the [PrefabRegistry] during setup. Components registered with `register_component` are also removed
+//! from spawned entities if they're deleted from a prefab that's reloaded while the game is running.
+//! `register_component_and_deps` also registers the struct types of the component's fields, as long as bevy's
+//! `TypeRegistry` knows about them. A game's types can also be registered in one place along with the built-in ones with
+//! `LazyPrefabsPlugin::new().with_type::<Health>()`, or `with_registration(|registry| { .. })` for anything else.
+//!
+//! Types using `#[reflect_value]` can be given a string or number literal, ie: `ItemId("sku-42")` or `id: "sku-42"`,
+//! if they're registered with `register_value_type` for types implementing `FromStr`, or `register_value_parser`.
+//!
+//! Components which are lists are given their elements as an array, ie: `Waypoints [1, 4, 9]`, and maps are given
+//! their entries as fields, ie: `Speeds { walk: 2.0, run: 5.5 }`. Map entries are added to any the component already has.
+//!
+//! Integers are converted to the type of the field they're written to, so fields such as a `u8` sprite index or a `u64`
+//! id can be written as plain numbers. A value which doesn't fit in the field's type is an error when the prefab is loaded.
+//! Random values and variables are converted when they're evaluated, and skipped with a warning if they don't fit.
+//!
+//! Ranges can be written as `(0..4)`, `(1..=5)` or `(0.5..2.0)`. They're converted to the type of range stored in the field,
+//! ie: a `Range<u8>` or `Range<f32>`. Inclusive ranges end one past their last value, so they're only allowed for integers.
 //!
 //! The above prefab isn't much use though - the entity won't be rendered since it has no mesh or material.
 //! For that we can use a [build_commands::BuildPrefabCommand].
 //!
+//! Random values can be written as `rand(-5.0..5.0)` for floats or `rand_int(0..4)` for integers. They're
+//! evaluated each time the prefab is spawned, so every instance gets its own value. Insert a seeded `PrefabRng`
+//! resource to make them deterministic.
+//!
+//! Text can be localized with `tr("key")`. The key is looked up in the `PrefabLocalization` resource each time
+//! the prefab is spawned, see `Localize`.
+//!
+//! Assets can be requested with `asset("path")`, ie: `Model { mesh: asset("models/rock.glb#Mesh0") }`. The asset is loaded
+//! through the `AssetServer` when the prefab is spawned, so prefabs can be parsed without a `World`. The request can be
+//! set on any `Handle<T>` field, and build commands can read it with `properties.try_get_handle::<T>("mesh")`. Loaded
+//! assets are kept alive by the `PrefabAssetHandles` resource.
+//!
+//! Numbers can have a unit. `90deg` and `0.25turn` are converted to radians. `16px` and `50%` become a UI `Val`,
+//! or a plain `f32` if that's what the field expects. `2.5s` and `300ms` become a `Duration`. Booleans are written
+//! as `true` or `false`.
+//!
+//! A `Val` can also be written as `Px(10)`, `Percent(50)`, `Auto` or `Undefined`. With the `ui` feature the common
+//! types plugin registers `Style` along with `Rect` and `Size` of `Val`, so UI layout can be authored in prefabs, ie:
+//! `Style { margin: Rect { left: Px(10), right: 5% }, size: Size { width: Auto, height: Percent(50) } }`.
+//!
+//! Values can also be read from the `PrefabVars` resource with `${name}`, ie: `Health { max: ${enemy_health} }`.
+//! Like random values they're read each time the prefab is spawned.
+//!
+//! Values set with `registry.set_config_value("quality", "high")` and environment variables written as `${env:NAME}`
+//! are instead substituted into the text when the prefab is loaded, so they can be used anywhere, ie:
+//! `"textures/${quality}/rock.png"`.
+//!
+//! Child entities can be declared inside a `children` block. Each child is written like any other prefab:
+//!
+//! ```ignore
+//! Parent {
+//!     Transform,
+//!     children [
+//!         { Transform, Visible },
+//!         Child { Transform },
+//!     ],
+//! }
+//! ```
+//!
+//! A prefab can describe itself with a `meta` block, ie: `meta { tags: ["enemy"], category: "units", description: ".." }`.
+//! It doesn't affect spawned entities, but cached prefabs can be found by tag with `registry.find_by_tag("enemy")`.
+//!
+//! Entities in the hierarchy can be given an anchor, such as `Turret @turret { .. }`. Components and commands
+//! can then refer to that entity with `@turret`, which is set to the spawned `Entity` when the prefab is spawned:
+//!
+//! ```ignore
+//! Tank {
+//!     children [
+//!         Gun { Aim { target: @turret } },
+//!         Turret @turret { Transform },
+//!     ],
+//! }
+//! ```
+//!
 //! # BuildPrefabCommands
 //!
 //! Build commands allow you to include complex components that require extra steps to correctly initialize,
@@ -42,11 +113,29 @@
 //!
 //! Custom commands can be authored, but there are several included for more common components:
 //! - `InsertSpriteBundle` - Inserts a `SpriteBundle` on an entity. Can specify `color` and `texture_path`.
-//! - `SetColorMaterial` - Modify an existing `ColorMaterial` on the entity.
+//! - `SetColorMaterial` - Modify an existing `ColorMaterial` on the entity, once it has loaded.
+//! - `InsertTextureAtlas` - Inserts a `Handle<TextureAtlas>` for a grid atlas, from `texture_path`, `tile_size`,
+//!   `columns` and `rows`. Identical atlases are shared.
 //! - `LoadPrefab` - Load an existing prefab and perform it's build steps on the current entity.
+//! - `SpawnTileMap` - Spawns child prefabs from rows of characters in `map` and a `legend` mapping characters to
+//!   prefab names.
+//! - `InsertTimer` - Inserts a `Timer` lasting `seconds`, which can be a duration value like `500ms`. Can specify
+//!   `repeating`.
+//! - `DespawnAfter` / `despawn_after` - Despawns the entity and its children once `seconds` have passed.
 //! - `InsertPbrBundle` - Inserts a `PbrBundle`. Can specify mesh `shape`, `size`, and `flip`.
 //! - `InsertOrthographicCameraBundle` - Inserts an `OrthographicCameraBundle`. Can specify `scale`.
 //! - `InsertPerspectiveCameraBundle` - Inserts a `PerspectiveCameraBundle`. Can specify `position` and `looking_at`.
+//! - `InsertText2dBundle` / `InsertTextBundle` - Inserts a `Text2dBundle` or UI `TextBundle`. Can specify `text`
+//!   and a `style { font: "fonts/Fira.ttf", size: 24.0, color: #ffffff }` property group.
+//! - `InsertShaderPipeline` - Inserts a `MeshBundle` rendered with custom `vertex` and `fragment` shaders.
+//! - `LoadMesh` / `LoadStandardMaterial` - Load an asset from `path` and insert its handle. More asset types can be
+//!   added with `PrefabAppBuilderExt::register_prefab_asset`.
+//! - `InsertRigidBody` / `InsertCollider` - Insert bevy_rapier3d physics bundles. Can specify body type, collider
+//!   `shape`, `size` and `mass`. Requires the `rapier` feature.
+//! - `InsertSpriteAnimation` - Inserts a `SpriteAnimation` which plays `frames` from a texture atlas at `fps`.
+//!   Requires the `animation` feature.
+//! - `InsertTilemapLayer` - Adds a bevy_ecs_tilemap layer from a `texture_path`, `tile_size`, `map_size` and `fill` tile.
+//!   Requires the `tilemap` feature.
 //!
 //!
 //! ## Example
@@ -65,7 +154,34 @@
 //!
 //! Note these 'fields' are not referring directly to fields in the bundle, but are optional properties that get passed
 //! to the build command and used in the initialization process. How these properties get used is defined by every
-//! individual build command.
+//! individual build command. Properties can be nested as
+//! `{ .. }` structs and `[ .. ]` lists, ie: `layers: [{ tile: 1 }, { tile: 2 }]`.
+//!
+//! Simple bundles don't need a command. A bundle registered with `registry.register_bundle::<SpriteBundle>()` can be
+//! written as `bundle SpriteBundle { Sprite { .. }, Transform { .. } }`, which inserts the bundle with its default values
+//! and then applies the listed components. The plugin registers bevy's bundles, such as `SpriteBundle`, `PbrBundle` and
+//! `NodeBundle`, for each enabled feature.
+//!
+//! Build steps run in phases: commands that insert bundles (such as `InsertSpriteBundle`) always run first,
+//! followed by components and other commands in the order they are written, followed by commands that modify
+//! existing components (such as `SetColorMaterial`). Custom commands can choose their phase by implementing
+//! `BuildPrefabCommand::phase`. Within a phase, components, their fields and commands are always applied in the order
+//! they're written. A command which must run relative to another can be given a `before` or `after` property naming
+//! it, ie: `SetColorMaterial!(color: Color::RED, after: "LoadTexture")`, which overrides the phases.
+//!
+//! Commands can be gated on runtime flags, such as quality settings chosen from a settings menu, by wrapping them in
+//! an `if` block, ie: `if "high_quality" { InsertPbrBundle!(shape: "sphere") }`. They only run while the flag is set in
+//! the `PrefabFlags` resource, with `flags.set("high_quality", true)`. Flags are checked each time a prefab is spawned.
+//!
+//! Project wide defaults for a command's properties can be set with `registry.set_command_defaults("InsertSpriteBundle",
+//! r#"filter: "nearest""#)` or `LazyPrefabsPlugin::with_command_defaults`. Properties written in a prefab file take precedence.
+//! A renamed command can keep its old key with `registry.alias_command("SpriteBundle", "InsertSpriteBundle")`, or
+//! `deprecate_command_alias` to also warn about each prefab still using it.
+//! `registry.command_keys()` lists the registered commands. Registering a key twice replaces the command with a
+//! warning by default, which can be changed to a panic or a silent override with `set_command_conflict_policy`.
+//!
+//! A command that fails, such as from a missing property or resource, or that isn't registered, returns a `PrefabCommandError` instead of
+//! panicking. The error is logged and sent as a `PrefabCommandFailed` event, and the rest of the prefab is still applied.
 //!
 //! # Spawning A Prefab
 //!
@@ -83,17 +199,131 @@
 //!   commands.spawn().insert_prefab(cam);
 //!  }
 //! ```
+//!
+//! With direct access to the `World`, `world.spawn_prefab("tank.prefab")` spawns a prefab immediately and returns a
+//! `PrefabInstanceHandle` with the root entity and its anchored children, ie: `tank.anchor("turret")`. Prefabs spawned by
+//! name with `Commands` send a `PrefabInstanceSpawned` event with the same handle.
+//!
+//! The `World` methods don't go through the app's command queue, so they also work in exclusive systems, custom
+//! `Schedule`s run by hand, and separate worlds such as a headless server simulation. Each world needs its own
+//! `PrefabRegistry`.
+//!
+//! Several named prefabs can be kept in a single library file. After calling `registry.load_library("items.prefabs")`
+//! each prefab in the file can be loaded by name, ie: `registry.load("items.prefabs#HealthPotion")`.
+//!
+//! A *.level* file lists prefab instances to spawn together, each with an optional name and overrides which are
+//! applied after the prefab. Spawn them all with `commands.spawn_level("level1.level")` and remove them again with
+//! `commands.despawn_level("level1.level")`:
+//!
+//! ```ignore
+//! Player: "player.prefab" { Transform { translation: Vec3 { x: -5.0 } } },
+//! Boss: "enemy_grunt.prefab" { Health { max: 50 } },
+//! "tree.prefab",
+//! ```
+//!
+//! A *.table* file lists prefabs with weights, ie: `"coin.prefab": 10, "sword.prefab": 0.5`. Pick one and spawn it
+//! with `commands.spawn_from_table("loot.table", &mut rng)`, using the `PrefabRng` resource, or load it with
+//! `registry.load_table` and call `PrefabTable::pick` directly.
+//!
+//! Prefab names written inside a file, such as in `LoadPrefab`, a tile map legend, a *.level* or a *.table*, are resolved
+//! relative to that file. In *enemies/goblin.prefab*, `"base.prefab"` is looked for as *enemies/base.prefab* before
+//! *base.prefab*, and `"../shared/hat.prefab"` refers to *shared/hat.prefab*. Fallback directories can be searched with
+//! `registry.set_resolver(RelativeResolver::default().with_fallback("common"))`, or replace the rules entirely with a
+//! custom `PrefabResolver`.
+//!
+//! Spawning hundreds of prefabs in one frame can cause a hitch. Push them to the `PrefabSpawnQueue` resource instead
+//! and they'll be spawned over several frames within a `SpawnBudget`, with a `PrefabBatchSpawned` event sent when each
+//! batch is done. A single very large prefab can be applied over several frames with `commands.spawn_prefab_streamed`.
+//!
+//! Loaded prefabs stay cached until they're unloaded with `registry.unload_prefab`, `unload_by_prefix("levels/")` or
+//! `unload_all`. `app.unload_prefabs_on::<LowMemory>()` unloads every prefab read from a file whenever the given event is
+//! sent, such as a memory warning or a level transition. They're read again the next time they're spawned.
+//!
+//! With `LazyPrefabsPlugin::with_usage_stats` the registry counts how often each prefab is spawned and when it was
+//! last spawned. `registry.usage_stats()` lists them, including cached prefabs which are never spawned.
+//!
+//! To find out why an entity doesn't look the way it should, `registry.set_explain_spawns(true)` logs every build step as
+//! prefabs are spawned: the field values applied to each component and the fields left as default, each build command with
+//! its properties and which of them came from the command defaults, and anything skipped or inserted as a companion.
+//! `registry.explain("goblin.prefab")` returns the same description as text without spawning anything.
+//!
+//! `registry.snapshot()` records which prefabs are loaded along with a hash of their contents. Store it with a save game
+//! or replay and pass it to `registry.restore_snapshot` on load to find any prefabs that have changed since.
+//!
+//! Prefab files are read synchronously when they're first loaded. On wasm this isn't possible, so prefabs must be loaded
+//! in the background with `registry.preload(&asset_server, "sprite.prefab")` before they can be spawned. A `Handle<PrefabText>`
+//! loaded through the `AssetServer`, such as one referenced by another asset, can be spawned with
+//! `commands.spawn_prefab_handle(handle)`. The prefab is applied to the entity once the asset has loaded.
+//!
+//! The 2D, 3D and UI commands are behind the `2d`, `3d` and `ui` features, which are enabled by default. A headless
+//! server can disable default features to spawn prefabs without pulling in bevy's renderer. Prefabs which use a `Color`
+//! or a `px` value will then fail to load with a `MissingFeature` error.
 
+#[cfg(feature = "animation")]
+mod animation;
+mod asset_path;
 mod bevy_commands;
-mod dynamic_cast;
+mod deferred;
+mod instance;
+#[cfg(feature = "json")]
+mod json;
+mod level;
+mod lifetime;
+mod lint;
 mod parse;
+#[cfg(feature = "rapier")]
+mod physics;
 mod plugin;
+mod pool;
 mod prefab;
+mod preload;
 mod registry;
+#[cfg(feature = "rhai")]
+mod script;
+mod snapshot;
+mod spawn_queue;
+mod stream;
+mod table;
+#[cfg(feature = "tilemap")]
+mod tilemap;
+mod writer;
 
 pub mod build_commands;
+pub mod dynamic_cast;
+#[cfg(feature = "editor")]
+pub mod editor;
+pub mod resolve;
+pub mod source;
+pub mod test_utils;
 
-pub use bevy_commands::SpawnPrefabCommands;
-pub use plugin::LazyPrefabsPlugin;
-pub use prefab::Prefab;
-pub use registry::PrefabRegistry;
+#[cfg(feature = "animation")]
+pub use animation::SpriteAnimation;
+pub use asset_path::PrefabAssetPath;
+pub use bevy_commands::{SpawnNamedPrefabCommands, SpawnPrefabCommands};
+pub use build_commands::PrefabCommandFailed;
+pub use deferred::{
+    Localize, PrefabAssetHandles, PrefabFlags, PrefabLocalization, PrefabRng, PrefabVars,
+};
+pub use instance::{PrefabInstanceHandle, PrefabInstanceSpawned, SpawnPrefabWorldExt};
+pub use level::{Level, LevelInstance, SpawnedFromLevel};
+pub use lifetime::PrefabLifetime;
+pub use lint::{LintEntity, PrefabLint};
+pub use parse::{check_syntax, parse_prefab_str, LoadPrefabError};
+pub use plugin::{LazyPrefabsPlugin, PrefabAppBuilderExt};
+pub use pool::{PoolInactive, PooledPrefab, PooledPrefabCommands, PrefabPool};
+pub use prefab::{
+    Prefab, PrefabBuildStep, PrefabCommandData, PrefabComponent, PrefabMeta, SpawnedFrom,
+};
+pub use preload::PrefabText;
+pub use registry::{
+    CommandConflictPolicy, PrefabCachePolicy, PrefabCacheStats, PrefabChanged, PrefabLoadFailed,
+    PrefabMigration, PrefabRegistry, PrefabUsage,
+};
+pub use snapshot::{PrefabSnapshot, SnapshotMismatch};
+pub use spawn_queue::{
+    PrefabBatchId, PrefabBatchSpawned, PrefabSpawnQueue, QueuedPrefab, SpawnBudget,
+};
+pub use stream::PrefabStreamProgress;
+pub use table::PrefabTable;
+#[cfg(feature = "tilemap")]
+pub use tilemap::{PendingTilemapLayers, TilemapLayer};
@@ -0,0 +1,93 @@
+use bevy::{prelude::*, reflect::DynamicStruct};
+
+use crate::{
+    build_commands::{BuildPrefabCommand, PrefabCommandError},
+    dynamic_cast::*,
+};
+
+/// Despawns the entity and its children once the timer finishes. Inserted by [DespawnAfter].
+#[derive(Debug, Clone)]
+pub struct PrefabLifetime(pub Timer);
+
+/// Inserts a [PrefabLifetime], despawning the entity and its children after a time. Can also
+/// be written as `despawn_after`.
+///
+/// ### Required Property:
+///
+/// - `seconds` - How long the entity lives, in seconds or as a duration value, ie: `500ms`.
+///
+/// ### Example
+///
+/// ```ignore
+/// Bullet {
+///     Transform,
+///     despawn_after!(seconds: 5.0),
+/// }
+/// ```
+#[derive(Default)]
+pub struct DespawnAfter;
+impl BuildPrefabCommand for DespawnAfter {
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
+        let props = properties
+            .filter(|props| props.field("seconds").is_some())
+            .ok_or_else(|| PrefabCommandError::MissingProperty("seconds".to_string()))?;
+        let duration = props.try_get_duration("seconds").map_err(|e| {
+            PrefabCommandError::InvalidProperty("seconds".to_string(), e.to_string())
+        })?;
+        world
+            .entity_mut(entity)
+            .insert(PrefabLifetime(Timer::new(duration, false)));
+        Ok(())
+    }
+
+    fn key(&self) -> &str {
+        "DespawnAfter"
+    }
+}
+
+pub(crate) fn despawn_expired(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut PrefabLifetime)>,
+) {
+    for (entity, mut lifetime) in query.iter_mut() {
+        if lifetime.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::*;
+
+    use crate::PrefabRegistry;
+
+    #[test]
+    fn despawn_after() {
+        use crate::build_commands::DespawnAfter;
+        use crate::lifetime::despawn_expired;
+        use bevy::ecs::schedule::{Stage, SystemStage};
+
+        let mut world = World::new();
+        world.insert_resource(Time::default());
+        let mut reg = PrefabRegistry::default();
+        reg.register_build_command::<DespawnAfter>();
+        reg.alias_command("despawn_after", "DespawnAfter");
+        world.insert_resource(reg);
+
+        let input = "{ despawn_after!(seconds: 0s), children [ { }, { } ] }";
+        let entity = crate::test_utils::spawn_prefab_str(&mut world, input).unwrap();
+        assert!(world.get::<crate::PrefabLifetime>(entity).is_some());
+        assert_eq!(world.entities().len(), 3);
+
+        let mut stage = SystemStage::single(despawn_expired.system());
+        stage.run(&mut world);
+        assert_eq!(world.entities().len(), 0);
+    }
+}
@@ -0,0 +1,129 @@
+use crate::{
+    build_commands::BuildPhase,
+    prefab::{Prefab, PrefabBuildStep},
+    PrefabRegistry,
+};
+
+/// A check run on each entity of a prefab when it's loaded. Returns a warning if
+/// something looks wrong, see [crate::PrefabRegistry::add_lint].
+pub type PrefabLint = Box<dyn Fn(&LintEntity) -> Option<String> + Send + Sync + 'static>;
+
+/// The components and build commands declared for a single entity in a prefab.
+#[derive(Debug, Default)]
+pub struct LintEntity<'a> {
+    /// The registered names of the entity's components.
+    pub components: Vec<&'a str>,
+    /// The names of the entity's build commands.
+    pub commands: Vec<&'a str>,
+    /// The names of the entity's build commands which insert a bundle, ie: run in
+    /// [BuildPhase::Bundle].
+    pub bundles: Vec<&'a str>,
+}
+
+impl LintEntity<'_> {
+    pub fn has_component(&self, name: &str) -> bool {
+        self.components.contains(&name)
+    }
+
+    pub fn has_command(&self, name: &str) -> bool {
+        self.commands.contains(&name)
+    }
+}
+
+/// Run every lint on the prefab and its children.
+pub(crate) fn lint_prefab(
+    lints: &[PrefabLint],
+    registry: &PrefabRegistry,
+    prefab: &Prefab,
+    warnings: &mut Vec<String>,
+) {
+    let mut entity = LintEntity::default();
+    for step in prefab.steps.iter() {
+        match step {
+            PrefabBuildStep::AddComponent(comp) => entity.components.push(&comp.type_name),
+            PrefabBuildStep::RunCommand(command) => {
+                entity.commands.push(&command.name);
+                let phase = registry
+                    .get_build_command(&command.name)
+                    .map(|command| command.phase());
+                if phase == Some(BuildPhase::Bundle) {
+                    entity.bundles.push(&command.name);
+                }
+            }
+            PrefabBuildStep::AddChild(child) => lint_prefab(lints, registry, child, warnings),
+        }
+    }
+    warnings.extend(lints.iter().filter_map(|lint| lint(&entity)));
+}
+
+/// Warns about entities with rendering components that will never be drawn because
+/// they have no `Transform`.
+pub(crate) fn missing_transform(entity: &LintEntity) -> Option<String> {
+    let renders = ["Visible", "Draw", "Sprite"]
+        .iter()
+        .any(|name| entity.has_component(name));
+    match renders && entity.bundles.is_empty() && !entity.has_component("Transform") {
+        true => Some("The entity has rendering components but no Transform.".to_string()),
+        false => None,
+    }
+}
+
+/// Warns about `SetColorMaterial` on an entity with no material for it to modify.
+#[cfg(feature = "2d")]
+pub(crate) fn color_material_without_sprite(entity: &LintEntity) -> Option<String> {
+    let has_material = entity.has_command("InsertSpriteBundle") || entity.has_command("LoadPrefab");
+    match entity.has_command("SetColorMaterial") && !has_material {
+        true => Some(
+            "SetColorMaterial modifies an existing ColorMaterial, but the entity has no InsertSpriteBundle or LoadPrefab."
+                .to_string(),
+        ),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::*;
+
+    use crate::{parse::parse_prefab_str, PrefabRegistry};
+
+    #[test]
+    fn lints() {
+        let mut reg = PrefabRegistry::default();
+        reg.register_type::<Visible>();
+        reg.register_type::<Transform>();
+        reg.register_build_command::<crate::build_commands::SetColorMaterial>();
+        reg.register_build_command::<crate::build_commands::InsertSpriteBundle>();
+        reg.add_lint(crate::lint::missing_transform);
+        reg.add_lint(crate::lint::color_material_without_sprite);
+
+        let prefab = parse_prefab_str("{ Visible, Transform }", &reg).unwrap();
+        assert!(reg.lint(&prefab).is_empty());
+        let prefab = parse_prefab_str("{ Visible, InsertSpriteBundle!() }", &reg).unwrap();
+        assert!(reg.lint(&prefab).is_empty());
+
+        // Only commands in the bundle phase count as a bundle, whatever they're named.
+        reg.register_command_fn("InsertMarker", |_, _, _| {});
+        let prefab = parse_prefab_str("{ Visible, InsertMarker!() }", &reg).unwrap();
+        assert_eq!(
+            reg.lint(&prefab),
+            ["The entity has rendering components but no Transform."]
+        );
+
+        let prefab = parse_prefab_str(
+            "{ Transform, children [ { Visible, SetColorMaterial!() } ] }",
+            &reg,
+        )
+        .unwrap();
+        let warnings = reg.lint(&prefab);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[1].ends_with("but the entity has no InsertSpriteBundle or LoadPrefab."));
+
+        reg.add_lint(|entity| match entity.components.len() {
+            0 => Some("Empty entity".to_string()),
+            _ => None,
+        });
+        let prefab = parse_prefab_str("{ }", &reg).unwrap();
+        assert_eq!(reg.lint(&prefab), vec!["Empty entity".to_string()]);
+    }
+}
@@ -1,17 +1,30 @@
 use bevy::{
     prelude::*,
-    reflect::{DynamicList, DynamicStruct, DynamicTuple, DynamicTupleStruct, Reflect},
+    reflect::{
+        DynamicList, DynamicMap, DynamicStruct, DynamicTuple, DynamicTupleStruct, Reflect,
+        ReflectRef, TypeRegistration,
+    },
+    utils::Duration,
+};
+use pest::{
+    error::Error,
+    iterators::{Pair, Pairs},
+    Parser,
 };
-use pest::{error::Error, iterators::Pair, Parser};
 use pest_derive::*;
-use std::{ops::Range, sync::Arc};
+use std::{borrow::Cow, ops::Range, sync::Arc};
 use thiserror::Error;
 
 use crate::{
+    build_commands::{bundle_command_key, BuildPhase},
+    deferred::{is_deferred, DeferredValue},
     dynamic_cast::*,
+    level::{Level, LevelInstance},
     prefab::PrefabBuildStep,
     prefab::*,
     registry::{PrefabRegistry, ReflectType, TypeInfo},
+    table::PrefabTable,
+    writer::write_value,
 };
 
 #[derive(Parser)]
@@ -28,7 +41,7 @@ struct ReflectField {
 impl From<PrefabComponent> for ReflectField {
     fn from(comp: PrefabComponent) -> Self {
         ReflectField {
-            name: comp.type_name,
+            name: comp.type_name.to_string(),
             value: comp.reflect,
         }
     }
@@ -36,33 +49,262 @@ impl From<PrefabComponent> for ReflectField {
 
 #[derive(Error, Debug)]
 pub enum LoadPrefabError {
-    #[error("Pest error parsing prefab string.")]
-    PestParseError(#[from] Error<Rule>),
+    #[error("Pest error parsing prefab string:\n{0}")]
+    PestParseError(#[from] Box<Error<Rule>>),
     #[error("Error parsing prefab - unknown field rule: {0}.")]
     UnhandledPrefabFieldRule(String),
     #[error("Error parsing prefab - unknown component field rule: {0}.")]
     UnhandledPrefabComponentFieldRule(String),
-    #[error("Error parsing component - {0} was not registered with the PrefabRegistry.")]
-    UnregisteredPrefabComponent(String),
+    #[error(
+        "Error parsing component - {0} was not registered with the PrefabRegistry.{} Components \
+        must derive Reflect and Default, have the #[reflect(Component)] attribute and be \
+        registered with PrefabRegistry::register_type.",
+        did_you_mean(.1)
+    )]
+    UnregisteredPrefabComponent(String, Vec<String>),
+    #[error(
+        "Error parsing prefab - the bundle {0} was not registered with the PrefabRegistry. \
+        Bundles must implement Default and be registered with PrefabRegistry::register_bundle."
+    )]
+    UnregisteredBundle(String),
     #[error("Error parsing value type '{0}' from '{1}'.")]
     ValueParseError(String, String),
     #[error("Error parsing prefab - unknown value rule: {0}.")]
     UnhandledValueRule(String),
     #[error("Error reading prefab file.")]
     FileReadError(#[from] std::io::Error),
+    #[error("Error deserializing scene file.")]
+    SceneDeserializeError(#[from] ron::Error),
+    #[error("Error parsing prefab library - prefab {0} has no name.")]
+    UnnamedLibraryPrefab(usize),
+    #[error("Error parsing value '{1}' - it requires the '{0}' feature.")]
+    MissingFeature(String, String),
+    #[error("Error parsing prefab - the environment variable '{0}' is not set.")]
+    MissingEnvVar(String),
+    #[error(
+        "Error ordering command {0} - there is no {1} command in the prefab to order it against."
+    )]
+    MissingOrderTarget(String, String),
+    #[cfg(feature = "json")]
+    #[error("Error reading prefab JSON.")]
+    JsonError(#[from] serde_json::Error),
 }
 
-pub(crate) fn parse_prefab_string(
-    input: &str,
-    registry: &mut PrefabRegistry,
-) -> Result<Prefab, LoadPrefabError> {
-    let mut parsed = PrefabParser::parse(Rule::prefab, input)?;
+fn did_you_mean(suggestions: &[String]) -> String {
+    match suggestions {
+        [] => String::new(),
+        [name] => format!(" Did you mean {}?", name),
+        _ => format!(" Did you mean one of {}?", suggestions.join(", ")),
+    }
+}
+
+impl From<Error<Rule>> for LoadPrefabError {
+    fn from(e: Error<Rule>) -> Self {
+        LoadPrefabError::PestParseError(Box::new(e))
+    }
+}
+
+/// Parse a [Prefab] from the text of a *.prefab* file.
+///
+/// Components in the prefab must be registered with the registry. Any migrations
+/// registered with [PrefabRegistry::add_migration] are applied. The prefab isn't cached,
+/// use [PrefabRegistry::load] or [PrefabRegistry::replace_prefab] for prefabs which will be
+/// spawned.
+///
+/// ## Example
+///
+/// ```
+/// use bevy::prelude::*;
+/// use bevy_lazy_prefabs::*;
+///
+/// let mut registry = PrefabRegistry::default();
+/// registry.register_type::<Transform>();
+///
+/// let prefab = parse_prefab_str("Thing { Transform }", &registry).unwrap();
+/// assert!(parse_prefab_str("Thing { Unknown }", &registry).is_err());
+/// ```
+pub fn parse_prefab_str(input: &str, registry: &PrefabRegistry) -> Result<Prefab, LoadPrefabError> {
+    let input = migrate_input(input, registry)?;
+
+    let mut parsed = PrefabParser::parse(Rule::prefab_file, &input)?;
 
     parse_prefab(parsed.next().unwrap(), registry)
 }
 
+/// Parse a prefab library containing any number of named prefabs.
+pub(crate) fn parse_prefab_library_string(
+    input: &str,
+    registry: &PrefabRegistry,
+) -> Result<Vec<Prefab>, LoadPrefabError> {
+    let input = migrate_input(input, registry)?;
+
+    let parsed = PrefabParser::parse(Rule::prefab_library, &input)?;
+
+    let mut prefabs = Vec::new();
+    for pair in parsed.filter(|pair| pair.as_rule() == Rule::prefab) {
+        let prefab = parse_prefab(pair, registry)?;
+        if prefab.name.is_none() {
+            return Err(LoadPrefabError::UnnamedLibraryPrefab(prefabs.len()));
+        }
+        prefabs.push(prefab);
+    }
+    Ok(prefabs)
+}
+
+/// Parse the text of a *.level* file.
+pub(crate) fn parse_level_string(
+    input: &str,
+    registry: &PrefabRegistry,
+) -> Result<Level, LoadPrefabError> {
+    let input = migrate_input(input, registry)?;
+
+    let parsed = PrefabParser::parse(Rule::level_file, &input)?;
+
+    let mut instances = Vec::new();
+    for pair in parsed.filter(|pair| pair.as_rule() == Rule::level_instance) {
+        let mut instance = LevelInstance {
+            name: None,
+            prefab: String::new(),
+            overrides: None,
+        };
+        for field in pair.into_inner() {
+            match field.as_rule() {
+                Rule::prefab_name => instance.name = Some(field.as_str().to_string()),
+                Rule::string => instance.prefab = parse_string(field),
+                Rule::prefab => instance.overrides = Some(Arc::new(parse_prefab(field, registry)?)),
+                _ => {
+                    let str = format!("{:#?}", field.as_rule());
+                    return Err(LoadPrefabError::UnhandledPrefabFieldRule(str));
+                }
+            }
+        }
+        instances.push(instance);
+    }
+    Ok(Level { instances })
+}
+
+/// Parse the text of a *.table* file.
+pub(crate) fn parse_table_string(
+    input: &str,
+    registry: &PrefabRegistry,
+) -> Result<PrefabTable, LoadPrefabError> {
+    let input = migrate_input(input, registry)?;
+
+    let parsed = PrefabParser::parse(Rule::table_file, &input)?;
+
+    let mut entries = Vec::new();
+    for pair in parsed.filter(|pair| pair.as_rule() == Rule::table_entry) {
+        let mut pairs = pair.into_inner();
+        let name = parse_string(pairs.next().unwrap());
+        let weight = pairs.next().unwrap().as_str();
+        match weight.parse::<f32>() {
+            Ok(w) if w >= 0.0 => entries.push((name, w)),
+            _ => {
+                return Err(LoadPrefabError::ValueParseError(
+                    "weight".to_string(),
+                    weight.to_string(),
+                ))
+            }
+        }
+    }
+    Ok(PrefabTable { entries })
+}
+
+/// Check that the text of a *.prefab* file is syntactically valid.
+///
+/// Doesn't require a [PrefabRegistry], so components and commands are not checked
+/// for registration and migrations are not applied.
+///
+/// ## Example
+///
+/// ```
+/// use bevy_lazy_prefabs::*;
+///
+/// assert!(check_syntax("version: 2 Thing { Transform { scale: Vec3 { x: 2.0 } } }").is_ok());
+/// assert!(check_syntax("Thing { Transform { scale: } }").is_err());
+/// ```
+pub fn check_syntax(input: &str) -> Result<(), LoadPrefabError> {
+    let (_, body) = split_version(input)?;
+    PrefabParser::parse(Rule::prefab_file, body)?;
+    Ok(())
+}
+
+/// Strip the version header from the input and run any migrations registered for that version.
+fn migrate_input<'a>(
+    input: &'a str,
+    registry: &PrefabRegistry,
+) -> Result<Cow<'a, str>, LoadPrefabError> {
+    let (version, body) = split_version(input)?;
+
+    let input = match registry.migrate(version, body) {
+        Some(migrated) => Cow::Owned(migrated),
+        None => Cow::Borrowed(body),
+    };
+    substitute_config(input, registry)
+}
+
+/// Replace each `${env:NAME}` with the value of the environment variable, and each `${name}`
+/// set with [PrefabRegistry::set_config_value] with its value. Any other `${name}` is left to
+/// be read from [crate::PrefabVars] when the prefab is spawned.
+fn substitute_config<'a>(
+    input: Cow<'a, str>,
+    registry: &PrefabRegistry,
+) -> Result<Cow<'a, str>, LoadPrefabError> {
+    if !input.contains("${") {
+        return Ok(input);
+    }
+    let mut out = String::new();
+    let mut rest: &str = &input;
+    while let Some(start) = rest.find("${") {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        let name = &rest[start + 2..end];
+        let value = match name.strip_prefix("env:") {
+            Some(var) => Some(
+                std::env::var(var).map_err(|_| LoadPrefabError::MissingEnvVar(var.to_string()))?,
+            ),
+            None => registry.config_value(name).map(str::to_string),
+        };
+        match value {
+            Some(value) => {
+                out.push_str(&rest[..start]);
+                out.push_str(&value);
+            }
+            None => out.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(Cow::Owned(out))
+}
+
+/// Split the input into its version and the text following the version header.
+fn split_version(input: &str) -> Result<(u32, &str), LoadPrefabError> {
+    // Avoid building a pest error for the common case of a file with no version header.
+    let header = match input.trim_start().starts_with("version") {
+        true => PrefabParser::parse(Rule::version, input).ok(),
+        false => None,
+    };
+    match header {
+        Some(mut parsed) => {
+            let pair = parsed.next().unwrap();
+            let end = pair.as_span().end();
+            let version = pair.into_inner().next().unwrap().as_str();
+            let version = version.parse::<u32>().map_err(|_| {
+                LoadPrefabError::ValueParseError("version".to_string(), version.to_string())
+            })?;
+            Ok((version, &input[end..]))
+        }
+        None => Ok((1, input)),
+    }
+}
+
 fn parse_prefab(pair: Pair<Rule>, registry: &PrefabRegistry) -> Result<Prefab, LoadPrefabError> {
     let mut name = None;
+    let mut anchor = None;
+    let mut meta = PrefabMeta::default();
     let mut steps = Vec::new();
 
     for field in pair.into_inner() {
@@ -70,14 +312,54 @@ fn parse_prefab(pair: Pair<Rule>, registry: &PrefabRegistry) -> Result<Prefab, L
             Rule::type_name => {
                 name = Some(field.as_str().to_string());
             }
+            Rule::anchor => {
+                anchor = Some(field.into_inner().next().unwrap().as_str().to_string());
+            }
             Rule::component => {
                 let comp = parse_component(field, registry)?;
                 steps.push(PrefabBuildStep::AddComponent(Arc::new(comp)));
             }
             Rule::command => {
-                let command = parse_command(field)?;
+                let command = parse_command(field, registry)?;
                 steps.push(PrefabBuildStep::RunCommand(Arc::new(command)));
             }
+            Rule::flagged_commands => {
+                let mut pairs = field.into_inner();
+                let flag = parse_string(pairs.next().unwrap());
+                for command in pairs {
+                    let mut command = parse_command(command, registry)?;
+                    command.flag = Some(flag.clone());
+                    steps.push(PrefabBuildStep::RunCommand(Arc::new(command)));
+                }
+            }
+            Rule::bundle => {
+                let mut pairs = field.into_inner();
+                let bundle = pairs.next().unwrap().as_str();
+                let name = bundle_command_key(bundle);
+                if !registry.has_command(&name) {
+                    return Err(LoadPrefabError::UnregisteredBundle(bundle.to_string()));
+                }
+                steps.push(PrefabBuildStep::RunCommand(Arc::new(PrefabCommandData {
+                    name,
+                    properties: None,
+                    deferred: false,
+                    deprecated_key: None,
+                    flag: None,
+                })));
+                for comp in pairs {
+                    let comp = parse_component(comp, registry)?;
+                    steps.push(PrefabBuildStep::AddComponent(Arc::new(comp)));
+                }
+            }
+            Rule::meta => {
+                meta = parse_meta(field, registry)?;
+            }
+            Rule::children => {
+                for child in field.into_inner() {
+                    let child = parse_prefab(child, registry)?;
+                    steps.push(PrefabBuildStep::AddChild(Arc::new(child)));
+                }
+            }
             _ => {
                 let str = format!("{:#?}", field.as_rule());
                 return Err(LoadPrefabError::UnhandledPrefabFieldRule(str));
@@ -85,7 +367,131 @@ fn parse_prefab(pair: Pair<Rule>, registry: &PrefabRegistry) -> Result<Prefab, L
         }
     }
 
-    Ok(Prefab { name, steps })
+    steps.sort_by_cached_key(|step| match step {
+        PrefabBuildStep::RunCommand(command) => registry
+            .get_build_command(&command.name)
+            .map(|command| command.phase())
+            .unwrap_or(BuildPhase::Default),
+        _ => BuildPhase::Default,
+    });
+    apply_order_hints(&mut steps, registry)?;
+
+    Ok(Prefab {
+        name,
+        source: None,
+        anchor,
+        meta,
+        steps,
+    })
+}
+
+/// Move each command with a `before` or `after` property next to the command it names, in
+/// the order they're written. Commands without a hint keep their place. The hints are removed
+/// from the moved commands' properties, so they aren't passed to the command.
+fn apply_order_hints(
+    steps: &mut Vec<PrefabBuildStep>,
+    registry: &PrefabRegistry,
+) -> Result<(), LoadPrefabError> {
+    let hinted: Vec<_> = steps
+        .iter()
+        .filter_map(|step| match step {
+            PrefabBuildStep::RunCommand(command) => {
+                let props = command.properties.as_ref()?;
+                ["before", "after"]
+                    .iter()
+                    .find_map(|hint| Some((*hint, props.field(hint)?)))
+                    .map(|(hint, target)| (command.clone(), hint, target.clone_value()))
+            }
+            _ => None,
+        })
+        .collect();
+
+    for (command, hint, target) in hinted {
+        let target = match target.downcast_ref::<String>() {
+            Some(target) => registry.resolve_command_key(target.clone()).0,
+            None => {
+                let value = format!("{:?}", target);
+                return Err(LoadPrefabError::ValueParseError(hint.to_string(), value));
+            }
+        };
+        let is_command = |step: &PrefabBuildStep, name: &str| matches!(step, PrefabBuildStep::RunCommand(c) if c.name == name);
+        let from = steps
+            .iter()
+            .position(
+                |step| matches!(step, PrefabBuildStep::RunCommand(c) if Arc::ptr_eq(c, &command)),
+            )
+            .unwrap();
+        steps.remove(from);
+        let step = PrefabBuildStep::RunCommand(Arc::new(without_order_hints(&command)));
+        let to = match hint {
+            "before" => steps.iter().position(|step| is_command(step, &target)),
+            _ => steps
+                .iter()
+                .rposition(|step| is_command(step, &target))
+                .map(|i| i + 1),
+        };
+        match to {
+            Some(to) => steps.insert(to, step),
+            None => {
+                return Err(LoadPrefabError::MissingOrderTarget(
+                    command.name.clone(),
+                    target,
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A copy of a command without its `before` and `after` properties.
+fn without_order_hints(command: &PrefabCommandData) -> PrefabCommandData {
+    let properties = command.properties.as_ref().and_then(|props| {
+        let mut stripped = DynamicStruct::default();
+        stripped.set_name(props.name().to_string());
+        for (i, value) in props.iter_fields().enumerate() {
+            let name = props.name_at(i).unwrap();
+            if name != "before" && name != "after" {
+                stripped.insert_boxed(name, value.clone_value());
+            }
+        }
+        Some(stripped).filter(|stripped| stripped.field_len() > 0)
+    });
+    PrefabCommandData {
+        name: command.name.clone(),
+        deferred: properties.as_ref().map_or(false, |p| is_deferred(p)),
+        properties,
+        deprecated_key: command.deprecated_key.clone(),
+        flag: command.flag.clone(),
+    }
+}
+
+fn parse_meta(pair: Pair<Rule>, registry: &PrefabRegistry) -> Result<PrefabMeta, LoadPrefabError> {
+    use bevy::reflect::List;
+
+    let mut meta = PrefabMeta::default();
+    for pair in pair.into_inner() {
+        let text = pair.as_str();
+        let err = || LoadPrefabError::ValueParseError("meta".to_string(), text.to_string());
+        let field = parse_field(pair, registry)?;
+        let value = &*field.value;
+        match field.name.as_str() {
+            "tags" => {
+                let tags = value.try_cast_ref::<DynamicList>().map_err(|_| err())?;
+                for tag in tags.iter() {
+                    meta.tags
+                        .push(tag.try_cast_ref::<String>().map_err(|_| err())?.clone());
+                }
+            }
+            "category" => {
+                meta.category = Some(value.try_cast_ref::<String>().map_err(|_| err())?.clone())
+            }
+            "description" => {
+                meta.description = Some(value.try_cast_ref::<String>().map_err(|_| err())?.clone())
+            }
+            _ => return Err(err()),
+        }
+    }
+    Ok(meta)
 }
 
 fn parse_component(
@@ -97,48 +503,92 @@ fn parse_component(
     let mut pairs = pair.into_inner();
     let type_name = pairs.next().unwrap().as_str();
 
+    let t = registry
+        .get_type_data(type_name)
+        .ok_or_else(|| registry.unregistered_type_error(type_name))?;
+
     // Prefab fields
     for field in pairs {
         match field.as_rule() {
             Rule::component => {
-                let nested_component = parse_component(field, registry).unwrap();
+                let nested_component = parse_component(field, registry)?;
                 fields.push(ReflectField::from(nested_component));
             }
             Rule::field => {
-                let field = parse_field(field)?;
+                let field = parse_field(field, registry)?;
                 fields.push(field);
             }
+            Rule::component_value => {
+                let value = parse_value(field.into_inner().next().unwrap(), registry)?;
+                fields.push(ReflectField {
+                    name: "value".to_string(),
+                    value,
+                });
+            }
+            Rule::array => {
+                for (i, value) in field.into_inner().enumerate() {
+                    fields.push(ReflectField {
+                        name: i.to_string(),
+                        value: parse_value(value, registry)?,
+                    });
+                }
+            }
             _ => {
                 let str = format!("{:#?}", field.as_rule());
                 return Err(LoadPrefabError::UnhandledPrefabComponentFieldRule(str));
             }
         }
     }
-    let t = registry
-        .get_type_data(type_name)
-        .ok_or_else(|| LoadPrefabError::UnregisteredPrefabComponent(type_name.to_string()))?;
 
-    let comp = build_component(t, fields);
+    let comp = build_component(registry, t, fields)?;
 
     Ok(PrefabComponent {
-        type_name: type_name.to_string(),
+        type_name: t.type_name.clone(),
+        deferred: is_deferred(&*comp),
         reflect: comp,
     })
 }
 
-fn build_component(type_info: &TypeInfo, fields: Vec<ReflectField>) -> Box<dyn Reflect> {
-    match type_info.reflect_type {
+fn build_component(
+    registry: &PrefabRegistry,
+    type_info: &TypeInfo,
+    fields: Vec<ReflectField>,
+) -> Result<Box<dyn Reflect>, LoadPrefabError> {
+    Ok(match type_info.reflect_type {
         ReflectType::Struct => {
             let mut root = DynamicStruct::default();
+            root.set_name(type_info.registration.name().to_string());
+            let instance = match type_info.instance.reflect_ref() {
+                ReflectRef::Struct(s) => Some(s),
+                _ => None,
+            };
             for field in fields {
-                root.insert_boxed(&field.name, field.value);
+                let target = instance.and_then(|s| s.field(&field.name));
+                let value = match target {
+                    Some(target) => {
+                        let value =
+                            registry.parse_value_literal(target.type_name(), field.value)?;
+                        fit_literal(fit_to_field(value, target), target)?
+                    }
+                    None => field.value,
+                };
+                root.insert_boxed(&field.name, value);
             }
             Box::new(root)
         }
         ReflectType::TupleStruct => {
             let mut root = DynamicTupleStruct::default();
-            for field in fields {
-                root.insert_boxed(field.value);
+            root.set_name(type_info.registration.name().to_string());
+            let instance = match type_info.instance.reflect_ref() {
+                ReflectRef::TupleStruct(s) => Some(s),
+                _ => None,
+            };
+            for (i, field) in fields.into_iter().enumerate() {
+                let value = match instance.and_then(|s| s.field(i)) {
+                    Some(target) => fit_literal(field.value, target)?,
+                    None => field.value,
+                };
+                root.insert_boxed(value);
             }
             Box::new(root)
         }
@@ -149,16 +599,246 @@ fn build_component(type_info: &TypeInfo, fields: Vec<ReflectField>) -> Box<dyn R
             }
             Box::new(root)
         }
-        ReflectType::List => todo!(),
-        ReflectType::Map => todo!(),
-        ReflectType::Value => todo!(),
+        ReflectType::List => {
+            let element = generic_args(type_info.registration.name()).first().copied();
+            let mut root = DynamicList::default();
+            for field in fields {
+                root.push_box(fit_element(registry, type_info, element, field.value)?);
+            }
+            Box::new(root)
+        }
+        ReflectType::Map => {
+            let value_type = generic_args(type_info.registration.name()).get(1).copied();
+            let mut root = DynamicMap::default();
+            for field in fields {
+                let value = fit_element(registry, type_info, value_type, field.value)?;
+                root.insert_boxed(Box::new(field.name), value);
+            }
+            Box::new(root)
+        }
+        ReflectType::Value => match fields.into_iter().next() {
+            Some(field) => {
+                let value =
+                    registry.parse_value_literal(type_info.registration.name(), field.value)?;
+                let value = fit_literal(value, &*type_info.instance)?;
+                // Applying a value of another type to the component would panic when spawned.
+                if value.type_name() != type_info.instance.type_name()
+                    && !value.is::<DeferredValue>()
+                {
+                    let text =
+                        write_value(&*value).unwrap_or_else(|| value.type_name().to_string());
+                    return Err(LoadPrefabError::ValueParseError(
+                        type_info.registration.short_name().to_string(),
+                        text,
+                    ));
+                }
+                value
+            }
+            None => type_info.instance.clone_value(),
+        },
+    })
+}
+
+/// The generic arguments in a type name, ie: `["i32"]` for `alloc::vec::Vec<i32>`.
+fn generic_args(type_name: &str) -> Vec<&str> {
+    let inner = match (type_name.find('<'), type_name.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &type_name[start + 1..end],
+        _ => return Vec::new(),
+    };
+    let mut args = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in inner.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            ',' if depth == 0 => {
+                args.push(inner[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    args.push(inner[start..].trim());
+    args
+}
+
+/// Convert a list element or map value to the collection's element type, ie: `[1, 2]` for a
+/// `Vec<u8>`. Elements of a type other than a primitive or registered value type are left as
+/// they're written.
+fn fit_element(
+    registry: &PrefabRegistry,
+    collection: &TypeInfo,
+    element_type: Option<&str>,
+    value: Box<dyn Reflect>,
+) -> Result<Box<dyn Reflect>, LoadPrefabError> {
+    let sample = match element_type.and_then(|name| sample_value(registry, name)) {
+        Some(sample) => sample,
+        None => return Ok(value),
+    };
+    let value = fit_literal(value, &*sample)?;
+    // Adding an element of another type to the collection would panic when spawned.
+    if value.type_name() != sample.type_name() && !value.is::<DeferredValue>() {
+        let text = write_value(&*value).unwrap_or_else(|| value.type_name().to_string());
+        return Err(LoadPrefabError::ValueParseError(
+            collection.registration.short_name().to_string(),
+            text,
+        ));
+    }
+    Ok(value)
+}
+
+/// A value of the primitive or registered value type with the given full name.
+fn sample_value(registry: &PrefabRegistry, type_name: &str) -> Option<Box<dyn Reflect>> {
+    macro_rules! sample {
+        ($($t:ty),*) => {$(
+            if type_name == std::any::type_name::<$t>() {
+                return Some(Box::new(<$t>::default()));
+            }
+        )*};
+    }
+    sample!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64, bool, String);
+    registry
+        .get_type_data(type_name)
+        .filter(|info| info.reflect_type == ReflectType::Value)
+        .map(|info| info.instance.clone_value())
+}
+
+/// Convert a `px` or `%` value to a plain `f32` if that's what the target field expects.
+#[cfg(feature = "render")]
+fn fit_to_field(value: Box<dyn Reflect>, target: &dyn Reflect) -> Box<dyn Reflect> {
+    if !target.is::<f32>() {
+        return value;
+    }
+    match value.downcast_ref::<Val>() {
+        Some(Val::Px(n)) | Some(Val::Percent(n)) => Box::new(*n),
+        _ => value,
+    }
+}
+
+#[cfg(not(feature = "render"))]
+fn fit_to_field(value: Box<dyn Reflect>, _: &dyn Reflect) -> Box<dyn Reflect> {
+    value
+}
+
+/// Convert a number or range to the type of the field it's written to. Deferred numbers are
+/// converted when they're evaluated.
+fn fit_literal(
+    value: Box<dyn Reflect>,
+    target: &dyn Reflect,
+) -> Result<Box<dyn Reflect>, LoadPrefabError> {
+    if let Some(number) = value
+        .downcast_ref::<DeferredValue>()
+        .and_then(|deferred| deferred.fit_number(target))
+    {
+        return Ok(Box::new(number));
     }
+    fit_range(fit_integer(value, target)?, target)
 }
 
-fn parse_field(field: Pair<Rule>) -> Result<ReflectField, LoadPrefabError> {
+/// Convert an integer to the integer type of the field it's written to, ie: `index: 3` for a
+/// `u8` field. Values which don't fit in the field's type are an error.
+fn fit_integer(
+    value: Box<dyn Reflect>,
+    target: &dyn Reflect,
+) -> Result<Box<dyn Reflect>, LoadPrefabError> {
+    let n = match (value.downcast_ref::<i32>(), value.downcast_ref::<i128>()) {
+        (Some(n), _) => *n as i128,
+        (_, Some(n)) => *n,
+        _ => return Ok(value),
+    };
+    macro_rules! fit {
+        ($($t:ty),*) => {$(
+            if target.is::<$t>() {
+                return <$t>::try_from(n)
+                    .map(|n| Box::new(n) as Box<dyn Reflect>)
+                    .map_err(|_| {
+                        LoadPrefabError::ValueParseError(stringify!($t).to_string(), n.to_string())
+                    });
+            }
+        )*};
+    }
+    fit!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+    match value.is::<i128>() {
+        // Only wider fields can hold a value outside the range of an i32.
+        true => Err(LoadPrefabError::ValueParseError(
+            "i32".to_string(),
+            n.to_string(),
+        )),
+        false => Ok(value),
+    }
+}
+
+/// Convert a range to the type of range stored in the field it's written to, ie: `(1..=5)` for
+/// a `Range<u8>` field. Ranges with bounds which don't fit in the field's type are an error.
+fn fit_range(
+    value: Box<dyn Reflect>,
+    target: &dyn Reflect,
+) -> Result<Box<dyn Reflect>, LoadPrefabError> {
+    let (start, end) = if let Some(r) = value.downcast_ref::<Range<i32>>() {
+        (r.start as i128, r.end as i128)
+    } else if let Some(r) = value.downcast_ref::<Range<i128>>() {
+        (r.start, r.end)
+    } else if let (Some(r), true) = (
+        value.downcast_ref::<Range<f32>>(),
+        target.is::<Range<f64>>(),
+    ) {
+        // Converted through the shortest text for the f32, so `0.1` is also 0.1 as an f64.
+        let to_f64 = |f: f32| f.to_string().parse::<f64>().unwrap_or(f as f64);
+        return Ok(Box::new(to_f64(r.start)..to_f64(r.end)));
+    } else {
+        let mismatched = value.type_name() != target.type_name();
+        return match mismatched && is_range(&*value) && is_range(target) {
+            true => Err(range_mismatch(&*value, target)),
+            false => Ok(value),
+        };
+    };
+    let err = |t: &str| {
+        LoadPrefabError::ValueParseError(format!("Range<{}>", t), format!("{}..{}", start, end))
+    };
+    macro_rules! fit {
+        ($($t:ty),*) => {$(
+            if target.is::<Range<$t>>() {
+                return match (<$t>::try_from(start), <$t>::try_from(end)) {
+                    (Ok(start), Ok(end)) => Ok(Box::new(start..end)),
+                    _ => Err(err(stringify!($t))),
+                };
+            }
+        )*};
+    }
+    fit!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+    if target.is::<Range<f32>>() {
+        return Ok(Box::new(start as f32..end as f32));
+    }
+    if target.is::<Range<f64>>() {
+        return Ok(Box::new(start as f64..end as f64));
+    }
+    if is_range(target) {
+        return Err(range_mismatch(&*value, target));
+    }
+    match value.is::<Range<i128>>() {
+        // Only wider fields can hold a range outside of an i32.
+        true => Err(err("i32")),
+        false => Ok(value),
+    }
+}
+
+fn is_range(value: &dyn Reflect) -> bool {
+    value.type_name().starts_with("core::ops::range::Range<")
+}
+
+/// The error for a range which can't be converted to the type of range in the field.
+fn range_mismatch(value: &dyn Reflect, target: &dyn Reflect) -> LoadPrefabError {
+    let text = write_value(value).unwrap_or_else(|| value.type_name().to_string());
+    LoadPrefabError::ValueParseError(TypeRegistration::get_short_name(target.type_name()), text)
+}
+
+fn parse_field(
+    field: Pair<Rule>,
+    registry: &PrefabRegistry,
+) -> Result<ReflectField, LoadPrefabError> {
     let mut field = field.into_inner();
     let field_name = field.next().unwrap().as_str();
-    let value = parse_value(field.next().unwrap())?;
+    let value = parse_value(field.next().unwrap(), registry)?;
 
     Ok(ReflectField {
         name: field_name.to_string(),
@@ -166,21 +846,66 @@ fn parse_field(field: Pair<Rule>) -> Result<ReflectField, LoadPrefabError> {
     })
 }
 
-fn parse_value(pair: Pair<Rule>) -> Result<Box<dyn Reflect>, LoadPrefabError> {
+fn parse_value(
+    pair: Pair<Rule>,
+    registry: &PrefabRegistry,
+) -> Result<Box<dyn Reflect>, LoadPrefabError> {
     let value_string = pair.as_str();
     match pair.as_rule() {
-        Rule::int => {
-            let num = value_string.parse::<i32>().map_err(|_| {
-                LoadPrefabError::ValueParseError("i32".to_string(), value_string.to_string())
-            })?;
-            Ok(Box::new(num))
-        }
+        Rule::int => match value_string.parse::<i32>() {
+            Ok(num) => Ok(Box::new(num)),
+            // Too big for an i32, but it can still be written to a wider field.
+            Err(_) => match value_string.parse::<i128>() {
+                Ok(num) => Ok(Box::new(num)),
+                Err(_) => Err(LoadPrefabError::ValueParseError(
+                    "i32".to_string(),
+                    value_string.to_string(),
+                )),
+            },
+        },
         Rule::float => {
             let f = value_string.parse::<f32>().map_err(|_| {
                 LoadPrefabError::ValueParseError("float".to_string(), value_string.to_string())
             })?;
             Ok(Box::new(f))
         }
+        Rule::unit_value => {
+            let mut pairs = pair.into_inner();
+            let number = pairs.next().unwrap().as_str();
+            let n = number.parse::<f32>().map_err(|_| {
+                LoadPrefabError::ValueParseError("number".to_string(), value_string.to_string())
+            })?;
+            Ok(match pairs.next().unwrap().as_str() {
+                "deg" => Box::new(n.to_radians()),
+                "turn" => Box::new(n * std::f32::consts::TAU),
+                #[cfg(feature = "render")]
+                "px" => Box::new(Val::Px(n)),
+                #[cfg(feature = "render")]
+                "%" => Box::new(Val::Percent(n)),
+                #[cfg(not(feature = "render"))]
+                "px" | "%" => return Err(missing_render_feature(value_string)),
+                unit @ ("s" | "ms") => {
+                    // Parsed again as an f64 so `300ms` is exactly 300 milliseconds.
+                    let secs = match number.parse::<f64>() {
+                        Ok(n) if n >= 0.0 && unit == "s" => n,
+                        Ok(n) if n >= 0.0 => n / 1000.0,
+                        _ => {
+                            return Err(LoadPrefabError::ValueParseError(
+                                "Duration".to_string(),
+                                value_string.to_string(),
+                            ))
+                        }
+                    };
+                    Box::new(Duration::from_secs_f64(secs))
+                }
+                _ => unreachable!(),
+            })
+        }
+        #[cfg(feature = "render")]
+        Rule::ui_val => parse_ui_val(pair),
+        #[cfg(not(feature = "render"))]
+        Rule::ui_val => Err(missing_render_feature(value_string)),
+        Rule::bool => Ok(Box::new(value_string == "true")),
         Rule::char => {
             let ch = value_string.chars().nth(1).ok_or_else(|| {
                 LoadPrefabError::ValueParseError("char".to_string(), value_string.to_string())
@@ -189,73 +914,126 @@ fn parse_value(pair: Pair<Rule>) -> Result<Box<dyn Reflect>, LoadPrefabError> {
         }
         Rule::string => {
             let str = parse_string(pair);
-            Ok(Box::new(str))
+            match str.contains("${") {
+                true => Ok(Box::new(DeferredValue::Template(str))),
+                false => Ok(Box::new(str)),
+            }
+        }
+        Rule::anchor => {
+            let name = pair.into_inner().next().unwrap().as_str();
+            Ok(Box::new(DeferredValue::EntityRef(name.to_string())))
+        }
+        Rule::localized => {
+            let key = parse_string(pair.into_inner().next().unwrap());
+            Ok(Box::new(DeferredValue::Localized(key)))
+        }
+        Rule::asset => {
+            let path = parse_string(pair.into_inner().next().unwrap());
+            Ok(Box::new(DeferredValue::Asset(path)))
+        }
+        Rule::var => {
+            let name = pair.into_inner().next().unwrap().as_str();
+            Ok(Box::new(DeferredValue::Var(name.to_string())))
         }
         Rule::array => {
             let mut list = DynamicList::default();
 
             for value in pair.into_inner() {
-                let array_val = parse_value(value)?;
+                let array_val = parse_value(value, registry)?;
                 list.push_box(array_val);
             }
 
             Ok(Box::new(list))
         }
-        Rule::range => {
-            let i0 = value_string.find("..").unwrap();
-            let i1 = value_string.rfind("..").unwrap() + 2;
-
-            let start = &value_string[1..i0].parse::<i32>().map_err(|_| {
-                LoadPrefabError::ValueParseError("range min".to_string(), value_string.to_string())
-            })?;
-            let end = &value_string[i1..value_string.len() - 1]
+        Rule::range => parse_range(pair),
+        Rule::vec2 => parse_vector(pair, registry, &["x", "y"], |v| {
+            Box::new(Vec2::new(v[0], v[1]))
+        }),
+        Rule::vec3 => parse_vector(pair, registry, &["x", "y", "z"], |v| {
+            Box::new(Vec3::new(v[0], v[1], v[2]))
+        }),
+        Rule::rand_float => {
+            let mut bounds = pair.into_inner().map(|pair| {
+                pair.as_str().parse::<f32>().map_err(|_| {
+                    LoadPrefabError::ValueParseError("rand".to_string(), value_string.to_string())
+                })
+            });
+            let (start, end) = (bounds.next().unwrap()?, bounds.next().unwrap()?);
+            Ok(Box::new(DeferredValue::RandomFloat(start..end)))
+        }
+        Rule::rand_int => {
+            let err = || {
+                LoadPrefabError::ValueParseError("rand_int".to_string(), value_string.to_string())
+            };
+            let mut pairs = pair.into_inner();
+            let start = pairs
+                .next()
+                .unwrap()
+                .as_str()
                 .parse::<i32>()
-                .map_err(|_| {
-                    LoadPrefabError::ValueParseError(
-                        "range max".to_string(),
-                        value_string.to_string(),
-                    )
-                })?;
-
-            Ok(Box::new(Range::<i32> {
-                start: *start,
-                end: *end,
-            }))
-        }
-        Rule::vec3 => {
-            let mut v = Vec3::default();
-            for field in pair.into_inner() {
-                let field = parse_field(field).unwrap();
-                let name = field.name;
-                let val = field.value.cast_ref::<f32>();
-                match name.as_str() {
-                    "x" => v.x = *val,
-                    "y" => v.y = *val,
-                    "z" => v.z = *val,
-                    _ => {} // Error here?
-                };
-            }
-            Ok(Box::new(v))
+                .map_err(|_| err())?;
+            let inclusive = pairs.next().unwrap().as_str() == "..=";
+            let end = pairs
+                .next()
+                .unwrap()
+                .as_str()
+                .parse::<i32>()
+                .map_err(|_| err())?;
+            let end = match inclusive {
+                true => end.checked_add(1).ok_or_else(err)?,
+                false => end,
+            };
+            Ok(Box::new(DeferredValue::RandomInt(start..end)))
+        }
+        Rule::hex_color | Rule::color => parse_color(pair, registry),
+        Rule::property_struct => Ok(Box::new(parse_properties(pair.into_inner(), registry)?)),
+        Rule::struct_value => {
+            let comp = parse_component(pair, registry)?;
+            Ok(comp.reflect)
+        }
+        Rule::shape => {
+            let shape = pair.into_inner().next().unwrap().as_str();
+            Ok(Box::new(shape.to_string()))
         }
+        _ => {
+            let str = format!("{:#?}", pair.as_rule());
+            Err(LoadPrefabError::UnhandledValueRule(str))
+        }
+    }
+}
+
+#[cfg(feature = "render")]
+fn parse_color(
+    pair: Pair<Rule>,
+    registry: &PrefabRegistry,
+) -> Result<Box<dyn Reflect>, LoadPrefabError> {
+    let value_string = pair.as_str();
+    match pair.as_rule() {
+        Rule::hex_color => Color::hex(&value_string[1..])
+            .map(|col| Box::new(col) as Box<dyn Reflect>)
+            .map_err(|_| {
+                LoadPrefabError::ValueParseError("hex color".to_string(), value_string.to_string())
+            }),
         Rule::color => {
             let mut col = Color::default();
             for pair in pair.into_inner() {
                 match pair.as_rule() {
                     Rule::field => {
-                        let field = parse_field(pair).unwrap();
-                        let val = field.value.cast_ref::<f32>();
+                        let text = pair.as_str();
+                        let field = parse_field(pair, registry)?;
+                        let val = field_to_f32(&*field.value, text)?;
                         match field.name.as_str() {
                             "r" => {
-                                col.set_r(*val);
+                                col.set_r(val);
                             }
                             "g" => {
-                                col.set_g(*val);
+                                col.set_g(val);
                             }
                             "b" => {
-                                col.set_b(*val);
+                                col.set_b(val);
                             }
                             "a" => {
-                                col.set_a(*val);
+                                col.set_a(val);
                             }
                             _ => {}
                         };
@@ -267,10 +1045,13 @@ fn parse_value(pair: Pair<Rule>) -> Result<Box<dyn Reflect>, LoadPrefabError> {
                             "GREEN" => Color::GREEN,
                             "YELLOW" => Color::YELLOW,
                             "PINK" => Color::PINK,
-                            _ => {
-                                let str = format!("Color::{}", value_string);
-                                return Err(LoadPrefabError::UnhandledValueRule(str));
-                            }
+                            name => match registry.get_color(name) {
+                                Some(col) => col,
+                                None => {
+                                    let str = format!("Color::{}", value_string);
+                                    return Err(LoadPrefabError::UnhandledValueRule(str));
+                                }
+                            },
                         };
                     }
                     _ => unreachable!(),
@@ -278,54 +1059,239 @@ fn parse_value(pair: Pair<Rule>) -> Result<Box<dyn Reflect>, LoadPrefabError> {
             }
             Ok(Box::new(col))
         }
-        Rule::shape => {
-            let shape = pair.into_inner().next().unwrap().as_str();
-            Ok(Box::new(shape.to_string()))
-        }
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(not(feature = "render"))]
+fn parse_color(pair: Pair<Rule>, _: &PrefabRegistry) -> Result<Box<dyn Reflect>, LoadPrefabError> {
+    Err(missing_render_feature(pair.as_str()))
+}
+
+#[cfg(not(feature = "render"))]
+fn missing_render_feature(value: &str) -> LoadPrefabError {
+    LoadPrefabError::MissingFeature("render".to_string(), value.to_string())
+}
+
+/// Parse a UI `Val` written as `Px(10)`, `Percent(50)`, `Auto` or `Undefined`.
+#[cfg(feature = "render")]
+fn parse_ui_val(pair: Pair<Rule>) -> Result<Box<dyn Reflect>, LoadPrefabError> {
+    let value_string = pair.as_str();
+    let mut pairs = pair.into_inner();
+    let kind = pairs.next().unwrap().as_str();
+    let val = match kind {
+        "Auto" => Val::Auto,
+        "Undefined" => Val::Undefined,
         _ => {
-            let str = format!("{:#?}", pair.as_rule());
-            Err(LoadPrefabError::UnhandledValueRule(str))
+            let n = pairs.next().unwrap().as_str().parse::<f32>().map_err(|_| {
+                LoadPrefabError::ValueParseError("Val".to_string(), value_string.to_string())
+            })?;
+            match kind {
+                "Px" => Val::Px(n),
+                _ => Val::Percent(n),
+            }
+        }
+    };
+    Ok(Box::new(val))
+}
+
+/// Parse a range as a `Range<i32>`, or a `Range<f32>` if either bound is a float. Inclusive
+/// ranges end one past their last value.
+fn parse_range(pair: Pair<Rule>) -> Result<Box<dyn Reflect>, LoadPrefabError> {
+    let text = pair.as_str();
+    let err = |t: &str| LoadPrefabError::ValueParseError(t.to_string(), text.to_string());
+    let mut pairs = pair.into_inner();
+    let (start, op, end) = (
+        pairs.next().unwrap(),
+        pairs.next().unwrap(),
+        pairs.next().unwrap(),
+    );
+    let inclusive = op.as_str() == "..=";
+    if start.as_rule() == Rule::int && end.as_rule() == Rule::int {
+        let start = start.as_str().parse::<i128>().map_err(|_| err("range"))?;
+        let end = end.as_str().parse::<i128>().map_err(|_| err("range"))?;
+        let end = match inclusive {
+            true => end.checked_add(1).ok_or_else(|| err("range"))?,
+            false => end,
+        };
+        return Ok(match (i32::try_from(start), i32::try_from(end)) {
+            (Ok(start), Ok(end)) => Box::new(start..end),
+            // Too big for an i32, but it can still be written to a wider field.
+            _ => Box::new(start..end),
+        });
+    }
+    if inclusive {
+        // A `Range<f32>` can't include its end.
+        return Err(err("Range<f32>"));
+    }
+    let start = start.as_str().parse::<f32>().map_err(|_| err("range"))?;
+    let end = end.as_str().parse::<f32>().map_err(|_| err("range"))?;
+    Ok(Box::new(start..end))
+}
+
+/// Parse the fields of a `Vec2` or `Vec3`. If any of the fields are random the vector is
+/// returned as a [DeferredValue].
+fn parse_vector(
+    pair: Pair<Rule>,
+    registry: &PrefabRegistry,
+    names: &[&str],
+    build: fn(&[f32]) -> Box<dyn Reflect>,
+) -> Result<Box<dyn Reflect>, LoadPrefabError> {
+    let mut values = vec![DeferredValue::Const(0.0); names.len()];
+    for field in pair.into_inner() {
+        let text = field.as_str();
+        let field = parse_field(field, registry)?;
+        let i = match names.iter().position(|name| *name == field.name) {
+            Some(i) => i,
+            None => continue,
+        };
+        values[i] = match field.value.downcast_ref::<DeferredValue>() {
+            Some(deferred) => deferred.clone(),
+            None => DeferredValue::Const(field_to_f32(&*fit_to_field(field.value, &0.0), text)?),
+        };
+    }
+
+    let mut v = Vec::with_capacity(values.len());
+    for value in values.iter() {
+        match value {
+            DeferredValue::Const(f) => v.push(*f),
+            _ => return Ok(Box::new(DeferredValue::Vector(values))),
         }
     }
+    Ok(build(&v))
+}
+
+/// Read a numeric value as an `f32`. Integers are converted, so `x: 5` is the same as `x: 5.0`.
+fn field_to_f32(value: &dyn Reflect, text: &str) -> Result<f32, LoadPrefabError> {
+    if let Ok(i) = value.try_cast_ref::<i32>() {
+        return Ok(*i as f32);
+    }
+    match value.try_cast_ref::<f32>() {
+        Ok(f) => Ok(*f),
+        Err(_) => Err(LoadPrefabError::ValueParseError(
+            "f32".to_string(),
+            text.to_string(),
+        )),
+    }
 }
 
 fn parse_string(pair: Pair<Rule>) -> String {
     let str = pair.as_str();
-    str[1..str.len().saturating_sub(1)].to_string()
+    let mut out = String::with_capacity(str.len());
+    let mut chars = str[1..str.len().saturating_sub(1)].chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            out.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some(ch) => out.push(ch),
+            None => {}
+        }
+    }
+    out
 }
 
-fn parse_command(pair: Pair<Rule>) -> Result<PrefabCommandData, LoadPrefabError> {
+fn parse_command(
+    pair: Pair<Rule>,
+    registry: &PrefabRegistry,
+) -> Result<PrefabCommandData, LoadPrefabError> {
     let mut pairs = pair.into_inner();
-    let command_name = pairs.next().unwrap().as_str().to_string();
-
-    let mut properties = None;
-
-    for field in pairs {
-        let field = parse_field(field)?;
-        let props = properties.get_or_insert(DynamicStruct::default());
+    let (command_name, deprecated_key) =
+        registry.resolve_command_key(pairs.next().unwrap().as_str().to_string());
 
-        props.insert_boxed(field.name.as_str(), field.value);
-    }
+    let properties = match pairs.peek() {
+        Some(_) => Some(parse_properties(pairs, registry)?),
+        None => None,
+    };
+    let properties = match (registry.get_command_defaults(&command_name), properties) {
+        (Some(defaults), Some(props)) => Some(merge_properties(defaults, &props)),
+        (Some(defaults), None) => Some(defaults.clone_dynamic()),
+        (None, props) => props,
+    };
 
     Ok(PrefabCommandData {
         name: command_name,
+        deferred: properties.as_ref().map_or(false, |p| is_deferred(p)),
         properties,
+        deprecated_key,
+        flag: None,
     })
 }
 
+/// Parse command properties written on their own, ie: `filter: "nearest", size: 2.0`.
+pub(crate) fn parse_command_properties(
+    input: &str,
+    registry: &PrefabRegistry,
+) -> Result<DynamicStruct, LoadPrefabError> {
+    let mut parsed = PrefabParser::parse(Rule::command_properties, input)?;
+    parse_properties(parsed.next().unwrap().into_inner(), registry)
+}
+
+/// Overlay `props` on a copy of `defaults`. Property groups in both are merged.
+fn merge_properties(defaults: &DynamicStruct, props: &DynamicStruct) -> DynamicStruct {
+    let mut merged = defaults.clone_dynamic();
+    for (i, value) in props.iter_fields().enumerate() {
+        let name = props.name_at(i).unwrap();
+        let group = (
+            defaults
+                .field(name)
+                .and_then(|v| v.downcast_ref::<DynamicStruct>()),
+            value.downcast_ref::<DynamicStruct>(),
+        );
+        match group {
+            (Some(default_group), Some(group)) => {
+                merged.insert(name, merge_properties(default_group, group))
+            }
+            _ => merged.insert_boxed(name, value.clone_value()),
+        }
+    }
+    merged
+}
+
+/// Parse command properties. Property groups and `{ .. }` values become nested [DynamicStruct]s.
+fn parse_properties(
+    pairs: Pairs<Rule>,
+    registry: &PrefabRegistry,
+) -> Result<DynamicStruct, LoadPrefabError> {
+    let mut props = DynamicStruct::default();
+    for pair in pairs {
+        match pair.as_rule() {
+            Rule::EOI => {}
+            Rule::property_group => {
+                let mut pairs = pair.into_inner();
+                let name = pairs.next().unwrap().as_str();
+                props.insert(name, parse_properties(pairs, registry)?);
+            }
+            _ => {
+                let field = parse_field(pair, registry)?;
+                props.insert_boxed(field.name.as_str(), field.value);
+            }
+        }
+    }
+    Ok(props)
+}
+
 #[cfg(test)]
 mod test {
+    use std::ops::Range;
+
     use bevy::prelude::*;
 
     use pest::Parser;
 
     use crate::dynamic_cast::*;
-    use crate::parse::parse_prefab;
+    use crate::parse::{parse_prefab, parse_prefab_str};
     use crate::prefab::PrefabBuildStep;
     use crate::registry::PrefabRegistry;
+    use crate::test_utils::Stats;
     use crate::{
         dynamic_cast::GetValue,
-        parse::{parse_component, parse_value, PrefabParser, Rule},
+        parse::{parse_component, parse_value, LoadPrefabError, PrefabParser, Rule},
+        registry::CommandConflictPolicy,
     };
 
     use super::{parse_command, parse_field, parse_string};
@@ -339,7 +1305,7 @@ mod test {
             .next()
             .unwrap();
 
-        let parsed = parse_command(parse).unwrap();
+        let parsed = parse_command(parse, &PrefabRegistry::default()).unwrap();
 
         let props = parsed.properties.unwrap();
 
@@ -348,6 +1314,147 @@ mod test {
         assert_eq!(i, 10);
     }
 
+    #[test]
+    fn command_defaults() {
+        let mut reg = PrefabRegistry::default();
+        reg.set_command_defaults("DoStuff", "a: 1, b: 2, style { x: 1, y: 2 }")
+            .unwrap();
+
+        let prefab =
+            parse_prefab_str("{ DoStuff!(b: 5, style { y: 7 }), DoStuff!() }", &reg).unwrap();
+        let props: Vec<_> = prefab
+            .steps
+            .iter()
+            .map(|step| match step {
+                PrefabBuildStep::RunCommand(data) => data.properties.as_ref().unwrap(),
+                _ => unreachable!(),
+            })
+            .collect();
+
+        assert_eq!(*props[0].get::<i32>("a"), 1);
+        assert_eq!(*props[0].get::<i32>("b"), 5);
+        assert_eq!(*props[0].get_path::<i32>("style.x"), 1);
+        assert_eq!(*props[0].get_path::<i32>("style.y"), 7);
+        assert_eq!(*props[1].get::<i32>("b"), 2);
+
+        assert!(reg.set_command_defaults("DoStuff", "a: ").is_err());
+        reg.clear_command_defaults("DoStuff");
+        let prefab = parse_prefab_str("{ DoStuff!() }", &reg).unwrap();
+        match &prefab.steps[0] {
+            PrefabBuildStep::RunCommand(data) => assert!(data.properties.is_none()),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn command_alias() {
+        let mut reg = PrefabRegistry::default();
+        reg.alias_command("Old", "New");
+        reg.deprecate_command_alias("Older", "New");
+        reg.set_command_defaults("New", "a: 1").unwrap();
+
+        let prefab = parse_prefab_str("{ Old!(), Older!(), Other!() }", &reg).unwrap();
+        let commands: Vec<_> = prefab
+            .steps
+            .iter()
+            .map(|step| match step {
+                PrefabBuildStep::RunCommand(data) => data,
+                _ => unreachable!(),
+            })
+            .collect();
+
+        assert_eq!(commands[0].name(), "New");
+        assert_eq!(commands[0].deprecated_key, None);
+        assert_eq!(*commands[0].properties.as_ref().unwrap().get::<i32>("a"), 1);
+        assert_eq!(commands[1].name(), "New");
+        assert_eq!(commands[1].deprecated_key.as_deref(), Some("Older"));
+        assert_eq!(commands[2].name(), "Other");
+    }
+
+    #[test]
+    fn command_conflicts() {
+        let mut reg = PrefabRegistry::default();
+        reg.register_command_fn("B", |_, _, _| {});
+        reg.register_command_fn("A", |_, _, _| {});
+        reg.alias_command("C", "A");
+        assert_eq!(reg.command_keys(), vec!["A", "B"]);
+        assert!(reg.has_command("C"));
+        assert!(!reg.has_command("D"));
+
+        reg.set_command_conflict_policy(CommandConflictPolicy::Override);
+        reg.register_command_fn("A", |_, _, _| {});
+        assert_eq!(reg.command_keys().len(), 2);
+
+        reg.set_command_conflict_policy(CommandConflictPolicy::Error);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            reg.register_command_fn("A", |_, _, _| {})
+        }));
+        assert!(result.is_err());
+    }
+
+    #[derive(Default, Clone, PartialEq, Debug, Reflect)]
+    #[reflect_value(Component)]
+    struct ItemId(u64);
+
+    impl std::str::FromStr for ItemId {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.strip_prefix("sku-") {
+                Some(id) => id.parse().map(ItemId).map_err(|_| s.to_string()),
+                None => s.parse().map(ItemId).map_err(|_| s.to_string()),
+            }
+        }
+    }
+
+    #[derive(Default, Reflect)]
+    #[reflect(Component)]
+    struct Shop {
+        item: ItemId,
+        price: i32,
+    }
+
+    #[test]
+    fn value_literals() {
+        use crate::test_utils::*;
+
+        let mut world = prefab_world();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_value_type::<ItemId>();
+            reg.register_type::<Shop>();
+        });
+
+        let entity = spawn_prefab_str(&mut world, "{ ItemId(\"sku-42\") }").unwrap();
+        assert_component(&world, entity, &ItemId(42));
+        let entity = spawn_prefab_str(&mut world, "{ ItemId(7) }").unwrap();
+        assert_component(&world, entity, &ItemId(7));
+
+        let entity =
+            spawn_prefab_str(&mut world, "{ Shop { item: \"sku-3\", price: 5 } }").unwrap();
+        assert_eq!(world.get::<Shop>(entity).unwrap().item, ItemId(3));
+
+        assert!(spawn_prefab_str(&mut world, "{ ItemId(\"bad\") }").is_err());
+
+        #[derive(Default, Clone, Reflect)]
+        #[reflect_value(Component)]
+        struct Wide(u64);
+
+        let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+        reg.register_type::<Wide>();
+        for input in ["{ Wide { x: 5 } }", "{ Wide(\"5\") }", "{ Wide(true) }"] {
+            let err = parse_prefab_str(input, &reg).unwrap_err();
+            assert!(
+                matches!(&err, LoadPrefabError::ValueParseError(t, _) if t == "Wide"),
+                "{}: {:?}",
+                input,
+                err
+            );
+        }
+        drop(reg);
+        let entity = spawn_prefab_str(&mut world, "{ Wide }").unwrap();
+        assert_eq!(world.get::<Wide>(entity).unwrap().0, 0);
+    }
+
     #[test]
     fn prefab_parse() {
         let input = "SomeName { dosomething!(), Visible, Draw }";
@@ -361,25 +1468,622 @@ mod test {
         assert_eq!(prefab.name, Some("SomeName".to_string()));
 
         match &prefab.steps[0] {
-            PrefabBuildStep::AddComponent(_) => unreachable!(),
             PrefabBuildStep::RunCommand(command) => {
                 assert_eq!(command.name, "dosomething");
             }
+            _ => unreachable!(),
         }
 
         match &prefab.steps[1] {
             PrefabBuildStep::AddComponent(comp) => {
-                assert_eq!(comp.type_name, "Visible");
+                assert_eq!(&*comp.type_name, "Visible");
             }
-            PrefabBuildStep::RunCommand(_) => unreachable!(),
+            _ => unreachable!(),
         }
 
         match &prefab.steps[2] {
             PrefabBuildStep::AddComponent(comp) => {
-                assert_eq!(comp.type_name, "Draw");
+                assert_eq!(&*comp.type_name, "Draw");
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn children_parse() {
+        let input = "{ Visible, children [ { Draw }, Child { Visible, children [ {} ] } ] }";
+        let mut parsed = PrefabParser::parse(Rule::prefab, input).unwrap();
+        let mut reg = PrefabRegistry::default();
+        reg.register_type::<Visible>();
+        reg.register_type::<Draw>();
+
+        let prefab = parse_prefab(parsed.next().unwrap(), &reg).unwrap();
+
+        assert_eq!(prefab.steps.len(), 3);
+
+        match &prefab.steps[2] {
+            PrefabBuildStep::AddChild(child) => {
+                assert_eq!(child.name, Some("Child".to_string()));
+                assert_eq!(child.steps.len(), 2);
             }
-            PrefabBuildStep::RunCommand(_) => unreachable!(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn version_migration() {
+        let mut reg = PrefabRegistry::default();
+        reg.register_type::<Visible>();
+        reg.register_type::<Draw>();
+        reg.add_migration(1, 2, |s| s.replace("Hidden", "Visible"));
+        reg.add_migration(2, 3, |s| s.replace("Visible", "Draw"));
+
+        let prefab = parse_prefab_str("{ Hidden }", &reg).unwrap();
+        match &prefab.steps[0] {
+            PrefabBuildStep::AddComponent(comp) => assert_eq!(&*comp.type_name, "Draw"),
+            _ => unreachable!(),
+        }
+
+        let prefab = parse_prefab_str("version: 2\n{ Visible }", &reg).unwrap();
+        match &prefab.steps[0] {
+            PrefabBuildStep::AddComponent(comp) => assert_eq!(&*comp.type_name, "Draw"),
+            _ => unreachable!(),
+        }
+
+        let prefab = parse_prefab_str("version: 3 { Visible }", &reg).unwrap();
+        match &prefab.steps[0] {
+            PrefabBuildStep::AddComponent(comp) => assert_eq!(&*comp.type_name, "Visible"),
+            _ => unreachable!(),
+        }
+    }
+
+    #[derive(Default, Reflect, Clone, PartialEq, Debug)]
+    #[reflect_value(PartialEq)]
+    struct Wrapper(i32);
+
+    #[test]
+    fn collection_component_parse() {
+        use bevy::reflect::GetTypeRegistration;
+
+        use crate::build_commands::apply_value;
+
+        let mut reg = PrefabRegistry::default();
+        reg.register_type_as::<Vec<i32>>("IntList");
+        reg.register_type_as::<Vec<u8>>("Bytes");
+        reg.register_type_as::<bevy::utils::HashMap<String, f32>>("Speeds");
+        reg.register_type::<Wrapper>();
+
+        let build = |input: &str| {
+            let parsed = PrefabParser::parse(Rule::component, input)
+                .unwrap()
+                .next()
+                .unwrap();
+            parse_component(parsed, &reg).unwrap().reflect
+        };
+
+        let mut list = Vec::<i32>::default();
+        list.apply(&*build("IntList [1, 2, 3]"));
+        assert_eq!(list, vec![1, 2, 3]);
+
+        let mut bytes = Vec::<u8>::default();
+        bytes.apply(&*build("Bytes [7, 255]"));
+        assert_eq!(bytes, vec![7, 255]);
+        let overflow = PrefabParser::parse(Rule::component, "Bytes [256]").unwrap();
+        assert!(parse_component(overflow.into_iter().next().unwrap(), &reg).is_err());
+
+        let mut map = bevy::utils::HashMap::<String, f32>::default();
+        let registration = bevy::utils::HashMap::<String, f32>::get_type_registration();
+        apply_value(
+            &registration,
+            &mut map,
+            &*build("Speeds { walk: 2.0, run: 5.5 }"),
+        );
+        apply_value(&registration, &mut map, &*build("Speeds { run: 6.0 }"));
+        assert_eq!(map.get("walk"), Some(&2.0));
+        assert_eq!(map.get("run"), Some(&6.0));
+
+        let value = build("Wrapper");
+        assert_eq!(value.downcast_ref::<Wrapper>(), Some(&Wrapper(0)));
+    }
+
+    #[test]
+    fn library_parse() {
+        use crate::parse::parse_prefab_library_string;
+
+        let mut reg = PrefabRegistry::default();
+        reg.register_type::<Transform>();
+        reg.register_type::<Visible>();
+
+        let input = "
+            HealthPotion { Transform }
+            // Comments are allowed between prefabs.
+            ManaPotion { Transform, Visible },
+        ";
+        let prefabs = parse_prefab_library_string(input, &reg).unwrap();
+        let names: Vec<_> = prefabs.iter().map(|p| p.name.as_deref().unwrap()).collect();
+        assert_eq!(names, ["HealthPotion", "ManaPotion"]);
+        assert_eq!(prefabs[1].steps.len(), 2);
+
+        let unnamed = parse_prefab_library_string("Named { Transform } { Visible }", &reg);
+        assert!(matches!(
+            unnamed,
+            Err(crate::LoadPrefabError::UnnamedLibraryPrefab(1))
+        ));
+    }
+
+    #[derive(Default, Reflect)]
+    #[reflect(Component)]
+    struct Gauge {
+        angle: f32,
+        width: f32,
+        margin: Val,
+    }
+
+    #[test]
+    fn unit_suffixes() {
+        use crate::test_utils::*;
+
+        let mut world = prefab_world();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_type::<Gauge>();
+        });
+
+        let input = "{ Gauge { angle: 90deg, width: 16px, margin: 50% } }";
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        let gauge = world.get::<Gauge>(entity).unwrap();
+        assert!((gauge.angle - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+        assert_eq!(gauge.width, 16.0);
+        assert_eq!(gauge.margin, Val::Percent(50.0));
+
+        let entity =
+            spawn_prefab_str(&mut world, "{ Gauge { angle: 0.5turn, margin: 8px } }").unwrap();
+        let gauge = world.get::<Gauge>(entity).unwrap();
+        assert!((gauge.angle - std::f32::consts::PI).abs() < 1e-6);
+        assert_eq!(gauge.margin, Val::Px(8.0));
+    }
+
+    #[test]
+    fn ui_values() {
+        use crate::test_utils::*;
+
+        let mut world = prefab_world();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_type::<Gauge>();
+            reg.register_component::<Style>();
+            reg.register_type_as::<Rect<Val>>("Rect");
+            reg.register_type_as::<Size<Val>>("Size");
+        });
+
+        let entity = spawn_prefab_str(&mut world, "{ Gauge { margin: Val::Auto } }").unwrap();
+        assert_eq!(world.get::<Gauge>(entity).unwrap().margin, Val::Auto);
+
+        let input = "{ Style {
+            margin: Rect { left: Px(10), right: Percent(50), top: 4px },
+            size: Size { width: Auto, height: Val::Percent(25.5) },
+        } }";
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        let style = world.get::<Style>(entity).unwrap();
+        assert_eq!(style.margin.left, Val::Px(10.0));
+        assert_eq!(style.margin.right, Val::Percent(50.0));
+        assert_eq!(style.margin.top, Val::Px(4.0));
+        assert_eq!(style.margin.bottom, Val::Undefined);
+        assert_eq!(style.size.width, Val::Auto);
+        assert_eq!(style.size.height, Val::Percent(25.5));
+
+        let reg = world.get_resource::<PrefabRegistry>().unwrap();
+        assert!(parse_prefab_str("{ Gauge { margin: Px(ten) } }", reg).is_err());
+    }
+
+    #[derive(Default, Reflect)]
+    #[reflect(Component)]
+    struct Cooldown {
+        time: bevy::utils::Duration,
+        ready: bool,
+    }
+
+    #[test]
+    fn durations() {
+        use crate::{build_commands::InsertTimer, test_utils::*};
+        use bevy::utils::Duration;
+
+        let mut world = prefab_world();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_type::<Cooldown>();
+            reg.register_build_command::<InsertTimer>();
+        });
+
+        let input = "{ Cooldown { time: 2.5s, ready: true }, InsertTimer!(seconds: 300ms, repeating: true) }";
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        let cooldown = world.get::<Cooldown>(entity).unwrap();
+        assert_eq!(cooldown.time, Duration::from_millis(2500));
+        assert!(cooldown.ready);
+        let timer = world.get::<Timer>(entity).unwrap();
+        assert_eq!(timer.duration(), Duration::from_millis(300));
+        assert!(timer.repeating());
+
+        let entity = spawn_prefab_str(&mut world, "{ InsertTimer!(seconds: 2) }").unwrap();
+        let timer = world.get::<Timer>(entity).unwrap();
+        assert_eq!(timer.duration(), Duration::from_secs(2));
+        assert!(!timer.repeating());
+
+        assert!(spawn_prefab_str(&mut world, "{ Cooldown { time: -1s } }").is_err());
+    }
+
+    #[derive(Default, Reflect)]
+    #[reflect(Component)]
+    struct Widths {
+        a: i8,
+        b: i16,
+        c: i64,
+        d: i128,
+        e: isize,
+        f: u8,
+        g: u16,
+        h: u32,
+        i: u64,
+        j: u128,
+        k: usize,
+    }
+
+    #[derive(Default, Reflect)]
+    #[reflect(Component)]
+    struct Layer(u8);
+
+    #[test]
+    fn integer_widths() {
+        use crate::{test_utils::*, PrefabVars};
+
+        let mut world = prefab_world();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_type::<Stats>();
+            reg.register_type::<Widths>();
+            reg.register_type::<Layer>();
+        });
+
+        let input = "{ Widths { a: -128, b: 300, c: 5000000000, d: -5, e: -7, f: 255, g: 65535, \
+            h: 4000000000, i: 18446744073709551615, j: 9, k: 12 }, Layer(3) }";
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        let widths = world.get::<Widths>(entity).unwrap();
+        assert_eq!(widths.a, -128);
+        assert_eq!(widths.b, 300);
+        assert_eq!(widths.c, 5_000_000_000);
+        assert_eq!(widths.d, -5);
+        assert_eq!(widths.e, -7);
+        assert_eq!(widths.f, 255);
+        assert_eq!(widths.g, 65535);
+        assert_eq!(widths.h, 4_000_000_000);
+        assert_eq!(widths.i, u64::MAX);
+        assert_eq!(widths.j, 9);
+        assert_eq!(widths.k, 12);
+        assert_eq!(world.get::<Layer>(entity).unwrap().0, 3);
+
+        let reg = world.get_resource::<PrefabRegistry>().unwrap();
+        let prefab = parse_prefab_str(input, reg).unwrap();
+        assert!(prefab.to_string().contains("i: 18446744073709551615"));
+
+        for (field, value, type_name) in [
+            ("a", "128", "i8"),
+            ("b", "-32769", "i16"),
+            ("c", "9223372036854775808", "i64"),
+            ("e", "170141183460469231731687303715884105728", "i32"),
+            ("f", "-1", "u8"),
+            ("g", "65536", "u16"),
+            ("h", "-1", "u32"),
+            ("i", "18446744073709551616", "u64"),
+            ("j", "-1", "u128"),
+            ("k", "-1", "usize"),
+        ] {
+            let input = format!("{{ Widths {{ {}: {} }} }}", field, value);
+            let err = parse_prefab_str(&input, reg).unwrap_err();
+            assert!(
+                matches!(&err, LoadPrefabError::ValueParseError(t, v) if t == type_name && v == value),
+                "{}: {:?}",
+                field,
+                err
+            );
         }
+        let err = parse_prefab_str("{ Layer(256) }", reg).unwrap_err();
+        assert!(matches!(err, LoadPrefabError::ValueParseError(t, _) if t == "u8"));
+        let err = parse_prefab_str("{ Stats { health: 3000000000 } }", reg).unwrap_err();
+        assert!(matches!(err, LoadPrefabError::ValueParseError(t, _) if t == "i32"));
+
+        // Deferred values are converted when they're evaluated.
+        let mut vars = PrefabVars::default();
+        vars.set("hp", 150);
+        vars.set("layer", 7);
+        vars.set("big", 300);
+        vars.set("scale", 2.5_f32);
+        world.insert_resource(vars);
+        let input = "{ Widths { h: rand_int(4..=4), i: ${hp}, f: ${layer}, g: ${big} }, \
+            Stats { speed: ${hp}, health: ${hp} }, Layer(${layer}) }";
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        let widths = world.get::<Widths>(entity).unwrap();
+        assert_eq!((widths.h, widths.i, widths.f, widths.g), (4, 150, 7, 300));
+        let stats = world.get::<Stats>(entity).unwrap();
+        assert_eq!((stats.speed, stats.health), (150.0, 150));
+        assert_eq!(world.get::<Layer>(entity).unwrap().0, 7);
+
+        // Values which don't fit are skipped.
+        let input = "{ Widths { f: ${big}, a: ${scale}, b: 2 } }";
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        let widths = world.get::<Widths>(entity).unwrap();
+        assert_eq!((widths.f, widths.a, widths.b), (0, 0, 2));
+    }
+
+    #[derive(Default, Reflect)]
+    #[reflect(Component)]
+    struct SpawnRanges {
+        interval: Range<f32>,
+        precise: Range<f64>,
+        count: Range<u8>,
+        frames: Range<usize>,
+        ids: Range<u64>,
+        offset: Range<i32>,
+    }
+
+    #[test]
+    fn typed_ranges() {
+        use super::parse_command_properties;
+        use crate::{deferred::DeferredValue, test_utils::*};
+
+        let mut world = prefab_world();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_type::<SpawnRanges>();
+        });
+
+        let input = "{ SpawnRanges { interval: (0.5..2.0), precise: (0.1..0.2), count: (1..=5), \
+            frames: (0..4), ids: (0..10000000000), offset: (-3..=3) } }";
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        let spawner = world.get::<SpawnRanges>(entity).unwrap();
+        assert_eq!(spawner.interval, 0.5..2.0);
+        assert_eq!(spawner.precise, 0.1..0.2);
+        assert_eq!(spawner.count, 1..6);
+        assert_eq!(spawner.frames, 0..4);
+        assert_eq!(spawner.ids, 0..10_000_000_000);
+        assert_eq!(spawner.offset, -3..4);
+
+        let reg = world.get_resource::<PrefabRegistry>().unwrap();
+        let text = parse_prefab_str(input, reg).unwrap().to_string();
+        assert!(text.contains("interval: (0.5..2.0)"));
+        assert!(text.contains("count: (1..6)"));
+        assert!(parse_prefab_str(&text, reg).is_ok());
+
+        // Without a field to fit, integer ranges are a Range<i32> and float ranges a Range<f32>.
+        let props =
+            parse_command_properties("a: (1..=3), b: (0..1.5), c: rand_int(1..=6)", reg).unwrap();
+        assert_eq!(*props.get::<Range<i32>>("a"), 1..4);
+        assert_eq!(*props.get::<Range<f32>>("b"), 0.0..1.5);
+        assert!(
+            matches!(props.get::<DeferredValue>("c"), DeferredValue::RandomInt(r) if *r == (1..7))
+        );
+
+        let err = parse_prefab_str("{ SpawnRanges { count: (0..=255) } }", reg).unwrap_err();
+        assert!(
+            matches!(&err, LoadPrefabError::ValueParseError(t, v) if t == "Range<u8>" && v == "0..256")
+        );
+        let err = parse_prefab_str("{ SpawnRanges { frames: (-1..2) } }", reg).unwrap_err();
+        assert!(matches!(err, LoadPrefabError::ValueParseError(t, _) if t == "Range<usize>"));
+        let err = parse_prefab_str("{ SpawnRanges { interval: (0.5..=2.0) } }", reg).unwrap_err();
+        assert!(matches!(err, LoadPrefabError::ValueParseError(t, _) if t == "Range<f32>"));
+        let err = parse_prefab_str("{ SpawnRanges { offset: (0.5..2.0) } }", reg).unwrap_err();
+        assert!(
+            matches!(&err, LoadPrefabError::ValueParseError(t, v) if t == "Range<i32>" && v == "(0.5..2.0)")
+        );
+        let err = parse_prefab_str("{ SpawnRanges { count: (0.5..2.0) } }", reg).unwrap_err();
+        assert!(matches!(err, LoadPrefabError::ValueParseError(t, _) if t == "Range<u8>"));
+    }
+
+    #[test]
+    fn nested_command_properties() {
+        use bevy::reflect::{DynamicList, DynamicStruct, List};
+
+        let mut reg = PrefabRegistry::default();
+        reg.register_command_fn("Emit", |_, _, _| {});
+
+        let input = "{ Emit!(layers: [{ tile: 1, offset: Vec2 { x: 2.0 } }, { tile: 2 }], shape: { radius: 3.0 }) }";
+        let prefab = parse_prefab_str(input, &reg).unwrap();
+        let props = match &prefab.steps[0] {
+            PrefabBuildStep::RunCommand(command) => command.properties.as_ref().unwrap(),
+            _ => unreachable!(),
+        };
+
+        let layers = props.get::<DynamicList>("layers");
+        assert_eq!(layers.len(), 2);
+        let layer = layers.get(0).unwrap().cast_ref::<DynamicStruct>();
+        assert_eq!(*layer.get::<i32>("tile"), 1);
+        assert_eq!(*layer.get::<Vec2>("offset"), Vec2::new(2.0, 0.0));
+        let shape = props.get::<DynamicStruct>("shape");
+        assert_eq!(*shape.get::<f32>("radius"), 3.0);
+
+        let text = prefab.to_string();
+        assert!(
+            text.contains("layers: [{ tile: 1, offset: Vec2 { x: 2.0, y: 0.0 } }, { tile: 2 }],")
+        );
+        assert!(text.contains("shape { radius: 3.0 },"));
+        assert!(parse_prefab_str(&text, &reg).is_ok());
+    }
+
+    #[test]
+    fn numeric_field_errors() {
+        use super::LoadPrefabError;
+
+        let mut reg = PrefabRegistry::default();
+        reg.register_type::<Transform>();
+
+        let prefab = parse_prefab_str("{ Transform { translation: Vec3 { x: 5 } } }", &reg);
+        assert!(prefab.is_ok());
+
+        let err = parse_prefab_str("{ Transform { translation: Vec3 { x: \"a\" } } }", &reg);
+        assert!(
+            matches!(err, Err(LoadPrefabError::ValueParseError(t, v)) if t == "f32" && v == "x: \"a\"")
+        );
+
+        let mut parsed = PrefabParser::parse(Rule::color, "Color { r: \"red\" }").unwrap();
+        let err = parse_value(parsed.next().unwrap(), &reg);
+        assert!(matches!(err, Err(LoadPrefabError::ValueParseError(..))));
+    }
+
+    #[test]
+    fn meta_block() {
+        let mut reg = PrefabRegistry::default();
+        reg.register_type::<Stats>();
+
+        let input = "Bat {
+            meta { tags: [\"enemy\", \"flying\"], category: \"units\", description: \"Flaps\" },
+            Stats,
+        }";
+        let prefab = reg.replace_prefab("bat", input).unwrap().clone();
+        assert_eq!(prefab.meta().tags, ["enemy", "flying"]);
+        assert_eq!(prefab.meta().category.as_deref(), Some("units"));
+        assert_eq!(prefab.meta().description.as_deref(), Some("Flaps"));
+        assert_eq!(prefab.steps().len(), 1);
+
+        let reloaded = parse_prefab_str(&prefab.to_string(), &reg).unwrap();
+        assert_eq!(reloaded.meta(), prefab.meta());
+
+        reg.replace_prefab("orc", "{ meta { tags: [\"enemy\"] } }")
+            .unwrap();
+        reg.replace_prefab("tree", "{ Stats }").unwrap();
+        assert_eq!(reg.find_by_tag("enemy"), ["bat", "orc"]);
+        assert_eq!(reg.find_by_tag("flying"), ["bat"]);
+        assert!(reg.find_by_tag("plant").is_empty());
+
+        assert!(parse_prefab_str("{ meta { tags: [1] } }", &reg).is_err());
+        assert!(parse_prefab_str("{ meta { colour: \"red\" } }", &reg).is_err());
+    }
+
+    #[test]
+    fn tolerant_parse() {
+        let mut reg = PrefabRegistry::default();
+        reg.register_type::<Visible>();
+        reg.register_type::<Draw>();
+        reg.register_type::<Transform>();
+
+        let input = "// A comment before the prefab
+            Tolerant {
+                Transform {
+                    translation: Vec3 {
+                        x: 1.0
+                        y: 2.0,
+                    }
+                }
+                Visible
+                DoStuff!(a: [1 2 3,], b: 2)
+                Draw {},
+                children [ {} {}, ],
+            } // A comment at the end";
+
+        let prefab = parse_prefab_str(input, &reg).unwrap();
+        assert_eq!(prefab.steps.len(), 6);
+
+        assert!(parse_prefab_str("{ Visible } Draw", &reg).is_err());
+    }
+
+    #[derive(Default, Reflect)]
+    struct Inner {
+        a: i32,
+        deeper: Deeper,
+    }
+
+    #[derive(Default, Reflect)]
+    struct Deeper {
+        b: f32,
+        c: String,
+    }
+
+    #[derive(Default, Reflect)]
+    #[reflect(Component)]
+    struct Outer {
+        inner: Inner,
+        d: i32,
+    }
+
+    #[test]
+    fn nested_struct_parse() {
+        let mut reg = PrefabRegistry::default();
+        reg.register_type::<Outer>();
+        reg.register_type::<Inner>();
+        reg.register_type::<Deeper>();
+
+        let input = "Outer {
+            inner: Inner {
+                a: 3,
+                deeper: Deeper { c: \"deep\" },
+            },
+            d: 5,
+        }";
+        let parsed = PrefabParser::parse(Rule::component, input)
+            .unwrap()
+            .next()
+            .unwrap();
+        let comp = parse_component(parsed, &reg).unwrap();
+
+        let mut outer = Outer::default();
+        outer.apply(&*comp.reflect);
+
+        assert_eq!(outer.inner.a, 3);
+        assert_eq!(outer.inner.deeper.b, 0.0);
+        assert_eq!(outer.inner.deeper.c, "deep");
+        assert_eq!(outer.d, 5);
+
+        let prefab = parse_prefab_str(&format!("{{ {} }}", input), &reg).unwrap();
+        assert_eq!(
+            prefab.to_string(),
+            "{
+    Outer {
+        inner: Inner { a: 3, deeper: Deeper { c: \"deep\" } },
+        d: 5,
+    },
+}
+"
+        );
+
+        let input = "Outer { inner: Unregistered { a: 3 } }";
+        let parsed = PrefabParser::parse(Rule::component, input)
+            .unwrap()
+            .next()
+            .unwrap();
+        assert!(parse_component(parsed, &reg).is_err());
+    }
+
+    #[derive(Default, Reflect)]
+    #[reflect(Component)]
+    struct Tinted {
+        range: ColorRange,
+        pair: Vec2Pair,
+    }
+
+    #[derive(Default, Reflect)]
+    struct ColorRange {
+        lo: f32,
+    }
+
+    #[derive(Default, Reflect)]
+    struct Vec2Pair {
+        a: Vec2,
+    }
+
+    #[test]
+    fn keyword_prefixed_struct_parse() {
+        let mut reg = PrefabRegistry::default();
+        reg.register_type::<Tinted>();
+        reg.register_type::<ColorRange>();
+        reg.register_type::<Vec2Pair>();
+
+        let input = "Tinted {
+            range: ColorRange { lo: 1.0 }
+            pair: Vec2Pair { a: Vec2 { x: 2.0 } }
+        }";
+        let parsed = PrefabParser::parse(Rule::component, input)
+            .unwrap()
+            .next()
+            .unwrap();
+        let comp = parse_component(parsed, &reg).unwrap();
+
+        let mut tinted = Tinted::default();
+        tinted.apply(&*comp.reflect);
+        assert_eq!(tinted.range.lo, 1.0);
+        assert_eq!(tinted.pair.a, Vec2::new(2.0, 0.0));
     }
 
     #[test]
@@ -389,7 +2093,7 @@ mod test {
             .unwrap()
             .next()
             .unwrap();
-        let parsed = parse_value(parse);
+        let parsed = parse_value(parse, &PrefabRegistry::default());
         assert!(parsed.is_ok());
         let val = *parsed.unwrap().downcast::<u8>().unwrap();
         assert_eq!(val as char, 'a');
@@ -403,7 +2107,7 @@ mod test {
             .next()
             .unwrap();
 
-        let parsed = parse_value(parse);
+        let parsed = parse_value(parse, &PrefabRegistry::default());
         let val = *parsed.unwrap().downcast::<Color>().unwrap();
 
         assert_eq!(Color::RED, val);
@@ -414,12 +2118,35 @@ mod test {
             .next()
             .unwrap();
 
-        let parsed = parse_value(parse);
+        let parsed = parse_value(parse, &PrefabRegistry::default());
         let col = *parsed.unwrap().downcast::<Color>().unwrap();
         assert_eq!(1.0, col.r());
         assert_eq!(0.5, col.g());
     }
 
+    #[test]
+    fn palette_color_parse() {
+        let mut reg = PrefabRegistry::default();
+        let accent = Color::rgb(0.1, 0.2, 0.3);
+        reg.register_color("UI_ACCENT", accent);
+
+        let parse = PrefabParser::parse(Rule::color, "Color::UI_ACCENT")
+            .unwrap()
+            .next()
+            .unwrap();
+        let col = *parse_value(parse, &reg)
+            .unwrap()
+            .downcast::<Color>()
+            .unwrap();
+        assert_eq!(accent, col);
+
+        let parse = PrefabParser::parse(Rule::color, "Color::NOT_A_COLOR")
+            .unwrap()
+            .next()
+            .unwrap();
+        assert!(parse_value(parse, &reg).is_err());
+    }
+
     #[test]
     fn vec_parse() {
         let input = "Vec3 { z: 3.0, x: 10.0 }";
@@ -435,7 +2162,7 @@ mod test {
 
         let mut v = Vec3::default();
 
-        let dynamic = parse_value(parse).unwrap();
+        let dynamic = parse_value(parse, &reg).unwrap();
 
         v.apply(&*dynamic);
 
@@ -474,6 +2201,16 @@ mod test {
         let str = parse_string(parsed.next().unwrap());
 
         assert_eq!("Hello", str);
+
+        let input = r#""a \"quoted\" path\\n
+        with\tescapes""#;
+        let mut parsed = PrefabParser::parse(Rule::string, input).unwrap();
+        let str = parse_string(parsed.next().unwrap());
+        assert_eq!("a \"quoted\" path\\n\n        with\tescapes", str);
+        assert_eq!(
+            crate::writer::write_value(&str).unwrap(),
+            r#""a \"quoted\" path\\n\n        with\tescapes""#
+        );
     }
 
     #[test]
@@ -481,7 +2218,7 @@ mod test {
         let input = "a: \"hi\"";
 
         let mut parse = PrefabParser::parse(Rule::field, input).unwrap();
-        let field = parse_field(parse.next().unwrap()).unwrap();
+        let field = parse_field(parse.next().unwrap(), &PrefabRegistry::default()).unwrap();
 
         assert_eq!("a", field.name);
         assert_eq!("hi", field.value.cast_ref::<String>());
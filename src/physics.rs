@@ -0,0 +1,158 @@
+use bevy::{prelude::*, reflect::DynamicStruct};
+use bevy_rapier3d::prelude::*;
+
+use crate::{
+    build_commands::{BuildPhase, BuildPrefabCommand, PrefabCommandError},
+    dynamic_cast::*,
+};
+
+/// Inserts a [RigidBodyBundle] positioned at the entity's `Transform`, if it has one. Requires
+/// the `rapier` feature, and `RapierPhysicsPlugin` must be added to the app.
+///
+/// ### Optional Properties:
+///
+/// - `body` - The type of rigid body. Accepts `"Dynamic"`, `"Static"`, `"KinematicPositionBased"`
+///    or `"KinematicVelocityBased"`. Defaults to `"Dynamic"`.
+/// - `linvel` - The initial linear velocity, as a `Vec3`.
+///
+/// ### Example
+///
+/// ```ignore
+/// {
+///     Transform { translation: Vec3 { y: 10.0 } },
+///     InsertRigidBody!(body: "Dynamic"),
+///     InsertCollider!(shape: "Ball", size: 0.5, mass: 2.0),
+/// }
+/// ```
+#[derive(Default)]
+pub struct InsertRigidBody;
+impl BuildPrefabCommand for InsertRigidBody {
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
+        let mut bundle = RigidBodyBundle::default();
+
+        if let Some(properties) = properties {
+            if let Ok(body) = properties.try_get::<String>("body") {
+                bundle.body_type = match body.as_str() {
+                    "Dynamic" => RigidBodyType::Dynamic,
+                    "Static" => RigidBodyType::Static,
+                    "KinematicPositionBased" => RigidBodyType::KinematicPositionBased,
+                    "KinematicVelocityBased" => RigidBodyType::KinematicVelocityBased,
+                    _ => {
+                        warn!("Unknown rigid body type '{}' in InsertRigidBody", body);
+                        RigidBodyType::Dynamic
+                    }
+                };
+            }
+            if let Ok(linvel) = properties.try_get::<Vec3>("linvel") {
+                bundle.velocity.linvel = [linvel.x, linvel.y, linvel.z].into();
+            }
+        }
+
+        let mut entity = world.entity_mut(entity);
+        if let Some(transform) = entity.get::<Transform>() {
+            let t = transform.translation;
+            bundle.position = [t.x, t.y, t.z].into();
+        }
+        entity
+            .insert_bundle(bundle)
+            .insert(RigidBodyPositionSync::Discrete);
+        Ok(())
+    }
+
+    fn key(&self) -> &str {
+        "InsertRigidBody"
+    }
+
+    fn phase(&self) -> BuildPhase {
+        // Runs after components so the body starts at the prefab's `Transform`.
+        BuildPhase::Post
+    }
+}
+
+/// Inserts a [ColliderBundle]. Requires the `rapier` feature.
+///
+/// If the entity has no rigid body the collider is static and positioned by its `Transform`.
+/// Otherwise it's attached to the body, so [InsertRigidBody] should be written first.
+///
+/// ### Optional Properties:
+///
+/// - `shape` - Accepts `"Ball"` or `"Cuboid"`. Defaults to `"Ball"`.
+/// - `size` - For a Ball the radius, as an `f32`. For a Cuboid the half extents, as a `Vec3`.
+/// - `mass` - The mass of the collider. The density is calculated from the size of the shape.
+/// - `density` - Used instead of `mass` to set the density directly.
+/// - `friction` - The friction coefficient, as an `f32`.
+/// - `restitution` - The restitution coefficient, as an `f32`.
+/// - `sensor` - If `true` the collider detects intersections without generating contacts.
+#[derive(Default)]
+pub struct InsertCollider;
+impl BuildPrefabCommand for InsertCollider {
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
+        let mut bundle = ColliderBundle::default();
+
+        if let Some(properties) = properties {
+            let shape = properties.get_or("shape", "Ball".to_string());
+            let volume = match shape.as_str() {
+                "Cuboid" => {
+                    let half = properties.get_or("size", Vec3::splat(0.5));
+                    bundle.shape = ColliderShape::cuboid(half.x, half.y, half.z);
+                    8.0 * half.x * half.y * half.z
+                }
+                other => {
+                    if other != "Ball" {
+                        warn!("Unknown collider shape '{}' in InsertCollider", other);
+                    }
+                    let radius = properties.try_get_f32("size").unwrap_or(0.5);
+                    bundle.shape = ColliderShape::ball(radius);
+                    4.0 / 3.0 * std::f32::consts::PI * radius.powi(3)
+                }
+            };
+
+            if let Ok(density) = properties.try_get_f32("density") {
+                bundle.mass_properties = ColliderMassProps::Density(density);
+            } else if let Ok(mass) = properties.try_get_f32("mass") {
+                if volume > 0.0 {
+                    bundle.mass_properties = ColliderMassProps::Density(mass / volume);
+                }
+            }
+            if let Ok(friction) = properties.try_get_f32("friction") {
+                bundle.material.friction = friction;
+            }
+            if let Ok(restitution) = properties.try_get_f32("restitution") {
+                bundle.material.restitution = restitution;
+            }
+            if properties.get_or("sensor", false) {
+                bundle.collider_type = ColliderType::Sensor;
+            }
+        }
+
+        let mut entity = world.entity_mut(entity);
+        if !entity.contains::<RigidBodyType>() {
+            if let Some(transform) = entity.get::<Transform>() {
+                let t = transform.translation;
+                bundle.position = [t.x, t.y, t.z].into();
+            }
+        }
+        entity
+            .insert_bundle(bundle)
+            .insert(ColliderPositionSync::Discrete);
+        Ok(())
+    }
+
+    fn key(&self) -> &str {
+        "InsertCollider"
+    }
+
+    fn phase(&self) -> BuildPhase {
+        BuildPhase::Post
+    }
+}
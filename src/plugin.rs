@@ -1,30 +1,379 @@
-use bevy::{
-    prelude::*,
-    render::{
-        camera::{Camera, OrthographicProjection},
-        render_graph::base::MainPass,
-    },
-};
+use std::path::PathBuf;
+
+use bevy::{asset::Asset, ecs::component::Component, prelude::*, reflect::GetTypeRegistration};
+
+#[cfg(feature = "render")]
+use bevy::render::camera::Camera;
+#[cfg(feature = "2d")]
+use bevy::render::camera::OrthographicProjection;
+#[cfg(feature = "3d")]
+use bevy::render::render_graph::base::MainPass;
 
-use crate::{build_commands::*, PrefabRegistry};
+use crate::{
+    asset_path::{resolve_asset_paths, PrefabAssetPath},
+    build_commands::*,
+    deferred::{PrefabFlags, PrefabLocalization, PrefabRng, PrefabVars},
+    dynamic_cast::GetValue,
+    instance::PrefabInstanceSpawned,
+    lifetime::despawn_expired,
+    lint,
+    pool::PrefabPool,
+    preload::{cache_preloaded_prefabs, spawn_loaded_prefab_handles, PrefabText, PrefabTextLoader},
+    registry::{PrefabChanged, PrefabLoadFailed},
+    spawn_queue::{spawn_queued_prefabs, PrefabBatchSpawned, PrefabSpawnQueue},
+    stream::apply_prefab_streams,
+    PrefabRegistry, SpawnNamedPrefabCommands,
+};
 
 /// Default plugin, registers many built-in bevy types and bundles and includes
 /// prefab commands for common assets.
-pub struct LazyPrefabsPlugin;
+///
+/// By default all of the built-in types and commands are registered. The plugin can
+/// be configured before it's added to the app. It must be added after bevy's `AssetPlugin`,
+/// which is part of `DefaultPlugins`, and panics otherwise.
+///
+/// ## Example
+///
+/// ```
+/// use bevy::prelude::*;
+/// use bevy_lazy_prefabs::*;
+///
+/// fn build(app: &mut AppBuilder) {
+///     app.add_plugin(LazyPrefabsPlugin::new().with_dir("data/prefabs").without_3d());
+/// }
+/// ```
+pub struct LazyPrefabsPlugin {
+    dir: Option<PathBuf>,
+    common_types: bool,
+    bevy_2d: bool,
+    bevy_3d: bool,
+    strict: bool,
+    track_usage: bool,
+    command_defaults: Vec<(String, String)>,
+    registrations: Vec<Registration>,
+}
+
+/// Registers types or commands with the [PrefabRegistry], see [LazyPrefabsPlugin::with_registration].
+type Registration = Box<dyn Fn(&mut PrefabRegistry) + Send + Sync + 'static>;
+
+impl Default for LazyPrefabsPlugin {
+    fn default() -> Self {
+        LazyPrefabsPlugin {
+            dir: None,
+            common_types: true,
+            bevy_2d: true,
+            bevy_3d: true,
+            strict: false,
+            track_usage: false,
+            command_defaults: Vec::new(),
+            registrations: Vec::new(),
+        }
+    }
+}
+
+impl LazyPrefabsPlugin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load prefab files from `dir` instead of *assets/prefabs*, see [PrefabRegistry::set_prefab_dir].
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    /// Don't register common bevy types such as `Transform`, or the `LoadPrefab` command.
+    pub fn without_common_types(mut self) -> Self {
+        self.common_types = false;
+        self
+    }
+
+    /// Don't register 2D types such as `Sprite`, or 2D commands such as `InsertSpriteBundle`.
+    ///
+    /// They're only registered if the `2d` feature is enabled.
+    pub fn without_2d(mut self) -> Self {
+        self.bevy_2d = false;
+        self
+    }
+
+    /// Don't register 3D types such as `Handle<Mesh>`, or 3D commands such as `InsertPbrBundle`.
+    ///
+    /// They're only registered if the `3d` feature is enabled.
+    pub fn without_3d(mut self) -> Self {
+        self.bevy_3d = false;
+        self
+    }
+
+    /// Set default properties for a build command, see [PrefabRegistry::set_command_defaults].
+    ///
+    /// Panics when the plugin is built if the properties can't be parsed.
+    pub fn with_command_defaults(mut self, command: &str, properties: &str) -> Self {
+        self.command_defaults
+            .push((command.to_string(), properties.to_string()));
+        self
+    }
+
+    /// Register a type along with the built-in types, see [PrefabRegistry::register_type].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// #[derive(Default, Reflect)]
+    /// #[reflect(Component)]
+    /// struct Health {
+    ///     max: i32,
+    /// }
+    ///
+    /// #[derive(Default, Reflect)]
+    /// #[reflect(Component)]
+    /// struct Speed(f32);
+    ///
+    /// fn build(app: &mut AppBuilder) {
+    ///     app.add_plugin(
+    ///         LazyPrefabsPlugin::new()
+    ///             .with_type::<Health>()
+    ///             .with_type::<Speed>(),
+    ///     );
+    /// }
+    /// ```
+    pub fn with_type<T: Reflect + GetTypeRegistration + Default>(self) -> Self {
+        self.with_registration(|reg| reg.register_type::<T>())
+    }
+
+    /// Register a component along with the built-in types, see
+    /// [PrefabRegistry::register_component].
+    pub fn with_component<T: Component + Reflect + GetTypeRegistration + Default>(self) -> Self {
+        self.with_registration(|reg| reg.register_component::<T>())
+    }
+
+    /// Run a function on the [PrefabRegistry] when the plugin is built, after the built-in types
+    /// and commands are registered. Useful for registering a game's types, commands and
+    /// companions in one place.
+    ///
+    /// Registrations run in the order they're added, so a later one can replace a built-in
+    /// command, see [PrefabRegistry::set_command_conflict_policy].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// #[derive(Default, Reflect)]
+    /// #[reflect(Component)]
+    /// struct Health {
+    ///     max: i32,
+    /// }
+    ///
+    /// fn register_game_types(registry: &mut PrefabRegistry) {
+    ///     registry.register_component::<Health>();
+    ///     registry.add_companion::<Health, Transform>();
+    /// }
+    ///
+    /// fn build(app: &mut AppBuilder) {
+    ///     app.add_plugin(LazyPrefabsPlugin::new().with_registration(register_game_types));
+    /// }
+    /// ```
+    pub fn with_registration(
+        mut self,
+        register: impl Fn(&mut PrefabRegistry) + Send + Sync + 'static,
+    ) -> Self {
+        self.registrations.push(Box::new(register));
+        self
+    }
+
+    /// Validate every prefab at the end of startup and panic with a report of all the
+    /// errors found, see [PrefabRegistry::validate_all].
+    ///
+    /// Validation runs after all startup systems, so types and commands registered in
+    /// startup systems are taken into account.
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    /// Count how many times each prefab is spawned, see [PrefabRegistry::usage_stats].
+    pub fn with_usage_stats(mut self) -> Self {
+        self.track_usage = true;
+        self
+    }
+}
 
 impl Plugin for LazyPrefabsPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_plugin(LazyPrefabsMinimalPlugin)
-            .add_plugin(LazyPrefabsCommonTypesPlugin)
-            .add_plugin(LazyPrefabsBevy3DPlugin)
-            .add_plugin(LazyPrefabsBevy2DPlugin);
+        app.add_plugin(LazyPrefabsMinimalPlugin);
+        if let Some(dir) = &self.dir {
+            app.world_mut()
+                .get_resource_mut::<PrefabRegistry>()
+                .unwrap()
+                .set_prefab_dir(dir);
+        }
+        if self.common_types {
+            app.add_plugin(LazyPrefabsCommonTypesPlugin);
+        }
+        #[cfg(feature = "3d")]
+        if self.bevy_3d {
+            app.add_plugin(LazyPrefabsBevy3DPlugin);
+        }
+        #[cfg(feature = "2d")]
+        if self.bevy_2d {
+            app.add_plugin(LazyPrefabsBevy2DPlugin);
+        }
+        let mut reg = app
+            .world_mut()
+            .get_resource_mut::<PrefabRegistry>()
+            .unwrap();
+        for register in self.registrations.iter() {
+            register(&mut reg);
+        }
+        if self.track_usage {
+            reg.set_track_usage(true);
+        }
+        for (command, properties) in self.command_defaults.iter() {
+            if let Err(e) = reg.set_command_defaults(command, properties) {
+                panic!("Invalid default properties for {}: {}", command, e);
+            }
+        }
+        if self.strict {
+            app.add_startup_system_to_stage(StartupStage::PostStartup, validate_prefabs.system());
+        }
     }
 }
 
+fn validate_prefabs(mut registry: ResMut<PrefabRegistry>) {
+    let errors = registry.validate_all();
+    if errors.is_empty() {
+        return;
+    }
+    let mut report = format!("Found {} invalid prefabs:", errors.len());
+    for e in errors {
+        report.push_str(&format!("\n{}: {}", e.name, e.error));
+    }
+    panic!("{}", report);
+}
+
 pub struct LazyPrefabsMinimalPlugin;
 impl Plugin for LazyPrefabsMinimalPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.init_resource::<PrefabRegistry>();
+        app.init_resource::<PrefabRegistry>()
+            .init_resource::<PrefabPool>()
+            .init_resource::<PrefabRng>()
+            .init_resource::<PrefabVars>()
+            .init_resource::<PrefabFlags>()
+            .init_resource::<PrefabLocalization>()
+            .add_event::<PrefabChanged>()
+            .add_event::<PrefabLoadFailed>()
+            .add_event::<PrefabInstanceSpawned>()
+            .add_event::<PrefabCommandFailed>()
+            .init_resource::<PrefabSpawnQueue>()
+            .add_event::<PrefabBatchSpawned>()
+            .add_system(spawn_queued_prefabs.exclusive_system())
+            .add_system(apply_prefab_streams.exclusive_system())
+            .add_system(send_prefab_changed_events.system())
+            .add_system(send_prefab_load_failed_events.system());
+
+        // Preloading needs the AssetServer, so without it preloaded prefabs would never load.
+        if app.world().get_resource::<AssetServer>().is_none() {
+            panic!("The prefab plugins must be added after bevy's AssetPlugin, ie: after DefaultPlugins.");
+        }
+        app.add_asset::<PrefabText>()
+            .init_asset_loader::<PrefabTextLoader>()
+            .add_system(cache_preloaded_prefabs.system())
+            .add_system(spawn_loaded_prefab_handles.system());
+    }
+}
+
+fn send_prefab_changed_events(
+    mut commands: Commands,
+    mut registry: ResMut<PrefabRegistry>,
+    mut events: EventWriter<PrefabChanged>,
+) {
+    for name in registry.take_changed() {
+        commands.update_prefab_instances(&name);
+        events.send(PrefabChanged { name });
+    }
+}
+
+/// Extension methods for registering prefab features that need their own systems.
+pub trait PrefabAppBuilderExt {
+    /// Register a build command which loads an asset of type `T` and inserts its
+    /// `Handle<T>` on the prefab entity.
+    ///
+    /// The command takes a single `path` property. The asset is loaded by a system
+    /// on the next update, see [crate::PrefabAssetPath].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn build(app: &mut AppBuilder) {
+    ///     // Used as `LoadTexture!(path: "alien.png")` in a prefab file.
+    ///     app.register_prefab_asset::<Texture>("LoadTexture");
+    /// }
+    /// ```
+    fn register_prefab_asset<T: Asset>(&mut self, key: &str) -> &mut Self;
+
+    /// Unload every prefab read from a file whenever an event of type `E` is sent, such as a
+    /// memory warning from the platform or a level transition, see
+    /// [PrefabRegistry::unload_files].
+    ///
+    /// The event must be added to the app separately.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// struct LowMemory;
+    ///
+    /// fn build(app: &mut AppBuilder) {
+    ///     app.add_event::<LowMemory>().unload_prefabs_on::<LowMemory>();
+    /// }
+    /// ```
+    fn unload_prefabs_on<E: Component>(&mut self) -> &mut Self;
+}
+
+impl PrefabAppBuilderExt for AppBuilder {
+    fn register_prefab_asset<T: Asset>(&mut self, key: &str) -> &mut Self {
+        let mut reg = self
+            .world_mut()
+            .get_resource_or_insert_with(PrefabRegistry::default);
+        reg.register_command_fn(key, |props, world, entity| {
+            if let Some(Ok(path)) = props.map(|p| p.try_get::<String>("path")) {
+                world
+                    .entity_mut(entity)
+                    .insert(PrefabAssetPath::<T>::new(path));
+            }
+        });
+        self.add_system(resolve_asset_paths::<T>.system())
+    }
+
+    fn unload_prefabs_on<E: Component>(&mut self) -> &mut Self {
+        self.add_system(unload_prefabs_on_event::<E>.system())
+    }
+}
+
+fn unload_prefabs_on_event<E: Component>(
+    mut registry: ResMut<PrefabRegistry>,
+    mut events: EventReader<E>,
+) {
+    if events.iter().count() > 0 {
+        registry.unload_files();
+    }
+}
+
+fn send_prefab_load_failed_events(
+    mut registry: ResMut<PrefabRegistry>,
+    mut events: EventWriter<PrefabLoadFailed>,
+) {
+    for failed in registry.take_failed() {
+        events.send(failed);
     }
 }
 
@@ -36,36 +385,80 @@ impl Plugin for LazyPrefabsCommonTypesPlugin {
             .get_resource_mut::<PrefabRegistry>()
             .unwrap();
 
-        reg.register_type::<Transform>();
-        reg.register_type::<GlobalTransform>();
-        reg.register_type::<Color>();
+        reg.register_component::<Transform>();
+        reg.register_component::<GlobalTransform>();
         reg.register_type::<Vec3>();
         reg.register_type::<Vec2>();
-        reg.register_type::<Camera>();
+        #[cfg(feature = "render")]
+        {
+            reg.register_type::<Color>();
+            reg.register_component::<Camera>();
+        }
+        #[cfg(feature = "ui")]
+        {
+            reg.register_bundle::<NodeBundle>();
+            reg.register_bundle::<ImageBundle>();
+            reg.register_bundle::<ButtonBundle>();
+            reg.register_bundle::<TextBundle>();
+            reg.register_bundle::<UiCameraBundle>();
+            reg.register_component::<Style>();
+            reg.register_type_as::<Rect<Val>>("Rect");
+            reg.register_type_as::<Size<Val>>("Size");
+        }
+
+        reg.add_companion::<Transform, GlobalTransform>();
+        reg.add_lint(lint::missing_transform);
 
         reg.register_build_command::<LoadPrefab>();
+        reg.register_build_command::<SpawnTileMap>();
+        reg.register_build_command::<InsertTimer>();
+        reg.register_build_command::<DespawnAfter>();
+        reg.alias_command("despawn_after", "DespawnAfter");
+        #[cfg(feature = "ui")]
+        reg.register_build_command::<InsertTextBundle>();
+        #[cfg(feature = "rhai")]
+        reg.register_build_command::<RunScript>();
+        #[cfg(feature = "rapier")]
+        {
+            reg.register_build_command::<InsertRigidBody>();
+            reg.register_build_command::<InsertCollider>();
+        }
+
+        app.add_system(despawn_expired.system());
     }
 }
 
+#[cfg(feature = "3d")]
 pub struct LazyPrefabsBevy3DPlugin;
+#[cfg(feature = "3d")]
 impl Plugin for LazyPrefabsBevy3DPlugin {
     fn build(&self, app: &mut AppBuilder) {
         let mut reg = app
             .world_mut()
             .get_resource_mut::<PrefabRegistry>()
             .unwrap();
-        reg.register_type::<Visible>();
-        reg.register_type::<Handle<Mesh>>();
-        reg.register_type::<RenderPipelines>();
-        reg.register_type::<Draw>();
-        reg.register_type::<MainPass>();
+        reg.register_component::<Visible>();
+        reg.register_component::<Handle<Mesh>>();
+        reg.register_component::<RenderPipelines>();
+        reg.register_component::<Draw>();
+        reg.register_component::<MainPass>();
+
+        reg.register_bundle::<PbrBundle>();
+        reg.register_bundle::<PerspectiveCameraBundle>();
+        reg.register_bundle::<LightBundle>();
 
         reg.register_build_command::<InsertPbrBundle>();
         reg.register_build_command::<InsertPerspectiveCameraBundle>();
+        reg.register_build_command::<InsertShaderPipeline>();
+
+        app.register_prefab_asset::<Mesh>("LoadMesh")
+            .register_prefab_asset::<StandardMaterial>("LoadStandardMaterial");
     }
 }
 
+#[cfg(feature = "2d")]
 pub struct LazyPrefabsBevy2DPlugin;
+#[cfg(feature = "2d")]
 impl Plugin for LazyPrefabsBevy2DPlugin {
     fn build(&self, app: &mut AppBuilder) {
         let mut reg = app
@@ -73,13 +466,136 @@ impl Plugin for LazyPrefabsBevy2DPlugin {
             .get_resource_mut::<PrefabRegistry>()
             .unwrap();
 
-        reg.register_type::<Sprite>();
-        reg.register_type::<OrthographicProjection>();
-        reg.register_type::<Handle<ColorMaterial>>();
-        reg.register_type::<Handle<TextureAtlas>>();
+        reg.register_component::<Sprite>();
+        reg.register_component::<OrthographicProjection>();
+        reg.register_component::<Handle<ColorMaterial>>();
+        reg.register_component::<Handle<TextureAtlas>>();
+
+        reg.register_bundle::<SpriteBundle>();
+        reg.register_bundle::<SpriteSheetBundle>();
+        reg.register_bundle::<Text2dBundle>();
 
         reg.register_build_command::<SetColorMaterial>();
         reg.register_build_command::<InsertSpriteBundle>();
+        reg.register_build_command::<InsertTextureAtlas>();
         reg.register_build_command::<InsertOrthographicCameraBundle>();
+        reg.register_build_command::<InsertText2dBundle>();
+
+        reg.add_lint(lint::color_material_without_sprite);
+
+        #[cfg(feature = "animation")]
+        {
+            reg.register_component::<crate::SpriteAnimation>();
+            reg.register_build_command::<InsertSpriteAnimation>();
+            app.add_system(crate::animation::animate_sprites.system());
+        }
+
+        #[cfg(feature = "tilemap")]
+        {
+            reg.register_build_command::<InsertTilemapLayer>();
+            app.add_system(crate::tilemap::build_tilemap_layers.system());
+        }
+
+        app.init_resource::<PendingTextureSamplers>()
+            .add_system(apply_pending_color_materials.system())
+            .add_system(apply_texture_samplers.system());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::*;
+
+    use crate::{
+        parse::parse_prefab_str,
+        test_utils::{Stats, TempDir},
+        PrefabRegistry,
+    };
+
+    #[test]
+    fn plugin_builder() {
+        use crate::LazyPrefabsPlugin;
+
+        let tmp = TempDir::new("plugin_dir");
+        let dir = tmp.path();
+        std::fs::write(dir.join("data.prefab"), "Data { Transform }").unwrap();
+
+        let mut app = App::build();
+        app.add_plugin(bevy::core::CorePlugin)
+            .add_plugin(bevy::asset::AssetPlugin)
+            .add_plugin(
+                LazyPrefabsPlugin::new()
+                    .with_dir(&dir)
+                    .without_3d()
+                    .without_2d(),
+            );
+        let mut reg = app
+            .world_mut()
+            .get_resource_mut::<PrefabRegistry>()
+            .unwrap();
+
+        let prefab = reg.load("data.prefab").unwrap();
+        assert_eq!(prefab.name.as_deref(), Some("Data"));
+        assert!(reg.load("bird.prefab").is_err());
+        assert!(parse_prefab_str("{ Visible }", &reg).is_err());
+    }
+
+    #[test]
+    fn plugin_requires_assets() {
+        use crate::LazyPrefabsPlugin;
+
+        let result = std::panic::catch_unwind(|| {
+            App::build().add_plugin(LazyPrefabsPlugin::new());
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn plugin_registrations() {
+        use crate::LazyPrefabsPlugin;
+
+        let mut app = App::build();
+        app.add_plugin(bevy::core::CorePlugin)
+            .add_plugin(bevy::asset::AssetPlugin)
+            .add_plugin(
+                LazyPrefabsPlugin::new()
+                    .without_3d()
+                    .without_2d()
+                    .with_type::<Stats>()
+                    .with_registration(|reg| reg.register_command_fn("Cheer", |_, _, _| {}))
+                    .with_command_defaults("Cheer", "volume: 2"),
+            );
+        let reg = app.world_mut().get_resource::<PrefabRegistry>().unwrap();
+
+        let prefab = parse_prefab_str("{ Transform, Stats { health: 1 }, Cheer!() }", reg);
+        assert!(prefab.is_ok());
+        assert!(reg.has_command("Cheer"));
+    }
+
+    #[test]
+    fn validate_all() {
+        let tmp = TempDir::new("validate_all");
+        let dir = tmp.path();
+        std::fs::write(dir.join("good.prefab"), "{ Visible }").unwrap();
+        std::fs::write(dir.join("bad_syntax.prefab"), "{ Visible ").unwrap();
+        std::fs::write(dir.join("unregistered.prefab"), "{ Hidden }").unwrap();
+        std::fs::write(
+            dir.join("lib.prefabs"),
+            "A { Visible } B { children [ { Go!() } ] }",
+        )
+        .unwrap();
+        std::fs::write(dir.join("notes.txt"), "Not a prefab").unwrap();
+
+        let mut reg = PrefabRegistry::default();
+        reg.set_prefab_dir(&dir);
+        reg.register_type::<Visible>();
+
+        let mut names: Vec<_> = reg.validate_all().into_iter().map(|e| e.name).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            ["bad_syntax.prefab", "lib.prefabs#B", "unregistered.prefab"]
+        );
+        assert!(reg.load("lib.prefabs#A").is_ok());
     }
 }
@@ -0,0 +1,348 @@
+use bevy::{
+    ecs::system::{Command, EntityCommands},
+    prelude::*,
+    transform::hierarchy::despawn_with_children_recursive,
+    utils::HashMap,
+};
+
+use crate::{
+    bevy_commands::tag_spawned,
+    build_commands::{apply_prefab, reset_prefab_components},
+    instance::{send_instance_spawned, PrefabInstanceHandle},
+    PrefabRegistry,
+};
+
+/// Inactive prefab instances waiting to be reused.
+///
+/// Entities are spawned from the pool with
+/// [crate::SpawnNamedPrefabCommands::spawn_prefab_pooled] and returned to it with
+/// [PooledPrefabCommands::despawn_pooled]. Pooled entities which are despawned some other way
+/// are dropped from the pool.
+#[derive(Default)]
+pub struct PrefabPool {
+    free: HashMap<String, Vec<Entity>>,
+}
+
+impl PrefabPool {
+    /// The number of inactive instances of a prefab in the pool.
+    pub fn free_count(&self, name: &str) -> usize {
+        self.free.get(name).map_or(0, Vec::len)
+    }
+
+    pub(crate) fn take(&mut self, name: &str) -> Option<Entity> {
+        self.free.get_mut(name)?.pop()
+    }
+
+    /// Take an inactive instance which still exists, dropping any which were despawned.
+    pub(crate) fn take_existing(world: &mut World, name: &str) -> Option<Entity> {
+        loop {
+            let entity = world.get_resource_mut::<PrefabPool>()?.take(name)?;
+            if world.get_entity(entity).is_some() {
+                return Some(entity);
+            }
+        }
+    }
+
+    /// Remove entities which are being despawned from the pool.
+    pub(crate) fn forget(&mut self, entities: &[Entity]) {
+        for free in self.free.values_mut() {
+            free.retain(|entity| !entities.contains(entity));
+        }
+    }
+}
+
+/// The prefab a pooled entity was spawned from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PooledPrefab(pub String);
+
+/// Added to pooled entities while they're inactive in the [PrefabPool].
+///
+/// Inactive entities keep all of their components, so systems which shouldn't
+/// affect them can filter them out with `Without<PoolInactive>`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolInactive;
+
+pub trait PooledPrefabCommands {
+    /// Return a pooled entity to the [PrefabPool] so it can be reused by a later spawn.
+    ///
+    /// The entity is hidden and marked [PoolInactive]. Entities that weren't spawned
+    /// from a pool are despawned as normal.
+    fn despawn_pooled(&mut self);
+}
+
+impl PooledPrefabCommands for EntityCommands<'_, '_> {
+    fn despawn_pooled(&mut self) {
+        let entity = self.id();
+        self.commands().add(DespawnPooledCommand { entity });
+    }
+}
+
+/// Spawns a pooled prefab instance, reusing an inactive one from the [PrefabPool] if there
+/// is one.
+pub(crate) struct SpawnFromPoolCommand {
+    pub name: String,
+}
+
+impl Command for SpawnFromPoolCommand {
+    fn write(self: Box<Self>, world: &mut World) {
+        let name = self.name;
+        match PrefabPool::take_existing(world, &name) {
+            Some(entity) => Box::new(ReusePooledCommand { entity, name }).write(world),
+            None => {
+                let entity = world.spawn().id();
+                Box::new(SpawnPooledCommand { entity, name }).write(world);
+            }
+        }
+    }
+}
+
+/// Builds a new pooled prefab instance.
+pub(crate) struct SpawnPooledCommand {
+    pub entity: Entity,
+    pub name: String,
+}
+
+impl Command for SpawnPooledCommand {
+    fn write(self: Box<Self>, world: &mut World) {
+        let entity = self.entity;
+        let anchors = world.resource_scope(|world, mut registry: Mut<PrefabRegistry>| {
+            let prefab = registry.load_or_fallback(&self.name)?;
+            registry.record_spawn(&self.name);
+            tag_spawned(&registry, world, entity, &self.name, &prefab);
+            Some(apply_prefab(&registry, &prefab, world, entity))
+        });
+        if let Some(anchors) = anchors {
+            send_instance_spawned(
+                world,
+                &self.name,
+                PrefabInstanceHandle::new(entity, anchors),
+            );
+        }
+        world.entity_mut(entity).insert(PooledPrefab(self.name));
+    }
+}
+
+/// Reactivates an inactive pooled entity, resetting the components declared in
+/// the prefab to their prefab values.
+///
+/// Build commands aren't run again since the bundles and assets they created are
+/// still on the entity.
+pub(crate) struct ReusePooledCommand {
+    pub entity: Entity,
+    pub name: String,
+}
+
+impl Command for ReusePooledCommand {
+    fn write(self: Box<Self>, world: &mut World) {
+        let entity = self.entity;
+        let mut entity_mut = match world.get_entity_mut(entity) {
+            Some(entity_mut) => entity_mut,
+            None => {
+                warn!(
+                    "Pooled instance {:?} of {} was despawned, building a new instance instead",
+                    entity, self.name
+                );
+                let entity = world.spawn().id();
+                Box::new(SpawnPooledCommand {
+                    entity,
+                    name: self.name,
+                })
+                .write(world);
+                return;
+            }
+        };
+        entity_mut.remove::<PoolInactive>();
+        #[cfg(feature = "render")]
+        if let Some(mut visible) = entity_mut.get_mut::<Visible>() {
+            visible.is_visible = true;
+        }
+
+        world.resource_scope(|world, mut registry: Mut<PrefabRegistry>| {
+            if let Some(prefab) = registry.load_or_fallback(&self.name) {
+                registry.record_spawn(&self.name);
+                reset_prefab_components(&registry, &prefab, world, entity);
+            }
+        });
+    }
+}
+
+struct DespawnPooledCommand {
+    entity: Entity,
+}
+
+impl Command for DespawnPooledCommand {
+    fn write(self: Box<Self>, world: &mut World) {
+        let entity = self.entity;
+        let name = match world.get::<PooledPrefab>(entity) {
+            Some(pooled) => pooled.0.clone(),
+            None => {
+                despawn_with_children_recursive(world, entity);
+                return;
+            }
+        };
+
+        let mut entity_mut = world.entity_mut(entity);
+        if entity_mut.contains::<PoolInactive>() {
+            return;
+        }
+        entity_mut.insert(PoolInactive);
+        #[cfg(feature = "render")]
+        if let Some(mut visible) = entity_mut.get_mut::<Visible>() {
+            visible.is_visible = false;
+        }
+
+        world
+            .get_resource_or_insert_with(PrefabPool::default)
+            .free
+            .entry(name)
+            .or_default()
+            .push(entity);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::{ecs::system::CommandQueue, prelude::*};
+
+    use crate::{
+        test_utils::*, PoolInactive, PooledPrefabCommands, PrefabPool, PrefabRegistry,
+        SpawnNamedPrefabCommands,
+    };
+
+    fn pool_world() -> World {
+        let mut world = prefab_world();
+        world.insert_resource(PrefabPool::default());
+        let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+        reg.register_type::<Transform>();
+        reg.set_tag_spawned(true);
+        reg.replace_prefab("orc", "{ Transform { translation: Vec3 { x: 1.0 } } }")
+            .unwrap();
+        world
+    }
+
+    fn active(world: &mut World) -> Vec<Entity> {
+        world
+            .query_filtered::<Entity, (With<Transform>, Without<PoolInactive>)>()
+            .iter(world)
+            .collect()
+    }
+
+    #[test]
+    fn spawn_without_pool_argument() {
+        let mut world = pool_world();
+        let mut queue = CommandQueue::default();
+
+        Commands::new(&mut queue, &world).spawn_prefab_pooled("orc");
+        queue.apply(&mut world);
+        let orc = active(&mut world)[0];
+
+        Commands::new(&mut queue, &world)
+            .entity(orc)
+            .despawn_pooled();
+        queue.apply(&mut world);
+        assert!(active(&mut world).is_empty());
+
+        Commands::new(&mut queue, &world).spawn_prefab_pooled("orc");
+        queue.apply(&mut world);
+        assert_eq!(active(&mut world), [orc]);
+        assert_component(&world, orc, &Transform::from_xyz(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn despawned_instances_leave_the_pool() {
+        let mut world = pool_world();
+        let mut queue = CommandQueue::default();
+
+        Commands::new(&mut queue, &world).spawn_prefab_pooled("orc");
+        queue.apply(&mut world);
+        let orc = active(&mut world)[0];
+        Commands::new(&mut queue, &world)
+            .entity(orc)
+            .despawn_pooled();
+        queue.apply(&mut world);
+
+        Commands::new(&mut queue, &world).despawn_prefab_instances("orc");
+        queue.apply(&mut world);
+        assert!(world.get_entity(orc).is_none());
+        let pool = world.get_resource::<PrefabPool>().unwrap();
+        assert_eq!(pool.free_count("orc"), 0);
+
+        Commands::new(&mut queue, &world).spawn_prefab_pooled("orc");
+        queue.apply(&mut world);
+        assert_eq!(active(&mut world).len(), 1);
+
+        // Despawned some other way, the stale entity is skipped.
+        let orc = active(&mut world)[0];
+        Commands::new(&mut queue, &world)
+            .entity(orc)
+            .despawn_pooled();
+        queue.apply(&mut world);
+        world.despawn(orc);
+        Commands::new(&mut queue, &world).spawn_prefab_pooled("orc");
+        queue.apply(&mut world);
+        let respawned = active(&mut world);
+        assert_eq!(respawned.len(), 1);
+        assert_ne!(respawned[0], orc);
+
+        // A stale entity taken straight from the pool is replaced by a new instance.
+        let orc = respawned[0];
+        Commands::new(&mut queue, &world)
+            .entity(orc)
+            .despawn_pooled();
+        queue.apply(&mut world);
+        world.despawn(orc);
+        world.resource_scope(|world, mut pool: Mut<PrefabPool>| {
+            Commands::new(&mut queue, world).spawn_prefab_pooled_in(&mut pool, "orc");
+        });
+        queue.apply(&mut world);
+        assert_eq!(active(&mut world).len(), 1);
+    }
+
+    #[test]
+    fn pooled_spawn() {
+        let mut world = prefab_world();
+        world.insert_resource(PrefabPool::default());
+        {
+            let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.register_type::<Transform>();
+            reg.replace_prefab("bullet", "{ Transform { translation: Vec3 { x: 1.0 } } }")
+                .unwrap();
+        }
+
+        let mut queue = CommandQueue::default();
+        world.resource_scope(|world, mut pool: Mut<PrefabPool>| {
+            Commands::new(&mut queue, world).spawn_prefab_pooled_in(&mut pool, "bullet");
+        });
+        queue.apply(&mut world);
+        let bullet = world
+            .query::<(Entity, &Transform)>()
+            .iter(&world)
+            .next()
+            .unwrap()
+            .0;
+
+        world.get_mut::<Transform>(bullet).unwrap().scale = Vec3::ZERO;
+        Commands::new(&mut queue, &world)
+            .entity(bullet)
+            .despawn_pooled();
+        queue.apply(&mut world);
+        assert!(world.get::<PoolInactive>(bullet).is_some());
+        assert_eq!(
+            world
+                .get_resource::<PrefabPool>()
+                .unwrap()
+                .free_count("bullet"),
+            1
+        );
+
+        let reused = world.resource_scope(|world, mut pool: Mut<PrefabPool>| {
+            Commands::new(&mut queue, world)
+                .spawn_prefab_pooled_in(&mut pool, "bullet")
+                .id()
+        });
+        queue.apply(&mut world);
+        assert_eq!(reused, bullet);
+        assert!(world.get::<PoolInactive>(bullet).is_none());
+        assert_component(&world, bullet, &Transform::from_xyz(1.0, 0.0, 0.0));
+    }
+}
@@ -1,10 +1,11 @@
-use std::sync::Arc;
+use std::{fmt, sync::Arc};
 
 use bevy::{
     prelude::*,
     reflect::{DynamicStruct, TypeUuid},
 };
 use derivative::*;
+use serde::{Deserialize, Serialize};
 
 /// An asset built from a *.prefab* file.
 ///
@@ -25,27 +26,251 @@ use derivative::*;
 #[derive(Debug, TypeUuid)]
 #[uuid = "6ea14da5-6bf8-3ea1-9886-1d7bf6c17d2f"]
 pub struct Prefab {
-    #[allow(dead_code)]
     pub(crate) name: Option<String>,
+    /// The name the prefab was loaded by in the [crate::PrefabRegistry].
+    pub(crate) source: Option<String>,
+    /// The `@name` other entities in the hierarchy can refer to this prefab's entity by.
+    pub(crate) anchor: Option<String>,
+    pub(crate) meta: PrefabMeta,
     pub(crate) steps: Vec<PrefabBuildStep>,
 }
 
+/// Information about a [Prefab] from its `meta { .. }` block. Doesn't affect spawned entities.
+///
+/// ```ignore
+/// Bat {
+///     meta { tags: ["enemy", "flying"], category: "units", description: "Flaps" },
+///     Transform,
+/// }
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrefabMeta {
+    /// Tags for finding the prefab, see [crate::PrefabRegistry::find_by_tag].
+    pub tags: Vec<String>,
+    pub category: Option<String>,
+    pub description: Option<String>,
+}
+
+impl PrefabMeta {
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == PrefabMeta::default()
+    }
+}
+
+/// A component identifying the [Prefab] an entity was spawned from.
+///
+/// Only inserted if enabled via [crate::PrefabRegistry::set_tag_spawned].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Reflect)]
+#[reflect(Component)]
+pub struct SpawnedFrom(pub String);
+
+/// The version of its prefab that was last applied to a tagged entity, used to update
+/// the entity when the prefab changes.
+pub(crate) struct AppliedPrefab(pub Arc<Prefab>);
+
+impl Prefab {
+    /// The prefab's name, if it was given one in the *.prefab* file.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The name the prefab was loaded by in the [crate::PrefabRegistry].
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    /// The prefab's `@name` anchor, if it has one.
+    pub fn anchor(&self) -> Option<&str> {
+        self.anchor.as_deref()
+    }
+
+    /// A rough estimate of the memory used by the prefab, in bytes.
+    pub(crate) fn approx_size(&self) -> usize {
+        let strings = [&self.name, &self.source, &self.anchor]
+            .iter()
+            .filter_map(|s| s.as_ref().map(String::len))
+            .sum::<usize>();
+        let steps: usize = self
+            .steps
+            .iter()
+            .map(|step| {
+                std::mem::size_of::<PrefabBuildStep>()
+                    + match step {
+                        PrefabBuildStep::AddComponent(comp) => {
+                            std::mem::size_of::<PrefabComponent>()
+                                + comp.type_name.len()
+                                + std::mem::size_of_val(&*comp.reflect)
+                        }
+                        PrefabBuildStep::RunCommand(command) => {
+                            std::mem::size_of::<PrefabCommandData>() + command.name.len()
+                        }
+                        PrefabBuildStep::AddChild(child) => child.approx_size(),
+                    }
+            })
+            .sum();
+        std::mem::size_of::<Prefab>() + strings + steps
+    }
+
+    /// The prefab's metadata.
+    pub fn meta(&self) -> &PrefabMeta {
+        &self.meta
+    }
+
+    /// The prefab's build steps, in the order they are applied.
+    pub fn steps(&self) -> &[PrefabBuildStep] {
+        &self.steps
+    }
+
+    /// The components added by the prefab, not including those of child prefabs.
+    pub fn components(&self) -> impl Iterator<Item = &PrefabComponent> {
+        self.steps.iter().filter_map(|step| match step {
+            PrefabBuildStep::AddComponent(comp) => Some(&**comp),
+            _ => None,
+        })
+    }
+
+    /// The build commands run by the prefab, not including those of child prefabs.
+    pub fn commands(&self) -> impl Iterator<Item = &PrefabCommandData> {
+        self.steps.iter().filter_map(|step| match step {
+            PrefabBuildStep::RunCommand(command) => Some(&**command),
+            _ => None,
+        })
+    }
+
+    /// The prefab's child prefabs.
+    pub fn children(&self) -> impl Iterator<Item = &Prefab> {
+        self.steps.iter().filter_map(|step| match step {
+            PrefabBuildStep::AddChild(child) => Some(&**child),
+            _ => None,
+        })
+    }
+}
+
+impl fmt::Display for Prefab {
+    /// Writes the prefab in *.prefab* format.
+    ///
+    /// Any field values that can't be represented in a *.prefab* file are omitted.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        crate::writer::write_prefab(&mut out, self, 0);
+        f.write_str(&out)
+    }
+}
+
+/// A single step in building a [Prefab] entity, see [Prefab::steps].
 #[derive(Debug)]
-pub(crate) enum PrefabBuildStep {
+pub enum PrefabBuildStep {
+    /// Insert a component, or modify it if the entity already has one.
     AddComponent(Arc<PrefabComponent>),
+    /// Run a build command.
     RunCommand(Arc<PrefabCommandData>),
+    /// Spawn a child entity from a prefab.
+    AddChild(Arc<Prefab>),
 }
 
+/// A component read from a *.prefab* file.
 #[derive(Debug)]
-pub(crate) struct PrefabComponent {
-    pub type_name: String,
-    pub reflect: Box<dyn Reflect>,
+pub struct PrefabComponent {
+    pub(crate) type_name: Arc<str>,
+    pub(crate) reflect: Box<dyn Reflect>,
+    /// True if the component contains values which are evaluated when it's applied.
+    pub(crate) deferred: bool,
+}
+
+impl PrefabComponent {
+    /// The name the component type was registered under.
+    pub fn type_name(&self) -> &str {
+        &self.type_name
+    }
+
+    /// The field values set by the prefab. Fields that weren't set are left out.
+    pub fn value(&self) -> &dyn Reflect {
+        &*self.reflect
+    }
+
+    /// Returns true if the component has values which are evaluated each time it's
+    /// applied, such as `rand(..)` or `${var}`.
+    pub fn is_deferred(&self) -> bool {
+        self.deferred
+    }
 }
 
+/// A build command read from a *.prefab* file.
 #[derive(Derivative)]
 #[derivative(Debug)]
-pub(crate) struct PrefabCommandData {
-    pub name: String,
+pub struct PrefabCommandData {
+    pub(crate) name: String,
     #[derivative(Debug = "ignore")]
-    pub properties: Option<DynamicStruct>,
+    pub(crate) properties: Option<DynamicStruct>,
+    /// True if the properties contain values which are evaluated when the command is run.
+    pub(crate) deferred: bool,
+    /// The key written in the file, if it was a deprecated alias of `name`.
+    pub(crate) deprecated_key: Option<String>,
+    /// The [crate::PrefabFlags] flag the command is gated on, from an `if "flag" { .. }` block.
+    pub(crate) flag: Option<String>,
+}
+
+impl PrefabCommandData {
+    /// The key of the [crate::build_commands::BuildPrefabCommand].
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The properties passed to the command, if any.
+    pub fn properties(&self) -> Option<&DynamicStruct> {
+        self.properties.as_ref()
+    }
+
+    /// Returns true if the properties have values which are evaluated each time the
+    /// command is run.
+    pub fn is_deferred(&self) -> bool {
+        self.deferred
+    }
+
+    /// The flag which must be set in [crate::PrefabFlags] for the command to run, if it was
+    /// written inside an `if "flag" { .. }` block.
+    pub fn flag(&self) -> Option<&str> {
+        self.flag.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::*;
+
+    use crate::{dynamic_cast::*, parse::parse_prefab_str, test_utils::Stats, PrefabRegistry};
+
+    #[test]
+    fn introspection() {
+        use bevy::reflect::DynamicStruct;
+
+        let mut reg = PrefabRegistry::default();
+        reg.register_type::<Stats>();
+        reg.register_command_fn("Emit", |_, _, _| {});
+
+        let input = "Enemy @root { Stats { health: 5 }, Emit!(rate: 2), children [ Gun { } ] }";
+        let prefab = parse_prefab_str(input, &reg).unwrap();
+        assert_eq!(prefab.name(), Some("Enemy"));
+        assert_eq!(prefab.anchor(), Some("root"));
+        assert_eq!(prefab.steps().len(), 3);
+
+        let comp = prefab.components().next().unwrap();
+        assert_eq!(comp.type_name(), "Stats");
+        assert!(!comp.is_deferred());
+        let value = comp.value().downcast_ref::<DynamicStruct>().unwrap();
+        assert_eq!(*value.get::<i32>("health"), 5);
+        assert!(value.field("speed").is_none());
+
+        let command = prefab.commands().next().unwrap();
+        assert_eq!(command.name(), "Emit");
+        assert_eq!(*command.properties().unwrap().get::<i32>("rate"), 2);
+
+        let child = prefab.children().next().unwrap();
+        assert_eq!(child.name(), Some("Gun"));
+    }
 }
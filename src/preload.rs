@@ -0,0 +1,185 @@
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadState, LoadedAsset},
+    ecs::system::Command,
+    prelude::*,
+    reflect::TypeUuid,
+};
+
+use crate::{bevy_commands::spawn_named, PrefabRegistry};
+
+/// The raw text of a *.prefab* file, loaded through the `AssetServer`.
+///
+/// See [PrefabRegistry::preload].
+#[derive(Debug, TypeUuid)]
+#[uuid = "7b2d4a64-2f0c-4a5e-9a57-3c1f0e0b8d21"]
+pub struct PrefabText(pub String);
+
+/// Loads *.prefab* files as [PrefabText] assets.
+#[derive(Default)]
+pub struct PrefabTextLoader;
+
+impl AssetLoader for PrefabTextLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let text = std::str::from_utf8(bytes)?.to_string();
+            load_context.set_default_asset(LoadedAsset::new(PrefabText(text)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["prefab"]
+    }
+}
+
+/// Parses and caches preloaded prefabs once the `AssetServer` has finished loading them.
+pub(crate) fn cache_preloaded_prefabs(
+    mut registry: ResMut<PrefabRegistry>,
+    server: Res<AssetServer>,
+    mut texts: ResMut<Assets<PrefabText>>,
+) {
+    if !registry.has_pending_preloads() {
+        return;
+    }
+    for (name, handle) in registry.take_pending_preloads() {
+        if let Some(text) = texts.remove(&handle) {
+            // Errors are reported through the `PrefabLoadFailed` event.
+            let _ = registry.cache_prefab_text(&name, &text.0);
+            continue;
+        }
+        match server.get_load_state(&handle) {
+            LoadState::Failed => registry.preload_failed(&name),
+            _ => registry.add_pending_preload(name, handle),
+        }
+    }
+}
+
+/// A prefab spawned with [crate::SpawnNamedPrefabCommands::spawn_prefab_handle] whose asset
+/// hasn't loaded yet.
+pub(crate) struct PendingPrefabHandle(pub Handle<PrefabText>);
+
+/// Applies prefabs spawned from a [PrefabText] handle once the asset has loaded. The prefab is
+/// cached under its path relative to the prefab directory, so it's only parsed again if the
+/// asset changes.
+pub(crate) fn spawn_loaded_prefab_handles(
+    mut commands: Commands,
+    mut registry: ResMut<PrefabRegistry>,
+    server: Res<AssetServer>,
+    texts: Res<Assets<PrefabText>>,
+    query: Query<(Entity, &PendingPrefabHandle)>,
+) {
+    for (entity, pending) in query.iter() {
+        let name = match server.get_handle_path(&pending.0) {
+            Some(path) => registry.prefab_asset_name(path.path()),
+            None => format!("{:?}", pending.0.id),
+        };
+        match texts.get(&pending.0) {
+            Some(text) => {
+                registry.cache_prefab_asset(&name, &text.0);
+                commands.add(SpawnLoadedPrefabCommand { entity, name });
+            }
+            None if server.get_load_state(&pending.0) == LoadState::Failed => {
+                registry.preload_failed(&name);
+            }
+            None => continue,
+        }
+        commands.entity(entity).remove::<PendingPrefabHandle>();
+    }
+}
+
+struct SpawnLoadedPrefabCommand {
+    entity: Entity,
+    name: String,
+}
+
+impl Command for SpawnLoadedPrefabCommand {
+    fn write(self: Box<Self>, world: &mut World) {
+        spawn_named(world, self.entity, &self.name, None);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::*;
+
+    use crate::{test_utils::Stats, PrefabRegistry};
+
+    #[test]
+    fn preloaded_prefab() {
+        use crate::preload::{cache_preloaded_prefabs, PrefabText};
+        use bevy::ecs::system::System;
+
+        let mut app = App::build();
+        app.add_plugin(bevy::core::CorePlugin)
+            .add_plugin(bevy::asset::AssetPlugin)
+            .add_asset::<PrefabText>()
+            .init_resource::<PrefabRegistry>();
+        let world = app.world_mut();
+
+        let handle = world
+            .get_resource_mut::<Assets<PrefabText>>()
+            .unwrap()
+            .add(PrefabText("Preloaded {}".to_string()));
+        world
+            .get_resource_mut::<PrefabRegistry>()
+            .unwrap()
+            .add_pending_preload("preloaded.prefab".to_string(), handle);
+
+        let mut system = cache_preloaded_prefabs.system();
+        system.initialize(world);
+        system.run((), world);
+
+        let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+        assert!(!reg.has_pending_preloads());
+        let prefab = reg.load("preloaded.prefab").unwrap();
+        assert_eq!(prefab.name.as_deref(), Some("Preloaded"));
+    }
+
+    #[test]
+    fn spawn_prefab_handle() {
+        use crate::preload::{spawn_loaded_prefab_handles, PendingPrefabHandle, PrefabText};
+        use crate::SpawnNamedPrefabCommands;
+        use bevy::{
+            asset::HandleId,
+            ecs::system::{CommandQueue, System},
+        };
+
+        let mut app = App::build();
+        app.add_plugin(bevy::core::CorePlugin)
+            .add_plugin(bevy::asset::AssetPlugin)
+            .add_asset::<PrefabText>()
+            .init_resource::<PrefabRegistry>();
+        let world = app.world_mut();
+        world
+            .get_resource_mut::<PrefabRegistry>()
+            .unwrap()
+            .register_type::<Stats>();
+
+        let id = HandleId::random::<PrefabText>();
+        let mut queue = CommandQueue::default();
+        let entity = Commands::new(&mut queue, world)
+            .spawn_prefab_handle(Handle::weak(id))
+            .id();
+        queue.apply(world);
+
+        let mut system = spawn_loaded_prefab_handles.system();
+        system.initialize(world);
+        system.run((), world);
+        system.apply_buffers(world);
+        assert!(world.get::<PendingPrefabHandle>(entity).is_some());
+        assert!(world.get::<Stats>(entity).is_none());
+
+        world
+            .get_resource_mut::<Assets<PrefabText>>()
+            .unwrap()
+            .set_untracked(id, PrefabText("{ Stats { health: 7 } }".to_string()));
+        system.run((), world);
+        system.apply_buffers(world);
+        assert!(world.get::<PendingPrefabHandle>(entity).is_none());
+        assert_eq!(world.get::<Stats>(entity).unwrap().health, 7);
+    }
+}
@@ -1,14 +1,46 @@
-use std::{fs, sync::Arc};
+use std::{
+    any::TypeId,
+    collections::BTreeSet,
+    fmt::{Display, Write},
+    io,
+    marker::PhantomData,
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
 
 use bevy::{
+    ecs::{bundle::Bundle, component::Component},
     prelude::*,
-    reflect::{GetTypeRegistration, ReflectRef, TypeRegistration},
-    utils::HashMap,
+    reflect::{
+        DynamicStruct, GetTypeRegistration, ReflectMut, ReflectRef, TypeRegistration, TypeRegistry,
+        TypeRegistryInternal,
+    },
+    scene::serde::SceneDeserializer,
+    utils::{HashMap, Instant},
 };
+use serde::de::DeserializeSeed;
 
 use crate::{
-    build_commands::BuildPrefabCommand, parse::parse_prefab_string, parse::LoadPrefabError,
-    prefab::Prefab,
+    build_commands::{
+        apply_value, bundle_command_key, BuildPrefabCommand, FnCommand, InsertBundle,
+    },
+    deferred::DeferredValue,
+    level::Level,
+    lint::{lint_prefab, LintEntity, PrefabLint},
+    parse::LoadPrefabError,
+    parse::{
+        parse_command_properties, parse_level_string, parse_prefab_library_string,
+        parse_prefab_str, parse_table_string,
+    },
+    prefab::{Prefab, PrefabBuildStep, PrefabComponent},
+    preload::PrefabText,
+    resolve::{PrefabResolver, RelativeResolver},
+    snapshot::content_hash,
+    source::PrefabSource,
+    table::PrefabTable,
+    writer::{explain_prefab, is_writable_type_name, write_component},
 };
 
 /// Manages and caches [Prefab] related data.
@@ -16,7 +48,155 @@ use crate::{
 pub struct PrefabRegistry {
     type_data: HashMap<String, TypeInfo>,
     commands: HashMap<String, Arc<dyn BuildPrefabCommand + Send + Sync + 'static>>,
+    command_defaults: HashMap<String, DynamicStruct>,
+    /// Alternative command keys, with the key they refer to and whether they're deprecated.
+    command_aliases: HashMap<String, (String, bool)>,
     prefabs: HashMap<String, Arc<Prefab>>,
+    changed: Vec<String>,
+    migrations: HashMap<u32, (u32, PrefabMigration)>,
+    aliases: HashMap<String, String>,
+    config: HashMap<String, String>,
+    /// Parsers for types given a literal in a *.prefab* file, by full type name.
+    value_parsers: HashMap<String, ValueParser>,
+    tag_spawned: bool,
+    explain_spawns: bool,
+    #[cfg(feature = "render")]
+    colors: HashMap<String, Color>,
+    roots: Vec<(i32, PathBuf)>,
+    prefab_dir: Option<PathBuf>,
+    /// Behind a lock so failures can be reported while the registry is borrowed, see
+    /// [PrefabRegistry::load_shared].
+    failed: Mutex<Vec<PrefabLoadFailed>>,
+    fallback: Option<String>,
+    source: SourceBox,
+    resolver: ResolverBox,
+    pending: Vec<(String, Handle<PrefabText>)>,
+    companions: Vec<CompanionRule>,
+    lints: Vec<PrefabLint>,
+    levels: HashMap<String, Arc<Level>>,
+    tables: HashMap<String, Arc<PrefabTable>>,
+    cache_policy: PrefabCachePolicy,
+    command_conflicts: CommandConflictPolicy,
+    /// When each prefab loaded from a file was last used, for [PrefabCachePolicy::Lru].
+    last_used: HashMap<String, u64>,
+    use_count: u64,
+    /// A hash of the text each cached prefab was parsed from, so unchanged text isn't parsed again.
+    text_hashes: HashMap<String, u64>,
+    /// Spawn counts for each prefab, if usage tracking is enabled.
+    usage: Option<HashMap<String, PrefabUsage>>,
+}
+
+/// How long prefabs loaded from *.prefab* files are kept in the [PrefabRegistry] cache, see
+/// [PrefabRegistry::set_cache_policy].
+///
+/// Prefabs from [PrefabRegistry::load_library], [PrefabRegistry::import_scene] and
+/// [PrefabRegistry::replace_prefab] can't be read again from a single file, so they're
+/// always kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrefabCachePolicy {
+    /// Keep every prefab until it's unloaded. The default.
+    KeepAll,
+    /// Keep at most this many prefabs loaded from files, evicting the least recently loaded
+    /// first. Prefabs which are always kept don't count towards the limit.
+    Lru(usize),
+    /// Only keep prefabs while they're referenced outside of the registry, such as by a
+    /// cloned `Arc<Prefab>`. Unreferenced prefabs are evicted the next time a prefab is loaded.
+    Weak,
+}
+
+impl Default for PrefabCachePolicy {
+    fn default() -> Self {
+        Self::KeepAll
+    }
+}
+
+/// What happens when a build command is registered with a key that's already in use, see
+/// [PrefabRegistry::set_command_conflict_policy].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandConflictPolicy {
+    /// Replace the existing command and log a warning. The default.
+    Warn,
+    /// Panic, naming the key.
+    Error,
+    /// Replace the existing command silently, ie: when a mod intentionally replaces a
+    /// built in command.
+    Override,
+}
+
+impl Default for CommandConflictPolicy {
+    fn default() -> Self {
+        Self::Warn
+    }
+}
+
+/// How often a prefab has been spawned, see [PrefabRegistry::usage_stats].
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PrefabUsage {
+    /// The number of times the prefab was spawned by name.
+    pub spawns: u64,
+    /// When the prefab was last spawned, or [None] if it hasn't been.
+    pub last_spawned: Option<Instant>,
+}
+
+/// The size of the [PrefabRegistry] cache, see [PrefabRegistry::cache_stats].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PrefabCacheStats {
+    /// The number of cached prefabs.
+    pub entries: usize,
+    /// The approximate memory used by the cached prefabs, in bytes.
+    pub approx_bytes: usize,
+}
+
+/// Inserts a companion component on an entity if it's missing. Returns the component's type
+/// name if it was inserted.
+type CompanionRule =
+    Box<dyn Fn(&mut World, Entity) -> Option<&'static str> + Send + Sync + 'static>;
+
+/// Converts the text of a literal to a value type, see [PrefabRegistry::register_value_parser].
+type ValueParser = Arc<dyn Fn(&str) -> Result<Box<dyn Reflect>, String> + Send + Sync + 'static>;
+
+struct ResolverBox(Box<dyn PrefabResolver>);
+
+impl Default for ResolverBox {
+    fn default() -> Self {
+        ResolverBox(Box::new(RelativeResolver::default()))
+    }
+}
+
+struct SourceBox(Box<dyn PrefabSource>);
+
+impl Default for SourceBox {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn default() -> Self {
+        SourceBox(Box::new(crate::source::FileSource::default()))
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn default() -> Self {
+        SourceBox(Box::new(crate::source::PreloadOnlySource))
+    }
+}
+
+/// The directory prefab files are loaded from by default, relative to the [PrefabSource] root.
+const BASE_PREFAB_DIR: &str = "prefabs/";
+
+/// A function which converts the text of a *.prefab* file from one version to another.
+pub type PrefabMigration = Box<dyn Fn(&str) -> String + Send + Sync + 'static>;
+
+/// Event sent when a cached [Prefab] is replaced via [PrefabRegistry::replace_prefab].
+#[derive(Debug, Clone)]
+pub struct PrefabChanged {
+    /// The name of the prefab that changed.
+    pub name: String,
+}
+
+/// Event sent when a prefab fails to load from disk.
+#[derive(Debug, Clone)]
+pub struct PrefabLoadFailed {
+    /// The name of the prefab that failed to load.
+    pub name: String,
+    /// A description of the error.
+    pub error: String,
 }
 
 impl PrefabRegistry {
@@ -46,17 +226,401 @@ impl PrefabRegistry {
     /// }
     /// ```
     pub fn register_type<T: Reflect + GetTypeRegistration + Default>(&mut self) {
+        let name = T::get_type_registration().short_name().to_string();
+        self.register_type_as::<T>(&name);
+    }
+
+    /// Register a component for use in a [Prefab] under a custom name.
+    ///
+    /// The type can then be referred to by `name` from a *.prefab* file instead of
+    /// its short type name. This can be used to disambiguate types from different crates
+    /// that share the same short name.
+    ///
+    /// Types can always be referred to by their fully qualified name, ie: `my_game::Health`.
+    /// If a different type was already registered with the same name a warning is logged
+    /// and the new type can only be referred to by it's fully qualified name.
+    pub fn register_type_as<T: Reflect + GetTypeRegistration + Default>(&mut self, name: &str) {
+        self.add_type_info(
+            name,
+            T::get_type_registration(),
+            Box::new(T::default()),
+            Constructor::Default(|| Box::new(T::default())),
+        );
+    }
+
+    /// Register a component which can't implement `Default`, such as one holding asset handles,
+    /// for use in a [Prefab].
+    ///
+    /// The component is created with [FromWorld] when it's inserted on an entity, and the
+    /// fields set in the prefab are applied to it. The component must be a struct with named
+    /// fields. Like other components it must derive `Reflect` and have the
+    /// `#[reflect(Component)]` attribute.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// #[derive(Reflect)]
+    /// #[reflect(Component)]
+    /// struct Explosion {
+    ///     radius: f32,
+    ///     material: Handle<ColorMaterial>,
+    /// }
+    ///
+    /// impl FromWorld for Explosion {
+    ///     fn from_world(world: &mut World) -> Self {
+    ///         let server = world.get_resource::<AssetServer>().unwrap();
+    ///         Explosion {
+    ///             radius: 1.0,
+    ///             material: server.load("explosion.png"),
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>) {
+    ///     registry.register_type_from_world::<Explosion>();
+    /// }
+    /// ```
+    pub fn register_type_from_world<T: Reflect + GetTypeRegistration + FromWorld>(&mut self) {
+        let reg = T::get_type_registration();
+        let name = reg.short_name().to_string();
+        // There's no instance of the type until a World is available, so fields are read
+        // from the prefab as-is.
+        let mut instance = DynamicStruct::default();
+        instance.set_name(reg.name().to_string());
+        self.add_type_info(
+            &name,
+            reg,
+            Box::new(instance),
+            Constructor::FromWorld(|world| Box::new(T::from_world(world))),
+        );
+    }
+
+    /// Register a component for use in a [Prefab], using `construct` to create new instances
+    /// instead of `Default`.
+    ///
+    /// This allows components from other crates which don't implement `Default` to be used
+    /// in prefabs. The component must derive `Reflect`, but doesn't need the
+    /// `#[reflect(Component)]` attribute.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// # mod other_crate {
+    /// #     use bevy::prelude::*;
+    /// #     #[derive(Reflect)]
+    /// #     pub struct Velocity { pub x: f32, pub y: f32 }
+    /// #     impl Velocity { pub fn new(x: f32, y: f32) -> Self { Velocity { x, y } } }
+    /// # }
+    /// use other_crate::Velocity;
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>) {
+    ///     registry.register_type_with(|| Velocity::new(0.0, 0.0));
+    /// }
+    /// ```
+    pub fn register_type_with<T, F>(&mut self, construct: F)
+    where
+        T: Component + Reflect + GetTypeRegistration,
+        F: Fn() -> T + Send + Sync + 'static,
+    {
         let reg = T::get_type_registration();
-        let instance = T::default();
         let name = reg.short_name().to_string();
+        let registration = reg.clone();
+        let construct = Arc::new(construct);
+        let instance = Box::new(construct());
+        let name = self.add_type_info(
+            &name,
+            reg,
+            instance,
+            Constructor::With({
+                let construct = construct.clone();
+                Arc::new(move || Box::new(construct()))
+            }),
+        );
+        let info = self.type_data.get_mut(&name).unwrap();
+        info.remove = Some(|world, entity| {
+            world.entity_mut(entity).remove::<T>();
+        });
+        info.apply = Some(Arc::new(move |world, entity, value| {
+            match world.get_mut::<T>(entity) {
+                Some(mut component) => apply_value(&registration, &mut *component, value),
+                None => {
+                    let mut component = construct();
+                    apply_value(&registration, &mut component, value);
+                    world.entity_mut(entity).insert(component);
+                }
+            }
+        }));
+    }
+
+    /// Register a function which converts a string or number literal in a *.prefab* file to
+    /// the type `T`.
+    ///
+    /// This allows types using `#[reflect_value]`, such as wrappers around data which can't
+    /// be reflected, to be written in a prefab. A component can be given a literal with
+    /// `ItemId("sku-42")`, and a field of the type can be written as `id: "sku-42"`. Numbers
+    /// are converted from their text, ie: `Level(3)` passes `"3"`.
+    ///
+    /// Components must also be registered with [PrefabRegistry::register_type]. See
+    /// [PrefabRegistry::register_value_type] for types which implement [FromStr].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// #[derive(Default, Clone, Reflect)]
+    /// #[reflect_value(Component)]
+    /// struct ItemId(u64);
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>) {
+    ///     registry.register_type::<ItemId>();
+    ///     registry.register_value_parser(|text: &str| match text.strip_prefix("sku-") {
+    ///         Some(id) => id.parse().map(ItemId).map_err(|e| e.to_string()),
+    ///         None => Err("missing sku- prefix".to_string()),
+    ///     });
+    /// }
+    /// ```
+    pub fn register_value_parser<T, F>(&mut self, parse: F)
+    where
+        T: Reflect,
+        F: Fn(&str) -> Result<T, String> + Send + Sync + 'static,
+    {
+        self.value_parsers.insert(
+            std::any::type_name::<T>().to_string(),
+            Arc::new(move |text| parse(text).map(|v| Box::new(v) as Box<dyn Reflect>)),
+        );
+    }
+
+    /// Register a value type and parse literals for it with [FromStr], see
+    /// [PrefabRegistry::register_value_parser].
+    pub fn register_value_type<T>(&mut self)
+    where
+        T: Reflect + GetTypeRegistration + Default + FromStr,
+        T::Err: Display,
+    {
+        self.register_type::<T>();
+        self.register_value_parser(|text: &str| text.parse::<T>().map_err(|e| e.to_string()));
+    }
+
+    /// Convert a literal to the type with the given full name, if it has a value parser.
+    /// Other values are returned unchanged.
+    pub(crate) fn parse_value_literal(
+        &self,
+        type_name: &str,
+        value: Box<dyn Reflect>,
+    ) -> Result<Box<dyn Reflect>, LoadPrefabError> {
+        let parse = match self.value_parsers.get(type_name) {
+            Some(parse) if value.type_name() != type_name => parse,
+            _ => return Ok(value),
+        };
+        let text = if let Some(s) = value.downcast_ref::<String>() {
+            s.clone()
+        } else if let Some(i) = value.downcast_ref::<i32>() {
+            i.to_string()
+        } else if let Some(f) = value.downcast_ref::<f32>() {
+            f.to_string()
+        } else if let Some(b) = value.downcast_ref::<bool>() {
+            b.to_string()
+        } else {
+            return Ok(value);
+        };
+        let short_name = TypeRegistration::get_short_name(type_name);
+        parse(&text)
+            .map_err(|e| LoadPrefabError::ValueParseError(short_name, format!("{}: {}", text, e)))
+    }
+
+    /// Add the type's info to the registry and return the name it was registered under.
+    fn add_type_info(
+        &mut self,
+        name: &str,
+        reg: TypeRegistration,
+        instance: Box<dyn Reflect>,
+        construct: Constructor,
+    ) -> String {
+        let full_name = reg.name();
+
+        let name = match self.type_data.get(name) {
+            Some(existing) if existing.registration.type_id() != reg.type_id() => {
+                warn!(
+                    "Prefab type name collision: '{}' is already registered for {}. {} must be referred to by its full name.",
+                    name,
+                    existing.registration.name(),
+                    full_name,
+                );
+                full_name
+            }
+            _ => {
+                self.aliases.insert(full_name.to_string(), name.to_string());
+                name
+            }
+        };
 
         let info = TypeInfo {
-            type_name: name.clone(),
+            type_name: name.into(),
             reflect_type: instance.reflect_ref().into(),
-            registration: reg,
+            registration: reg.clone(),
+            instance,
+            construct,
+            remove: None,
+            apply: None,
+        };
+
+        self.type_data.insert(name.to_string(), info);
+        name.to_string()
+    }
+
+    /// Register a component for use in a [Prefab], see [PrefabRegistry::register_type].
+    ///
+    /// Unlike types registered with [PrefabRegistry::register_type], the component is removed
+    /// from spawned entities if it's deleted from their prefab while the game is running.
+    pub fn register_component<T: Component + Reflect + GetTypeRegistration + Default>(&mut self) {
+        self.register_type::<T>();
+        for info in self.type_data.values_mut() {
+            if info.registration.type_id() == TypeId::of::<T>() {
+                info.remove = Some(|world, entity| {
+                    world.entity_mut(entity).remove::<T>();
+                });
+            }
+        }
+    }
+
+    /// Register a component along with the struct types of its fields, and of their fields,
+    /// so they can be written by name in a *.prefab* file, ie: `Unit { stats: Stats { .. } }`.
+    ///
+    /// Field types are found by name in bevy's type registry, so they must be registered with
+    /// the app, ie: `app.register_type::<Stats>()`. Most built in bevy types already are. A
+    /// warning is logged for any field type which can't be found.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::{prelude::*, reflect::TypeRegistry};
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// #[derive(Default, Reflect)]
+    /// struct Stats {
+    ///     health: i32,
+    /// }
+    ///
+    /// #[derive(Default, Reflect)]
+    /// #[reflect(Component)]
+    /// struct Unit {
+    ///     stats: Stats,
+    /// }
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>, types: Res<TypeRegistry>) {
+    ///     registry.register_component_and_deps::<Unit>(&types);
+    /// }
+    /// ```
+    pub fn register_component_and_deps<T: Component + Reflect + GetTypeRegistration + Default>(
+        &mut self,
+        type_registry: &TypeRegistry,
+    ) {
+        self.register_component::<T>();
+        let type_registry = type_registry.read();
+        self.register_field_types(&T::default(), &type_registry);
+    }
+
+    /// Register the struct types of the value's fields, recursively.
+    fn register_field_types(&mut self, value: &dyn Reflect, type_registry: &TypeRegistryInternal) {
+        let fields: Vec<&dyn Reflect> = match value.reflect_ref() {
+            ReflectRef::Struct(s) => s.iter_fields().collect(),
+            ReflectRef::TupleStruct(s) => s.iter_fields().collect(),
+            _ => return,
         };
+        for field in fields {
+            if !matches!(
+                field.reflect_ref(),
+                ReflectRef::Struct(_) | ReflectRef::TupleStruct(_)
+            ) {
+                continue;
+            }
+            let type_name = field.type_name();
+            if self
+                .type_data
+                .values()
+                .any(|info| info.registration.name() == type_name)
+            {
+                continue;
+            }
+            let reg = match type_registry.get_with_name(type_name) {
+                Some(reg) => reg.clone(),
+                None => {
+                    warn!(
+                        "Unable to register field type {} for prefabs, it must be registered with the app",
+                        type_name
+                    );
+                    continue;
+                }
+            };
+            let name = reg.short_name().to_string();
+            let instance: Arc<dyn Reflect> = field.clone_value().into();
+            self.add_type_info(
+                &name,
+                reg,
+                instance.clone_value(),
+                Constructor::With(Arc::new(move || instance.clone_value())),
+            );
+            self.register_field_types(field, type_registry);
+        }
+    }
+
+    /// Register an alternate name for an already registered type.
+    ///
+    /// *.prefab* files can refer to the type by either name. Useful for keeping
+    /// old prefab files working after a type has been renamed.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>) {
+    ///     registry.register_alias("Pos", "Transform");
+    /// }
+    /// ```
+    pub fn register_alias(&mut self, alias: &str, type_name: &str) {
+        self.aliases
+            .insert(alias.to_string(), type_name.to_string());
+    }
+
+    /// Set a config value, substituted for `${name}` in *.prefab* files when they're loaded,
+    /// ie: `"textures/${quality}/rock.png"`. Environment variables can be read in the same way
+    /// with `${env:NAME}`.
+    ///
+    /// Unlike [crate::PrefabVars], config values are part of the prefab's text, so they can be
+    /// used anywhere and only affect prefabs loaded after they're set.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>) {
+    ///     registry.set_config_value("quality", "high");
+    /// }
+    /// ```
+    pub fn set_config_value(&mut self, name: &str, value: impl ToString) {
+        self.config.insert(name.to_string(), value.to_string());
+    }
+
+    /// Get a config value set with [PrefabRegistry::set_config_value].
+    pub fn config_value(&self, name: &str) -> Option<&str> {
+        self.config.get(name).map(|value| value.as_str())
+    }
 
-        self.type_data.insert(name, info);
+    /// Remove a config value, returning it.
+    pub fn remove_config_value(&mut self, name: &str) -> Option<String> {
+        self.config.remove(name)
     }
 
     /// Register a [BuildPrefabCommand] for use in a [Prefab].
@@ -67,80 +631,2664 @@ impl PrefabRegistry {
         &mut self,
     ) {
         let t = T::default();
-        self.commands.insert(t.key().to_string(), Arc::new(t));
+        self.insert_command(t.key().to_string(), Arc::new(t));
     }
 
-    /// Load the [Prefab] from disk, or retrieve it if it's already been loaded.
+    /// Register a closure as a [BuildPrefabCommand] for use in a [Prefab].
     ///
-    /// When first called for a prefab this will load it from disk and cache it internally.
-    /// Future load calls for the same prefab will re-use this cached result.
-    pub fn load(&mut self, name: &str) -> Result<&Arc<Prefab>, LoadPrefabError> {
-        if self.prefabs.contains_key(name) {
-            return Ok(self.prefabs.get(name).unwrap());
+    /// `key` is the name used to refer to the command from a *.prefab* file. This
+    /// is a convenient alternative to implementing [BuildPrefabCommand] for simple
+    /// one-off commands.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// struct Gold(i32);
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>) {
+    ///     // Used as `GiveGold!()` in a prefab file.
+    ///     registry.register_command_fn("GiveGold", |_props, world, entity| {
+    ///         world.entity_mut(entity).insert(Gold(10));
+    ///     });
+    /// }
+    /// ```
+    pub fn register_command_fn<F>(&mut self, key: &str, func: F)
+    where
+        F: Fn(Option<&DynamicStruct>, &mut World, Entity) + Send + Sync + 'static,
+    {
+        let command = FnCommand {
+            key: key.to_string(),
+            func,
         };
+        self.insert_command(key.to_string(), Arc::new(command));
+    }
 
-        let path = ["assets/prefabs/", name].join("");
-
-        let prefab_string = match fs::read_to_string(path) {
-            Ok(str) => str,
-            Err(e) => return Err(LoadPrefabError::FileReadError(e)),
+    /// Register a bundle for use in a [Prefab] as `bundle Name { .. }`, where `Name` is the
+    /// bundle's type name without its module path.
+    ///
+    /// The bundle is inserted with its default values before the prefab's other build steps,
+    /// then any components listed inside the braces are applied over it. Those components
+    /// must be registered with [PrefabRegistry::register_type] as usual. The plugin registers
+    /// bevy's bundles for each enabled feature, such as `SpriteBundle` and `PbrBundle`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// #[derive(Bundle, Default)]
+    /// struct UnitBundle {
+    ///     transform: Transform,
+    ///     global_transform: GlobalTransform,
+    /// }
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>) {
+    ///     // Used as `bundle UnitBundle { Transform { scale: Vec3 { x: 2.0 } } }` in a prefab file.
+    ///     registry.register_bundle::<UnitBundle>();
+    /// }
+    /// ```
+    pub fn register_bundle<B: Bundle + Default>(&mut self) {
+        let name = TypeRegistration::get_short_name(std::any::type_name::<B>());
+        let key = bundle_command_key(&name);
+        let command = InsertBundle::<B> {
+            key: key.clone(),
+            bundle: PhantomData,
         };
+        self.insert_command(key, Arc::new(command));
+    }
 
-        match parse_prefab_string(&prefab_string, self) {
-            Ok(prefab) => {
-                //let entry = self.prefab_map.entry(prefab_name.to_string());
-                let entry = self.prefabs.entry(name.to_string());
-                Ok(entry.or_insert_with(|| Arc::new(prefab)))
+    fn insert_command(
+        &mut self,
+        key: String,
+        command: Arc<dyn BuildPrefabCommand + Send + Sync + 'static>,
+    ) {
+        if self.commands.contains_key(&key) {
+            match self.command_conflicts {
+                CommandConflictPolicy::Warn => {
+                    warn!(
+                        "Build command '{}' was registered more than once, replacing it",
+                        key
+                    )
+                }
+                CommandConflictPolicy::Error => {
+                    panic!("Build command '{}' was registered more than once", key)
+                }
+                CommandConflictPolicy::Override => {}
             }
-            Err(e) => Err(e),
         }
+        self.commands.insert(key, command);
     }
 
-    /// Remove a cached [Prefab] from the registry.
+    /// Set what happens when a build command is registered with a key that's already in use.
     ///
-    /// The next time the prefab is loaded it will be read from disk.
-    pub fn unload_prefab(&mut self, name: &str) {
-        self.prefabs.remove(name);
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>) {
+    ///     // This mod replaces the built in command on purpose.
+    ///     registry.set_command_conflict_policy(CommandConflictPolicy::Override);
+    ///     registry.register_command_fn("InsertSpriteBundle", |_props, _world, _entity| {});
+    /// }
+    /// ```
+    pub fn set_command_conflict_policy(&mut self, policy: CommandConflictPolicy) {
+        self.command_conflicts = policy;
     }
 
-    pub(crate) fn get_build_command(
-        &self,
-        name: &str,
-    ) -> Option<&Arc<dyn BuildPrefabCommand + Send + Sync + 'static>> {
-        self.commands.get(name)
+    /// The keys of every registered build command, sorted. Aliases aren't included.
+    pub fn command_keys(&self) -> Vec<&str> {
+        let mut keys: Vec<_> = self.commands.keys().map(|k| k.as_str()).collect();
+        keys.sort_unstable();
+        keys
     }
 
-    pub(crate) fn get_type_data(&self, name: &str) -> Option<&TypeInfo> {
-        self.type_data.get(name)
+    /// Returns true if a build command is registered with the given key or alias.
+    pub fn has_command(&self, key: &str) -> bool {
+        self.commands.contains_key(key)
+            || self
+                .command_aliases
+                .get(key)
+                .map_or(false, |(command, _)| self.commands.contains_key(command))
     }
-}
 
-pub(crate) struct TypeInfo {
-    #[allow(dead_code)]
-    pub type_name: String,
-    pub reflect_type: ReflectType,
-    pub registration: TypeRegistration,
-}
+    /// Let prefab files refer to a build command by another key, ie: a key that was used
+    /// before the command was renamed.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>) {
+    ///     registry.alias_command("SpriteBundle", "InsertSpriteBundle");
+    /// }
+    /// ```
+    pub fn alias_command(&mut self, alias: &str, command: &str) {
+        self.command_aliases
+            .insert(alias.to_string(), (command.to_string(), false));
+    }
 
-#[derive(PartialEq, Eq, Clone, Debug)]
-pub(crate) enum ReflectType {
-    Struct,
-    TupleStruct,
-    Tuple,
-    List,
-    Map,
-    Value,
-}
+    /// Like [PrefabRegistry::alias_command], but a warning naming the prefab and the old key is
+    /// logged when a prefab using the alias is loaded.
+    pub fn deprecate_command_alias(&mut self, alias: &str, command: &str) {
+        self.command_aliases
+            .insert(alias.to_string(), (command.to_string(), true));
+    }
 
-impl From<ReflectRef<'_>> for ReflectType {
-    fn from(reflect: ReflectRef) -> Self {
-        match reflect {
-            ReflectRef::Struct(_) => ReflectType::Struct,
-            ReflectRef::TupleStruct(_) => ReflectType::TupleStruct,
-            ReflectRef::Tuple(_) => ReflectType::Tuple,
-            ReflectRef::List(_) => ReflectType::List,
-            ReflectRef::Map(_) => ReflectType::Map,
-            ReflectRef::Value(_) => ReflectType::Value,
+    /// Resolve a command key written in a prefab file. Returns the key of the command, and
+    /// the written key if it's a deprecated alias.
+    pub(crate) fn resolve_command_key(&self, key: String) -> (String, Option<String>) {
+        match self.command_aliases.get(&key) {
+            Some((command, true)) => (command.clone(), Some(key)),
+            Some((command, false)) => (command.clone(), None),
+            None => (key, None),
         }
     }
+
+    /// Set default properties for a build command, written as they would be inside the
+    /// command's parentheses. Properties written in a prefab file take precedence over the
+    /// defaults, and property groups are merged.
+    ///
+    /// Defaults are applied when a prefab is parsed, so they only affect prefabs loaded
+    /// after they're set. See also [crate::LazyPrefabsPlugin::with_command_defaults].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>) {
+    ///     // Pixel art sprites unless a prefab says otherwise.
+    ///     registry
+    ///         .set_command_defaults("InsertSpriteBundle", r#"filter: "nearest""#)
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn set_command_defaults(
+        &mut self,
+        command: &str,
+        properties: &str,
+    ) -> Result<(), LoadPrefabError> {
+        self.command_defaults.remove(command);
+        let properties = parse_command_properties(properties, self)?;
+        self.command_defaults
+            .insert(command.to_string(), properties);
+        Ok(())
+    }
+
+    /// Remove the default properties set for a build command.
+    pub fn clear_command_defaults(&mut self, command: &str) {
+        self.command_defaults.remove(command);
+    }
+
+    pub(crate) fn get_command_defaults(&self, command: &str) -> Option<&DynamicStruct> {
+        self.command_defaults.get(command)
+    }
+
+    /// Register a rule that any prefab entity with a `T` component also needs a `C` component.
+    ///
+    /// After a prefab's build steps have run, a default `C` is inserted on the entity if it has
+    /// a `T` but no `C`. Rules can chain, ie: if `A` implies `B` and `B` implies `C` then an
+    /// entity with an `A` gets both. The plugin registers `Transform` implies `GlobalTransform`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>) {
+    ///     registry.add_companion::<Sprite, Visible>();
+    ///     registry.add_companion::<Sprite, Draw>();
+    /// }
+    /// ```
+    pub fn add_companion<T: Component, C: Component + Default>(&mut self) {
+        self.companions.push(Box::new(|world, entity| {
+            let e = world.entity(entity);
+            if !e.contains::<T>() || e.contains::<C>() {
+                return None;
+            }
+            world.entity_mut(entity).insert(C::default());
+            Some(std::any::type_name::<C>())
+        }));
+    }
+
+    /// Insert any missing companion components on the entity, see [PrefabRegistry::add_companion].
+    /// Returns the type names of the inserted components.
+    pub(crate) fn insert_companions(&self, world: &mut World, entity: Entity) -> Vec<&'static str> {
+        let mut inserted = Vec::new();
+        // Repeat until nothing changes so rules can depend on each other.
+        for _ in 0..self.companions.len() {
+            let count = inserted.len();
+            for rule in self.companions.iter() {
+                inserted.extend(rule(world, entity));
+            }
+            if inserted.len() == count {
+                break;
+            }
+        }
+        inserted
+    }
+
+    /// Register a check which is run on every entity of a prefab when the prefab is loaded.
+    ///
+    /// The check receives the entity's components and build commands and returns a warning
+    /// message if something looks wrong. Warnings are logged, they don't prevent the prefab
+    /// from loading. The plugin registers checks for common mistakes, such as rendering
+    /// components on an entity with no `Transform`.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>) {
+    ///     registry.add_lint(|entity| match entity.has_component("Health") {
+    ///         true if !entity.has_component("Team") => Some("Health without a Team".to_string()),
+    ///         _ => None,
+    ///     });
+    /// }
+    /// ```
+    pub fn add_lint<F>(&mut self, lint: F)
+    where
+        F: Fn(&LintEntity) -> Option<String> + Send + Sync + 'static,
+    {
+        self.lints.push(Box::new(lint));
+    }
+
+    /// Run every registered lint on the prefab, returning the warnings.
+    pub fn lint(&self, prefab: &Prefab) -> Vec<String> {
+        let mut warnings = Vec::new();
+        lint_prefab(&self.lints, self, prefab, &mut warnings);
+        warnings
+    }
+
+    fn warn_lints(&self, name: &str, prefab: &Prefab) {
+        for warning in self.lint(prefab) {
+            warn!("Prefab {}: {}", name, warning);
+        }
+        for (old, new) in deprecated_command_keys(prefab) {
+            warn!(
+                "Prefab {}: the command key '{}' is deprecated, use '{}' instead.",
+                name, old, new
+            );
+        }
+    }
+
+    /// Register a named color which can be referred to from a *.prefab* file.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>) {
+    ///     // Used as `color: Color::UI_ACCENT` in a prefab file.
+    ///     registry.register_color("UI_ACCENT", Color::rgb(0.9, 0.4, 0.1));
+    /// }
+    /// ```
+    #[cfg(feature = "render")]
+    pub fn register_color(&mut self, name: &str, color: Color) {
+        self.colors.insert(name.to_string(), color);
+    }
+
+    #[cfg(feature = "render")]
+    pub(crate) fn get_color(&self, name: &str) -> Option<Color> {
+        self.colors.get(name).cloned()
+    }
+
+    /// Register a migration which converts *.prefab* files from one format version to another.
+    ///
+    /// A *.prefab* file can declare its version with a `version: 2` header before the prefab.
+    /// Files without a header are considered to be version 1. When a file is loaded, migrations
+    /// are applied one after another starting from the file's version until there are no
+    /// further migrations registered. Each migration receives the prefab text (without the
+    /// header) and returns the updated text.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>) {
+    ///     // `Position` was renamed to `Transform` in version 2.
+    ///     registry.add_migration(1, 2, |text| text.replace("Position", "Transform"));
+    /// }
+    /// ```
+    pub fn add_migration(
+        &mut self,
+        from: u32,
+        to: u32,
+        migration: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) {
+        self.migrations.insert(from, (to, Box::new(migration)));
+    }
+
+    /// Apply all migrations starting from `version`. Returns [None] if no migrations applied.
+    pub(crate) fn migrate(&self, mut version: u32, text: &str) -> Option<String> {
+        let mut migrated: Option<String> = None;
+        while let Some((to, migration)) = self.migrations.get(&version) {
+            if *to <= version {
+                break;
+            }
+            migrated = Some(migration(migrated.as_deref().unwrap_or(text)));
+            version = *to;
+        }
+        migrated
+    }
+
+    /// Set the [PrefabSource] prefab files are read from. Defaults to a [FileSource]
+    /// reading from the *assets* directory.
+    ///
+    /// Only affects prefabs that haven't already been loaded.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::{asset::FileAssetIo, prelude::*};
+    /// use bevy_lazy_prefabs::{source::AssetIoSource, *};
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>) {
+    ///     let io = FileAssetIo::new("assets");
+    ///     registry.set_source(AssetIoSource::new(Box::new(io)));
+    /// }
+    /// ```
+    pub fn set_source(&mut self, source: impl PrefabSource) {
+        self.source = SourceBox(Box::new(source));
+    }
+
+    /// Set how the names of prefabs referenced from other files are resolved, such as the
+    /// `name` of a `LoadPrefab` command or the prefabs in a *.level* file. Defaults to a
+    /// [RelativeResolver], which looks for names relative to the including file.
+    ///
+    /// Only affects prefabs that haven't already been loaded.
+    pub fn set_resolver(&mut self, resolver: impl PrefabResolver) {
+        self.resolver = ResolverBox(Box::new(resolver));
+    }
+
+    /// The prefab `name` refers to when it's written in the file `from`, see
+    /// [PrefabRegistry::set_resolver].
+    ///
+    /// The first candidate from the resolver which is already loaded or has a file in one of
+    /// the prefab directories is returned, or the first candidate if none do.
+    pub fn resolve(&self, name: &str, from: &str) -> String {
+        let candidates = self.resolver.0.candidates(name, from);
+        candidates
+            .iter()
+            .find(|candidate| self.prefab_exists(candidate))
+            .or_else(|| candidates.first())
+            .cloned()
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    fn prefab_exists(&self, name: &str) -> bool {
+        // Prefabs from a library are named `file#Name`.
+        let file = name.split('#').next().unwrap_or(name);
+        self.prefabs.contains_key(name)
+            || self.levels.contains_key(name)
+            || self.tables.contains_key(name)
+            || self
+                .prefab_dirs()
+                .iter()
+                .any(|dir| self.source.0.exists(&dir.join(file)))
+    }
+
+    /// Resolve the names of prefabs referenced by the prefab's build commands, relative to
+    /// the file `from`. See [BuildPrefabCommand::prefab_properties].
+    fn resolve_includes(&self, from: &str, prefab: &mut Prefab) {
+        for step in prefab.steps.iter_mut() {
+            match step {
+                PrefabBuildStep::RunCommand(data) => {
+                    let paths = match self.get_build_command(&data.name) {
+                        Some(command) => command.prefab_properties(),
+                        None => continue,
+                    };
+                    // Freshly parsed prefabs aren't shared yet.
+                    let data = match Arc::get_mut(data) {
+                        Some(data) => data,
+                        None => continue,
+                    };
+                    if let Some(props) = data.properties.as_mut() {
+                        for path in paths {
+                            let path: Vec<_> = path.split('.').collect();
+                            self.resolve_property(props, &path, from);
+                        }
+                    }
+                }
+                PrefabBuildStep::AddChild(child) => {
+                    if let Some(child) = Arc::get_mut(child) {
+                        self.resolve_includes(from, child);
+                    }
+                }
+                PrefabBuildStep::AddComponent(_) => {}
+            }
+        }
+    }
+
+    fn resolve_property(&self, value: &mut dyn Reflect, path: &[&str], from: &str) {
+        if let Some(name) = value.downcast_mut::<String>() {
+            if path.is_empty() {
+                *name = self.resolve(name, from);
+            }
+            return;
+        }
+        match value.reflect_mut() {
+            ReflectMut::List(list) => {
+                for i in 0..list.len() {
+                    self.resolve_property(list.get_mut(i).unwrap(), path, from);
+                }
+            }
+            ReflectMut::Struct(s) => {
+                if let Some((field, rest)) = path.split_first() {
+                    if let Some(field) = s.field_mut(field) {
+                        self.resolve_property(field, rest, from);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Add a directory to search for prefab files, such as a directory of mods.
+    ///
+    /// The path is relative to the root of the [PrefabSource], which is the *assets*
+    /// directory by default. When a file is loaded, directories are searched from highest to lowest `priority`
+    /// and the first one containing the file is used. The prefab directory (*assets/prefabs* by
+    /// default) has a priority of 0, so a root with a higher priority can replace any of the base game's
+    /// prefabs by providing a file with the same name. Roots with the same priority are
+    /// searched in the order they were added.
+    ///
+    /// Note: unlike 0.2, a relative `path` is resolved against the [PrefabSource] root rather
+    /// than the working directory, ie: `"mods/prefabs"` is read from *assets/mods/prefabs*. Use
+    /// an absolute path to search a directory outside of it.
+    ///
+    /// Only affects prefabs that haven't already been loaded.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>) {
+    ///     registry.add_prefab_root("mods/prefabs", 10);
+    /// }
+    /// ```
+    pub fn add_prefab_root(&mut self, path: impl Into<PathBuf>, priority: i32) {
+        let index = self
+            .roots
+            .iter()
+            .position(|(p, _)| *p < priority)
+            .unwrap_or(self.roots.len());
+        self.roots.insert(index, (priority, path.into()));
+    }
+
+    /// Set the directory prefab files are loaded from, relative to the root of the [PrefabSource].
+    /// Defaults to *prefabs*, ie: *assets/prefabs* when loading from the *assets* directory.
+    ///
+    /// Only affects prefabs that haven't already been loaded.
+    pub fn set_prefab_dir(&mut self, path: impl Into<PathBuf>) {
+        self.prefab_dir = Some(path.into());
+    }
+
+    /// The directory set with [PrefabRegistry::set_prefab_dir].
+    fn base_dir(&self) -> &Path {
+        self.prefab_dir
+            .as_deref()
+            .unwrap_or_else(|| Path::new(BASE_PREFAB_DIR))
+    }
+
+    /// All prefab directories, from highest to lowest priority.
+    fn prefab_dirs(&self) -> Vec<&Path> {
+        let base = self
+            .roots
+            .iter()
+            .position(|(p, _)| *p <= 0)
+            .unwrap_or(self.roots.len());
+        let mut dirs: Vec<_> = self.roots.iter().map(|(_, path)| path.as_path()).collect();
+        dirs.insert(base, self.base_dir());
+        dirs
+    }
+
+    /// Read the highest priority file with the given name.
+    fn read_prefab_file(&self, name: &str) -> io::Result<String> {
+        let mut not_found = None;
+        for dir in self.prefab_dirs() {
+            match self.source.0.read_to_string(&dir.join(name)) {
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    // Report the error from the base directory if no root contains the file.
+                    if dir == self.base_dir() {
+                        not_found = Some(e);
+                    }
+                }
+                result => return result,
+            }
+        }
+        Err(not_found.unwrap())
+    }
+
+    /// Load the [Prefab] from disk, or retrieve it if it's already been loaded.
+    ///
+    /// When first called for a prefab this will load it from disk and cache it internally.
+    /// Future load calls for the same prefab will re-use this cached result.
+    pub fn load(&mut self, name: &str) -> Result<&Arc<Prefab>, LoadPrefabError> {
+        if self.prefabs.contains_key(name) {
+            self.touch(name);
+            return Ok(self.prefabs.get(name).unwrap());
+        };
+
+        let text = match self.read_prefab_file(name) {
+            Ok(text) => text,
+            Err(e) => return Err(self.report_failure(name, e.into())),
+        };
+        self.cache_prefab_text(name, &text)
+    }
+
+    /// Start loading a prefab in the background through the `AssetServer`.
+    ///
+    /// Once the file has loaded the prefab is parsed and cached, after which
+    /// [PrefabRegistry::load] returns it without reading the file again. This is
+    /// required on wasm, where files can't be read synchronously. Preloaded files are
+    /// read from the prefab directory inside the asset directory, prefab roots and the
+    /// [PrefabSource] are not used.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>, server: Res<AssetServer>) {
+    ///     registry.preload(&server, "sprite.prefab");
+    /// }
+    ///
+    /// fn spawn(mut commands: Commands, mut registry: ResMut<PrefabRegistry>) {
+    ///     if !registry.has_pending_preloads() {
+    ///         let sprite = registry.load("sprite.prefab").unwrap();
+    ///         commands.spawn().insert_prefab(sprite);
+    ///     }
+    /// }
+    /// ```
+    pub fn preload(&mut self, server: &AssetServer, name: &str) {
+        if self.prefabs.contains_key(name) {
+            return;
+        }
+        let handle = server.load(self.base_dir().join(name));
+        self.add_pending_preload(name.to_string(), handle);
+    }
+
+    /// Returns true if any prefabs passed to [PrefabRegistry::preload] haven't finished loading.
+    pub fn has_pending_preloads(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub(crate) fn add_pending_preload(&mut self, name: String, handle: Handle<PrefabText>) {
+        self.pending.push((name, handle));
+    }
+
+    pub(crate) fn take_pending_preloads(&mut self) -> Vec<(String, Handle<PrefabText>)> {
+        std::mem::take(&mut self.pending)
+    }
+
+    pub(crate) fn preload_failed(&mut self, name: &str) {
+        let e = io::Error::new(
+            io::ErrorKind::Other,
+            "the AssetServer failed to load the file",
+        );
+        self.report_failure(name, e.into());
+    }
+
+    /// Load a prefab without caching it, for when the registry can't be borrowed mutably, ie:
+    /// from a build command while a prefab is being applied. Cached prefabs are returned
+    /// as they are.
+    pub(crate) fn load_shared(&self, name: &str) -> Result<Arc<Prefab>, LoadPrefabError> {
+        if let Some(prefab) = self.prefabs.get(name) {
+            return Ok(prefab.clone());
+        }
+        let text = self
+            .read_prefab_file(name)
+            .map_err(|e| self.report_failure(name, e.into()))?;
+        self.parse_prefab_text(name, &text)
+            .map(Arc::new)
+            .map_err(|e| self.report_failure(name, e))
+    }
+
+    /// Load a prefab with [PrefabRegistry::load_shared], or the fallback prefab if it fails
+    /// to load.
+    pub(crate) fn load_shared_or_fallback(&self, name: &str) -> Option<Arc<Prefab>> {
+        self.load_shared(name)
+            .ok()
+            .or_else(|| self.load_shared(self.fallback.as_deref()?).ok())
+    }
+
+    /// Parse prefab text loaded from the file `name`.
+    fn parse_prefab_text(&self, name: &str, text: &str) -> Result<Prefab, LoadPrefabError> {
+        let mut prefab = parse_prefab_str(text, self)?;
+        self.resolve_includes(name, &mut prefab);
+        prefab.source = Some(name.to_string());
+        self.warn_lints(name, &prefab);
+        Ok(prefab)
+    }
+
+    /// Parse prefab text and cache the result under `name`.
+    pub(crate) fn cache_prefab_text(
+        &mut self,
+        name: &str,
+        text: &str,
+    ) -> Result<&Arc<Prefab>, LoadPrefabError> {
+        match self.parse_prefab_text(name, text) {
+            Ok(prefab) => {
+                self.prefabs.insert(name.to_string(), Arc::new(prefab));
+                self.text_hashes
+                    .insert(name.to_string(), content_hash(text));
+                self.last_used.insert(name.to_string(), 0);
+                self.touch(name);
+                self.trim_cache(name);
+                Ok(self.prefabs.get(name).unwrap())
+            }
+            Err(e) => Err(self.report_failure(name, e)),
+        }
+    }
+
+    /// Cache prefab text loaded as an asset, unless the cached prefab was already parsed from
+    /// the same text.
+    pub(crate) fn cache_prefab_asset(&mut self, name: &str, text: &str) {
+        if !self.is_unchanged(name, content_hash(text)) {
+            // Errors are reported through the `PrefabLoadFailed` event.
+            let _ = self.cache_prefab_text(name, text);
+        }
+    }
+
+    /// The name a prefab asset is cached under - its path relative to the prefab directory,
+    /// or its full asset path if it's outside of it.
+    pub(crate) fn prefab_asset_name(&self, path: &Path) -> String {
+        path.strip_prefix(self.base_dir())
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/")
+    }
+
+    /// Log a load error and queue a [PrefabLoadFailed] event for it.
+    fn report_failure(&self, name: &str, e: LoadPrefabError) -> LoadPrefabError {
+        error!("Error loading prefab {}: {}", name, e);
+        self.failed.lock().unwrap().push(PrefabLoadFailed {
+            name: name.to_string(),
+            error: e.to_string(),
+        });
+        e
+    }
+
+    /// Set a prefab to spawn in place of prefabs which fail to load, such as a
+    /// brightly colored error cube. Pass [None] to disable the fallback.
+    ///
+    /// Used by commands which load a prefab by name when they're applied, such as
+    /// [crate::SpawnNamedPrefabCommands::spawn_prefab_at] and the `LoadPrefab` build
+    /// command. A [PrefabLoadFailed] event is sent for every failed load either way.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>) {
+    ///     registry.set_fallback_prefab(Some("error_cube.prefab"));
+    /// }
+    /// ```
+    pub fn set_fallback_prefab(&mut self, name: Option<&str>) {
+        self.fallback = name.map(str::to_string);
+    }
+
+    /// Load a prefab, or the fallback prefab if it fails to load.
+    pub(crate) fn load_or_fallback(&mut self, name: &str) -> Option<Arc<Prefab>> {
+        if let Ok(prefab) = self.load(name) {
+            return Some(prefab.clone());
+        }
+        let fallback = self.fallback.clone()?;
+        self.load(&fallback).ok().cloned()
+    }
+
+    /// Load every prefab in the prefab directories whose path matches `pattern`.
+    ///
+    /// In the file name part of the pattern `*` matches any number of characters and
+    /// `?` matches a single character. The directory part is matched literally. Returns
+    /// the names of the loaded prefabs in alphabetical order, which can be passed to
+    /// [PrefabRegistry::load].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn setup(mut commands: Commands, mut registry: ResMut<PrefabRegistry>) {
+    ///     for name in registry.load_glob("enemies/*.prefab").unwrap() {
+    ///         let prefab = registry.load(&name).unwrap();
+    ///         commands.spawn().insert_prefab(prefab);
+    ///     }
+    /// }
+    /// ```
+    pub fn load_glob(&mut self, pattern: &str) -> Result<Vec<String>, LoadPrefabError> {
+        let names = self.glob_names(pattern)?;
+        for name in names.iter() {
+            self.load(name)?;
+        }
+        Ok(names)
+    }
+
+    /// The names of the files in the prefab directories matching `pattern`, see [PrefabRegistry::load_glob].
+    fn glob_names(&self, pattern: &str) -> Result<Vec<String>, LoadPrefabError> {
+        let (dir, file_pattern) = match pattern.rfind('/') {
+            Some(i) => (&pattern[..=i], &pattern[i + 1..]),
+            None => ("", pattern),
+        };
+
+        let mut names = BTreeSet::new();
+        for root in self.prefab_dirs() {
+            let entries = match self.source.0.read_dir(&root.join(dir)) {
+                Ok(entries) => entries,
+                // Overlay directories only need to contain the files they override.
+                Err(e) if e.kind() == io::ErrorKind::NotFound && root != self.base_dir() => {
+                    continue
+                }
+                Err(e) => return Err(e.into()),
+            };
+            for file_name in entries {
+                if wildcard_match(file_pattern, &file_name) {
+                    names.insert([dir, &file_name].join(""));
+                }
+            }
+        }
+        Ok(names.into_iter().collect())
+    }
+
+    /// The names of every cached prefab and every *.prefab* file in the prefab
+    /// directories, sorted.
+    pub fn prefab_names(&self) -> Vec<String> {
+        let mut names: BTreeSet<String> = self.prefabs.keys().cloned().collect();
+        names.extend(self.glob_names("*.prefab").unwrap_or_default());
+        names.into_iter().collect()
+    }
+
+    /// Load every *.prefab* and *.prefabs* file in the prefab directories and return
+    /// every error found.
+    ///
+    /// Besides parse errors, prefabs which use a build command that isn't registered are
+    /// reported. Only the top level of each directory is searched. Prefabs which load
+    /// successfully are cached. Used by [crate::LazyPrefabsPlugin::strict].
+    pub fn validate_all(&mut self) -> Vec<PrefabLoadFailed> {
+        let mut errors = Vec::new();
+        let mut error = |name: &str, e: &dyn std::fmt::Display| {
+            errors.push(PrefabLoadFailed {
+                name: name.to_string(),
+                error: e.to_string(),
+            })
+        };
+
+        let mut prefabs = Vec::new();
+        for (pattern, library) in [("*.prefab", false), ("*.prefabs", true)] {
+            let names = match self.glob_names(pattern) {
+                Ok(names) => names,
+                Err(e) => {
+                    error(pattern, &e);
+                    continue;
+                }
+            };
+            for name in names {
+                match library {
+                    false => match self.load(&name) {
+                        Ok(prefab) => prefabs.push(prefab.clone()),
+                        Err(e) => error(&name, &e),
+                    },
+                    true => match self.load_library(&name) {
+                        Ok(names) => prefabs.extend(names.iter().map(|n| self.prefabs[n].clone())),
+                        Err(e) => error(&name, &e),
+                    },
+                }
+            }
+        }
+
+        for prefab in prefabs {
+            if let Some(command) = self.find_unregistered_command(&prefab) {
+                let name = prefab.source.as_deref().unwrap_or_default();
+                let e = format!(
+                    "The build command {} was not registered with the PrefabRegistry.",
+                    command
+                );
+                error(name, &e);
+            }
+        }
+        errors
+    }
+
+    fn find_unregistered_command(&self, prefab: &Prefab) -> Option<String> {
+        prefab.steps.iter().find_map(|step| match step {
+            PrefabBuildStep::RunCommand(command)
+                if self.get_build_command(&command.name).is_none() =>
+            {
+                Some(command.name.clone())
+            }
+            PrefabBuildStep::AddChild(child) => self.find_unregistered_command(child),
+            _ => None,
+        })
+    }
+
+    /// Load a prefab library from the *assets/prefabs* directory.
+    ///
+    /// A library file contains any number of named prefabs. Each one is cached under the
+    /// name `"{name}#{prefab}"` and can be retrieved with [PrefabRegistry::load] like any
+    /// other prefab. Returns the names of the loaded prefabs.
+    ///
+    /// ## Example
+    ///
+    /// ```ignore
+    /// // items.prefabs
+    /// HealthPotion { Transform, Potion { heal: 10 } }
+    /// ManaPotion { Transform, Potion { mana: 10 } }
+    /// ```
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn setup(mut commands: Commands, mut registry: ResMut<PrefabRegistry>) {
+    ///     registry.load_library("items.prefabs").unwrap();
+    ///     let potion = registry.load("items.prefabs#HealthPotion").unwrap();
+    ///     commands.spawn().insert_prefab(potion);
+    /// }
+    /// ```
+    pub fn load_library(&mut self, name: &str) -> Result<Vec<String>, LoadPrefabError> {
+        let library_string = self.read_prefab_file(name)?;
+
+        let prefabs = parse_prefab_library_string(&library_string, self)?;
+
+        let mut names = Vec::new();
+        for mut prefab in prefabs {
+            let prefab_name = format!("{}#{}", name, prefab.name.as_deref().unwrap());
+            self.resolve_includes(name, &mut prefab);
+            prefab.source = Some(prefab_name.clone());
+            self.warn_lints(&prefab_name, &prefab);
+            self.last_used.remove(&prefab_name);
+            self.text_hashes.remove(&prefab_name);
+            self.prefabs.insert(prefab_name.clone(), Arc::new(prefab));
+            names.push(prefab_name);
+        }
+        Ok(names)
+    }
+
+    /// Load a *.level* file from the prefab directory, or retrieve it if it's already been loaded.
+    ///
+    /// The prefabs in the level aren't loaded until it's spawned, see
+    /// [crate::SpawnNamedPrefabCommands::spawn_level].
+    pub fn load_level(&mut self, name: &str) -> Result<&Arc<Level>, LoadPrefabError> {
+        if !self.levels.contains_key(name) {
+            let text = match self.read_prefab_file(name) {
+                Ok(text) => text,
+                Err(e) => return Err(self.report_failure(name, e.into())),
+            };
+            let mut level = match parse_level_string(&text, self) {
+                Ok(level) => level,
+                Err(e) => return Err(self.report_failure(name, e)),
+            };
+            for instance in level.instances.iter_mut() {
+                instance.prefab = self.resolve(&instance.prefab, name);
+                if let Some(overrides) = instance.overrides.as_mut().and_then(Arc::get_mut) {
+                    self.resolve_includes(name, overrides);
+                }
+            }
+            self.levels.insert(name.to_string(), Arc::new(level));
+        }
+        Ok(self.levels.get(name).unwrap())
+    }
+
+    /// Load a *.table* file from the prefab directory, or retrieve it if it's already been loaded.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn drop_loot(mut registry: ResMut<PrefabRegistry>) {
+    ///     if let Ok(table) = registry.load_table("loot.table") {
+    ///         let item = table.pick(0.5);
+    ///     }
+    /// }
+    /// ```
+    pub fn load_table(&mut self, name: &str) -> Result<&Arc<PrefabTable>, LoadPrefabError> {
+        if !self.tables.contains_key(name) {
+            let text = match self.read_prefab_file(name) {
+                Ok(text) => text,
+                Err(e) => return Err(self.report_failure(name, e.into())),
+            };
+            let mut table = match parse_table_string(&text, self) {
+                Ok(table) => table,
+                Err(e) => return Err(self.report_failure(name, e)),
+            };
+            for (prefab, _) in table.entries.iter_mut() {
+                *prefab = self.resolve(prefab, name);
+            }
+            self.tables.insert(name.to_string(), Arc::new(table));
+        }
+        Ok(self.tables.get(name).unwrap())
+    }
+
+    /// Parse `source` and replace the cached [Prefab] with the given name.
+    ///
+    /// This allows prefabs to be redefined at runtime without touching the filesystem.
+    /// Entities that were already spawned from the old prefab are not affected. A
+    /// [PrefabChanged] event will be sent for the prefab on the next update.
+    ///
+    /// If parsing fails the cached prefab is left untouched. If `source` is the same as the
+    /// text the cached prefab was parsed from, it isn't parsed again and no event is sent.
+    pub fn replace_prefab(
+        &mut self,
+        name: &str,
+        source: &str,
+    ) -> Result<&Arc<Prefab>, LoadPrefabError> {
+        let hash = content_hash(source);
+        if self.is_unchanged(name, hash) {
+            return Ok(self.prefabs.get(name).unwrap());
+        }
+        let mut prefab = parse_prefab_str(source, self)?;
+        self.resolve_includes(name, &mut prefab);
+        prefab.source = Some(name.to_string());
+        self.warn_lints(name, &prefab);
+        self.last_used.remove(name);
+        self.text_hashes.insert(name.to_string(), hash);
+        self.prefabs.insert(name.to_string(), Arc::new(prefab));
+        self.changed.push(name.to_string());
+        Ok(self.prefabs.get(name).unwrap())
+    }
+
+    /// Read a prefab from disk again and replace the cached [Prefab], such as when an asset
+    /// watcher reports the file has changed.
+    ///
+    /// If the file's text is the same as when it was last loaded it isn't parsed again.
+    /// Returns true if the prefab changed, in which case a [PrefabChanged] event will be sent
+    /// on the next update.
+    pub fn reload(&mut self, name: &str) -> Result<bool, LoadPrefabError> {
+        let text = match self.read_prefab_file(name) {
+            Ok(text) => text,
+            Err(e) => return Err(self.report_failure(name, e.into())),
+        };
+        if self.is_unchanged(name, content_hash(&text)) {
+            return Ok(false);
+        }
+        self.cache_prefab_text(name, &text)?;
+        self.changed.push(name.to_string());
+        Ok(true)
+    }
+
+    /// Call [PrefabRegistry::reload] on every prefab in the prefab directories whose path
+    /// matches `pattern`, see [PrefabRegistry::load_glob]. Returns the names of the prefabs
+    /// which changed.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn on_folder_changed(mut registry: ResMut<PrefabRegistry>) {
+    ///     for name in registry.reload_glob("enemies/*.prefab").unwrap() {
+    ///         info!("Reloaded {}", name);
+    ///     }
+    /// }
+    /// ```
+    pub fn reload_glob(&mut self, pattern: &str) -> Result<Vec<String>, LoadPrefabError> {
+        let mut changed = Vec::new();
+        for name in self.glob_names(pattern)? {
+            if self.reload(&name)? {
+                changed.push(name);
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Returns true if the cached prefab was parsed from text with the given hash.
+    fn is_unchanged(&self, name: &str, hash: u64) -> bool {
+        self.prefabs.contains_key(name) && self.text_hashes.get(name) == Some(&hash)
+    }
+
+    /// Import a serialized bevy scene (*.scn*) from the *assets/prefabs* directory.
+    ///
+    /// Every entity in the scene is converted into a [Prefab] and cached under the name
+    /// `"{name}#{entity}"`, where `entity` is the entity id written in the scene file.
+    /// The imported prefabs can then be retrieved with [PrefabRegistry::load] like any other
+    /// prefab. Returns the names of the imported prefabs.
+    ///
+    /// All components in the scene must be registered with the registry. If the scene can't be
+    /// read or imported a [PrefabLoadFailed] event is sent, like for a prefab which fails to load.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn setup(mut commands: Commands, mut registry: ResMut<PrefabRegistry>) {
+    ///     let names = registry.import_scene("level.scn").unwrap();
+    ///     for name in names {
+    ///         let prefab = registry.load(&name).unwrap();
+    ///         commands.spawn().insert_prefab(prefab);
+    ///     }
+    /// }
+    /// ```
+    pub fn import_scene(&mut self, name: &str) -> Result<Vec<String>, LoadPrefabError> {
+        let prefabs = self
+            .read_scene(name)
+            .map_err(|e| self.report_failure(name, e))?;
+
+        let mut names = Vec::new();
+        for prefab in prefabs {
+            let prefab_name = prefab.name.clone().unwrap();
+            self.last_used.remove(&prefab_name);
+            self.text_hashes.remove(&prefab_name);
+            self.prefabs.insert(prefab_name.clone(), Arc::new(prefab));
+            names.push(prefab_name);
+        }
+
+        Ok(names)
+    }
+
+    /// Read a scene file and convert each of its entities into a [Prefab].
+    fn read_scene(&self, name: &str) -> Result<Vec<Prefab>, LoadPrefabError> {
+        let scene_string = self.read_prefab_file(name)?;
+
+        let type_registry = self.reflect_type_registry();
+        let mut deserializer = ron::de::Deserializer::from_str(&scene_string)?;
+        let scene = SceneDeserializer {
+            type_registry: &type_registry,
+        }
+        .deserialize(&mut deserializer)?;
+
+        let mut prefabs = Vec::new();
+        for entity in scene.entities {
+            let mut steps = Vec::new();
+            for reflect in entity.components {
+                let type_name = self
+                    .get_type_data(reflect.type_name())
+                    .map(|info| info.type_name.clone())
+                    .ok_or_else(|| self.unregistered_type_error(reflect.type_name()))?;
+                steps.push(PrefabBuildStep::AddComponent(Arc::new(PrefabComponent {
+                    type_name,
+                    reflect,
+                    deferred: false,
+                })));
+            }
+
+            let prefab_name = format!("{}#{}", name, entity.entity);
+            prefabs.push(Prefab {
+                name: Some(prefab_name.clone()),
+                source: Some(prefab_name),
+                anchor: None,
+                meta: Default::default(),
+                steps,
+            });
+        }
+        Ok(prefabs)
+    }
+
+    /// Capture an entity's registered components, and those of its children, as a [Prefab]
+    /// cached under `name`, replacing any prefab already cached with that name.
+    ///
+    /// The prefab can then be spawned by name like any other, which is useful for stamping
+    /// copies of an entity set up in code or in a runtime editor. Component values are copied
+    /// as they are when this is called, later changes to the entity don't affect the prefab.
+    /// Components which aren't registered with the [PrefabRegistry] are left out. A
+    /// [PrefabChanged] event will be sent for the prefab on the next update.
+    ///
+    /// Panics if the entity doesn't exist.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn stamp(world: &mut World, configured: Entity) {
+    ///     world.resource_scope(|world, mut registry: Mut<PrefabRegistry>| {
+    ///         registry.register_prefab_from_entity(world, configured, "stamp");
+    ///     });
+    ///     world.spawn_prefab("stamp");
+    /// }
+    /// ```
+    pub fn register_prefab_from_entity(
+        &mut self,
+        world: &World,
+        entity: Entity,
+        name: &str,
+    ) -> &Arc<Prefab> {
+        let mut prefab = self.capture_entity(world, entity);
+        prefab.name = Some(name.to_string());
+        prefab.source = Some(name.to_string());
+        self.last_used.remove(name);
+        self.text_hashes.remove(name);
+        self.prefabs.insert(name.to_string(), Arc::new(prefab));
+        self.changed.push(name.to_string());
+        self.prefabs.get(name).unwrap()
+    }
+
+    fn capture_entity(&self, world: &World, entity: Entity) -> Prefab {
+        let mut types: Vec<_> = self.type_data.values().collect();
+        types.sort_by(|a, b| a.type_name.cmp(&b.type_name));
+        types.dedup_by(|a, b| a.type_name == b.type_name);
+
+        let mut steps = Vec::new();
+        for t in types {
+            let component = t
+                .registration
+                .data::<ReflectComponent>()
+                .and_then(|reflect| reflect.reflect_component(world, entity));
+            if let Some(component) = component {
+                steps.push(PrefabBuildStep::AddComponent(Arc::new(PrefabComponent {
+                    type_name: t.type_name.clone(),
+                    reflect: component.clone_value(),
+                    deferred: false,
+                })));
+            }
+        }
+        if let Some(children) = world.entity(entity).get::<Children>() {
+            for child in children.iter() {
+                let child = self.capture_entity(world, *child);
+                steps.push(PrefabBuildStep::AddChild(Arc::new(child)));
+            }
+        }
+
+        Prefab {
+            name: None,
+            source: None,
+            anchor: None,
+            meta: Default::default(),
+            steps,
+        }
+    }
+
+    /// Write a set of entities to a *.prefab* file through the [PrefabSource], so it can be
+    /// loaded again with [PrefabRegistry::load].
+    ///
+    /// The file is written to the highest priority prefab root which already contains a file
+    /// with that name, or the prefab directory (*assets/prefabs* by default) if none do. Only
+    /// sources which support writing, such as the default [crate::source::FileSource], can be
+    /// exported to.
+    ///
+    /// Any registered components on the entities are written along with their fields. All
+    /// children of the entities are written as nested `children` blocks, including children
+    /// which aren't in `entities`, so exporting a root entity exports its whole hierarchy.
+    /// If more than one root
+    /// entity is given, the file is written as a prefab library with a prefab for each root,
+    /// named `Root1`, `Root2` and so on in the order they're given, see
+    /// [PrefabRegistry::load_library].
+    ///
+    /// Only fields that can be represented in a *.prefab* file are written, any others will be
+    /// initialized to default when the prefab is loaded.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn save(world: &mut World, entities: &[Entity]) {
+    ///     world.resource_scope(|world, registry: Mut<PrefabRegistry>| {
+    ///         registry.export_entities(world, entities, "saved.prefab").unwrap();
+    ///     });
+    /// }
+    /// ```
+    pub fn export_entities(
+        &self,
+        world: &World,
+        entities: &[Entity],
+        name: &str,
+    ) -> std::io::Result<()> {
+        if cfg!(target_arch = "wasm32") {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Prefabs can't be exported on wasm",
+            ));
+        }
+        let out = self.write_entities(world, entities);
+        let dir = self
+            .prefab_dirs()
+            .into_iter()
+            .find(|dir| self.source.0.exists(&dir.join(name)))
+            .unwrap_or_else(|| self.base_dir());
+        self.source.0.write(&dir.join(name), &out)
+    }
+
+    /// Write entities as a prefab, or a prefab library if there's more than one root entity.
+    fn write_entities(&self, world: &World, entities: &[Entity]) -> String {
+        let roots: Vec<_> = entities
+            .iter()
+            .filter(|e| match world.get::<Parent>(**e) {
+                Some(parent) => !entities.contains(&parent.0),
+                None => true,
+            })
+            .collect();
+
+        let mut out = String::new();
+        if roots.len() == 1 {
+            self.write_entity(&mut out, world, *roots[0], 0);
+        } else {
+            for (i, root) in roots.into_iter().enumerate() {
+                write!(out, "Root{} ", i + 1).unwrap();
+                self.write_entity(&mut out, world, *root, 0);
+            }
+        }
+        out
+    }
+
+    pub(crate) fn write_entity(
+        &self,
+        out: &mut String,
+        world: &World,
+        entity: Entity,
+        depth: usize,
+    ) {
+        let indent = "    ".repeat(depth);
+        writeln!(out, "{}{{", indent).unwrap();
+
+        let mut types: Vec<_> = self
+            .type_data
+            .values()
+            .filter(|t| is_writable_type_name(&t.type_name))
+            .collect();
+        types.sort_by(|a, b| a.type_name.cmp(&b.type_name));
+
+        for t in types {
+            let component = t
+                .registration
+                .data::<ReflectComponent>()
+                .and_then(|reflect| reflect.reflect_component(world, entity));
+            if let Some(component) = component {
+                write_component(out, &t.type_name, component, depth + 1);
+            }
+        }
+
+        if let Some(children) = world.get::<Children>(entity) {
+            writeln!(out, "{}    children [", indent).unwrap();
+            for child in children.iter() {
+                self.write_entity(out, world, *child, depth + 2);
+            }
+            writeln!(out, "{}    ],", indent).unwrap();
+        }
+
+        let end = if depth == 0 { "}" } else { "}," };
+        writeln!(out, "{}{}", indent, end).unwrap();
+    }
+
+    /// Enable or disable tagging spawned prefab entities with a [crate::SpawnedFrom] component.
+    ///
+    /// When enabled, entities that have a prefab applied via
+    /// [crate::SpawnPrefabCommands::insert_prefab] will receive a [crate::SpawnedFrom] component
+    /// containing the name the prefab was loaded by. Disabled by default.
+    ///
+    /// Tagged entities are updated when their prefab changes, such as from
+    /// [PrefabRegistry::reload]. Components deleted from the prefab are removed, and fields
+    /// whose values changed in the prefab are applied again. Other fields keep any changes
+    /// made at runtime. Build commands and children are not run again.
+    pub fn set_tag_spawned(&mut self, enabled: bool) {
+        self.tag_spawned = enabled;
+    }
+
+    pub(crate) fn tag_spawned(&self) -> bool {
+        self.tag_spawned
+    }
+
+    /// Log every build step as prefabs are spawned, to help find out why an entity doesn't
+    /// look the way it should. Disabled by default.
+    ///
+    /// Each component is logged with the field values it's given and the fields left as
+    /// default. Build commands are logged with their properties, noting any that came from
+    /// [PrefabRegistry::set_command_defaults]. Deferred values which couldn't be evaluated and
+    /// the components inserted by [PrefabRegistry::add_companion] are logged as well.
+    ///
+    /// Steps are logged at the `info` level. See also [PrefabRegistry::explain].
+    pub fn set_explain_spawns(&mut self, enabled: bool) {
+        self.explain_spawns = enabled;
+    }
+
+    pub(crate) fn explain_spawns(&self) -> bool {
+        self.explain_spawns
+    }
+
+    /// Describe the build steps of a prefab, one per line, with children indented beneath the
+    /// step which adds them. The description is the same as the one logged for each spawn by
+    /// [PrefabRegistry::set_explain_spawns], without the parts decided at spawn time.
+    ///
+    /// ## Example
+    ///
+    /// ```no_run
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn debug_goblin(mut registry: ResMut<PrefabRegistry>) {
+    ///     // insert Transform { translation: Vec3 { x: 0.0, y: 2.0, z: 0.0 } }, left as default: rotation, scale
+    ///     // run InsertSpriteBundle(texture_path: "goblin.png", filter: "nearest"), from command defaults: filter
+    ///     info!("{}", registry.explain("goblin.prefab").unwrap());
+    /// }
+    /// ```
+    pub fn explain(&mut self, name: &str) -> Result<String, LoadPrefabError> {
+        let prefab = self.load(name)?.clone();
+        let mut out = String::new();
+        explain_prefab(&mut out, self, &prefab, 0);
+        Ok(out)
+    }
+
+    /// Count how many times each prefab is spawned and when it was last spawned, see
+    /// [PrefabRegistry::usage_stats]. Disabled by default. Disabling it clears the counts.
+    ///
+    /// See also [crate::LazyPrefabsPlugin::with_usage_stats].
+    pub fn set_track_usage(&mut self, enabled: bool) {
+        match (enabled, self.usage.is_some()) {
+            (true, false) => self.usage = Some(HashMap::default()),
+            (false, _) => self.usage = None,
+            _ => {}
+        }
+    }
+
+    /// Record that a prefab was spawned by name, if usage tracking is enabled.
+    pub(crate) fn record_spawn(&mut self, name: &str) {
+        if let Some(usage) = &mut self.usage {
+            let usage = usage.entry(name.to_string()).or_default();
+            usage.spawns += 1;
+            usage.last_spawned = Some(Instant::now());
+        }
+    }
+
+    /// How often each prefab has been spawned, sorted by name. Cached prefabs which haven't
+    /// been spawned are included with a count of zero, to help find unused content.
+    ///
+    /// Empty unless usage tracking is enabled with [PrefabRegistry::set_track_usage].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn report_unused(registry: Res<PrefabRegistry>) {
+    ///     for (name, usage) in registry.usage_stats() {
+    ///         if usage.spawns == 0 {
+    ///             info!("{} is never spawned", name);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn usage_stats(&self) -> Vec<(String, PrefabUsage)> {
+        let usage = match &self.usage {
+            Some(usage) => usage,
+            None => return Vec::new(),
+        };
+        let mut stats: Vec<_> = usage
+            .iter()
+            .map(|(name, usage)| (name.clone(), *usage))
+            .collect();
+        stats.extend(
+            self.prefabs
+                .keys()
+                .filter(|name| !usage.contains_key(*name))
+                .map(|name| (name.clone(), PrefabUsage::default())),
+        );
+        stats.sort_by(|a, b| a.0.cmp(&b.0));
+        stats
+    }
+
+    /// Clear the spawn counts, if usage tracking is enabled.
+    pub fn reset_usage_stats(&mut self) {
+        if let Some(usage) = &mut self.usage {
+            usage.clear();
+        }
+    }
+
+    /// Create a component value from a prefab without spawning it.
+    ///
+    /// The prefab is loaded if it isn't already. Returns a default instance of the
+    /// concrete type with the prefab's values applied, or [None] if the prefab couldn't be
+    /// loaded, doesn't have the component, or the component has values which can only be
+    /// evaluated when spawned (such as `rand(..)` or `${var}`). Child prefabs are not searched.
+    ///
+    /// Types registered with [PrefabRegistry::register_type_from_world] need a [World] to be
+    /// created, so they always return [None].
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>) {
+    ///     if let Some(value) = registry.instantiate("sprite.prefab", "Transform") {
+    ///         let transform = value.downcast_ref::<Transform>().unwrap();
+    ///         println!("{:?}", transform.translation);
+    ///     }
+    /// }
+    /// ```
+    pub fn instantiate(&mut self, name: &str, type_name: &str) -> Option<Box<dyn Reflect>> {
+        let prefab = self.load(name).ok()?.clone();
+        let info = self.get_type_data(type_name)?;
+        let mut found = false;
+        let mut value = info.construct_default()?;
+        for step in prefab.steps.iter() {
+            if let PrefabBuildStep::AddComponent(comp) = step {
+                if comp.type_name != info.type_name {
+                    continue;
+                }
+                if comp.deferred {
+                    return None;
+                }
+                value.apply(&*comp.reflect);
+                found = true;
+            }
+        }
+        if found {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// The names of every cached prefab with the given tag in its `meta` block, sorted.
+    ///
+    /// Only prefabs which have been loaded are searched, see [PrefabRegistry::load_glob].
+    pub fn find_by_tag(&self, tag: &str) -> Vec<&str> {
+        let mut names: Vec<_> = self
+            .prefabs
+            .iter()
+            .filter(|(_, prefab)| prefab.meta.has_tag(tag))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Remove a cached [Prefab] from the registry.
+    ///
+    /// The next time the prefab is loaded it will be read from disk.
+    pub fn unload_prefab(&mut self, name: &str) {
+        self.prefabs.remove(name);
+        self.last_used.remove(name);
+        self.text_hashes.remove(name);
+    }
+
+    /// Remove every cached [Prefab], *.level* and *.table* from the registry, including
+    /// prefabs created at runtime such as with [PrefabRegistry::replace_prefab].
+    ///
+    /// Entities already spawned from the prefabs are not affected.
+    pub fn unload_all(&mut self) {
+        self.prefabs.clear();
+        self.levels.clear();
+        self.tables.clear();
+        self.last_used.clear();
+        self.text_hashes.clear();
+    }
+
+    /// Remove every cached [Prefab], *.level* and *.table* whose name starts with `prefix`,
+    /// ie: `"levels/"` to unload everything in the *levels* directory.
+    pub fn unload_by_prefix(&mut self, prefix: &str) {
+        let names: Vec<_> = self
+            .prefabs
+            .keys()
+            .filter(|name| name.starts_with(prefix))
+            .cloned()
+            .collect();
+        for name in names {
+            self.unload_prefab(&name);
+        }
+        self.levels.retain(|name, _| !name.starts_with(prefix));
+        self.tables.retain(|name, _| !name.starts_with(prefix));
+    }
+
+    /// Remove every cached [Prefab], *.level* and *.table* which was read from a file, so it
+    /// can be read again when it's next needed. Prefabs created at runtime, such as with
+    /// [PrefabRegistry::replace_prefab], are kept since they can't be read again.
+    ///
+    /// See also [crate::PrefabAppBuilderExt::unload_prefabs_on] to call this when an event
+    /// is sent, such as a memory warning from the platform.
+    pub fn unload_files(&mut self) {
+        let names: Vec<_> = self.last_used.keys().cloned().collect();
+        for name in names {
+            self.unload_prefab(&name);
+        }
+        self.levels.clear();
+        self.tables.clear();
+    }
+
+    /// Set how long prefabs loaded from *.prefab* files are cached. Defaults to
+    /// [PrefabCachePolicy::KeepAll].
+    ///
+    /// Evicted prefabs are read from disk again the next time they're loaded. Entities
+    /// already spawned from them are not affected.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn setup(mut registry: ResMut<PrefabRegistry>) {
+    ///     registry.set_cache_policy(PrefabCachePolicy::Lru(256));
+    /// }
+    /// ```
+    pub fn set_cache_policy(&mut self, policy: PrefabCachePolicy) {
+        self.cache_policy = policy;
+        self.trim_cache("");
+    }
+
+    /// The number of cached prefabs and an estimate of the memory they use.
+    pub fn cache_stats(&self) -> PrefabCacheStats {
+        PrefabCacheStats {
+            entries: self.prefabs.len(),
+            approx_bytes: self
+                .prefabs
+                .iter()
+                .map(|(name, prefab)| name.len() + prefab.approx_size())
+                .sum(),
+        }
+    }
+
+    /// Mark a prefab as used for [PrefabCachePolicy::Lru].
+    fn touch(&mut self, name: &str) {
+        if let Some(last_used) = self.last_used.get_mut(name) {
+            self.use_count += 1;
+            *last_used = self.use_count;
+        }
+    }
+
+    /// Evict prefabs according to the cache policy. The prefab named `keep` is never evicted.
+    fn trim_cache(&mut self, keep: &str) {
+        let mut evicted = Vec::new();
+        match self.cache_policy {
+            PrefabCachePolicy::KeepAll => {}
+            PrefabCachePolicy::Lru(capacity) => {
+                let mut candidates: Vec<_> = self
+                    .last_used
+                    .iter()
+                    .filter(|(name, _)| name.as_str() != keep)
+                    .map(|(name, used)| (*used, name.clone()))
+                    .collect();
+                candidates.sort_unstable();
+                let excess = self.last_used.len().saturating_sub(capacity);
+                evicted.extend(candidates.into_iter().take(excess).map(|(_, name)| name));
+            }
+            PrefabCachePolicy::Weak => {
+                evicted.extend(
+                    self.last_used
+                        .keys()
+                        .filter(|name| name.as_str() != keep)
+                        .filter(|name| Arc::strong_count(&self.prefabs[name.as_str()]) == 1)
+                        .cloned(),
+                );
+            }
+        }
+        for name in evicted {
+            self.unload_prefab(&name);
+        }
+    }
+
+    pub(crate) fn get_cached(&self, name: &str) -> Option<&Arc<Prefab>> {
+        self.prefabs.get(name)
+    }
+
+    pub(crate) fn cached_prefabs(&self) -> impl Iterator<Item = (&str, &Arc<Prefab>)> {
+        self.prefabs
+            .iter()
+            .map(|(name, prefab)| (name.as_str(), prefab))
+    }
+
+    pub(crate) fn take_changed(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.changed)
+    }
+
+    pub(crate) fn take_failed(&mut self) -> Vec<PrefabLoadFailed> {
+        std::mem::take(self.failed.get_mut().unwrap())
+    }
+
+    pub(crate) fn get_build_command(
+        &self,
+        name: &str,
+    ) -> Option<&Arc<dyn BuildPrefabCommand + Send + Sync + 'static>> {
+        self.commands.get(name)
+    }
+
+    #[cfg(feature = "rhai")]
+    pub(crate) fn registered_types(&self) -> impl Iterator<Item = &TypeInfo> {
+        self.type_data.values()
+    }
+
+    /// Read a file relative to the root of the [PrefabSource].
+    #[cfg(feature = "rhai")]
+    pub(crate) fn read_source_file(&self, path: &str) -> io::Result<String> {
+        self.source.0.read_to_string(Path::new(path))
+    }
+
+    /// A bevy type registry with every registered type, along with the value types which can
+    /// be written in a *.prefab* file.
+    pub(crate) fn reflect_type_registry(&self) -> TypeRegistryInternal {
+        let mut type_registry = TypeRegistryInternal::default();
+        for info in self.type_data.values() {
+            type_registry.add_registration(info.registration.clone());
+        }
+        type_registry.register::<f32>();
+        type_registry.register::<i32>();
+        type_registry.register::<u8>();
+        type_registry.register::<bool>();
+        type_registry.register::<String>();
+        type_registry.register::<Range<i32>>();
+        type_registry.register::<Range<f32>>();
+        type_registry.register::<Vec2>();
+        type_registry.register::<Vec3>();
+        type_registry.register::<Quat>();
+        type_registry.register::<Entity>();
+        #[cfg(feature = "render")]
+        {
+            type_registry.register::<Color>();
+            type_registry.register::<Val>();
+        }
+        type_registry.register::<DeferredValue>();
+        type_registry
+    }
+
+    pub(crate) fn get_type_data(&self, name: &str) -> Option<&TypeInfo> {
+        match self.type_data.get(name) {
+            Some(info) => Some(info),
+            None => self
+                .aliases
+                .get(name)
+                .and_then(|name| self.type_data.get(name)),
+        }
+    }
+
+    /// An error for a type name which isn't registered, suggesting registered names which
+    /// are spelled similarly.
+    pub(crate) fn unregistered_type_error(&self, name: &str) -> LoadPrefabError {
+        let max_distance = (name.len() / 3).max(1);
+        let lower = name.to_lowercase();
+        let mut suggestions: Vec<_> = self
+            .type_data
+            .keys()
+            .chain(self.aliases.keys().filter(|alias| !alias.contains("::")))
+            .map(|known| (edit_distance(&lower, &known.to_lowercase()), known))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .collect();
+        suggestions.sort();
+        suggestions.dedup_by(|a, b| a.1 == b.1);
+        let suggestions = suggestions
+            .into_iter()
+            .take(3)
+            .map(|(_, known)| known.clone())
+            .collect();
+        LoadPrefabError::UnregisteredPrefabComponent(name.to_string(), suggestions)
+    }
+}
+
+/// The number of single character insertions, deletions or substitutions needed to turn
+/// `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            row.push((prev[j] + cost).min(prev[j + 1] + 1).min(row[j] + 1));
+        }
+        prev = row;
+    }
+    prev[b.len()]
+}
+
+pub(crate) struct TypeInfo {
+    pub type_name: Arc<str>,
+    pub reflect_type: ReflectType,
+    pub registration: TypeRegistration,
+    pub instance: Box<dyn Reflect>,
+    pub construct: Constructor,
+    /// Removes the component from an entity, see [PrefabRegistry::register_component].
+    pub remove: Option<fn(&mut World, Entity)>,
+    /// Applies a value to the component on an entity, inserting it if needed. Used instead of
+    /// `ReflectComponent` for types registered with [PrefabRegistry::register_type_with].
+    pub apply: Option<ApplyComponent>,
+}
+
+type ApplyComponent = Arc<dyn Fn(&mut World, Entity, &dyn Reflect) + Send + Sync>;
+
+/// Creates new instances of a registered type.
+#[derive(Clone)]
+pub(crate) enum Constructor {
+    Default(fn() -> Box<dyn Reflect>),
+    FromWorld(fn(&mut World) -> Box<dyn Reflect>),
+    With(Arc<dyn Fn() -> Box<dyn Reflect> + Send + Sync>),
+}
+
+impl TypeInfo {
+    /// A new instance of the concrete type.
+    pub fn construct(&self, world: &mut World) -> Box<dyn Reflect> {
+        match &self.construct {
+            Constructor::Default(construct) => construct(),
+            Constructor::FromWorld(construct) => construct(world),
+            Constructor::With(construct) => construct(),
+        }
+    }
+
+    /// A new instance of the concrete type, or [None] if it needs a [World] to be created.
+    pub fn construct_default(&self) -> Option<Box<dyn Reflect>> {
+        match &self.construct {
+            Constructor::Default(construct) => Some(construct()),
+            Constructor::FromWorld(_) => None,
+            Constructor::With(construct) => Some(construct()),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub(crate) enum ReflectType {
+    Struct,
+    TupleStruct,
+    Tuple,
+    List,
+    Map,
+    Value,
+}
+
+/// The deprecated command keys used in the prefab hierarchy, with the key they refer to.
+fn deprecated_command_keys(prefab: &Prefab) -> Vec<(&str, &str)> {
+    let mut keys = Vec::new();
+    for step in prefab.steps.iter() {
+        match step {
+            PrefabBuildStep::RunCommand(data) => {
+                if let Some(old) = &data.deprecated_key {
+                    keys.push((old.as_str(), data.name.as_str()));
+                }
+            }
+            PrefabBuildStep::AddChild(child) => keys.extend(deprecated_command_keys(child)),
+            PrefabBuildStep::AddComponent(_) => {}
+        }
+    }
+    keys
+}
+
+/// Match `text` against a pattern where `*` matches any number of characters
+/// and `?` matches exactly one.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // The position of the last `*` in the pattern and the text position it was tried at.
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, star_t)) => {
+                    backtrack = Some((star, star_t + 1));
+                    p = star + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+impl From<ReflectRef<'_>> for ReflectType {
+    fn from(reflect: ReflectRef) -> Self {
+        match reflect {
+            ReflectRef::Struct(_) => ReflectType::Struct,
+            ReflectRef::TupleStruct(_) => ReflectType::TupleStruct,
+            ReflectRef::Tuple(_) => ReflectType::Tuple,
+            ReflectRef::List(_) => ReflectType::List,
+            ReflectRef::Map(_) => ReflectType::Map,
+            ReflectRef::Value(_) => ReflectType::Value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::{prelude::*, reflect::TypeRegistryArc, scene::DynamicScene};
+
+    use crate::{
+        dynamic_cast::*,
+        parse::{parse_prefab_library_string, parse_prefab_str},
+        source::FileSource,
+        test_utils::*,
+        LoadPrefabError, PrefabBuildStep, PrefabRegistry, SpawnPrefabWorldExt,
+    };
+
+    #[test]
+    fn import_scene_round_trip() {
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+        let mut world = prefab_world();
+        let names = world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.set_source(FileSource::new(fixtures));
+            reg.register_type::<Transform>();
+            reg.register_type::<Visible>();
+            reg.import_scene("level.scn.ron").unwrap()
+        });
+        assert_eq!(names, ["level.scn.ron#0", "level.scn.ron#1"]);
+
+        let mut spawned = World::new();
+        spawned.insert_resource(world.remove_resource::<PrefabRegistry>().unwrap());
+        for name in &names {
+            spawned.spawn_prefab(name).unwrap();
+        }
+
+        let types = TypeRegistryArc::default();
+        {
+            let mut types = types.write();
+            types.register::<Transform>();
+            types.register::<Visible>();
+        }
+        let scene = DynamicScene::from_world(&spawned, &types);
+        let expected = std::fs::read_to_string(format!("{}/prefabs/level.scn.ron", fixtures));
+        assert_eq!(
+            scene.serialize_ron(&types).unwrap().trim(),
+            expected.unwrap().trim()
+        );
+
+        let mut reg = PrefabRegistry::default();
+        reg.set_source(FileSource::new(fixtures));
+        assert!(reg.import_scene("missing.scn.ron").is_err());
+        assert!(reg.import_scene("level.scn.ron").is_err());
+        let failed: Vec<_> = reg.take_failed().into_iter().map(|f| f.name).collect();
+        assert_eq!(failed, ["missing.scn.ron", "level.scn.ron"]);
+    }
+
+    #[test]
+    fn export_multiple_roots() {
+        let mut world = prefab_world();
+        {
+            let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.register_type::<Transform>();
+            reg.register_type::<Visible>();
+        }
+        let parent = world
+            .spawn()
+            .insert(Transform::from_xyz(1.0, 0.0, 0.0))
+            .id();
+        let child = world
+            .spawn()
+            .insert(Transform::from_xyz(0.0, 2.0, 0.0))
+            .id();
+        world.entity_mut(parent).push_children(&[child]);
+        let other = world
+            .spawn()
+            .insert(Transform::from_xyz(0.0, 0.0, 3.0))
+            .insert(Visible {
+                is_visible: false,
+                is_transparent: true,
+            })
+            .id();
+
+        let reg = world.get_resource::<PrefabRegistry>().unwrap();
+        let text = reg.write_entities(&world, &[parent, child, other]);
+        let prefabs = parse_prefab_library_string(&text, reg).unwrap();
+        let names: Vec<_> = prefabs.iter().map(|p| p.name.clone().unwrap()).collect();
+        assert_eq!(names, ["Root1", "Root2"]);
+
+        for (prefab, original) in prefabs.iter().zip([parent, other]) {
+            let entity = world.spawn().id();
+            world.insert_prefab(entity, prefab);
+            assert_eq!(
+                entity_snapshot(&world, entity),
+                entity_snapshot(&world, original)
+            );
+        }
+        let respawned_child = world.query::<&Children>().iter(&world).last().unwrap()[0];
+        assert_component(&world, respawned_child, &Transform::from_xyz(0.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn export_and_load() {
+        let tmp = TempDir::new("export");
+        let dir = tmp.path();
+        let mods = dir.join("mods");
+        std::fs::create_dir_all(&mods).unwrap();
+        std::fs::write(mods.join("modded.prefab"), "{ }").unwrap();
+
+        let mut world = prefab_world();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.set_prefab_dir(dir.join("prefabs"));
+            reg.add_prefab_root(&mods, 10);
+            reg.register_type::<Transform>();
+        });
+        let entity = world
+            .spawn()
+            .insert(Transform::from_xyz(1.0, 2.0, 3.0))
+            .id();
+
+        let prefabs = world.resource_scope(|world, mut reg: Mut<PrefabRegistry>| {
+            reg.export_entities(world, &[entity], "saved.prefab")
+                .unwrap();
+            reg.export_entities(world, &[entity], "modded.prefab")
+                .unwrap();
+            ["saved.prefab", "modded.prefab"].map(|name| reg.load(name).unwrap().clone())
+        });
+        assert!(dir.join("prefabs/saved.prefab").exists());
+        assert!(!dir.join("prefabs/modded.prefab").exists());
+
+        for prefab in prefabs.iter() {
+            let loaded = world.spawn().id();
+            world.insert_prefab(loaded, prefab);
+            assert_component(&world, loaded, &Transform::from_xyz(1.0, 2.0, 3.0));
+        }
+    }
+
+    #[derive(Default, Reflect, Debug, PartialEq)]
+    struct Scope {
+        zoom: f32,
+    }
+
+    #[derive(Default, Reflect, Debug, PartialEq)]
+    struct Weapon {
+        damage: i32,
+        scope: Scope,
+    }
+
+    #[derive(Default, Reflect)]
+    #[reflect(Component)]
+    struct Loadout {
+        weapon: Weapon,
+    }
+
+    #[test]
+    fn register_deps() {
+        use bevy::reflect::TypeRegistry;
+
+        let types = TypeRegistry::default();
+        types.write().register::<Weapon>();
+        types.write().register::<Scope>();
+
+        let mut world = prefab_world();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_component_and_deps::<Loadout>(&types);
+        });
+
+        let input = "{ Loadout { weapon: Weapon { damage: 5, scope: Scope { zoom: 2.5 } } } }";
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        let loadout = world.get::<Loadout>(entity).unwrap();
+        assert_eq!(
+            loadout.weapon,
+            Weapon {
+                damage: 5,
+                scope: Scope { zoom: 2.5 }
+            }
+        );
+    }
+
+    #[test]
+    fn unregistered_suggestions() {
+        let mut reg = PrefabRegistry::default();
+        reg.register_type::<Transform>();
+        reg.register_type::<GlobalTransform>();
+        reg.register_type::<Stats>();
+        reg.register_alias("Pos", "Transform");
+
+        let err = parse_prefab_str("{ Tranform }", &reg).unwrap_err();
+        assert!(matches!(
+            &err,
+            LoadPrefabError::UnregisteredPrefabComponent(name, suggestions)
+                if name == "Tranform" && suggestions == &["Transform".to_string()]
+        ));
+        assert!(err.to_string().contains("Did you mean Transform?"));
+        assert!(err.to_string().contains("#[reflect(Component)]"));
+
+        let err = parse_prefab_str("{ pos }", &reg).unwrap_err();
+        assert!(err.to_string().contains("Did you mean Pos?"));
+
+        let err = parse_prefab_str("{ Velocity }", &reg).unwrap_err();
+        assert!(!err.to_string().contains("Did you mean"));
+    }
+
+    #[test]
+    fn alias_parse() {
+        let mut reg = PrefabRegistry::default();
+        reg.register_type_as::<Visible>("Vis");
+        reg.register_alias("Shown", "Vis");
+
+        let prefab = parse_prefab_str("{ Vis, Shown }", &reg).unwrap();
+        assert_eq!(prefab.steps.len(), 2);
+        assert!(parse_prefab_str("{ Visible }", &reg).is_err());
+    }
+
+    mod other {
+        use bevy::prelude::*;
+
+        #[derive(Default, Reflect)]
+        #[reflect(Component)]
+        pub struct Visible;
+    }
+
+    #[test]
+    fn name_collision() {
+        let mut reg = PrefabRegistry::default();
+        reg.register_type::<Visible>();
+        reg.register_type::<other::Visible>();
+
+        let prefab = parse_prefab_str(
+            "{ Visible, bevy_lazy_prefabs::registry::test::other::Visible }",
+            &mut reg,
+        )
+        .unwrap();
+        let type_name = |i: usize| match &prefab.steps[i] {
+            PrefabBuildStep::AddComponent(comp) => reg
+                .get_type_data(&comp.type_name)
+                .unwrap()
+                .registration
+                .name(),
+            _ => unreachable!(),
+        };
+        assert_eq!(type_name(0), "bevy_render::draw::Visible");
+        assert_eq!(
+            type_name(1),
+            "bevy_lazy_prefabs::registry::test::other::Visible"
+        );
+    }
+
+    #[test]
+    fn glob_load() {
+        let mut reg = PrefabRegistry::default();
+        let names = reg.load_glob("*bird.pre?ab").unwrap();
+        assert_eq!(names, ["bird.prefab", "blue_bird.prefab"]);
+        assert!(reg.load_glob("*.nothing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn prefab_root_priority() {
+        let tmp = TempDir::new("root_priority");
+        let dir = tmp.path();
+        std::fs::write(dir.join("bird.prefab"), "ModdedBird {}").unwrap();
+        std::fs::write(dir.join("modded_bird.prefab"), "{}").unwrap();
+
+        let mut reg = PrefabRegistry::default();
+        reg.add_prefab_root(&dir, -1);
+        assert_eq!(reg.load("bird.prefab").unwrap().name, None);
+
+        let mut reg = PrefabRegistry::default();
+        reg.add_prefab_root(&dir, 1);
+        let bird = reg.load("bird.prefab").unwrap();
+        assert_eq!(bird.name.as_deref(), Some("ModdedBird"));
+
+        let names = reg.load_glob("*bird.prefab").unwrap();
+        assert_eq!(
+            names,
+            ["bird.prefab", "blue_bird.prefab", "modded_bird.prefab"]
+        );
+
+        // Relative roots are resolved against the source root.
+        std::fs::create_dir_all(dir.join("prefabs")).unwrap();
+        std::fs::create_dir_all(dir.join("mods")).unwrap();
+        std::fs::write(dir.join("prefabs/bird.prefab"), "{}").unwrap();
+        std::fs::write(dir.join("mods/bird.prefab"), "ModBird {}").unwrap();
+        let mut reg = PrefabRegistry::default();
+        reg.set_source(FileSource::new(dir));
+        reg.add_prefab_root("mods", 1);
+        let bird = reg.load("bird.prefab").unwrap();
+        assert_eq!(bird.name.as_deref(), Some("ModBird"));
+    }
+
+    #[test]
+    fn prefab_snapshots() {
+        let tmp = TempDir::new("snapshots");
+        let dir = tmp.path();
+        std::fs::write(
+            dir.join("guard.prefab"),
+            "{ Transform { translation: Vec3 { x: 2.0 } } children [ { Visible } ] }",
+        )
+        .unwrap();
+
+        let mut world = prefab_world();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.set_prefab_dir(&dir);
+            reg.register_type::<Vec3>();
+            reg.register_type::<Transform>();
+            reg.register_type::<Visible>();
+        });
+
+        let snapshot = prefab_snapshot(&mut world, "guard.prefab").unwrap();
+        assert!(snapshot.contains("Transform"));
+        assert!(snapshot.contains("children"));
+        assert!(snapshot.contains("Visible"));
+        assert!(world.get_resource::<PrefabRegistry>().is_some());
+
+        let golden = dir.join("guard.snap");
+        assert_prefab_snapshot(&mut world, "guard.prefab", &golden);
+        assert_eq!(std::fs::read_to_string(&golden).unwrap(), snapshot);
+        assert_prefab_snapshot(&mut world, "guard.prefab", &golden);
+
+        std::fs::write(&golden, "{ }").unwrap();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            assert_prefab_snapshot(&mut world, "guard.prefab", &golden)
+        }));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn explain() {
+        let tmp = TempDir::new("explain");
+        let dir = tmp.path();
+        std::fs::write(
+            dir.join("guard.prefab"),
+            "{ Transform { translation: Vec3 { y: 2.0 } }, Shout!(volume: 3), children [ Hat { Visible } ] }",
+        )
+        .unwrap();
+
+        let mut reg = PrefabRegistry::default();
+        reg.set_prefab_dir(&dir);
+        reg.register_type::<Vec3>();
+        reg.register_type::<Transform>();
+        reg.register_type::<Visible>();
+        reg.register_command_fn("Shout", |_, _, _| {});
+        reg.set_command_defaults("Shout", "pitch: 1.5").unwrap();
+
+        let explained = reg.explain("guard.prefab").unwrap();
+        let lines: Vec<_> = explained.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("insert Transform { translation: Vec3 {"));
+        assert!(lines[0].ends_with("left as default: rotation, scale"));
+        assert!(lines[1].starts_with("run Shout("));
+        assert!(lines[1].contains("volume: 3"));
+        assert!(lines[1].ends_with("from command defaults: pitch"));
+        assert_eq!(lines[2], "add child Hat");
+        assert!(lines[3].starts_with("    insert Visible"));
+
+        assert!(reg.explain("missing.prefab").is_err());
+    }
+
+    #[test]
+    fn relative_includes() {
+        use crate::build_commands::{LoadPrefab, SpawnTileMap};
+        use crate::resolve::RelativeResolver;
+        use bevy::reflect::{DynamicList, DynamicStruct, List};
+
+        let tmp = TempDir::new("relative_includes");
+        let dir = tmp.path();
+        for sub in ["enemies", "shared", "common"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        for file in [
+            "enemies/base.prefab",
+            "shared/hat.prefab",
+            "common/torch.prefab",
+            "root.prefab",
+        ] {
+            std::fs::write(dir.join(file), "{ Visible }").unwrap();
+        }
+        std::fs::write(
+            dir.join("enemies/goblin.prefab"),
+            r#"{
+                LoadPrefab!(name: "base.prefab"),
+                LoadPrefab!(name: "../shared/hat.prefab"),
+                LoadPrefab!(name: "torch.prefab"),
+                LoadPrefab!(name: "root.prefab"),
+                SpawnTileMap!(map: ["b"], legend: [{ char: 'b', prefab: "./base.prefab" }]),
+            }"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("enemies/wave.level"),
+            r#""base.prefab", "root.prefab""#,
+        )
+        .unwrap();
+
+        let mut reg = PrefabRegistry::default();
+        reg.set_prefab_dir(&dir);
+        reg.register_type::<Visible>();
+        reg.register_build_command::<LoadPrefab>();
+        reg.register_build_command::<SpawnTileMap>();
+        reg.set_resolver(RelativeResolver::default().with_fallback("common"));
+
+        let prefab = reg.load("enemies/goblin.prefab").unwrap().clone();
+        let mut commands = prefab.commands();
+        let names: Vec<_> = commands
+            .by_ref()
+            .take(4)
+            .map(|c| c.properties().unwrap().get::<String>("name").clone())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "enemies/base.prefab",
+                "shared/hat.prefab",
+                "common/torch.prefab",
+                "root.prefab"
+            ]
+        );
+        let legend = commands.next().unwrap().properties().unwrap();
+        let legend = legend.get::<DynamicList>("legend");
+        let entry = legend.get(0).unwrap().cast_ref::<DynamicStruct>();
+        assert_eq!(entry.get::<String>("prefab"), "enemies/base.prefab");
+
+        let level = reg.load_level("enemies/wave.level").unwrap();
+        let prefabs: Vec<_> = level.instances().iter().map(|i| i.prefab()).collect();
+        assert_eq!(prefabs, ["enemies/base.prefab", "root.prefab"]);
+
+        assert_eq!(
+            reg.resolve("missing.prefab", "enemies/goblin.prefab"),
+            "enemies/missing.prefab"
+        );
+        assert_eq!(
+            reg.resolve("../../up.prefab", "enemies/goblin.prefab"),
+            "up.prefab"
+        );
+    }
+
+    #[test]
+    fn unload_prefabs() {
+        use crate::PrefabAppBuilderExt;
+        use bevy::app::Events;
+
+        let tmp = TempDir::new("unload");
+        let dir = tmp.path();
+        std::fs::create_dir_all(dir.join("levels")).unwrap();
+        for file in ["levels/a.prefab", "levels/b.prefab", "c.prefab"] {
+            std::fs::write(dir.join(file), "{ Visible }").unwrap();
+        }
+        std::fs::write(dir.join("levels/one.level"), r#""a.prefab""#).unwrap();
+
+        let mut reg = PrefabRegistry::default();
+        reg.set_prefab_dir(&dir);
+        reg.register_type::<Visible>();
+        let load_all = |reg: &mut PrefabRegistry| {
+            for name in ["levels/a.prefab", "levels/b.prefab", "c.prefab"] {
+                reg.load(name).unwrap();
+            }
+            reg.load_level("levels/one.level").unwrap();
+        };
+        load_all(&mut reg);
+        reg.replace_prefab("runtime", "{ Visible }").unwrap();
+        assert_eq!(reg.cache_stats().entries, 4);
+
+        reg.unload_by_prefix("levels/");
+        assert_eq!(reg.cache_stats().entries, 2);
+        assert!(reg.load("c.prefab").is_ok());
+
+        load_all(&mut reg);
+        reg.unload_files();
+        assert_eq!(reg.cache_stats().entries, 1);
+        assert!(reg.load("runtime").is_ok());
+
+        reg.unload_all();
+        assert_eq!(reg.cache_stats().entries, 0);
+        assert!(reg.load("runtime").is_err());
+
+        struct LowMemory;
+        load_all(&mut reg);
+        let mut app = App::build();
+        app.insert_resource(reg)
+            .add_event::<LowMemory>()
+            .unload_prefabs_on::<LowMemory>();
+        app.app.update();
+        let reg = app.world_mut().get_resource::<PrefabRegistry>().unwrap();
+        assert_eq!(reg.cache_stats().entries, 3);
+
+        app.world_mut()
+            .get_resource_mut::<Events<LowMemory>>()
+            .unwrap()
+            .send(LowMemory);
+        app.app.update();
+        let reg = app.world_mut().get_resource::<PrefabRegistry>().unwrap();
+        assert_eq!(reg.cache_stats().entries, 0);
+    }
+
+    #[test]
+    fn usage_stats() {
+        let mut world = prefab_world();
+        {
+            let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.replace_prefab("a.prefab", "{ }").unwrap();
+            reg.replace_prefab("b.prefab", "{ }").unwrap();
+        }
+        world.spawn_prefab("a.prefab").unwrap();
+        assert!(world
+            .get_resource::<PrefabRegistry>()
+            .unwrap()
+            .usage_stats()
+            .is_empty());
+
+        world
+            .get_resource_mut::<PrefabRegistry>()
+            .unwrap()
+            .set_track_usage(true);
+        world.spawn_prefab("a.prefab").unwrap();
+        world.spawn_prefab("a.prefab").unwrap();
+
+        let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+        let stats = reg.usage_stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].0, "a.prefab");
+        assert_eq!(stats[0].1.spawns, 2);
+        assert!(stats[0].1.last_spawned.is_some());
+        assert_eq!(stats[1].0, "b.prefab");
+        assert_eq!(stats[1].1, crate::PrefabUsage::default());
+
+        reg.reset_usage_stats();
+        assert_eq!(reg.usage_stats()[0].1.spawns, 0);
+    }
+
+    #[test]
+    fn cache_policy() {
+        use crate::PrefabCachePolicy;
+
+        let tmp = TempDir::new("cache_policy");
+        let dir = tmp.path();
+        for name in ["a", "b", "c"] {
+            std::fs::write(dir.join(format!("{}.prefab", name)), "{ Stats }").unwrap();
+        }
+
+        let mut reg = PrefabRegistry::default();
+        reg.set_prefab_dir(&dir);
+        reg.register_type::<Stats>();
+        reg.replace_prefab("kept", "{ Stats }").unwrap();
+        reg.load_glob("*.prefab").unwrap();
+        let stats = reg.cache_stats();
+        assert_eq!(stats.entries, 4);
+        assert!(stats.approx_bytes > 0);
+
+        let cached = |reg: &PrefabRegistry| {
+            let mut names: Vec<_> = reg.cached_prefabs().map(|(n, _)| n.to_string()).collect();
+            names.sort();
+            names
+        };
+
+        // Least recently used first, prefabs which aren't from a file are always kept and
+        // don't count towards the limit.
+        reg.load("a.prefab").unwrap();
+        reg.set_cache_policy(PrefabCachePolicy::Lru(3));
+        assert_eq!(cached(&reg), ["a.prefab", "b.prefab", "c.prefab", "kept"]);
+        reg.set_cache_policy(PrefabCachePolicy::Lru(2));
+        assert_eq!(cached(&reg), ["a.prefab", "c.prefab", "kept"]);
+        reg.load("b.prefab").unwrap();
+        assert_eq!(cached(&reg), ["a.prefab", "b.prefab", "kept"]);
+
+        reg.set_cache_policy(PrefabCachePolicy::Weak);
+        assert_eq!(cached(&reg), ["kept"]);
+        let a = reg.load("a.prefab").unwrap().clone();
+        reg.load("b.prefab").unwrap();
+        reg.load("c.prefab").unwrap();
+        assert_eq!(cached(&reg), ["a.prefab", "c.prefab", "kept"]);
+        drop(a);
+        reg.load("b.prefab").unwrap();
+        assert_eq!(cached(&reg), ["b.prefab", "kept"]);
+    }
+
+    #[test]
+    fn reload_skips_unchanged() {
+        use std::sync::Arc;
+
+        let tmp = TempDir::new("reload");
+        let dir = tmp.path();
+        std::fs::write(dir.join("a.prefab"), "{ Stats { health: 1 } }").unwrap();
+        std::fs::write(dir.join("b.prefab"), "{ Stats }").unwrap();
+
+        let mut reg = PrefabRegistry::default();
+        reg.set_prefab_dir(&dir);
+        reg.register_type::<Stats>();
+        let a = reg.load("a.prefab").unwrap().clone();
+        assert!(!reg.reload("a.prefab").unwrap());
+        assert!(Arc::ptr_eq(&a, reg.load("a.prefab").unwrap()));
+
+        std::fs::write(dir.join("a.prefab"), "{ Stats { health: 2 } }").unwrap();
+        assert_eq!(
+            reg.reload_glob("*.prefab").unwrap(),
+            ["a.prefab", "b.prefab"]
+        );
+        assert!(!Arc::ptr_eq(&a, reg.load("a.prefab").unwrap()));
+        assert!(reg.reload_glob("*.prefab").unwrap().is_empty());
+        assert_eq!(reg.take_changed(), ["a.prefab", "b.prefab"]);
+
+        reg.replace_prefab("c", "{ Stats }").unwrap();
+        reg.replace_prefab("c", "{ Stats }").unwrap();
+        assert_eq!(reg.take_changed(), ["c"]);
+    }
+
+    #[test]
+    fn update_instances_on_change() {
+        use crate::SpawnNamedPrefabCommands;
+        use bevy::ecs::system::CommandQueue;
+
+        let mut world = prefab_world();
+        {
+            let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.register_component::<Stats>();
+            reg.register_component::<Transform>();
+            reg.register_type::<Visible>();
+            reg.set_tag_spawned(true);
+            reg.replace_prefab(
+                "orc",
+                "{ Stats { health: 5, speed: 1.0 }, Transform, Visible }",
+            )
+            .unwrap();
+        }
+        let mut queue = CommandQueue::default();
+        let orc = Commands::new(&mut queue, &world)
+            .spawn_prefab_at("orc", Vec3::X)
+            .id();
+        queue.apply(&mut world);
+        {
+            let mut stats = world.get_mut::<Stats>(orc).unwrap();
+            stats.health = 3;
+            stats.speed = 9.0;
+        }
+
+        world
+            .get_resource_mut::<PrefabRegistry>()
+            .unwrap()
+            .replace_prefab("orc", "{ Stats { health: 5, speed: 2.0, title: \"Orc\" } }")
+            .unwrap();
+        Commands::new(&mut queue, &world).update_prefab_instances("orc");
+        queue.apply(&mut world);
+
+        // Only fields changed in the prefab are applied.
+        let expected = Stats {
+            health: 3,
+            speed: 2.0,
+            title: "Orc".to_string(),
+        };
+        assert_component(&world, orc, &expected);
+        assert!(world.get::<Transform>(orc).is_none());
+        // Types registered with register_type can't be removed.
+        assert!(world.get::<Visible>(orc).is_some());
+    }
+
+    #[derive(Reflect, Debug, PartialEq)]
+    #[reflect(Component)]
+    struct Spawner {
+        rate: f32,
+        limit: i32,
+    }
+
+    impl FromWorld for Spawner {
+        fn from_world(world: &mut World) -> Self {
+            Spawner {
+                rate: 1.0,
+                limit: *world.get_resource::<i32>().unwrap(),
+            }
+        }
+    }
+
+    #[test]
+    fn from_world_type() {
+        use crate::SpawnPrefabCommands;
+        use bevy::ecs::system::CommandQueue;
+
+        let mut world = prefab_world();
+        world.insert_resource(10);
+        {
+            let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.register_type_from_world::<Spawner>();
+            reg.set_tag_spawned(true);
+            reg.replace_prefab("spawner", "{ Spawner { rate: 2.0 } }")
+                .unwrap();
+            assert!(reg.instantiate("spawner", "Spawner").is_none());
+        }
+
+        let entity = spawn_prefab_str(&mut world, "{ Spawner { rate: 2.0 } }").unwrap();
+        let expected = Spawner {
+            rate: 2.0,
+            limit: 10,
+        };
+        assert_component(&world, entity, &expected);
+
+        world.get_mut::<Spawner>(entity).unwrap().limit = 0;
+        world
+            .entity_mut(entity)
+            .insert(crate::SpawnedFrom("spawner".to_string()));
+        let mut queue = CommandQueue::default();
+        Commands::new(&mut queue, &world)
+            .entity(entity)
+            .reset_to_prefab();
+        queue.apply(&mut world);
+        assert_component(&world, entity, &expected);
+    }
+
+    #[derive(Reflect, Debug, PartialEq)]
+    struct Velocity {
+        x: f32,
+        y: f32,
+    }
+
+    #[test]
+    fn construct_with() {
+        let mut world = prefab_world();
+        {
+            let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.register_type_with(|| Velocity { x: 1.0, y: 1.0 });
+            reg.replace_prefab("fast", "{ Velocity { x: 5.0 } }")
+                .unwrap();
+            let value = reg.instantiate("fast", "Velocity").unwrap();
+            assert_eq!(
+                value.downcast_ref::<Velocity>(),
+                Some(&Velocity { x: 5.0, y: 1.0 })
+            );
+        }
+
+        let entity = spawn_prefab_str(&mut world, "{ Velocity { y: 2.0 } }").unwrap();
+        assert_component(&world, entity, &Velocity { x: 1.0, y: 2.0 });
+    }
+
+    #[test]
+    fn prefab_from_entity() {
+        let mut world = prefab_world();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_type::<Stats>();
+            reg.register_type::<Transform>();
+        });
+
+        let child = world
+            .spawn()
+            .insert(Stats {
+                health: 1,
+                ..Default::default()
+            })
+            .id();
+        let entity = world
+            .spawn()
+            .insert(Stats {
+                health: 7,
+                ..Default::default()
+            })
+            .insert(Transform::from_xyz(1.0, 2.0, 3.0))
+            .insert(Timer::from_seconds(1.0, false))
+            .push_children(&[child])
+            .id();
+
+        world.resource_scope(|world, mut reg: Mut<PrefabRegistry>| {
+            let prefab = reg.register_prefab_from_entity(world, entity, "stamp");
+            assert_eq!(prefab.name.as_deref(), Some("stamp"));
+            assert_eq!(prefab.components().count(), 2);
+        });
+        world.get_mut::<Stats>(entity).unwrap().health = 0;
+
+        let stamped = world.spawn_prefab("stamp").unwrap().entity();
+        assert_eq!(world.get::<Stats>(stamped).unwrap().health, 7);
+        assert_eq!(
+            world.get::<Transform>(stamped).unwrap().translation,
+            Vec3::new(1.0, 2.0, 3.0)
+        );
+        assert!(world.get::<Timer>(stamped).is_none());
+
+        let children = world.get::<Children>(stamped).unwrap();
+        assert_eq!(children.len(), 1);
+        assert_ne!(children[0], child);
+        assert_eq!(world.get::<Stats>(children[0]).unwrap().health, 1);
+    }
+
+    #[test]
+    fn config_values() {
+        use crate::PrefabVars;
+
+        let mut world = prefab_world();
+        world.insert_resource(PrefabVars::default());
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_type::<Stats>();
+            reg.set_config_value("quality", "high");
+            reg.set_config_value("health", 20);
+        });
+        std::env::set_var("LAZY_PREFABS_TEST_SPEED", "3.5");
+        world
+            .get_resource_mut::<PrefabVars>()
+            .unwrap()
+            .set("level", 2);
+
+        let input = "{ Stats {
+            health: ${health},
+            speed: ${env:LAZY_PREFABS_TEST_SPEED},
+            title: \"${quality}/rock-${level}.png\",
+        } }";
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        let expected = Stats {
+            health: 20,
+            speed: 3.5,
+            title: "high/rock-2.png".to_string(),
+        };
+        assert_component(&world, entity, &expected);
+
+        let err = spawn_prefab_str(&mut world, "{ Stats { speed: ${env:LAZY_PREFABS_UNSET} } }");
+        assert!(matches!(err, Err(crate::LoadPrefabError::MissingEnvVar(_))));
+    }
+
+    #[test]
+    fn instantiate_component() {
+        let mut reg = PrefabRegistry::default();
+        reg.register_type::<Stats>();
+        reg.register_type::<Transform>();
+        reg.replace_prefab(
+            "stats",
+            "{ Stats { health: 5 }, Stats { speed: 2.0 }, Transform { translation: Vec3 { x: rand(0.0..1.0) } } }",
+        )
+        .unwrap();
+
+        let stats = reg.instantiate("stats", "Stats").unwrap();
+        let expected = Stats {
+            health: 5,
+            speed: 2.0,
+            title: String::new(),
+        };
+        assert_eq!(stats.downcast_ref::<Stats>(), Some(&expected));
+        assert!(reg.instantiate("stats", "Transform").is_none());
+        assert!(reg.instantiate("stats", "Visible").is_none());
+    }
 }
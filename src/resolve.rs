@@ -0,0 +1,76 @@
+//! Resolving the names of prefabs referenced from other prefab files.
+
+/// Decides which prefab a name written inside another file refers to, such as the `name` of a
+/// `LoadPrefab` command, a tile map legend entry or a *.level* instance.
+///
+/// Set a custom resolver with [crate::PrefabRegistry::set_resolver]. The default is a
+/// [RelativeResolver].
+pub trait PrefabResolver: Send + Sync + 'static {
+    /// The names `name` could refer to when it's written in the file `from`, in the order they
+    /// should be tried. The first candidate which is already loaded or has a file in one of the
+    /// prefab directories is used. If none exist, the first candidate is used so loading it
+    /// reports a useful error.
+    ///
+    /// Both `name` and `from` are relative to the prefab directories and use `/` separators.
+    fn candidates(&self, name: &str, from: &str) -> Vec<String>;
+}
+
+/// Resolves names relative to the directory of the file they're written in.
+///
+/// - Names starting with `./` or `../` only refer to a file relative to the including file,
+///   ie: `../shared/hat.prefab` written in `enemies/goblin.prefab` is `shared/hat.prefab`.
+/// - Other names are looked up next to the including file first, then in each fallback
+///   directory in the order they were added, then from the top of the prefab directories.
+///
+/// ## Example
+///
+/// ```
+/// use bevy::prelude::*;
+/// use bevy_lazy_prefabs::{resolve::RelativeResolver, *};
+///
+/// fn setup(mut registry: ResMut<PrefabRegistry>) {
+///     // Any prefab can refer to `common/torch.prefab` as just `torch.prefab`.
+///     registry.set_resolver(RelativeResolver::default().with_fallback("common"));
+/// }
+/// ```
+#[derive(Default, Clone)]
+pub struct RelativeResolver {
+    fallbacks: Vec<String>,
+}
+
+impl RelativeResolver {
+    /// Add a directory to search for names which aren't found next to the including file.
+    pub fn with_fallback(mut self, dir: impl Into<String>) -> Self {
+        self.fallbacks.push(dir.into());
+        self
+    }
+}
+
+impl PrefabResolver for RelativeResolver {
+    fn candidates(&self, name: &str, from: &str) -> Vec<String> {
+        let dir = from.rsplit_once('/').map_or("", |(dir, _)| dir);
+        if name.starts_with("./") || name.starts_with("../") {
+            return vec![join(dir, name)];
+        }
+        let mut candidates = vec![join(dir, name)];
+        candidates.extend(self.fallbacks.iter().map(|fallback| join(fallback, name)));
+        candidates.push(name.to_string());
+        candidates.dedup();
+        candidates
+    }
+}
+
+/// Join a relative name onto a directory, removing any `.` and `..` segments.
+pub fn join(dir: &str, name: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in dir.split('/').chain(name.split('/')) {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
@@ -0,0 +1,278 @@
+use bevy::{
+    prelude::*,
+    reflect::{DynamicStruct, ReflectMut, ReflectRef},
+};
+use rhai::{Dynamic, Engine, Map, Scope, FLOAT, INT};
+
+use crate::{
+    build_commands::{BuildPrefabCommand, PrefabCommandError},
+    dynamic_cast::*,
+    PrefabRegistry,
+};
+
+/// Runs a [rhai](https://rhai.rs) script on the prefab entity. Requires the `rhai` feature.
+///
+/// The script can read and modify the entity's registered components through the
+/// `components` map. Number, bool and string fields are available, as well as
+/// any structs made from them. `Vec2`, `Vec3` and `Quat` fields are maps with
+/// `x`, `y`, `z` and `w` keys.
+///
+/// ### Properties (one is required):
+///
+/// - `source` - The text of the script.
+/// - `path` - The path to a script file, relative to the *assets* directory.
+///
+/// ### Example
+///
+/// ```ignore
+/// {
+///     Transform,
+///     RunScript!(source: "components.Transform.translation.x = 10.0 * 2.0;"),
+/// }
+/// ```
+pub struct RunScript {
+    engine: Engine,
+}
+
+impl Default for RunScript {
+    fn default() -> Self {
+        RunScript {
+            engine: Engine::new(),
+        }
+    }
+}
+
+impl BuildPrefabCommand for RunScript {
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
+        if !world.contains_resource::<PrefabRegistry>() {
+            return Err(PrefabCommandError::missing_resource::<PrefabRegistry>());
+        }
+        world.resource_scope(|world, registry: Mut<PrefabRegistry>| {
+            self.run_with_registry(&registry, properties, world, entity)
+        })
+    }
+
+    fn run_with_registry(
+        &self,
+        registry: &PrefabRegistry,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
+        let script = get_script(properties, registry)?;
+
+        let mut components = Map::new();
+        for info in registry.registered_types() {
+            let component = info
+                .registration
+                .data::<ReflectComponent>()
+                .and_then(|reflect| reflect.reflect_component(world, entity))
+                .and_then(to_dynamic);
+            if let Some(component) = component {
+                components.insert(info.type_name.as_ref().into(), component);
+            }
+        }
+
+        let mut scope = Scope::new();
+        scope.push("components", components);
+        self.engine
+            .run_with_scope(&mut scope, &script)
+            .map_err(|e| PrefabCommandError::Other(format!("Error running script: {}", e)))?;
+
+        let components = scope.get_value::<Map>("components").unwrap_or_default();
+        for (name, value) in components {
+            let reflect = registry
+                .get_type_data(&name)
+                .and_then(|info| info.registration.data::<ReflectComponent>());
+            if let Some(reflect) = reflect {
+                if let Some(mut component) = reflect.reflect_component_mut(world, entity) {
+                    apply_dynamic(component.reflect_mut(), &value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn key(&self) -> &str {
+        "RunScript"
+    }
+}
+
+fn get_script(
+    properties: Option<&DynamicStruct>,
+    registry: &PrefabRegistry,
+) -> Result<String, PrefabCommandError> {
+    let missing = || PrefabCommandError::MissingProperty("source".to_string());
+    let properties = properties.ok_or_else(missing)?;
+    if let Ok(source) = properties.try_get::<String>("source") {
+        return Ok(source.clone());
+    }
+    let path = properties
+        .try_get::<String>("path")
+        .map_err(|_| missing())?;
+    registry
+        .read_source_file(path)
+        .map_err(|e| PrefabCommandError::Other(format!("Error reading script {}: {}", path, e)))
+}
+
+/// Convert a reflected value to a script value, or [None] if it's not supported.
+fn to_dynamic(value: &dyn Reflect) -> Option<Dynamic> {
+    if let Some(f) = value.downcast_ref::<f32>() {
+        return Some(Dynamic::from_float(*f as FLOAT));
+    }
+    if let Some(i) = value.downcast_ref::<i32>() {
+        return Some(Dynamic::from_int(*i as INT));
+    }
+    if let Some(b) = value.downcast_ref::<bool>() {
+        return Some(Dynamic::from_bool(*b));
+    }
+    if let Some(s) = value.downcast_ref::<String>() {
+        return Some(s.clone().into());
+    }
+    if let Some(v) = value.downcast_ref::<Vec2>() {
+        return Some(float_map(&[("x", v.x), ("y", v.y)]));
+    }
+    if let Some(v) = value.downcast_ref::<Vec3>() {
+        return Some(float_map(&[("x", v.x), ("y", v.y), ("z", v.z)]));
+    }
+    if let Some(q) = value.downcast_ref::<Quat>() {
+        return Some(float_map(&[("x", q.x), ("y", q.y), ("z", q.z), ("w", q.w)]));
+    }
+    if let ReflectRef::Struct(s) = value.reflect_ref() {
+        let mut map = Map::new();
+        for i in 0..s.field_len() {
+            if let Some(field) = s.field_at(i).and_then(to_dynamic) {
+                map.insert(s.name_at(i).unwrap().into(), field);
+            }
+        }
+        return Some(map.into());
+    }
+    None
+}
+
+fn float_map(fields: &[(&str, f32)]) -> Dynamic {
+    let mut map = Map::new();
+    for (name, value) in fields {
+        map.insert((*name).into(), Dynamic::from_float(*value as FLOAT));
+    }
+    map.into()
+}
+
+/// Read a number from a script value. Integers are converted.
+fn to_float(value: &Dynamic) -> Option<f32> {
+    value
+        .as_float()
+        .ok()
+        .or_else(|| value.as_int().ok().map(|i| i as FLOAT))
+        .map(|f| f as f32)
+}
+
+/// Read the fields of a script map into `fields`, leaving missing fields unchanged.
+fn read_floats(value: &Dynamic, fields: &mut [(&str, &mut f32)]) {
+    if let Some(map) = value.read_lock::<Map>() {
+        for (name, field) in fields.iter_mut() {
+            if let Some(new) = map.get(*name).and_then(to_float) {
+                **field = new;
+            }
+        }
+    }
+}
+
+/// Write a script value back to a reflected value. Mismatched types are ignored.
+fn apply_dynamic(target: ReflectMut, value: &Dynamic) {
+    match target {
+        ReflectMut::Struct(s) => {
+            let map = match value.read_lock::<Map>() {
+                Some(map) => map,
+                None => return,
+            };
+            for (name, value) in map.iter() {
+                if let Some(field) = s.field_mut(name) {
+                    apply_dynamic(field.reflect_mut(), value);
+                }
+            }
+        }
+        ReflectMut::Value(v) => {
+            if let Some(f) = v.downcast_mut::<f32>() {
+                if let Some(new) = to_float(value) {
+                    *f = new;
+                }
+            } else if let Some(v) = v.downcast_mut::<Vec2>() {
+                let (mut x, mut y) = (v.x, v.y);
+                read_floats(value, &mut [("x", &mut x), ("y", &mut y)]);
+                *v = Vec2::new(x, y);
+            } else if let Some(v) = v.downcast_mut::<Vec3>() {
+                let (mut x, mut y, mut z) = (v.x, v.y, v.z);
+                read_floats(value, &mut [("x", &mut x), ("y", &mut y), ("z", &mut z)]);
+                *v = Vec3::new(x, y, z);
+            } else if let Some(q) = v.downcast_mut::<Quat>() {
+                let (mut x, mut y, mut z, mut w) = (q.x, q.y, q.z, q.w);
+                let fields = &mut [("x", &mut x), ("y", &mut y), ("z", &mut z), ("w", &mut w)];
+                read_floats(value, fields);
+                *q = Quat::from_xyzw(x, y, z, w);
+            } else if let Some(i) = v.downcast_mut::<i32>() {
+                if let Ok(new) = value.as_int() {
+                    *i = new as i32;
+                }
+            } else if let Some(b) = v.downcast_mut::<bool>() {
+                if let Ok(new) = value.as_bool() {
+                    *b = new;
+                }
+            } else if let Some(s) = v.downcast_mut::<String>() {
+                if let Ok(new) = value.clone().into_string() {
+                    *s = new;
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::*;
+
+    use super::RunScript;
+    use crate::{test_utils::*, PrefabRegistry};
+
+    #[test]
+    fn documented_example() {
+        let mut world = prefab_world();
+        world.resource_scope(|_, mut reg: Mut<PrefabRegistry>| {
+            reg.register_type::<Transform>();
+            reg.register_build_command::<RunScript>();
+        });
+
+        let input = r#"{
+            Transform,
+            RunScript!(source: "components.Transform.translation.x = 10.0 * 2.0;"),
+        }"#;
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        assert_component(
+            &world,
+            entity,
+            &Transform::from_translation(Vec3::new(20.0, 0.0, 0.0)),
+        );
+
+        let input = r#"{
+            Transform { scale: Vec3 { x: 2.0, y: 2.0, z: 2.0 } },
+            RunScript!(source: "
+                let t = components.Transform;
+                t.translation.y = t.scale.x * 3;
+                t.rotation.w = 1.0;
+                components.Transform = t;
+                components.Transform.scale.z = \"big\".len;
+            "),
+        }"#;
+        let entity = spawn_prefab_str(&mut world, input).unwrap();
+        let transform = world.get::<Transform>(entity).unwrap();
+        assert_eq!(transform.translation, Vec3::new(0.0, 6.0, 0.0));
+        assert_eq!(transform.scale, Vec3::new(2.0, 2.0, 3.0));
+        assert_eq!(transform.rotation, Quat::IDENTITY);
+    }
+}
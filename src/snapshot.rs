@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{prefab::Prefab, PrefabRegistry};
+
+/// A record of which prefabs were loaded in a [PrefabRegistry] and their contents, see
+/// [PrefabRegistry::snapshot].
+///
+/// Can be serialized with any serde format and stored alongside a save game or replay.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrefabSnapshot {
+    /// The content hash of each cached prefab, by name.
+    pub prefabs: BTreeMap<String, u64>,
+}
+
+/// A prefab whose content doesn't match a [PrefabSnapshot].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotMismatch {
+    /// The prefab could not be loaded.
+    Missing { name: String, error: String },
+    /// The prefab loaded, but its content has changed since the snapshot was taken.
+    Changed { name: String },
+}
+
+impl PrefabRegistry {
+    /// Record the name and content hash of every cached prefab.
+    ///
+    /// The hash is taken from the prefab's contents rather than its file, so formatting
+    /// changes and comments don't affect it.
+    pub fn snapshot(&self) -> PrefabSnapshot {
+        PrefabSnapshot {
+            prefabs: self
+                .cached_prefabs()
+                .map(|(name, prefab)| (name.to_string(), prefab_hash(prefab)))
+                .collect(),
+        }
+    }
+
+    /// Load every prefab recorded in the snapshot and compare it against the recorded hash.
+    ///
+    /// Returns the prefabs which failed to load or whose content has changed. An empty list
+    /// means the game is running against the same prefab content as when the snapshot was taken.
+    ///
+    /// ## Example
+    ///
+    /// ```
+    /// use bevy::prelude::*;
+    /// use bevy_lazy_prefabs::*;
+    ///
+    /// fn load_game(mut registry: ResMut<PrefabRegistry>, saved: Res<PrefabSnapshot>) {
+    ///     for mismatch in registry.restore_snapshot(&saved) {
+    ///         warn!("Save game prefab mismatch: {:?}", mismatch);
+    ///     }
+    /// }
+    /// ```
+    pub fn restore_snapshot(&mut self, snapshot: &PrefabSnapshot) -> Vec<SnapshotMismatch> {
+        let mut mismatches = Vec::new();
+        for (name, hash) in snapshot.prefabs.iter() {
+            match self.load(name) {
+                Ok(prefab) if prefab_hash(prefab) != *hash => {
+                    mismatches.push(SnapshotMismatch::Changed { name: name.clone() })
+                }
+                Ok(_) => {}
+                Err(e) => mismatches.push(SnapshotMismatch::Missing {
+                    name: name.clone(),
+                    error: e.to_string(),
+                }),
+            }
+        }
+        mismatches
+    }
+}
+
+fn prefab_hash(prefab: &Prefab) -> u64 {
+    content_hash(&prefab.to_string())
+}
+
+/// A 64 bit FNV-1a hash of the text. Unlike `DefaultHasher` the result is stable across
+/// platforms and rust versions, so it can be saved to disk.
+pub(crate) fn content_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{test_utils::Stats, PrefabRegistry};
+
+    #[test]
+    fn snapshot() {
+        use crate::SnapshotMismatch;
+
+        let mut reg = PrefabRegistry::default();
+        reg.register_type::<Stats>();
+        reg.replace_prefab("orc", "{ Stats { health: 5 } }")
+            .unwrap();
+        reg.replace_prefab("bat", "{ Stats { speed: 2.0 } }")
+            .unwrap();
+        let snapshot = reg.snapshot();
+        assert_eq!(snapshot.prefabs.len(), 2);
+
+        reg.replace_prefab(
+            "orc",
+            "{\n  // Formatting doesn't matter\n  Stats{health:5}\n}",
+        )
+        .unwrap();
+        assert!(reg.restore_snapshot(&snapshot).is_empty());
+
+        reg.replace_prefab("orc", "{ Stats { health: 6 } }")
+            .unwrap();
+        reg.unload_prefab("bat");
+        let mismatches = reg.restore_snapshot(&snapshot);
+        assert_eq!(mismatches.len(), 2);
+        assert!(matches!(&mismatches[0], SnapshotMismatch::Missing { name, .. } if name == "bat"));
+        assert_eq!(
+            mismatches[1],
+            SnapshotMismatch::Changed {
+                name: "orc".to_string()
+            }
+        );
+    }
+}
@@ -0,0 +1,346 @@
+//! Sources that *.prefab* files can be read from.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::asset::{AssetIo, AssetIoError};
+#[cfg(all(feature = "remote", not(target_arch = "wasm32")))]
+use bevy::utils::HashMap;
+#[cfg(all(feature = "remote", not(target_arch = "wasm32")))]
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Reads prefab text for the [crate::PrefabRegistry].
+///
+/// Paths are relative to the root of the source, ie: `prefabs/bird.prefab`. Set a
+/// custom source with [crate::PrefabRegistry::set_source] to load prefabs from archives
+/// or other virtual filesystems.
+pub trait PrefabSource: Send + Sync + 'static {
+    /// Read an entire file as a string.
+    ///
+    /// Should return an error of kind [io::ErrorKind::NotFound] if the file doesn't exist.
+    fn read_to_string(&self, path: &Path) -> io::Result<String>;
+
+    /// The names of the files in a directory. Sub-directories are not included.
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<String>>;
+
+    /// Whether a file exists. Reads the whole file by default, so sources which can check
+    /// without reading should override this.
+    fn exists(&self, path: &Path) -> bool {
+        self.read_to_string(path).is_ok()
+    }
+
+    /// Write a file, used by [crate::PrefabRegistry::export_entities]. Sources are read only
+    /// unless they override this.
+    fn write(&self, path: &Path, _contents: &str) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{} can't be written to this prefab source", path.display()),
+        ))
+    }
+}
+
+/// Reads prefabs from a directory on disk. The default source reads from *assets*.
+pub struct FileSource {
+    root: PathBuf,
+}
+
+impl FileSource {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FileSource { root: root.into() }
+    }
+}
+
+impl Default for FileSource {
+    fn default() -> Self {
+        FileSource::new("assets")
+    }
+}
+
+impl PrefabSource for FileSource {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(self.root.join(path))
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for entry in fs::read_dir(self.root.join(path))? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            if let Some(name) = entry.file_name().to_str() {
+                names.push(name.to_string());
+            }
+        }
+        Ok(names)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.root.join(path).is_file()
+    }
+
+    fn write(&self, path: &Path, contents: &str) -> io::Result<()> {
+        let path = self.root.join(path);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, contents)
+    }
+}
+
+/// Reads prefabs through a bevy [AssetIo], such as the one used by the `AssetServer`.
+///
+/// Reads block until the [AssetIo] has finished loading the file, so this isn't
+/// available on wasm.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct AssetIoSource {
+    io: Box<dyn AssetIo>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl AssetIoSource {
+    pub fn new(io: Box<dyn AssetIo>) -> Self {
+        AssetIoSource { io }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl PrefabSource for AssetIoSource {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        let bytes = futures_lite::future::block_on(self.io.load_path(path)).map_err(to_io_error)?;
+        String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<String>> {
+        let names = self
+            .io
+            .read_directory(path)
+            .map_err(to_io_error)?
+            .filter(|path| !self.io.is_directory(path))
+            .filter_map(|path| Some(path.file_name()?.to_str()?.to_string()))
+            .collect();
+        Ok(names)
+    }
+}
+
+/// Fetches prefabs from a web server. Requires the `remote` feature.
+///
+/// A file at `prefabs/bird.prefab` is fetched from `{base_url}/prefabs/bird.prefab`.
+/// Fetched files are cached in memory. If a request fails and an older copy of the file
+/// is cached, the cached copy is used instead. Directories can't be listed, so
+/// [crate::PrefabRegistry::load_glob] isn't supported.
+///
+/// ## Example
+///
+/// ```
+/// use bevy::prelude::*;
+/// use bevy_lazy_prefabs::{source::RemoteSource, *};
+/// use std::time::Duration;
+///
+/// fn setup(mut registry: ResMut<PrefabRegistry>) {
+///     let source = RemoteSource::new("https://example.com/content")
+///         .with_max_age(Duration::from_secs(60 * 5));
+///     registry.set_source(source);
+/// }
+/// ```
+#[cfg(all(feature = "remote", not(target_arch = "wasm32")))]
+pub struct RemoteSource {
+    base_url: String,
+    max_age: Option<Duration>,
+    cache: Mutex<HashMap<PathBuf, (Instant, String)>>,
+}
+
+#[cfg(all(feature = "remote", not(target_arch = "wasm32")))]
+impl RemoteSource {
+    pub fn new(base_url: &str) -> Self {
+        RemoteSource {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            max_age: None,
+            cache: Default::default(),
+        }
+    }
+
+    /// Re-fetch cached files once they're older than `max_age`. By default files are
+    /// only fetched once.
+    ///
+    /// Note the [crate::PrefabRegistry] also caches parsed prefabs, use
+    /// [crate::PrefabRegistry::unload_prefab] to load a prefab again.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    fn fetch(&self, path: &Path) -> io::Result<String> {
+        let url = format!("{}/{}", self.base_url, path.to_string_lossy());
+        match ureq::get(&url).call() {
+            Ok(response) => response.into_string(),
+            Err(ureq::Error::Status(404, _)) => Err(io::Error::new(io::ErrorKind::NotFound, url)),
+            Err(e) => Err(io::Error::new(io::ErrorKind::Other, e)),
+        }
+    }
+}
+
+#[cfg(all(feature = "remote", not(target_arch = "wasm32")))]
+impl PrefabSource for RemoteSource {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        let mut cache = self.cache.lock().unwrap();
+        let cached = cache.get(path);
+        if let Some((fetched, text)) = cached {
+            if self.max_age.map_or(true, |max| fetched.elapsed() < max) {
+                return Ok(text.clone());
+            }
+        }
+
+        match self.fetch(path) {
+            Ok(text) => {
+                cache.insert(path.to_path_buf(), (Instant::now(), text.clone()));
+                Ok(text)
+            }
+            Err(e) if e.kind() != io::ErrorKind::NotFound => match cached {
+                Some((_, text)) => Ok(text.clone()),
+                None => Err(e),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read_dir(&self, _: &Path) -> io::Result<Vec<String>> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Remote prefab directories can't be listed",
+        ))
+    }
+}
+
+/// The default source on wasm, where files can't be read synchronously. Prefabs must be
+/// loaded with [crate::PrefabRegistry::preload] before they can be used.
+pub struct PreloadOnlySource;
+
+impl PrefabSource for PreloadOnlySource {
+    fn read_to_string(&self, path: &Path) -> io::Result<String> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "{} must be loaded with PrefabRegistry::preload first",
+                path.display()
+            ),
+        ))
+    }
+
+    fn read_dir(&self, _: &Path) -> io::Result<Vec<String>> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Prefab directories can't be read on this platform",
+        ))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn to_io_error(e: AssetIoError) -> io::Error {
+    match e {
+        AssetIoError::Io(e) => e,
+        AssetIoError::NotFound(path) => {
+            io::Error::new(io::ErrorKind::NotFound, path.display().to_string())
+        }
+        e => io::Error::new(io::ErrorKind::Other, e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::PrefabRegistry;
+
+    #[test]
+    fn custom_source() {
+        use crate::source::PrefabSource;
+        use std::{io, path::Path};
+
+        struct MemorySource;
+        impl PrefabSource for MemorySource {
+            fn read_to_string(&self, path: &Path) -> io::Result<String> {
+                match path.to_str() {
+                    Some("prefabs/a.prefab") => Ok("A {}".to_string()),
+                    _ => Err(io::ErrorKind::NotFound.into()),
+                }
+            }
+
+            fn read_dir(&self, _: &Path) -> io::Result<Vec<String>> {
+                Ok(vec!["a.prefab".to_string()])
+            }
+        }
+
+        let mut reg = PrefabRegistry::default();
+        reg.set_source(MemorySource);
+        assert_eq!(reg.load("a.prefab").unwrap().name.as_deref(), Some("A"));
+        assert_eq!(reg.load_glob("*").unwrap(), ["a.prefab"]);
+        assert!(reg.load("b.prefab").is_err());
+
+        reg.replace_prefab("c", "{ }").unwrap();
+        assert_eq!(reg.prefab_names(), ["a.prefab", "c"]);
+        assert!(MemorySource.exists(Path::new("prefabs/a.prefab")));
+        assert!(!MemorySource.exists(Path::new("prefabs/b.prefab")));
+    }
+
+    #[test]
+    fn file_source_exists() {
+        use crate::{
+            source::{FileSource, PrefabSource},
+            test_utils::TempDir,
+        };
+        use std::path::Path;
+
+        let tmp = TempDir::new("file_source");
+        let dir = tmp.path();
+        std::fs::create_dir_all(dir.join("prefabs")).unwrap();
+        std::fs::write(dir.join("prefabs/a.prefab"), "{ }").unwrap();
+
+        let source = FileSource::new(dir);
+        assert!(source.exists(Path::new("prefabs/a.prefab")));
+        assert!(!source.exists(Path::new("prefabs/b.prefab")));
+        assert!(!source.exists(Path::new("prefabs")));
+    }
+
+    #[cfg(feature = "remote")]
+    #[test]
+    fn remote_source() {
+        use crate::source::RemoteSource;
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Serve a single request, later requests must be answered from the cache.
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 1024];
+            let n = stream.read(&mut buf).unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).to_string();
+            let body = "Remote {}";
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+            request
+        });
+
+        let mut reg = PrefabRegistry::default();
+        reg.set_source(RemoteSource::new(&format!("http://{}/content/", addr)));
+        let prefab = reg.load("remote.prefab").unwrap();
+        assert_eq!(prefab.name.as_deref(), Some("Remote"));
+
+        reg.unload_prefab("remote.prefab");
+        assert!(reg.load("remote.prefab").is_ok());
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("GET /content/prefabs/remote.prefab "));
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    app::Events,
+    prelude::*,
+    utils::{Duration, HashMap, Instant},
+};
+
+use crate::bevy_commands::spawn_named;
+
+/// How many queued prefabs the [PrefabSpawnQueue] spawns each frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpawnBudget {
+    /// Spawn up to this many prefabs per frame.
+    Count(usize),
+    /// Keep spawning until this much time has passed. At least one prefab is spawned each
+    /// frame, however long it takes.
+    Time(Duration),
+}
+
+impl Default for SpawnBudget {
+    fn default() -> Self {
+        SpawnBudget::Count(64)
+    }
+}
+
+impl SpawnBudget {
+    /// Returns true if there's budget left after `done` items, started at `start`.
+    pub(crate) fn allows(&self, start: Instant, done: usize) -> bool {
+        match *self {
+            SpawnBudget::Count(count) => done < count,
+            SpawnBudget::Time(time) => done == 0 || start.elapsed() < time,
+        }
+    }
+}
+
+/// Identifies a batch of prefabs pushed to the [PrefabSpawnQueue].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PrefabBatchId(u64);
+
+/// A prefab waiting in the [PrefabSpawnQueue].
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueuedPrefab {
+    pub name: String,
+    /// Moves the entity after it's spawned, like [crate::SpawnNamedPrefabCommands::spawn_prefab_at].
+    pub translation: Option<Vec3>,
+}
+
+impl From<&str> for QueuedPrefab {
+    fn from(name: &str) -> Self {
+        QueuedPrefab {
+            name: name.to_string(),
+            translation: None,
+        }
+    }
+}
+
+impl From<(&str, Vec3)> for QueuedPrefab {
+    fn from((name, translation): (&str, Vec3)) -> Self {
+        QueuedPrefab {
+            name: name.to_string(),
+            translation: Some(translation),
+        }
+    }
+}
+
+/// An event sent when every prefab in a batch has been spawned.
+#[derive(Debug, Clone)]
+pub struct PrefabBatchSpawned {
+    pub batch: PrefabBatchId,
+    /// The spawned entities, in the order they were pushed.
+    pub entities: Vec<Entity>,
+}
+
+/// Spawns large numbers of prefabs over several frames to avoid a hitch.
+///
+/// Prefabs are spawned in the order they're pushed, within the [SpawnBudget] for each
+/// frame. A [PrefabBatchSpawned] event is sent when a batch is finished.
+///
+/// ## Example
+///
+/// ```
+/// use bevy::prelude::*;
+/// use bevy_lazy_prefabs::*;
+///
+/// fn spawn_forest(mut queue: ResMut<PrefabSpawnQueue>) {
+///     queue.set_budget(SpawnBudget::Count(20));
+///     let trees = (0..500).map(|i| ("tree.prefab", Vec3::new(i as f32, 0.0, 0.0)));
+///     queue.push_batch(trees);
+/// }
+///
+/// fn forest_ready(mut events: EventReader<PrefabBatchSpawned>) {
+///     for batch in events.iter() {
+///         info!("Spawned {} trees", batch.entities.len());
+///     }
+/// }
+/// ```
+#[derive(Default)]
+pub struct PrefabSpawnQueue {
+    budget: SpawnBudget,
+    pending: VecDeque<(PrefabBatchId, QueuedPrefab)>,
+    batches: HashMap<PrefabBatchId, (usize, Vec<Entity>)>,
+    next_batch: u64,
+}
+
+impl PrefabSpawnQueue {
+    pub fn new(budget: SpawnBudget) -> Self {
+        PrefabSpawnQueue {
+            budget,
+            ..Default::default()
+        }
+    }
+
+    pub fn budget(&self) -> SpawnBudget {
+        self.budget
+    }
+
+    pub fn set_budget(&mut self, budget: SpawnBudget) {
+        self.budget = budget;
+    }
+
+    /// Queue a batch of prefabs to be spawned. An empty batch is finished immediately.
+    pub fn push_batch<T: Into<QueuedPrefab>>(
+        &mut self,
+        prefabs: impl IntoIterator<Item = T>,
+    ) -> PrefabBatchId {
+        let batch = PrefabBatchId(self.next_batch);
+        self.next_batch += 1;
+        let before = self.pending.len();
+        self.pending
+            .extend(prefabs.into_iter().map(|prefab| (batch, prefab.into())));
+        self.batches
+            .insert(batch, (self.pending.len() - before, Vec::new()));
+        batch
+    }
+
+    /// The number of prefabs waiting to be spawned.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Spawn prefabs from the front of the queue until the budget is used up. Returns the
+    /// batches which were finished.
+    ///
+    /// Called by the plugin every frame.
+    pub fn process(&mut self, world: &mut World) -> Vec<PrefabBatchSpawned> {
+        let start = Instant::now();
+        let mut spawned = 0;
+        while self.budget.allows(start, spawned) {
+            let (batch, prefab) = match self.pending.pop_front() {
+                Some(next) => next,
+                None => break,
+            };
+            let entity = world.spawn().id();
+            spawn_named(world, entity, &prefab.name, prefab.translation);
+            if let Some((_, entities)) = self.batches.get_mut(&batch) {
+                entities.push(entity);
+            }
+            spawned += 1;
+        }
+
+        let finished: Vec<_> = self
+            .batches
+            .iter()
+            .filter(|(_, (count, entities))| entities.len() == *count)
+            .map(|(batch, _)| *batch)
+            .collect();
+        let mut finished: Vec<_> = finished
+            .into_iter()
+            .map(|batch| PrefabBatchSpawned {
+                batch,
+                entities: self.batches.remove(&batch).unwrap().1,
+            })
+            .collect();
+        finished.sort_by_key(|event| event.batch.0);
+        finished
+    }
+}
+
+/// Spawns prefabs from the [PrefabSpawnQueue] and sends [PrefabBatchSpawned] events.
+pub(crate) fn spawn_queued_prefabs(world: &mut World) {
+    let finished = world.resource_scope(|world, mut queue: Mut<PrefabSpawnQueue>| {
+        match queue.is_empty() && queue.batches.is_empty() {
+            true => Vec::new(),
+            false => queue.process(world),
+        }
+    });
+    if let Some(mut events) = world.get_resource_mut::<Events<PrefabBatchSpawned>>() {
+        for event in finished {
+            events.send(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::*;
+
+    use crate::PrefabRegistry;
+
+    #[test]
+    fn spawn_queue() {
+        use crate::test_utils::*;
+        use crate::{PrefabSpawnQueue, SpawnBudget};
+
+        let mut world = prefab_world();
+        {
+            let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.register_type::<Stats>();
+            reg.register_type::<Transform>();
+            reg.replace_prefab("a", "{ Stats { health: 1 } }").unwrap();
+        }
+
+        let mut queue = PrefabSpawnQueue::new(SpawnBudget::Count(2));
+        let first = queue.push_batch(vec!["a", "a", "a"]);
+        let second = queue.push_batch(vec![("a", Vec3::X)]);
+        assert_eq!(queue.len(), 4);
+
+        assert!(queue.process(&mut world).is_empty());
+        assert_eq!(queue.len(), 2);
+
+        let finished = queue.process(&mut world);
+        assert!(queue.is_empty());
+        assert_eq!(finished.len(), 2);
+        assert_eq!(finished[0].batch, first);
+        assert_eq!(finished[0].entities.len(), 3);
+        assert_eq!(finished[1].batch, second);
+        let moved = finished[1].entities[0];
+        assert_eq!(world.get::<Stats>(moved).unwrap().health, 1);
+        assert_component(&world, moved, &Transform::from_translation(Vec3::X));
+    }
+}
@@ -0,0 +1,250 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use bevy::{ecs::system::Command, prelude::*, utils::Instant};
+
+use crate::{
+    bevy_commands::tag_spawned,
+    build_commands::{
+        apply_component, command_failed, explain_skipped_command, run_command, spawn_children,
+        PrefabCommandError,
+    },
+    deferred::Anchors,
+    prefab::{Prefab, PrefabBuildStep, PrefabCommandData, PrefabComponent},
+    writer::{explain_command, explain_component},
+    PrefabRegistry, SpawnBudget,
+};
+
+/// The progress of a prefab being applied over several frames, see
+/// [crate::SpawnNamedPrefabCommands::spawn_prefab_streamed].
+///
+/// Stays on the entity once the prefab has been fully applied.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PrefabStreamProgress {
+    /// The number of build steps applied so far.
+    pub applied: usize,
+    /// The total number of build steps in the prefab and its children.
+    pub total: usize,
+}
+
+impl PrefabStreamProgress {
+    /// How much of the prefab has been applied, from 0 to 1.
+    pub fn fraction(&self) -> f32 {
+        match self.total {
+            0 => 1.0,
+            total => self.applied as f32 / total as f32,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.applied >= self.total
+    }
+}
+
+enum StreamStep {
+    Component(Entity, Arc<PrefabComponent>),
+    Command(Entity, Arc<PrefabCommandData>),
+    PushChild(Entity, Entity),
+    Companions(Entity),
+}
+
+/// The remaining build steps of a streamed prefab.
+pub(crate) struct PrefabStream {
+    steps: VecDeque<StreamStep>,
+    anchors: Anchors,
+    budget: SpawnBudget,
+}
+
+/// Flatten the prefab's steps into the order they'd be applied by `apply_prefab`.
+fn flatten_steps(
+    prefab: &Prefab,
+    entity: Entity,
+    children: &mut impl Iterator<Item = Entity>,
+    steps: &mut VecDeque<StreamStep>,
+) {
+    for step in prefab.steps.iter() {
+        match step {
+            PrefabBuildStep::AddComponent(comp) => {
+                steps.push_back(StreamStep::Component(entity, comp.clone()))
+            }
+            PrefabBuildStep::RunCommand(data) => {
+                steps.push_back(StreamStep::Command(entity, data.clone()))
+            }
+            PrefabBuildStep::AddChild(child) => {
+                let child_entity = children.next().unwrap();
+                flatten_steps(child, child_entity, children, steps);
+                steps.push_back(StreamStep::PushChild(entity, child_entity));
+            }
+        }
+    }
+    steps.push_back(StreamStep::Companions(entity));
+}
+
+pub(crate) struct StartPrefabStreamCommand {
+    pub entity: Entity,
+    pub name: String,
+    pub budget: SpawnBudget,
+}
+
+impl Command for StartPrefabStreamCommand {
+    fn write(self: Box<Self>, world: &mut World) {
+        let entity = self.entity;
+        world.resource_scope(|world, mut registry: Mut<PrefabRegistry>| {
+            let prefab = match registry.load_or_fallback(&self.name) {
+                Some(prefab) => prefab,
+                None => return,
+            };
+            registry.record_spawn(&self.name);
+            tag_spawned(&registry, world, entity, &self.name, &prefab);
+
+            let mut children = Vec::new();
+            let mut anchors = Anchors::default();
+            spawn_children(&prefab, entity, &mut children, &mut anchors, &mut || {
+                world.spawn().id()
+            });
+            let mut steps = VecDeque::new();
+            flatten_steps(&prefab, entity, &mut children.into_iter(), &mut steps);
+
+            world
+                .entity_mut(entity)
+                .insert(PrefabStreamProgress {
+                    applied: 0,
+                    total: steps.len(),
+                })
+                .insert(PrefabStream {
+                    steps,
+                    anchors,
+                    budget: self.budget,
+                });
+        });
+    }
+}
+
+/// Applies the next build steps of every streamed prefab, within each stream's budget.
+pub(crate) fn apply_prefab_streams(world: &mut World) {
+    let streaming: Vec<Entity> = world
+        .query_filtered::<Entity, With<PrefabStream>>()
+        .iter(world)
+        .collect();
+    for entity in streaming {
+        let mut stream = world.entity_mut(entity).remove::<PrefabStream>().unwrap();
+        let applied = world.resource_scope(|world, registry: Mut<PrefabRegistry>| {
+            apply_stream_steps(&registry, &mut stream, world)
+        });
+
+        // The root may have been despawned by one of its own build commands.
+        if world.get_entity(entity).is_none() {
+            continue;
+        }
+        if let Some(mut progress) = world.get_mut::<PrefabStreamProgress>(entity) {
+            progress.applied += applied;
+        }
+        if !stream.steps.is_empty() {
+            world.entity_mut(entity).insert(stream);
+        }
+    }
+}
+
+fn apply_stream_steps(reg: &PrefabRegistry, stream: &mut PrefabStream, world: &mut World) -> usize {
+    let start = Instant::now();
+    let mut applied = 0;
+    while stream.budget.allows(start, applied) {
+        let step = match stream.steps.pop_front() {
+            Some(step) => step,
+            None => break,
+        };
+        match step {
+            StreamStep::Component(entity, comp) => {
+                if reg.explain_spawns() {
+                    info!("{:?}: {}", entity, explain_component(reg, &comp));
+                }
+                apply_component(reg, &comp, world, entity, &stream.anchors)
+            }
+            StreamStep::Command(entity, data) => {
+                if reg.explain_spawns() {
+                    info!("{:?}: {}", entity, explain_command(reg, &data));
+                    explain_skipped_command(&data, world, entity);
+                }
+                match reg.get_build_command(&data.name) {
+                    Some(cmd) => {
+                        run_command(Some(reg), &**cmd, &data, world, entity, &stream.anchors)
+                    }
+                    None => command_failed(
+                        world,
+                        &data.name,
+                        entity,
+                        PrefabCommandError::UnregisteredCommand(data.name.clone()),
+                    ),
+                }
+            }
+            StreamStep::PushChild(parent, child) => {
+                world.entity_mut(parent).push_children(&[child]);
+            }
+            StreamStep::Companions(entity) => {
+                let companions = reg.insert_companions(world, entity);
+                if reg.explain_spawns() && !companions.is_empty() {
+                    info!(
+                        "{:?}: inserted companions {}",
+                        entity,
+                        companions.join(", ")
+                    );
+                }
+            }
+        }
+        applied += 1;
+    }
+    applied
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::*;
+
+    use crate::PrefabRegistry;
+
+    #[test]
+    fn spawn_streamed() {
+        use crate::stream::apply_prefab_streams;
+        use crate::test_utils::*;
+        use crate::{PrefabStreamProgress, SpawnBudget, SpawnNamedPrefabCommands};
+        use bevy::ecs::system::CommandQueue;
+
+        let mut world = prefab_world();
+        {
+            let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.register_type::<Stats>();
+            reg.register_type::<Transform>();
+            reg.replace_prefab(
+                "big",
+                "{ Stats { health: 3 }, Transform, children [ { Stats }, { Stats } ] }",
+            )
+            .unwrap();
+        }
+
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        let entity = commands
+            .spawn_prefab_streamed("big", SpawnBudget::Count(4))
+            .id();
+        queue.apply(&mut world);
+
+        // 2 components, 2 children with a component and companions each, then companions.
+        let progress = *world.get::<PrefabStreamProgress>(entity).unwrap();
+        assert_eq!(progress.total, 9);
+        assert!(world.get::<Stats>(entity).is_none());
+
+        apply_prefab_streams(&mut world);
+        assert_eq!(
+            world.get::<PrefabStreamProgress>(entity).unwrap().applied,
+            4
+        );
+        assert_eq!(world.get::<Stats>(entity).unwrap().health, 3);
+        assert!(world.get::<Children>(entity).is_none());
+
+        apply_prefab_streams(&mut world);
+        apply_prefab_streams(&mut world);
+        let progress = world.get::<PrefabStreamProgress>(entity).unwrap();
+        assert!(progress.is_done());
+        assert_eq!(progress.fraction(), 1.0);
+        assert_eq!(world.get::<Children>(entity).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,125 @@
+use bevy::{ecs::system::Command, prelude::*};
+
+use crate::{bevy_commands::spawn_named, PrefabRegistry};
+
+/// A list of prefab names with weights loaded from a *.table* file, see
+/// [PrefabRegistry::load_table].
+///
+/// ```ignore
+/// // loot.table
+/// "coin.prefab": 10,
+/// "potion.prefab": 4,
+/// "sword.prefab": 0.5,
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefabTable {
+    pub(crate) entries: Vec<(String, f32)>,
+}
+
+impl PrefabTable {
+    /// The prefab names and their weights, in the order they were written.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, f32)> {
+        self.entries
+            .iter()
+            .map(|(name, weight)| (name.as_str(), *weight))
+    }
+
+    /// The sum of every weight in the table.
+    pub fn total_weight(&self) -> f32 {
+        self.entries.iter().map(|(_, weight)| weight).sum()
+    }
+
+    /// Pick a prefab from a `roll` between 0 and 1. Each prefab's chance of being picked is
+    /// proportional to its weight. Returns [None] if the table has no weight.
+    pub fn pick(&self, roll: f32) -> Option<&str> {
+        let total = self.total_weight();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut target = roll.clamp(0.0, 1.0) * total;
+        let mut picked = None;
+        for (name, weight) in self.entries.iter().filter(|(_, weight)| *weight > 0.0) {
+            picked = Some(name.as_str());
+            if target < *weight {
+                break;
+            }
+            target -= weight;
+        }
+        picked
+    }
+}
+
+pub(crate) struct SpawnFromTableCommand {
+    pub entity: Entity,
+    pub table: String,
+    pub roll: f32,
+}
+
+impl Command for SpawnFromTableCommand {
+    fn write(self: Box<Self>, world: &mut World) {
+        let picked = world.resource_scope(|_, mut registry: Mut<PrefabRegistry>| {
+            match registry.load_table(&self.table) {
+                Ok(table) => table.pick(self.roll).map(str::to_string),
+                Err(e) => {
+                    warn!("Unable to spawn from table {}: {}", self.table, e);
+                    None
+                }
+            }
+        });
+        if let Some(name) = picked {
+            spawn_named(world, self.entity, &name, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::*;
+
+    use crate::PrefabRegistry;
+
+    #[test]
+    fn spawn_from_table() {
+        use crate::{test_utils::*, PrefabRng, SpawnNamedPrefabCommands};
+        use bevy::ecs::system::CommandQueue;
+
+        let tmp = TempDir::new("spawn_table");
+        let dir = tmp.path();
+        std::fs::write(dir.join("coin.prefab"), "{ Stats { health: 1 } }").unwrap();
+        std::fs::write(dir.join("sword.prefab"), "{ Stats { health: 2 } }").unwrap();
+        std::fs::write(
+            dir.join("loot.table"),
+            "\"coin.prefab\": 3, \"gem.prefab\": 0, \"sword.prefab\": 1.0",
+        )
+        .unwrap();
+        std::fs::write(dir.join("bad.table"), "\"coin.prefab\": -1").unwrap();
+
+        let mut world = prefab_world();
+        {
+            let mut reg = world.get_resource_mut::<PrefabRegistry>().unwrap();
+            reg.set_prefab_dir(&dir);
+            reg.register_type::<Stats>();
+            let table = reg.load_table("loot.table").unwrap();
+            assert_eq!(table.total_weight(), 4.0);
+            assert_eq!(table.pick(0.0), Some("coin.prefab"));
+            assert_eq!(table.pick(0.74), Some("coin.prefab"));
+            assert_eq!(table.pick(0.75), Some("sword.prefab"));
+            assert_eq!(table.pick(1.0), Some("sword.prefab"));
+            assert!(reg.load_table("bad.table").is_err());
+        }
+
+        let mut rng = PrefabRng::with_seed(7);
+        let mut queue = CommandQueue::default();
+        for _ in 0..20 {
+            Commands::new(&mut queue, &world).spawn_from_table("loot.table", &mut rng);
+        }
+        queue.apply(&mut world);
+        let healths: Vec<_> = world
+            .query::<&Stats>()
+            .iter(&world)
+            .map(|stats| stats.health)
+            .collect();
+        assert_eq!(healths.len(), 20);
+        assert!(healths.contains(&1) && healths.contains(&2));
+    }
+}
@@ -0,0 +1,193 @@
+//! Helpers for testing prefabs without running a full bevy app.
+//!
+//! ## Example
+//!
+//! ```
+//! use bevy::prelude::*;
+//! use bevy_lazy_prefabs::test_utils::*;
+//!
+//! let mut world = prefab_world();
+//! world
+//!     .get_resource_mut::<bevy_lazy_prefabs::PrefabRegistry>()
+//!     .unwrap()
+//!     .register_type::<Transform>();
+//!
+//! let entity = spawn_prefab_str(&mut world, "{ Transform }").unwrap();
+//! assert_component(&world, entity, &Transform::default());
+//! ```
+
+use std::{fmt::Debug, path::Path};
+
+use bevy::{ecs::component::Component, prelude::*};
+
+use crate::{
+    build_commands::apply_prefab, parse::parse_prefab_str, LoadPrefabError, PrefabRegistry,
+};
+
+/// Create an empty [World] containing a default [PrefabRegistry].
+///
+/// No types or build commands are registered.
+pub fn prefab_world() -> World {
+    let mut world = World::new();
+    world.insert_resource(PrefabRegistry::default());
+    world
+}
+
+/// Parse a prefab from a string and immediately spawn it into the world.
+///
+/// Components and build commands are applied before this function returns.
+/// The world must contain a [PrefabRegistry].
+pub fn spawn_prefab_str(world: &mut World, source: &str) -> Result<Entity, LoadPrefabError> {
+    world.resource_scope(|world, registry: Mut<PrefabRegistry>| {
+        let prefab = parse_prefab_str(source, &registry)?;
+        let entity = world.spawn().id();
+        apply_prefab(&registry, &prefab, world, entity);
+        Ok(entity)
+    })
+}
+
+/// Assert that an entity has a component equal to `expected`.
+///
+/// Panics if the component is missing or doesn't match.
+pub fn assert_component<T: Component + PartialEq + Debug>(
+    world: &World,
+    entity: Entity,
+    expected: &T,
+) {
+    match world.get::<T>(entity) {
+        Some(component) => assert_eq!(component, expected),
+        None => panic!(
+            "Entity {:?} is missing component {}",
+            entity,
+            std::any::type_name::<T>()
+        ),
+    }
+}
+
+/// Write an entity and its children as canonical text, for comparing against a golden file.
+///
+/// Every component registered with the world's [PrefabRegistry] is written in *.prefab*
+/// format, sorted by type name, followed by the entity's children in order. Fields which
+/// can't be written in a *.prefab* file, such as entity references, are left out.
+pub fn entity_snapshot(world: &World, entity: Entity) -> String {
+    let registry = world
+        .get_resource::<PrefabRegistry>()
+        .expect("The world must contain a PrefabRegistry");
+    let mut out = String::new();
+    registry.write_entity(&mut out, world, entity, 0);
+    out
+}
+
+/// Load a prefab by name from the world's [PrefabRegistry], spawn it into an empty scratch
+/// world and return its [entity_snapshot].
+///
+/// The scratch world only contains the registry, so build commands which need other
+/// resources, such as `Assets<Mesh>`, fail and are left out of the snapshot.
+pub fn prefab_snapshot(world: &mut World, name: &str) -> Result<String, LoadPrefabError> {
+    let registry = world
+        .remove_resource::<PrefabRegistry>()
+        .expect("The world must contain a PrefabRegistry");
+    let mut scratch = World::new();
+    scratch.insert_resource(registry);
+
+    let snapshot = scratch.resource_scope(|scratch, mut registry: Mut<PrefabRegistry>| {
+        let prefab = registry.load(name)?.clone();
+        let entity = scratch.spawn().id();
+        apply_prefab(&registry, &prefab, scratch, entity);
+        Ok(entity)
+    });
+    let snapshot = snapshot.map(|entity| entity_snapshot(&scratch, entity));
+
+    world.insert_resource(scratch.remove_resource::<PrefabRegistry>().unwrap());
+    snapshot
+}
+
+/// Compare a prefab's [prefab_snapshot] against a golden file, panicking with both versions
+/// if they differ.
+///
+/// If the golden file doesn't exist, or the `UPDATE_PREFAB_SNAPSHOTS` environment variable
+/// is set, the file is written instead. Review and commit it along with the prefab.
+///
+/// ## Example
+///
+/// ```no_run
+/// use bevy::prelude::*;
+/// use bevy_lazy_prefabs::{test_utils::*, PrefabRegistry};
+///
+/// let mut world = prefab_world();
+/// world
+///     .get_resource_mut::<PrefabRegistry>()
+///     .unwrap()
+///     .register_type::<Transform>();
+///
+/// assert_prefab_snapshot(&mut world, "goblin.prefab", "tests/snapshots/goblin.snap");
+/// ```
+pub fn assert_prefab_snapshot(world: &mut World, name: &str, golden: impl AsRef<Path>) {
+    let golden = golden.as_ref();
+    let snapshot = match prefab_snapshot(world, name) {
+        Ok(snapshot) => snapshot,
+        Err(e) => panic!("Error loading prefab {}: {}", name, e),
+    };
+
+    if std::env::var_os("UPDATE_PREFAB_SNAPSHOTS").is_some() || !golden.exists() {
+        if let Some(dir) = golden.parent() {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        std::fs::write(golden, &snapshot).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(golden).unwrap();
+    if expected != snapshot {
+        panic!(
+            "Prefab {} doesn't match the snapshot {}. Set UPDATE_PREFAB_SNAPSHOTS to update it.\n\
+            Expected:\n{}\nFound:\n{}",
+            name,
+            golden.display(),
+            expected,
+            snapshot
+        );
+    }
+}
+
+/// A uniquely named directory in the system temp directory, removed when dropped.
+#[cfg(test)]
+pub(crate) struct TempDir(std::path::PathBuf);
+
+#[cfg(test)]
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = std::env::temp_dir().join(format!(
+            "lazy_prefabs_{}_{}_{}",
+            name,
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A component shared by the crate's own tests.
+#[cfg(test)]
+#[derive(Default, Reflect, Debug, PartialEq)]
+#[reflect(Component)]
+pub(crate) struct Stats {
+    pub health: i32,
+    pub speed: f32,
+    pub title: String,
+}
@@ -0,0 +1,143 @@
+use bevy::{prelude::*, reflect::DynamicStruct};
+use bevy_ecs_tilemap::prelude::*;
+
+use crate::{
+    build_commands::{required, BuildPhase, BuildPrefabCommand, PrefabCommandError},
+    dynamic_cast::*,
+};
+
+/// The settings for a tilemap layer read from a *.prefab* file, waiting to be built.
+///
+/// Inserted by [InsertTilemapLayer]. On the next update the layers are built and this
+/// component is replaced with a bevy_ecs_tilemap `Map`.
+#[derive(Debug, Default, Clone)]
+pub struct PendingTilemapLayers {
+    pub map_id: u16,
+    pub layers: Vec<TilemapLayer>,
+}
+
+/// A single layer of a [PendingTilemapLayers].
+#[derive(Debug, Clone)]
+pub struct TilemapLayer {
+    pub texture_path: String,
+    pub texture_size: Vec2,
+    pub tile_size: Vec2,
+    pub map_size: UVec2,
+    pub chunk_size: UVec2,
+    pub fill: Option<u16>,
+}
+
+/// Adds a bevy_ecs_tilemap layer to the entity, making it a tilemap. Requires the `tilemap`
+/// feature, and `TilemapPlugin` must be added to the app.
+///
+/// Can be used more than once to add several layers, which are numbered in the order they're
+/// written.
+///
+/// ### Properties:
+///
+/// - `texture_path` - The path to the tile atlas texture.
+/// - `texture_size` - The size of the atlas texture, as a `Vec2`.
+/// - `tile_size` - Optional size of a tile, as a `Vec2`. Defaults to 16x16.
+/// - `map_size` - Optional number of chunks in the map, as a `Vec2`. Defaults to 1x1.
+/// - `chunk_size` - Optional number of tiles in a chunk, as a `Vec2`. Defaults to 32x32.
+/// - `fill` - Optional index of the tile to fill the layer with. If omitted the layer is empty.
+/// - `map_id` - Optional id of the map, used to find it with `MapQuery`. Defaults to 0.
+///
+/// ### Example
+///
+/// ```ignore
+/// Background {
+///     Transform,
+///     InsertTilemapLayer!(
+///         texture_path: "tiles.png",
+///         texture_size: Vec2 { x: 96.0, y: 16.0 },
+///         map_size: Vec2 { x: 2.0, y: 2.0 },
+///         fill: 3,
+///     ),
+/// }
+/// ```
+#[derive(Default)]
+pub struct InsertTilemapLayer;
+impl BuildPrefabCommand for InsertTilemapLayer {
+    fn run(
+        &self,
+        properties: Option<&DynamicStruct>,
+        world: &mut World,
+        entity: Entity,
+    ) -> Result<(), PrefabCommandError> {
+        let texture_path = required::<String>(properties, "texture_path")?.clone();
+        let texture_size = *required::<Vec2>(properties, "texture_size")?;
+        let props = properties.unwrap();
+        let to_uvec = |v: Vec2| UVec2::new(v.x.max(1.0) as u32, v.y.max(1.0) as u32);
+        let layer = TilemapLayer {
+            texture_path,
+            texture_size,
+            tile_size: props.get_or("tile_size", Vec2::splat(16.0)),
+            map_size: to_uvec(props.get_or("map_size", Vec2::ONE)),
+            chunk_size: to_uvec(props.get_or("chunk_size", Vec2::splat(32.0))),
+            fill: props.try_get_u32("fill").ok().map(|i| i as u16),
+        };
+
+        let mut entity = world.entity_mut(entity);
+        let map_id = props.try_get_u32("map_id").unwrap_or(0) as u16;
+        match entity.get_mut::<PendingTilemapLayers>() {
+            Some(mut pending) => pending.layers.push(layer),
+            None => {
+                entity.insert(PendingTilemapLayers {
+                    map_id,
+                    layers: vec![layer],
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn key(&self) -> &str {
+        "InsertTilemapLayer"
+    }
+
+    fn phase(&self) -> BuildPhase {
+        BuildPhase::Bundle
+    }
+}
+
+/// Builds the layers of each [PendingTilemapLayers] and inserts the tilemap.
+pub(crate) fn build_tilemap_layers(
+    mut commands: Commands,
+    server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut map_query: MapQuery,
+    query: Query<(Entity, &PendingTilemapLayers)>,
+) {
+    for (entity, pending) in query.iter() {
+        let mut map = Map::new(pending.map_id, entity);
+        for (layer_id, layer) in pending.layers.iter().enumerate() {
+            let layer_id = layer_id as u16;
+            let settings = LayerSettings::new(
+                layer.map_size,
+                layer.chunk_size,
+                layer.tile_size,
+                layer.texture_size,
+            );
+            let (mut builder, _) =
+                LayerBuilder::<TileBundle>::new(&mut commands, settings, pending.map_id, layer_id);
+            if let Some(texture_index) = layer.fill {
+                builder.set_all(TileBundle {
+                    tile: Tile {
+                        texture_index,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                });
+            }
+            let texture = server.load(layer.texture_path.as_str());
+            let material = materials.add(ColorMaterial::texture(texture));
+            let layer_entity = map_query.build_layer(&mut commands, builder, material);
+            map.add_layer(&mut commands, layer_id, layer_entity);
+        }
+        commands
+            .entity(entity)
+            .insert(map)
+            .remove::<PendingTilemapLayers>();
+    }
+}
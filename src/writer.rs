@@ -0,0 +1,541 @@
+use std::{fmt::Write, ops::Range};
+
+use bevy::{
+    prelude::*,
+    reflect::{DynamicStruct, DynamicTupleStruct, ReflectRef, Struct, TypeRegistration},
+    utils::Duration,
+};
+
+use crate::{
+    deferred::DeferredValue,
+    prefab::{Prefab, PrefabBuildStep, PrefabCommandData, PrefabComponent, PrefabMeta},
+    PrefabRegistry,
+};
+
+const INDENT: &str = "    ";
+
+/// Write a [Prefab] in *.prefab* format.
+pub(crate) fn write_prefab(out: &mut String, prefab: &Prefab, depth: usize) {
+    let indent = INDENT.repeat(depth);
+    write!(out, "{}", indent).unwrap();
+    if let Some(name) = prefab.name.as_deref().filter(|n| is_writable_type_name(n)) {
+        write!(out, "{} ", name).unwrap();
+    }
+    if let Some(anchor) = &prefab.anchor {
+        write!(out, "@{} ", anchor).unwrap();
+    }
+    writeln!(out, "{{").unwrap();
+
+    if !prefab.meta.is_empty() {
+        writeln!(out, "{}{}{},", indent, INDENT, write_meta(&prefab.meta)).unwrap();
+    }
+
+    let mut steps = prefab.steps.iter().peekable();
+    while let Some(step) = steps.next() {
+        match step {
+            PrefabBuildStep::AddComponent(comp) => {
+                write_component(out, &comp.type_name, &*comp.reflect, depth + 1);
+            }
+            PrefabBuildStep::RunCommand(command) => match &command.flag {
+                Some(flag) => {
+                    writeln!(out, "{}{}if \"{}\" {{", indent, INDENT, flag).unwrap();
+                    write_command(out, command, depth + 2);
+                    writeln!(out, "{}{}}},", indent, INDENT).unwrap();
+                }
+                None => match command.name.strip_prefix("bundle ") {
+                    Some(bundle) => {
+                        writeln!(out, "{}{}bundle {} {{}},", indent, INDENT, bundle).unwrap()
+                    }
+                    None => write_command(out, command, depth + 1),
+                },
+            },
+            PrefabBuildStep::AddChild(child) => {
+                writeln!(out, "{}{}children [", indent, INDENT).unwrap();
+                write_prefab(out, child, depth + 2);
+                while let Some(PrefabBuildStep::AddChild(child)) = steps.peek() {
+                    write_prefab(out, child, depth + 2);
+                    steps.next();
+                }
+                writeln!(out, "{}{}],", indent, INDENT).unwrap();
+            }
+        }
+    }
+
+    let end = if depth == 0 { "}" } else { "}," };
+    writeln!(out, "{}{}", indent, end).unwrap();
+}
+
+fn write_meta(meta: &PrefabMeta) -> String {
+    let mut fields = Vec::new();
+    if !meta.tags.is_empty() {
+        let tags: Vec<_> = meta.tags.iter().filter_map(|t| write_value(t)).collect();
+        fields.push(format!("tags: [{}]", tags.join(", ")));
+    }
+    if let Some(category) = meta.category.as_ref().and_then(|c| write_value(c)) {
+        fields.push(format!("category: {}", category));
+    }
+    if let Some(description) = meta.description.as_ref().and_then(|d| write_value(d)) {
+        fields.push(format!("description: {}", description));
+    }
+    format!("meta {{ {} }}", fields.join(", "))
+}
+
+/// Write a build command in *.prefab* format.
+///
+/// Properties which can't be represented in a *.prefab* file are skipped.
+pub(crate) fn write_command(out: &mut String, command: &PrefabCommandData, depth: usize) {
+    let indent = INDENT.repeat(depth);
+    let props: Vec<_> = match &command.properties {
+        Some(props) => (0..props.field_len())
+            .filter_map(|i| write_property(props.name_at(i)?, props.field_at(i)?))
+            .collect(),
+        None => Vec::new(),
+    };
+
+    if props.is_empty() {
+        writeln!(out, "{}{}!(),", indent, command.name).unwrap();
+        return;
+    }
+
+    writeln!(out, "{}{}!(", indent, command.name).unwrap();
+    for prop in props {
+        writeln!(out, "{}{}{},", indent, INDENT, prop).unwrap();
+    }
+    writeln!(out, "{}),", indent).unwrap();
+}
+
+/// Write a command property as `name: value`, or `name { .. }` for a property group.
+fn write_property(name: &str, value: &dyn Reflect) -> Option<String> {
+    match value.downcast_ref::<DynamicStruct>() {
+        Some(group) if group.name().is_empty() => {
+            let props: Vec<_> = (0..group.field_len())
+                .filter_map(|i| write_property(group.name_at(i)?, group.field_at(i)?))
+                .collect();
+            Some(format!("{} {{ {} }}", name, props.join(", ")))
+        }
+        _ => Some(format!("{}: {}", name, write_value(value)?)),
+    }
+}
+
+/// Write a component in *.prefab* format.
+///
+/// Fields which can't be represented in a *.prefab* file are skipped, so
+/// they will be initialized to default when the prefab is loaded.
+pub(crate) fn write_component(
+    out: &mut String,
+    type_name: &str,
+    reflect: &dyn Reflect,
+    depth: usize,
+) {
+    let fields: Vec<(String, String)> = match reflect.reflect_ref() {
+        ReflectRef::Struct(s) => (0..s.field_len())
+            .filter_map(|i| {
+                let value = write_value(s.field_at(i)?)?;
+                Some((s.name_at(i)?.to_string(), value))
+            })
+            .collect(),
+        ReflectRef::TupleStruct(ts) => {
+            let values: Option<Vec<_>> = ts.iter_fields().map(write_value).collect();
+            values
+                .unwrap_or_default()
+                .into_iter()
+                .enumerate()
+                .map(|(i, value)| (format!("_{}", i), value))
+                .collect()
+        }
+        // Only keys which can be written as field names are kept, in order so the output
+        // doesn't change between runs.
+        ReflectRef::Map(m) => {
+            let mut entries: Vec<_> = m
+                .iter()
+                .filter_map(|(key, value)| {
+                    let key = key.downcast_ref::<String>().filter(|k| is_field_name(k))?;
+                    Some((key.clone(), write_value(value)?))
+                })
+                .collect();
+            entries.sort();
+            entries
+        }
+        ReflectRef::List(l) => {
+            let values: Option<Vec<_>> = l.iter().map(write_value).collect();
+            let values = values.unwrap_or_default().join(", ");
+            writeln!(out, "{}{} [{}],", INDENT.repeat(depth), type_name, values).unwrap();
+            return;
+        }
+        _ => Vec::new(),
+    };
+
+    let indent = INDENT.repeat(depth);
+    if fields.is_empty() {
+        writeln!(out, "{}{},", indent, type_name).unwrap();
+        return;
+    }
+
+    writeln!(out, "{}{} {{", indent, type_name).unwrap();
+    for (name, value) in fields {
+        writeln!(out, "{}{}{}: {},", indent, INDENT, name, value).unwrap();
+    }
+    writeln!(out, "{}}},", indent).unwrap();
+}
+
+/// Whether a map key can be written as a field name, ie: `walk` but not `"top speed"`.
+fn is_field_name(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .map_or(false, |c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Describe a build step on a single line, for [PrefabRegistry::set_explain_spawns].
+///
+/// Values which can't be written in *.prefab* format are shown as `..`.
+pub(crate) fn explain_step(reg: &PrefabRegistry, step: &PrefabBuildStep) -> String {
+    match step {
+        PrefabBuildStep::AddComponent(comp) => explain_component(reg, comp),
+        PrefabBuildStep::RunCommand(data) => explain_command(reg, data),
+        PrefabBuildStep::AddChild(child) => {
+            match child.name.as_deref().or_else(|| child.anchor.as_deref()) {
+                Some(name) => format!("add child {}", name),
+                None => "add child".to_string(),
+            }
+        }
+    }
+}
+
+/// Describe a component with the values the prefab gives its fields, and the fields it
+/// leaves as default.
+pub(crate) fn explain_component(reg: &PrefabRegistry, comp: &PrefabComponent) -> String {
+    let mut line = format!("insert {}", explain_value(&*comp.reflect));
+    let unset = unset_fields(reg, comp);
+    if !unset.is_empty() {
+        write!(line, ", left as default: {}", unset.join(", ")).unwrap();
+    }
+    line
+}
+
+/// Describe a build command with its properties, and which of them came from the
+/// command's defaults.
+pub(crate) fn explain_command(reg: &PrefabRegistry, data: &PrefabCommandData) -> String {
+    let mut props = Vec::new();
+    let mut defaulted = Vec::new();
+    let defaults = reg.get_command_defaults(&data.name);
+    if let Some(properties) = &data.properties {
+        for i in 0..properties.field_len() {
+            let name = properties.name_at(i).unwrap();
+            let value = properties.field_at(i).unwrap();
+            let written = write_property(name, value);
+            // Floats don't support reflected comparison, so compare the written values.
+            let default = defaults.and_then(|d| write_property(name, d.field(name)?));
+            if written.is_some() && written == default {
+                defaulted.push(name);
+            }
+            props.push(written.unwrap_or_else(|| format!("{}: ..", name)));
+        }
+    }
+    if let Some(bundle) = data.name.strip_prefix("bundle ") {
+        return format!("insert default {}", bundle);
+    }
+    let mut line = format!("run {}({})", data.name, props.join(", "));
+    if let Some(flag) = &data.flag {
+        line = format!("if \"{}\": {}", flag, line);
+    }
+    if !defaulted.is_empty() {
+        write!(line, ", from command defaults: {}", defaulted.join(", ")).unwrap();
+    }
+    line
+}
+
+/// Describe every build step of a prefab and its children, one per line.
+pub(crate) fn explain_prefab(
+    out: &mut String,
+    reg: &PrefabRegistry,
+    prefab: &Prefab,
+    depth: usize,
+) {
+    let indent = INDENT.repeat(depth);
+    for step in prefab.steps.iter() {
+        writeln!(out, "{}{}", indent, explain_step(reg, step)).unwrap();
+        if let PrefabBuildStep::AddChild(child) = step {
+            explain_prefab(out, reg, child, depth + 1);
+        }
+    }
+}
+
+/// Write a value on a single line, with `..` for anything that can't be written.
+pub(crate) fn explain_value(value: &dyn Reflect) -> String {
+    let type_name = TypeRegistration::get_short_name(value.type_name());
+    match value.reflect_ref() {
+        ReflectRef::Struct(s) if value.is::<DynamicStruct>() => {
+            let fields: Vec<_> = (0..s.field_len())
+                .map(|i| {
+                    let value = explain_value(s.field_at(i).unwrap());
+                    format!("{}: {}", s.name_at(i).unwrap(), value)
+                })
+                .collect();
+            match (type_name.is_empty(), fields.is_empty()) {
+                (true, _) => format!("{{ {} }}", fields.join(", ")),
+                (false, true) => type_name,
+                (false, false) => format!("{} {{ {} }}", type_name, fields.join(", ")),
+            }
+        }
+        ReflectRef::TupleStruct(ts) if value.is::<DynamicTupleStruct>() => {
+            let fields: Vec<_> = ts.iter_fields().map(explain_value).collect();
+            format!("{}({})", type_name, fields.join(", "))
+        }
+        _ => write_value(value).unwrap_or_else(|| "..".to_string()),
+    }
+}
+
+/// The fields of a component type which the prefab doesn't set.
+fn unset_fields<'a>(reg: &'a PrefabRegistry, comp: &PrefabComponent) -> Vec<&'a str> {
+    let instance = match reg.get_type_data(&comp.type_name) {
+        Some(info) => &info.instance,
+        None => return Vec::new(),
+    };
+    match (instance.reflect_ref(), comp.reflect.reflect_ref()) {
+        (ReflectRef::Struct(instance), ReflectRef::Struct(set)) => (0..instance.field_len())
+            .filter_map(|i| instance.name_at(i))
+            .filter(|name| set.field(name).is_none())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Write a value of any integer type.
+fn write_integer(value: &dyn Reflect) -> Option<String> {
+    macro_rules! integers {
+        ($($t:ty),*) => {$(
+            if let Some(i) = value.downcast_ref::<$t>() {
+                return Some(i.to_string());
+            }
+        )*};
+    }
+    integers!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+    None
+}
+
+/// Write a range of any integer type, or of `f32`s.
+fn write_range(value: &dyn Reflect) -> Option<String> {
+    if let Some(r) = value.downcast_ref::<Range<f32>>() {
+        return Some(format!(
+            "({}..{})",
+            write_float(r.start)?,
+            write_float(r.end)?
+        ));
+    }
+    macro_rules! ranges {
+        ($($t:ty),*) => {$(
+            if let Some(r) = value.downcast_ref::<Range<$t>>() {
+                return Some(format!("({}..{})", r.start, r.end));
+            }
+        )*};
+    }
+    ranges!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+    None
+}
+
+/// Write a value in *.prefab* format, or [None] if the value can't be represented.
+pub(crate) fn write_value(value: &dyn Reflect) -> Option<String> {
+    if let Some(deferred) = value.downcast_ref::<DeferredValue>() {
+        return write_deferred(deferred);
+    }
+    if let Some(f) = value.downcast_ref::<f32>() {
+        return write_float(*f);
+    }
+    if let Some(i) = write_integer(value) {
+        return Some(i);
+    }
+    if let Some(r) = write_range(value) {
+        return Some(r);
+    }
+    if let Some(b) = value.downcast_ref::<bool>() {
+        return Some(b.to_string());
+    }
+    if let Some(d) = value.downcast_ref::<Duration>() {
+        return Some(format!("{}s", write_float(d.as_secs_f32())?));
+    }
+    if let Some(s) = value.downcast_ref::<String>() {
+        return Some(write_string(s));
+    }
+    if let Some(v) = value.downcast_ref::<Vec2>() {
+        return Some(format!(
+            "Vec2 {{ x: {}, y: {} }}",
+            write_float(v.x)?,
+            write_float(v.y)?
+        ));
+    }
+    if let Some(v) = value.downcast_ref::<Vec3>() {
+        return Some(format!(
+            "Vec3 {{ x: {}, y: {}, z: {} }}",
+            write_float(v.x)?,
+            write_float(v.y)?,
+            write_float(v.z)?
+        ));
+    }
+    #[cfg(feature = "render")]
+    if let Some(col) = value.downcast_ref::<Color>() {
+        let [r, g, b, a] = col.as_rgba_f32();
+        return Some(format!(
+            "Color {{ r: {}, g: {}, b: {}, a: {} }}",
+            write_float(r)?,
+            write_float(g)?,
+            write_float(b)?,
+            write_float(a)?
+        ));
+    }
+    #[cfg(feature = "render")]
+    if let Some(val) = value.downcast_ref::<Val>() {
+        return match val {
+            Val::Undefined => Some("Undefined".to_string()),
+            Val::Auto => Some("Auto".to_string()),
+            Val::Px(n) => Some(format!("Px({})", write_float(*n)?)),
+            Val::Percent(n) => Some(format!("Percent({})", write_float(*n)?)),
+        };
+    }
+    if let ReflectRef::Struct(s) = value.reflect_ref() {
+        if s.type_name().is_empty() {
+            let props: Vec<_> = (0..s.field_len())
+                .filter_map(|i| write_property(s.name_at(i)?, s.field_at(i)?))
+                .collect();
+            return Some(format!("{{ {} }}", props.join(", ")));
+        }
+        let type_name = TypeRegistration::get_short_name(s.type_name());
+        if !is_writable_type_name(&type_name) {
+            return None;
+        }
+        let fields: Vec<_> = (0..s.field_len())
+            .filter_map(|i| {
+                Some(format!(
+                    "{}: {}",
+                    s.name_at(i)?,
+                    write_value(s.field_at(i)?)?
+                ))
+            })
+            .collect();
+        return Some(format!("{} {{ {} }}", type_name, fields.join(", ")));
+    }
+    if let ReflectRef::List(list) = value.reflect_ref() {
+        let values: Option<Vec<_>> = list.iter().map(write_value).collect();
+        return Some(format!("[{}]", values?.join(", ")));
+    }
+    None
+}
+
+/// Write a quoted string, escaping any quotes, backslashes and line breaks.
+fn write_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn write_deferred(value: &DeferredValue) -> Option<String> {
+    match value {
+        DeferredValue::Const(f) => write_float(*f),
+        DeferredValue::RandomFloat(range) => Some(format!(
+            "rand({}..{})",
+            write_float(range.start)?,
+            write_float(range.end)?
+        )),
+        DeferredValue::Var(name) => Some(format!("${{{}}}", name)),
+        DeferredValue::EntityRef(name) => Some(format!("@{}", name)),
+        DeferredValue::Localized(key) => Some(format!("tr({})", write_string(key))),
+        DeferredValue::Asset(path) => Some(format!("asset({})", write_string(path))),
+        DeferredValue::Template(text) => Some(write_string(text)),
+        DeferredValue::Number(value, _) => write_deferred(value),
+        DeferredValue::RandomInt(range) => {
+            Some(format!("rand_int({}..{})", range.start, range.end))
+        }
+        DeferredValue::Vector(values) => {
+            let fields: Option<Vec<_>> = ["x", "y", "z"]
+                .iter()
+                .zip(values)
+                .map(|(name, value)| Some(format!("{}: {}", name, write_deferred(value)?)))
+                .collect();
+            let type_name = if values.len() == 2 { "Vec2" } else { "Vec3" };
+            Some(format!("{} {{ {} }}", type_name, fields?.join(", ")))
+        }
+    }
+}
+
+fn write_float(f: f32) -> Option<String> {
+    if !f.is_finite() {
+        return None;
+    }
+    let mut s = format!("{:?}", f);
+    // The prefab grammar requires a decimal point in float values.
+    if !s.contains('.') {
+        let i = s.find('e').unwrap_or(s.len());
+        s.insert_str(i, ".0");
+    }
+    Some(s)
+}
+
+/// Returns true if the type name can be written to and read from a *.prefab* file.
+pub(crate) fn is_writable_type_name(type_name: &str) -> bool {
+    type_name.split("::").all(|ident| {
+        let mut chars = ident.chars();
+        match chars.next() {
+            Some(c) if c.is_alphabetic() || c == '_' => {}
+            _ => return false,
+        }
+        chars.all(|c| c.is_alphanumeric() || c == '_')
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use bevy::prelude::*;
+
+    use crate::{parse::parse_prefab_str, PrefabRegistry};
+
+    #[test]
+    fn display_round_trip() {
+        let mut reg = PrefabRegistry::default();
+        reg.register_type::<Visible>();
+        reg.register_type::<Transform>();
+
+        let input = "Thing {
+            Transform { translation: Vec3 { x: 1.5, y: -2.0 } },
+            DoStuff!(color: Color::RED, name: \"hi\", count: 3),
+            children [ { Visible }, Child { Visible } ],
+        }";
+        let prefab = parse_prefab_str(input, &reg).unwrap();
+        let text = prefab.to_string();
+
+        let expected = "Thing {
+    Transform {
+        translation: Vec3 { x: 1.5, y: -2.0, z: 0.0 },
+    },
+    DoStuff!(
+        color: Color { r: 1.0, g: 0.0, b: 0.0, a: 1.0 },
+        name: \"hi\",
+        count: 3,
+    ),
+    children [
+        {
+            Visible,
+        },
+        Child {
+            Visible,
+        },
+    ],
+}
+";
+        assert_eq!(text, expected);
+
+        let reparsed = parse_prefab_str(&text, &reg).unwrap();
+        assert_eq!(reparsed.to_string(), expected);
+    }
+}